@@ -17,6 +17,9 @@ fi
 # Build the Rust WASM module
 wasm-pack build --target web --out-dir ../../../public/wasm/rust
 
+# Worker script and its page-side client sit next to the generated module they import
+cp js/worker.js js/worker_client.js ../../../public/wasm/rust/
+
 cd ../../..
 
 # Copy Python analyzer to public directory
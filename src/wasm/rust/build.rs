@@ -0,0 +1,55 @@
+//! Embeds build identification for `core::build_info`: the git commit (overridable with
+//! `SUMMA2_GIT_COMMIT` for builds from a source tarball), the cargo profile and the
+//! enabled features; and for `core::codec_info`, the resolved codec crate versions.
+
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !text.trim().is_empty()).then(|| text.trim().to_string())
+}
+
+// Version of `package` as resolved in Cargo.lock, which cargo writes before running
+// build scripts
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let mut lines = lock.lines().skip_while(|line| *line != format!("name = \"{}\"", package));
+    lines.next()?;
+    let version = lines.next()?.strip_prefix("version = \"")?.strip_suffix('"')?;
+    Some(version.to_string())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SUMMA2_GIT_COMMIT");
+
+    let commit = std::env::var("SUMMA2_GIT_COMMIT")
+        .ok()
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    // Rebuild when HEAD moves, whether by checkout or by a new commit on the branch
+    for path in ["HEAD", "packed-refs"] {
+        if let Some(path) = git(&["rev-parse", "--git-path", path]) {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]).and_then(|r| git(&["rev-parse", "--git-path", &r])) {
+        println!("cargo:rerun-if-changed={}", branch);
+    }
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=SUMMA2_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=SUMMA2_BUILD_PROFILE={}", std::env::var("PROFILE").unwrap_or_default());
+    println!("cargo:rustc-env=SUMMA2_FEATURES={}", features.join(","));
+
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    for (package, key) in [("image", "SUMMA2_IMAGE_VERSION"), ("png", "SUMMA2_PNG_VERSION")] {
+        let version = locked_version(&lock, package).unwrap_or_else(|| "unknown".to_string());
+        println!("cargo:rustc-env={}={}", key, version);
+    }
+}
@@ -0,0 +1,6 @@
+import init, { handle_message } from "./document_converter.js";
+const ready = init();
+self.onmessage = async ({ data }) => {
+  const reply = await ready.then(() => handle_message(data));
+  self.postMessage(reply, reply.buffers ?? []);
+};
@@ -0,0 +1,82 @@
+// Page side of the worker protocol in src/worker.rs. Each request gets an id; replies
+// and progress messages are routed back to the promise and callback of that request.
+//
+//   const converter = new ConverterWorker(new URL("./worker.js", import.meta.url));
+//   await converter.setConfig(config);
+//   const { result } = await converter.convert(files, { onProgress });
+//   img.src = URL.createObjectURL(result.files[0].blob);
+export class ConverterWorker {
+  constructor(url) {
+    this.worker = new Worker(url, { type: "module" });
+    this.nextId = 1;
+    this.pending = new Map();
+    this.worker.onmessage = ({ data }) => {
+      const request = this.pending.get(data.id);
+      if (!request) return;
+      if ("progress" in data) {
+        request.onProgress?.(data.progress);
+      } else if ("error" in data) {
+        this.pending.delete(data.id);
+        request.reject(new Error(data.error));
+      } else {
+        this.pending.delete(data.id);
+        request.resolve(data);
+      }
+    };
+  }
+
+  request(op, payload, { transfer = [], onProgress } = {}) {
+    const id = this.nextId++;
+    return new Promise((resolve, reject) => {
+      this.pending.set(id, { resolve, reject, onProgress });
+      this.worker.postMessage({ id, op, payload }, transfer);
+    });
+  }
+
+  // `config` is a ConversionConfig object or its JSON
+  async setConfig(config) {
+    await this.request("set_config", config);
+  }
+
+  // Resolves to the ConversionResult the direct API gives, except that each converted
+  // file carries its bytes as a `blob` and its `data_url` is empty; the raw bytes are
+  // also in `buffers`. Buffers are transferred both ways, not copied. Use `dataUrl` for
+  // a file that must be sent on as a data URL.
+  async convert(files, { onProgress } = {}) {
+    const payload = {
+      files: await Promise.all(
+        Array.from(files, async (file) => ({ name: file.name, type: file.type, data: await file.arrayBuffer() })),
+      ),
+    };
+    const { result, buffers, types } = await this.request("convert", payload, {
+      transfer: payload.files.map((file) => file.data),
+      onProgress,
+    });
+    result.files.forEach((file, index) => {
+      file.blob = new Blob([buffers[index]], { type: types[index] });
+    });
+    return { result, buffers };
+  }
+
+  // The base64 data URL of a converted file's `blob`
+  static dataUrl(file) {
+    return new Promise((resolve, reject) => {
+      const reader = new FileReader();
+      reader.onload = () => resolve(reader.result);
+      reader.onerror = () => reject(reader.error);
+      reader.readAsDataURL(file.blob);
+    });
+  }
+
+  // Stops running conversions before their next file; `convert` then resolves with the
+  // files done so far and the rest reported as cancelled
+  async cancel() {
+    await this.request("cancel");
+  }
+
+  terminate() {
+    this.worker.terminate();
+    for (const request of this.pending.values()) request.reject(new Error("Worker terminated"));
+    this.pending.clear();
+  }
+}
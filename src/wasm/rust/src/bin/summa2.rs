@@ -0,0 +1,84 @@
+//! Native front-end for the converter, for batch jobs that re-validate uploads outside
+//! the browser. Conversion goes through the same `core` code as the wasm build.
+
+use clap::{Parser, Subcommand};
+use document_converter::core::{self, Batch, ConversionResult, ConvertError, NoopObserver};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "summa2", version, about = "Convert exam documents to an upload spec")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert inputs into `--out` and print a JSON `ConversionResult` report.
+    /// Exits 1 when any input fails, 2 when the run cannot start.
+    Convert {
+        /// `ConversionConfig` JSON, the same document the web app passes to `set_config`
+        #[arg(long)]
+        config: PathBuf,
+        /// Directory for converted files; created if missing
+        #[arg(long)]
+        out: PathBuf,
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Command::Convert { config, out, inputs } => match convert(&config, &out, &inputs) {
+            Ok(result) => {
+                match serde_json::to_string_pretty(&result) {
+                    Ok(report) => println!("{}", report),
+                    Err(e) => {
+                        eprintln!("summa2: cannot serialize report: {}", e);
+                        return ExitCode::from(2);
+                    }
+                }
+                if result.success {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::from(1)
+                }
+            }
+            Err(message) => {
+                eprintln!("summa2: {}", message);
+                ExitCode::from(2)
+            }
+        },
+    }
+}
+
+fn convert(config_path: &Path, out: &Path, inputs: &[PathBuf]) -> Result<ConversionResult, String> {
+    let config_json = fs::read_to_string(config_path)
+        .map_err(|e| format!("cannot read {}: {}", config_path.display(), e))?;
+    let config = core::parse_config(&config_json).map_err(|e| e.to_string())?;
+    fs::create_dir_all(out).map_err(|e| format!("cannot create {}: {}", out.display(), e))?;
+
+    let mut batch = Batch::new(&config, HashSet::new(), inputs.len());
+    for input in inputs {
+        let file_name = input
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| input.display().to_string());
+        let data = fs::read(input).map_err(|e| ConvertError::Read(format!("Failed to read file: {}", e)));
+        batch.push(&file_name, core::mime_type_from_name(&file_name), data, &NoopObserver);
+    }
+    let result = batch.finish();
+
+    for file in &result.files {
+        let path = out.join(&file.converted_name);
+        let data = file
+            .decode_data()
+            .ok_or_else(|| format!("invalid data URL for {}", file.original_name))?;
+        fs::write(&path, data).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    }
+    Ok(result)
+}
@@ -0,0 +1,158 @@
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::codecs::{check_decoded_size, check_input, declared_layout};
+use super::exif::exif_orientation;
+use super::spec::{ColorType, DEFAULT_MAX_DECODED_PIXELS};
+use super::ConvertError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ImageAnalysis {
+    pub width: u32,
+    pub height: u32,
+    pub luminance: LuminanceStats,
+    pub unique_colors: u32,
+    // Fraction of pixels sitting on a strong luminance edge; high for line art and text
+    pub edge_density: f32,
+    pub has_transparency: bool,
+    /// Color type and bits per pixel the image is stored in: as its JPEG or PNG header
+    /// declares them for `analyze_data`, so a palette stays indexed, or as decoded
+    pub color_type: ColorType,
+    pub bit_depth: u8,
+    /// EXIF orientation (1..=8) of the source, as recorded; the statistics above are
+    /// computed on the stored pixels, unrotated. Only `analyze_data` can read it.
+    pub orientation: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct LuminanceStats {
+    pub mean: f32,
+    pub min: u8,
+    pub max: u8,
+    pub stddev: f32,
+}
+
+const ANALYSIS_MAX_EDGE: u32 = 512;
+const EDGE_THRESHOLD: i32 = 32;
+
+/// Decode `data` and compute its statistics.
+pub fn analyze_data(data: &[u8]) -> Result<ImageAnalysis, ConvertError> {
+    check_input(data, "")?;
+    check_decoded_size(data, DEFAULT_MAX_DECODED_PIXELS)?;
+    let img = image::load_from_memory(data)
+        .map_err(|e| ConvertError::Decode(format!("Failed to load image: {}", e)))?;
+    let analysis = analyze(&img);
+    let (color_type, bit_depth) = declared_layout(data).unwrap_or((analysis.color_type, analysis.bit_depth));
+    Ok(ImageAnalysis { color_type, bit_depth, orientation: exif_orientation(data), ..analysis })
+}
+
+// Statistics are computed on a nearest-neighbour reduction (which keeps the color set
+// intact) so large photos stay cheap; transparency is checked at full resolution.
+pub fn analyze(img: &image::DynamicImage) -> ImageAnalysis {
+    let (width, height) = img.dimensions();
+    let sample = if width > ANALYSIS_MAX_EDGE || height > ANALYSIS_MAX_EDGE {
+        img.resize(ANALYSIS_MAX_EDGE, ANALYSIS_MAX_EDGE, image::imageops::FilterType::Nearest)
+    } else {
+        img.clone()
+    };
+    let luma = sample.to_luma8();
+    let count = luma.len().max(1) as f64;
+
+    let (mut sum, mut sum_sq, mut min, mut max) = (0f64, 0f64, u8::MAX, u8::MIN);
+    for &value in luma.as_raw() {
+        sum += value as f64;
+        sum_sq += (value as f64).powi(2);
+        min = min.min(value);
+        max = max.max(value);
+    }
+    let mean = sum / count;
+    let stddev = (sum_sq / count - mean * mean).max(0.0).sqrt();
+
+    let unique_colors = sample
+        .to_rgb8()
+        .pixels()
+        .map(|p| p.0)
+        .collect::<HashSet<[u8; 3]>>()
+        .len() as u32;
+
+    let (sample_width, sample_height) = luma.dimensions();
+    let mut edges = 0u32;
+    for y in 0..sample_height.saturating_sub(1) {
+        for x in 0..sample_width.saturating_sub(1) {
+            let here = luma.get_pixel(x, y).0[0] as i32;
+            let gradient = (luma.get_pixel(x + 1, y).0[0] as i32 - here).abs()
+                + (luma.get_pixel(x, y + 1).0[0] as i32 - here).abs();
+            if gradient > EDGE_THRESHOLD {
+                edges += 1;
+            }
+        }
+    }
+
+    let has_transparency = img.color().has_alpha() && img.to_rgba8().pixels().any(|p| p.0[3] < 255);
+    let color_type = match (img.color().has_color(), img.color().has_alpha()) {
+        (false, false) => ColorType::Grayscale,
+        (false, true) => ColorType::GrayscaleAlpha,
+        (true, false) => ColorType::Rgb,
+        (true, true) => ColorType::Rgba,
+    };
+
+    ImageAnalysis {
+        width,
+        height,
+        luminance: LuminanceStats {
+            mean: mean as f32,
+            min: if luma.is_empty() { 0 } else { min },
+            max,
+            stddev: stddev as f32,
+        },
+        unique_colors,
+        edge_density: edges as f32 / count as f32,
+        has_transparency,
+        color_type,
+        bit_depth: img.color().bits_per_pixel() as u8,
+        orientation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_flat_image() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(64, 32, image::Rgb([100, 100, 100])));
+        let analysis = analyze(&img);
+        assert_eq!((analysis.width, analysis.height), (64, 32));
+        assert_eq!(analysis.luminance, LuminanceStats { mean: 100.0, min: 100, max: 100, stddev: 0.0 });
+        assert_eq!(analysis.unique_colors, 1);
+        assert_eq!(analysis.edge_density, 0.0);
+        assert!(!analysis.has_transparency);
+        assert_eq!((analysis.color_type, analysis.bit_depth), (ColorType::Rgb, 24));
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn analyze_data_reports_the_stored_layout() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_luma16(16, 16).write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).unwrap();
+        let analysis = analyze_data(&png).unwrap();
+        assert_eq!((analysis.color_type, analysis.bit_depth), (ColorType::Grayscale, 16));
+    }
+
+    #[test]
+    fn analyze_checkerboard_and_transparency() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(1024, 1024, |x, y| {
+            let on = (x / 4 + y / 4) % 2 == 0;
+            image::Rgba(if on { [255, 255, 255, 255] } else { [0, 0, 0, 0] })
+        }));
+        let analysis = analyze(&img);
+        assert_eq!((analysis.luminance.min, analysis.luminance.max), (0, 255));
+        assert!((analysis.luminance.mean - 127.5).abs() < 2.0);
+        assert_eq!(analysis.unique_colors, 2);
+        assert!(analysis.edge_density > 0.5, "{}", analysis.edge_density);
+        assert!(analysis.has_transparency);
+    }
+}
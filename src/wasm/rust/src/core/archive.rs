@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+use super::result::ConvertedFile;
+use super::ConvertError;
+
+// Entries are stored uncompressed: JPEG, PNG and PDF output is already compressed, and
+// deflating it again only costs time
+const STORED: u16 = 0;
+const VERSION_NEEDED: u16 = 20;
+// General purpose flag bit 11: file names are UTF-8
+const UTF8_NAMES: u16 = 1 << 11;
+// 1980-01-01 00:00, the earliest DOS timestamp, so archives of the same files are identical
+const DOS_DATE: u16 = (1 << 5) | 1;
+const DOS_TIME: u16 = 0;
+
+/// Package converted files into a ZIP archive under their `converted_name`. Repeated
+/// names get a `_2`, `_3`, ... suffix before the extension. Without ZIP64 an archive is
+/// limited to 65535 entries and 4GB, which a browser batch never approaches.
+pub fn zip_files(files: &[ConvertedFile]) -> Result<Vec<u8>, ConvertError> {
+    if files.len() > u16::MAX as usize {
+        return Err(ConvertError::Encode(format!("Too many files for a ZIP archive: {}", files.len())));
+    }
+    let mut archive = Vec::new();
+    let mut central_directory = Vec::new();
+    let mut names = HashSet::new();
+
+    for file in files {
+        let data = file
+            .decode_data()
+            .ok_or_else(|| ConvertError::Encode(format!("{} has an undecodable data URL", file.converted_name)))?;
+        let name = unique_name(&file.converted_name, &mut names);
+        let offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let crc = crc32fast::hash(&data);
+
+        // Local file header, then the data
+        put_u32(&mut archive, 0x0403_4b50);
+        put_entry_fields(&mut archive, crc, size, name.len() as u16);
+        put_u16(&mut archive, 0); // extra field length
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&data);
+
+        put_u32(&mut central_directory, 0x0201_4b50);
+        put_u16(&mut central_directory, VERSION_NEEDED); // version made by
+        put_entry_fields(&mut central_directory, crc, size, name.len() as u16);
+        // Extra field, comment, disk number, internal and external attributes
+        central_directory.extend_from_slice(&[0; 12]);
+        put_u32(&mut central_directory, offset);
+        central_directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+    archive.extend_from_slice(&central_directory);
+    put_u32(&mut archive, 0x0605_4b50);
+    put_u32(&mut archive, 0); // this disk and the directory's disk
+    put_u16(&mut archive, files.len() as u16);
+    put_u16(&mut archive, files.len() as u16);
+    put_u32(&mut archive, central_directory.len() as u32);
+    put_u32(&mut archive, directory_offset);
+    put_u16(&mut archive, 0); // comment length
+    Ok(archive)
+}
+
+// Fields shared by the local header and the central directory record, from "version
+// needed" through the file name length
+fn put_entry_fields(out: &mut Vec<u8>, crc: u32, size: u32, name_length: u16) {
+    put_u16(out, VERSION_NEEDED);
+    put_u16(out, UTF8_NAMES);
+    put_u16(out, STORED);
+    put_u16(out, DOS_TIME);
+    put_u16(out, DOS_DATE);
+    put_u32(out, crc);
+    put_u32(out, size); // compressed
+    put_u32(out, size); // uncompressed
+    put_u16(out, name_length);
+}
+
+/// `name`, or with a `_2`, `_3`, ... suffix before the extension if `taken` has it, which
+/// it then records.
+pub fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (name, String::new()),
+    };
+    let mut candidate = name.to_string();
+    let mut n = 1;
+    while !taken.insert(candidate.clone()) {
+        n += 1;
+        candidate = format!("{}_{}{}", stem, n, extension);
+    }
+    candidate
+}
+
+fn too_large() -> ConvertError {
+    ConvertError::Encode("Converted files are too large for a ZIP archive (4GB limit)".to_string())
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::events::NoopObserver;
+    use crate::core::pipeline::convert;
+    use crate::core::test_support::{config_from, encode_png, gradient};
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn archive_lists_every_file_under_a_unique_name() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(16, 16)));
+        let config = config_from(serde_json::json!({}));
+        let file = convert("scan.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let archive = zip_files(&[file.clone(), file.clone()]).unwrap();
+
+        // End of central directory record: entry count, directory size and offset
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(&archive, end), 0x0605_4b50);
+        assert_eq!(u16_at(&archive, end + 10), 2);
+        let mut entry = u32_at(&archive, end + 16) as usize;
+
+        let data = file.decode_data().unwrap();
+        let mut names = Vec::new();
+        for _ in 0..2 {
+            assert_eq!(u32_at(&archive, entry), 0x0201_4b50);
+            assert_eq!(u32_at(&archive, entry + 16), crc32fast::hash(&data));
+            let name_length = u16_at(&archive, entry + 28) as usize;
+            names.push(String::from_utf8(archive[entry + 46..entry + 46 + name_length].to_vec()).unwrap());
+
+            // The local header points at the stored bytes
+            let local = u32_at(&archive, entry + 42) as usize;
+            assert_eq!(u32_at(&archive, local), 0x0403_4b50);
+            let start = local + 30 + u16_at(&archive, local + 26) as usize;
+            assert_eq!(&archive[start..start + data.len()], &data[..]);
+            entry += 46 + name_length;
+        }
+        assert_eq!(names, ["photo_scan.jpg", "photo_scan_2.jpg"]);
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use super::spec::BackgroundSpec;
+
+/// What `background` found around the subject and whether it was repainted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct BackgroundReport {
+    /// Detected background color as `#RRGGBB`
+    pub color: String,
+    /// Fraction of the image connected to the border in that color
+    pub coverage: f32,
+    /// Whether the detected color was already within tolerance of `required`
+    pub matches: bool,
+    pub replaced: bool,
+    /// Why a mismatched background was left alone
+    pub warning: Option<String>,
+}
+
+// At least this share of the image border must be background for it to count as plain
+const MIN_BORDER_MATCH: f32 = 0.6;
+// Outside this coverage the subject either merged into the background or fills the frame
+const COVERAGE_RANGE: (f32, f32) = (0.1, 0.9);
+// Subject pixels within this distance of the background are blended toward the new color
+const FEATHER_RADIUS: u32 = 2;
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2])
+}
+
+fn near(pixel: &image::Rgba<u8>, color: [u8; 3], tolerance: u8) -> bool {
+    (0..3).all(|c| pixel.0[c].abs_diff(color[c]) <= tolerance)
+}
+
+/// Detect the plain background around the subject and, with `auto_replace`, repaint it
+/// in `required`. The background is the region connected to the image border whose
+/// color stays within `tolerance` of the border's median color. Replacement is refused
+/// (reported in `warning`) when the border is too varied to be a plain backdrop or the
+/// region is too large or small to separate from the subject. Returns the image, changed
+/// only when `replaced`.
+pub fn replace_background(img: image::DynamicImage, spec: &BackgroundSpec) -> (image::DynamicImage, BackgroundReport) {
+    let required = spec.required_rgb().unwrap_or([255, 255, 255]);
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let border: Vec<(u32, u32)> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((1..height.saturating_sub(1)).flat_map(|y| [(0, y), (width - 1, y)]))
+        .collect();
+
+    let median = |channel: usize| {
+        let mut values: Vec<u8> = border.iter().map(|&(x, y)| rgba.get_pixel(x, y).0[channel]).collect();
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    let color = [median(0), median(1), median(2)];
+
+    // Flood fill from every border pixel in the background color
+    let mut mask = vec![false; (width * height) as usize];
+    let mut stack: Vec<(u32, u32)> = border
+        .iter()
+        .copied()
+        .filter(|&(x, y)| near(rgba.get_pixel(x, y), color, spec.tolerance))
+        .collect();
+    let border_match = stack.len() as f32 / border.len() as f32;
+    while let Some((x, y)) = stack.pop() {
+        let index = (y * width + x) as usize;
+        if mask[index] || !near(rgba.get_pixel(x, y), color, spec.tolerance) {
+            continue;
+        }
+        mask[index] = true;
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+    let coverage = mask.iter().filter(|&&m| m).count() as f32 / mask.len() as f32;
+
+    let matches = (0..3).all(|c| color[c].abs_diff(required[c]) <= spec.tolerance);
+    let warning = if matches || !spec.auto_replace {
+        None
+    } else if border_match < MIN_BORDER_MATCH {
+        Some("background is textured or not uniform".to_string())
+    } else if !(COVERAGE_RANGE.0..=COVERAGE_RANGE.1).contains(&coverage) {
+        Some("subject cannot be separated from the background".to_string())
+    } else {
+        None
+    };
+    let replaced = !matches && spec.auto_replace && warning.is_none();
+    let report = BackgroundReport { color: hex(color), coverage, matches, replaced, warning };
+    if !replaced {
+        return (img, report);
+    }
+
+    // Share of background in each pixel's neighbourhood, from a summed-area table
+    let stride = width as usize + 1;
+    let mut sums = vec![0u32; stride * (height as usize + 1)];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let above = sums[y * stride + x + 1] + sums[(y + 1) * stride + x] - sums[y * stride + x];
+            sums[(y + 1) * stride + x + 1] = mask[y * width as usize + x] as u32 + above;
+        }
+    }
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        let weight = if mask[(y * width + x) as usize] {
+            1.0
+        } else {
+            let (x0, y0) = (x.saturating_sub(FEATHER_RADIUS) as usize, y.saturating_sub(FEATHER_RADIUS) as usize);
+            let (x1, y1) = ((x + FEATHER_RADIUS + 1).min(width) as usize, (y + FEATHER_RADIUS + 1).min(height) as usize);
+            let count = sums[y1 * stride + x1] + sums[y0 * stride + x0] - sums[y0 * stride + x1] - sums[y1 * stride + x0];
+            // Halved so even the outermost subject pixels keep most of their own color
+            0.5 * count as f32 / ((x1 - x0) * (y1 - y0)) as f32
+        };
+        if weight > 0.0 {
+            for (channel, target) in pixel.0.iter_mut().zip(required) {
+                *channel = (*channel as f32 * (1.0 - weight) + target as f32 * weight).round() as u8;
+            }
+        }
+    }
+    let replaced_img = if img.color().has_alpha() {
+        image::DynamicImage::ImageRgba8(rgba)
+    } else {
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(rgba).to_rgb8())
+    };
+    (replaced_img, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(json: serde_json::Value) -> BackgroundSpec {
+        serde_json::from_value(json).unwrap()
+    }
+
+    // A dark head-and-shoulders shape on a plain backdrop with a little sensor noise
+    fn portrait(backdrop: [u8; 3]) -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(120, 160, |x, y| {
+            let (dx, dy) = (x as i32 - 60, y as i32 - 60);
+            if dx * dx + dy * dy < 900 || (y > 110 && (20..100).contains(&x)) {
+                image::Rgb([90, 60, 50])
+            } else {
+                let noise = ((x * 7 + y * 13) % 5) as u8;
+                image::Rgb([backdrop[0] - noise, backdrop[1] - noise, backdrop[2] - noise])
+            }
+        }))
+    }
+
+    #[test]
+    fn light_blue_backdrop_is_repainted_and_subject_kept() {
+        let spec = spec(serde_json::json!({ "required": "#FFFFFF", "auto_replace": true }));
+        let (img, report) = replace_background(portrait([205, 225, 245]), &spec);
+        assert!(report.replaced && !report.matches, "{:?}", report);
+        assert_eq!(report.color, "#CBDFF3");
+        assert!((0.4..0.8).contains(&report.coverage), "{}", report.coverage);
+
+        let rgb = img.to_rgb8();
+        assert_eq!(rgb.get_pixel(2, 2).0, [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(60, 60).0, [90, 60, 50]);
+        // The subject's outermost pixels are feathered toward white, not left with a blue fringe
+        let edge = rgb.get_pixel(60, 31).0;
+        assert!(edge[0] > 90 && edge[0] < 255, "{:?}", edge);
+    }
+
+    #[test]
+    fn matching_textured_and_report_only_backgrounds_are_left_alone() {
+        let replace = spec(serde_json::json!({ "required": "#FFFFFF", "auto_replace": true }));
+        let (_, report) = replace_background(portrait([252, 252, 252]), &replace);
+        assert!(report.matches && !report.replaced);
+
+        let report_only = spec(serde_json::json!({ "required": "#FFFFFF" }));
+        let original = portrait([205, 225, 245]);
+        let (img, report) = replace_background(original.clone(), &report_only);
+        assert!(!report.matches && !report.replaced && report.warning.is_none());
+        assert_eq!(img, original);
+
+        let textured = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(80, 80, |x, y| {
+            let grain = ((x * 31 + y * 17) % 97) as u8;
+            image::Rgb([150 + grain, 120 + grain / 2, 90 + grain])
+        }));
+        let (_, report) = replace_background(textured, &replace);
+        assert_eq!(report.warning.as_deref(), Some("background is textured or not uniform"));
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+
+use super::events::{LogEventKind, LogLevel, Observer};
+use super::pipeline::{convert, convert_hashed, sha256_hex};
+use super::result::{ConversionResult, ConvertedFile, FailedFile, FileCompletion, SkippedFile};
+use super::spec::ConversionConfig;
+use super::ConvertError;
+
+/// Convert one already-read input (or report why it could not be read), with session
+/// accounting, and package it as a single-file `ConversionResult`.
+pub fn convert_one(
+    file_name: &str,
+    file_type: &str,
+    data: Result<Vec<u8>, ConvertError>,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> ConversionResult {
+    single_result(file_name, data.and_then(|data| convert(file_name, file_type, &data, config, observer)), observer)
+}
+
+/// `convert_one` for an input the host converted some other way, such as through
+/// `HostEncode`: the session accounting and packaging alone.
+pub fn single_result(file_name: &str, outcome: Result<ConvertedFile, ConvertError>, observer: &dyn Observer) -> ConversionResult {
+    match count_outcome(file_name, outcome, observer) {
+        Ok(converted) => ConversionResult {
+            success: true,
+            files: vec![converted],
+            error: None,
+            error_code: None,
+            failures: vec![],
+            skipped: vec![],
+            attempts: vec![],
+        },
+        Err(e) => ConversionResult {
+            success: false,
+            files: vec![],
+            error: Some(format!("Conversion failed: {}", e)),
+            error_code: e.code().map(str::to_string),
+            failures: vec![],
+            skipped: vec![],
+            attempts: e.attempts().to_vec(),
+        },
+    }
+}
+
+/// The session accounting of `single_result` for one input converted into something
+/// other than a `ConversionResult`, such as tiles or a preview pair. Returns `outcome`.
+pub fn count_outcome<T>(file_name: &str, outcome: Result<T, ConvertError>, observer: &dyn Observer) -> Result<T, ConvertError> {
+    observer.record(&|stats| stats.files_attempted += 1);
+    match &outcome {
+        Ok(_) => observer.record(&|stats| stats.files_succeeded += 1),
+        Err(e) => report_failure(file_name, e, observer),
+    }
+    outcome
+}
+
+/// State of a multi-file conversion. Hosts read each input themselves and feed it in
+/// order; inputs whose SHA-256 is in `completed` are skipped so a batch can resume.
+pub struct Batch<'a> {
+    config: &'a ConversionConfig,
+    completed: HashSet<String>,
+    file_count: usize,
+    result: ConversionResult,
+    // Which list the input fed in last went to, for `completion`
+    last_recorded: Option<Recorded>,
+}
+
+#[derive(Clone, Copy)]
+enum Recorded {
+    Converted,
+    Failed,
+    Skipped,
+}
+
+impl<'a> Batch<'a> {
+    pub fn new(config: &'a ConversionConfig, completed: HashSet<String>, file_count: usize) -> Self {
+        Batch {
+            config,
+            completed,
+            file_count,
+            result: ConversionResult {
+                success: true,
+                files: vec![],
+                error: None,
+                error_code: None,
+                failures: vec![],
+                skipped: vec![],
+                attempts: vec![],
+            },
+            last_recorded: None,
+        }
+    }
+
+    /// Whether the host should stop feeding inputs. Checks the observer, so a batch
+    /// cancelled between files is recorded as cancelled.
+    pub fn should_stop(&mut self, observer: &dyn Observer) -> bool {
+        if self.result.error_code.is_none() && observer.is_cancelled() {
+            observer.debug(LogEventKind::Batch, None, format!("Batch conversion cancelled after {} files", self.processed()));
+            self.result.error_code = Some(ConvertError::Cancelled.to_string());
+        }
+        self.result.error_code.is_some()
+    }
+
+    /// Feed the next input, or the error that prevented reading it.
+    pub fn push(
+        &mut self,
+        file_name: &str,
+        file_type: &str,
+        data: Result<Vec<u8>, ConvertError>,
+        observer: &dyn Observer,
+    ) {
+        if let Some((data, input_hash)) = self.admit(file_name, data, observer) {
+            let outcome = convert_hashed(file_name, file_type, &data, &input_hash, self.config, observer);
+            self.record(file_name, input_hash, outcome, observer);
+        }
+    }
+
+    /// `push` in two halves, for hosts that convert some inputs themselves. Returns the
+    /// input and its hash when it still needs converting, recording read failures and
+    /// already completed inputs; pass its outcome to `record`.
+    pub fn admit(
+        &mut self,
+        file_name: &str,
+        data: Result<Vec<u8>, ConvertError>,
+        observer: &dyn Observer,
+    ) -> Option<(Vec<u8>, String)> {
+        self.last_recorded = None;
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                observer.record(&|stats| stats.files_attempted += 1);
+                observer.record(&|stats| stats.record_failure(&e));
+                self.result.failures.push(FailedFile {
+                    original_name: file_name.to_string(),
+                    input_hash: None,
+                    error: format!("Conversion failed: {}", e),
+                    error_code: e.code().map(str::to_string),
+                    attempts: vec![],
+                });
+                self.last_recorded = Some(Recorded::Failed);
+                return None;
+            }
+        };
+
+        let input_hash = sha256_hex(&data);
+        if self.completed.contains(&input_hash) {
+            observer.debug(LogEventKind::Batch, Some(file_name), "Skipping an already converted file".to_string());
+            observer.record(&|stats| stats.files_skipped += 1);
+            self.result.skipped.push(SkippedFile {
+                original_name: file_name.to_string(),
+                input_hash,
+                reason: "already completed".to_string(),
+            });
+            self.last_recorded = Some(Recorded::Skipped);
+            return None;
+        }
+        Some((data, input_hash))
+    }
+
+    pub fn record(
+        &mut self,
+        file_name: &str,
+        input_hash: String,
+        outcome: Result<ConvertedFile, ConvertError>,
+        observer: &dyn Observer,
+    ) {
+        observer.record(&|stats| stats.files_attempted += 1);
+        match outcome {
+            Ok(converted) => {
+                observer.record(&|stats| stats.files_succeeded += 1);
+                self.result.files.push(converted);
+                self.last_recorded = Some(Recorded::Converted);
+            }
+            Err(e) if e.is_cancelled() => {
+                observer.record(&|stats| stats.record_failure(&e));
+                observer.debug(LogEventKind::Batch, Some(file_name), "Batch conversion cancelled during this file".to_string());
+                self.result.error_code = Some(e.to_string());
+            }
+            Err(e) => {
+                report_failure(file_name, &e, observer);
+                self.result.failures.push(FailedFile {
+                    original_name: file_name.to_string(),
+                    input_hash: Some(input_hash),
+                    error: format!("Conversion failed: {}", e),
+                    error_code: e.code().map(str::to_string),
+                    attempts: e.attempts().to_vec(),
+                });
+                self.last_recorded = Some(Recorded::Failed);
+            }
+        }
+    }
+
+    /// The input fed in last, at `file_index`, as it will appear in the result. None
+    /// when its conversion was cancelled, which ends the batch instead.
+    pub fn completion(&self, file_index: usize) -> Option<FileCompletion> {
+        let mut completion = FileCompletion {
+            file_index: file_index as u32,
+            file_count: self.file_count as u32,
+            file: None,
+            failure: None,
+            skipped: None,
+        };
+        match self.last_recorded? {
+            Recorded::Converted => completion.file = self.result.files.last().cloned(),
+            Recorded::Failed => completion.failure = self.result.failures.last().cloned(),
+            Recorded::Skipped => completion.skipped = self.result.skipped.last().cloned(),
+        }
+        Some(completion)
+    }
+
+    pub fn finish(mut self) -> ConversionResult {
+        let result = &mut self.result;
+        if result.error_code.is_some() {
+            result.success = false;
+            result.error = Some(format!("Batch cancelled after converting {} of {} files",
+                result.files.len(), self.file_count));
+        } else if !result.failures.is_empty() {
+            result.success = false;
+            result.error = Some(format!("{} of {} files failed to convert",
+                result.failures.len(), self.file_count));
+        }
+        self.result
+    }
+
+    fn processed(&self) -> usize {
+        self.result.files.len() + self.result.failures.len() + self.result.skipped.len()
+    }
+}
+
+fn report_failure(file_name: &str, error: &ConvertError, observer: &dyn Observer) {
+    observer.record(&|stats| stats.record_failure(error));
+    observer.event(
+        LogLevel::Error,
+        LogEventKind::Failed,
+        Some(file_name),
+        serde_json::json!({ "error": error.to_string() }),
+    );
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::events::NoopObserver;
+    use crate::core::test_support::{config_from, encode_png, gradient};
+
+    #[test]
+    fn each_input_is_reported_as_it_completes() {
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 } }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(40, 30)));
+        let mut batch = Batch::new(&config, HashSet::from([sha256_hex(b"done before")]), 4);
+
+        batch.push("photo.png", "image/png", Ok(png), &NoopObserver);
+        let completion = batch.completion(0).unwrap();
+        assert_eq!((completion.file_index, completion.file_count), (0, 4));
+        assert_eq!(completion.file.unwrap().original_name, "photo.png");
+        assert!(completion.failure.is_none() && completion.skipped.is_none());
+
+        batch.push("notes.txt", "text/plain", Ok(b"plain text".to_vec()), &NoopObserver);
+        assert_eq!(batch.completion(1).unwrap().failure.unwrap().error, "Conversion failed: Unsupported file type: text/plain");
+        batch.push("lost.png", "image/png", Err(ConvertError::Read("gone".to_string())), &NoopObserver);
+        assert_eq!(batch.completion(2).unwrap().failure.unwrap().original_name, "lost.png");
+        batch.push("old.png", "image/png", Ok(b"done before".to_vec()), &NoopObserver);
+        assert_eq!(batch.completion(3).unwrap().skipped.unwrap().original_name, "old.png");
+
+        let result = batch.finish();
+        assert_eq!((result.files.len(), result.failures.len(), result.skipped.len()), (1, 2, 1));
+    }
+
+    #[test]
+    fn other_outputs_are_counted_like_converted_files() {
+        struct Stats(std::cell::Cell<crate::core::SessionStats>);
+        impl Observer for Stats {
+            fn record(&self, update: &dyn Fn(&mut crate::core::SessionStats)) {
+                let mut stats = self.0.get();
+                update(&mut stats);
+                self.0.set(stats);
+            }
+        }
+        let stats = Stats(Default::default());
+        assert_eq!(count_outcome("tiles.png", Ok(vec![1, 2]), &stats), Ok(vec![1, 2]));
+        let error = ConvertError::Read("gone".to_string());
+        assert_eq!(count_outcome::<()>("lost.png", Err(error.clone()), &stats), Err(error));
+        assert_eq!(count_outcome::<()>("late.png", Err(ConvertError::Cancelled), &stats), Err(ConvertError::Cancelled));
+        let stats = stats.0.get();
+        assert_eq!((stats.files_attempted, stats.files_succeeded, stats.files_failed, stats.files_cancelled), (3, 1, 1, 1));
+    }
+
+    #[test]
+    fn completed_inputs_are_skipped_without_converting() {
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 } }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(40, 30)));
+        let mut batch = Batch::new(&config, HashSet::from([sha256_hex(&png)]), 1);
+
+        assert!(batch.admit("photo.png", Ok(png.clone()), &NoopObserver).is_none());
+        let result = batch.finish();
+        assert!(result.success && result.files.is_empty());
+        assert_eq!(result.skipped[0].input_hash, sha256_hex(&png));
+        assert_eq!(result.skipped[0].reason, "already completed");
+    }
+
+    #[test]
+    fn new_inputs_are_converted_under_their_admitted_hash() {
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 } }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(40, 30)));
+        let mut batch = Batch::new(&config, HashSet::from([sha256_hex(b"done before")]), 1);
+
+        let (data, input_hash) = batch.admit("photo.png", Ok(png.clone()), &NoopObserver).unwrap();
+        assert_eq!((data, input_hash.as_str()), (png.clone(), sha256_hex(&png).as_str()));
+        batch.push("photo.png", "image/png", Ok(png.clone()), &NoopObserver);
+        let result = batch.finish();
+        assert!(result.skipped.is_empty());
+        assert_eq!(result.files[0].input_hash, input_hash);
+    }
+}
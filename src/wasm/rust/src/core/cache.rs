@@ -0,0 +1,126 @@
+//! Recent outputs kept in memory, keyed by input and config, so converting the same file
+//! again under the same rules (an applicant clicking "convert" twice) hands back the
+//! earlier result instead of redoing the work. Hosts opt in with a byte budget.
+
+use std::collections::VecDeque;
+
+use super::pipeline::{converted_filename, sha256_hex};
+use super::result::ConvertedFile;
+use super::spec::ConversionConfig;
+
+/// Converted files by (input SHA-256, config hash), least recently used evicted first
+/// once their data URLs total more than `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct ResultCache {
+    max_bytes: usize,
+    used_bytes: usize,
+    // Least recently used first
+    entries: VecDeque<((String, String), ConvertedFile)>,
+}
+
+impl ResultCache {
+    pub fn new(max_bytes: usize) -> Self {
+        ResultCache { max_bytes, used_bytes: 0, entries: VecDeque::new() }
+    }
+
+    /// The earlier output for `data`, whose `sha256_hex` is `input_hash`, under `config`,
+    /// flagged `cache_hit` and named for `file_name`, which may differ from the name it
+    /// was converted under.
+    pub fn get(&mut self, file_name: &str, data: &[u8], input_hash: &str, config: &ConversionConfig) -> Option<ConvertedFile> {
+        let key = (input_hash.to_string(), config_hash(config));
+        let position = self.entries.iter().position(|(entry_key, _)| *entry_key == key)?;
+        let entry = self.entries.remove(position)?;
+        let mut converted = entry.1.clone();
+        self.entries.push_back(entry);
+        converted.cache_hit = true;
+        converted.original_name = file_name.to_string();
+        converted.converted_name = converted_filename(file_name, data, &converted.format, config);
+        Some(converted)
+    }
+
+    /// Keep `converted`, the output for its input under `config`. Outputs larger than the
+    /// whole budget are not kept.
+    pub fn insert(&mut self, converted: &ConvertedFile, config: &ConversionConfig) {
+        let size = converted.data_url.len();
+        if size > self.max_bytes {
+            return;
+        }
+        let key = (converted.input_hash.clone(), config_hash(config));
+        if let Some(position) = self.entries.iter().position(|(entry_key, _)| *entry_key == key) {
+            self.evict(position);
+        }
+        while self.used_bytes + size > self.max_bytes {
+            self.evict(0);
+        }
+        self.used_bytes += size;
+        self.entries.push_back((key, ConvertedFile { cache_hit: false, ..converted.clone() }));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used_bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn evict(&mut self, position: usize) {
+        if let Some((_, converted)) = self.entries.remove(position) {
+            self.used_bytes -= converted.data_url.len();
+        }
+    }
+}
+
+// SHA-256 hex digest of a whole config's JSON form, since outputs depend on more than
+// the spec
+fn config_hash(config: &ConversionConfig) -> String {
+    sha256_hex(serde_json::to_string(config).unwrap_or_default().as_bytes())
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::events::NoopObserver;
+    use crate::core::pipeline::convert;
+    use crate::core::test_support::{config_from, encode_png, gradient};
+
+    #[test]
+    fn repeat_conversions_are_served_until_evicted() {
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 } }));
+        let (small, large) = (encode_png(&image::DynamicImage::ImageRgb8(gradient(16, 12))), encode_png(&image::DynamicImage::ImageRgb8(gradient(40, 30))));
+        let small_output = convert("small.png", "image/png", &small, &config, &NoopObserver).unwrap();
+        let large_output = convert("large.png", "image/png", &large, &config, &NoopObserver).unwrap();
+        let (small_hash, large_hash) = (sha256_hex(&small), sha256_hex(&large));
+
+        let mut cache = ResultCache::new(small_output.data_url.len() + large_output.data_url.len());
+        cache.insert(&small_output, &config);
+        let hit = cache.get("copy.png", &small, &small_hash, &config).unwrap();
+        assert!(hit.cache_hit);
+        assert_eq!((hit.original_name.as_str(), hit.converted_name.as_str()), ("copy.png", "photo_copy.png"));
+        assert_eq!(hit.data_url, small_output.data_url);
+
+        // Another config, or other bytes, are misses
+        let signature = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 400 } }));
+        assert!(cache.get("small.png", &small, &small_hash, &signature).is_none());
+        assert!(cache.get("large.png", &large, &large_hash, &config).is_none());
+
+        // The least recently used output goes first once the budget is spent
+        cache.insert(&large_output, &config);
+        assert!(cache.get("small.png", &small, &small_hash, &config).is_some());
+        cache.insert(&small_output, &signature);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("large.png", &large, &large_hash, &config).is_none());
+        assert!(cache.get("small.png", &small, &small_hash, &signature).is_some());
+
+        cache.clear();
+        assert!(cache.is_empty());
+        let mut tiny = ResultCache::new(8);
+        tiny.insert(&small_output, &config);
+        assert!(tiny.is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use image::ImageFormat;
+
+use super::spec::ColorType;
+use super::ConvertError;
+
+// Image formats the converter knows by name. `format` is None for formats the image
+// crate cannot read at all, so no feature set supports them.
+struct Codec {
+    name: &'static str,
+    mime_types: &'static [&'static str],
+    format: Option<ImageFormat>,
+}
+
+const CODECS: &[Codec] = &[
+    Codec { name: "JPEG", mime_types: &["image/jpeg", "image/jpg"], format: Some(ImageFormat::Jpeg) },
+    Codec { name: "PNG", mime_types: &["image/png"], format: Some(ImageFormat::Png) },
+    Codec { name: "WEBP", mime_types: &["image/webp"], format: Some(ImageFormat::WebP) },
+    Codec { name: "GIF", mime_types: &["image/gif"], format: Some(ImageFormat::Gif) },
+    Codec { name: "TIFF", mime_types: &["image/tiff"], format: Some(ImageFormat::Tiff) },
+    Codec { name: "BMP", mime_types: &["image/bmp"], format: Some(ImageFormat::Bmp) },
+    Codec { name: "AVIF", mime_types: &["image/avif"], format: Some(ImageFormat::Avif) },
+    Codec { name: "HEIC", mime_types: &["image/heic", "image/heif"], format: None },
+];
+
+/// Image formats this build can decode, by the names used in `ConvertedFile::format`.
+pub fn supported_input_formats() -> Vec<String> {
+    CODECS
+        .iter()
+        .filter(|codec| codec.format.is_some_and(|format| format.reading_enabled()))
+        .map(|codec| codec.name.to_string())
+        .collect()
+}
+
+/// Target formats this build can write. PDF inputs are passed through unchanged, so PDF
+/// needs no codec.
+pub fn supported_output_formats() -> Vec<String> {
+    ["JPEG", "PNG", "PDF"].into_iter().filter(|format| can_encode(format)).map(str::to_string).collect()
+}
+
+// Only JPEG and PNG have encoders in the pipeline; other names are left for the encoder
+// to reject as unsupported
+pub(crate) fn check_output(target_format: &str) -> Result<(), ConvertError> {
+    match target_format.to_uppercase().as_str() {
+        format @ ("JPEG" | "JPG" | "PNG") if !can_encode(format) => {
+            Err(ConvertError::CodecUnavailable(if format == "JPG" { "JPEG" } else { format }.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fails when `data` (or, if its signature is not recognised, `file_type`) names a
+/// format this build cannot decode. Unknown inputs pass and fail at decode instead.
+pub(crate) fn check_input(data: &[u8], file_type: &str) -> Result<(), ConvertError> {
+    let sniffed = image::guess_format(data).ok();
+    let codec = CODECS.iter().find(|codec| match sniffed {
+        Some(format) => codec.format == Some(format),
+        None => codec.mime_types.contains(&file_type),
+    });
+    match codec {
+        Some(codec) if !codec.format.is_some_and(|format| format.reading_enabled()) => {
+            Err(ConvertError::CodecUnavailable(codec.name.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fails when the dimensions in `data`'s header come to more than `max_pixels`, before
+/// any pixel data is read. Inputs whose header cannot be read pass and fail at decode.
+pub(crate) fn check_decoded_size(data: &[u8], max_pixels: u64) -> Result<(), ConvertError> {
+    let dimensions = image::io::Reader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    match dimensions {
+        Some((width, height)) if width as u64 * height as u64 > max_pixels => {
+            Err(ConvertError::DecompressionBomb { width, height, max_pixels })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The format `data` holds by its signature, named as in `ConvertedFile::format`, or
+/// None when it is neither a known image format nor a PDF.
+pub(crate) fn sniff_format(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"%PDF-") {
+        return Some("PDF");
+    }
+    let sniffed = image::guess_format(data).ok()?;
+    CODECS.iter().find(|codec| codec.format == Some(sniffed)).map(|codec| codec.name)
+}
+
+/// The color type and bits per pixel a JPEG or PNG header declares. JPEGs with three
+/// components decode to RGB whatever their internal color space. None for other formats
+/// and for headers that cannot be read.
+pub(crate) fn declared_layout(data: &[u8]) -> Option<(ColorType, u8)> {
+    if let Some(chunks) = data.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        let header = chunks.get(..18).filter(|header| &header[4..8] == b"IHDR")?;
+        let color_type = match header[17] {
+            0 => ColorType::Grayscale,
+            2 => ColorType::Rgb,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayscaleAlpha,
+            6 => ColorType::Rgba,
+            _ => return None,
+        };
+        return Some((color_type, header[16].checked_mul(color_type.channels())?));
+    }
+    // Segments up to the first start-of-frame, which gives the precision and components
+    let mut rest = data.strip_prefix(b"\xFF\xD8")?;
+    loop {
+        let (&[0xFF, marker], segment) = rest.split_first_chunk::<2>()? else {
+            return None;
+        };
+        let length = u16::from_be_bytes(*segment.first_chunk::<2>()?) as usize;
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let (precision, components) = (*segment.get(2)?, *segment.get(7)?);
+            let color_type = match components {
+                1 => ColorType::Grayscale,
+                3 => ColorType::Rgb,
+                4 => ColorType::Cmyk,
+                _ => return None,
+            };
+            return Some((color_type, precision.checked_mul(components)?));
+        }
+        rest = segment.get(length..)?;
+    }
+}
+
+/// The layout a JPEG or PNG header declares, named as in `output_color_type` ("rgb8",
+/// "rgba8") when it is one of those, or described otherwise.
+pub(crate) fn declared_color_type(data: &[u8]) -> Option<String> {
+    let (color_type, bits) = declared_layout(data)?;
+    let depth = bits / color_type.channels();
+    Some(match (color_type, depth) {
+        (ColorType::Rgb, 8) => "rgb8".to_string(),
+        (ColorType::Rgba, 8) => "rgba8".to_string(),
+        (ColorType::Rgb, _) => format!("{}-bit RGB", depth),
+        (ColorType::Rgba, _) => format!("{}-bit RGBA", depth),
+        (ColorType::Indexed, _) => "indexed color".to_string(),
+        (ColorType::Grayscale, _) => "grayscale".to_string(),
+        (ColorType::GrayscaleAlpha, _) => "grayscale with alpha".to_string(),
+        (ColorType::Cmyk, _) => "CMYK".to_string(),
+    })
+}
+
+/// Whether `target_format` can only be written by a host encoder, such as a browser's
+/// canvas. WebP has no encoder among the codec features.
+pub fn needs_host_encoder(target_format: &str) -> bool {
+    target_format.eq_ignore_ascii_case("WEBP")
+}
+
+fn can_encode(format: &str) -> bool {
+    let jpeg = cfg!(feature = "jpeg") && matches!(format, "JPEG" | "JPG");
+    let png = cfg!(feature = "png") && format == "PNG";
+    jpeg || png || format == "PDF"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "jpeg", feature = "png", feature = "webp", not(feature = "tiff")))]
+    fn compiled_codecs_are_reported_and_others_rejected() {
+        assert_eq!(supported_input_formats(), ["JPEG", "PNG", "WEBP"]);
+        assert_eq!(supported_output_formats(), ["JPEG", "PNG", "PDF"]);
+        assert_eq!(check_output("jpg"), Ok(()));
+
+        // Sniffed signatures win over the declared MIME type
+        let tiff = b"II*\0\x08\0\0\0";
+        assert_eq!(check_input(tiff, "image/png"), Err(ConvertError::CodecUnavailable("TIFF".to_string())));
+        assert_eq!(
+            check_input(b"\0\0\0\x18ftypheic", "image/heic").unwrap_err().to_string(),
+            "Converter not built with HEIC support"
+        );
+        assert_eq!(check_input(b"garbage", "image/x-unknown"), Ok(()));
+    }
+
+    #[test]
+    fn encoders_follow_the_codec_features() {
+        for (format, compiled) in [("JPEG", cfg!(feature = "jpeg")), ("PNG", cfg!(feature = "png"))] {
+            assert_eq!(supported_output_formats().contains(&format.to_string()), compiled);
+            match check_output(&format.to_lowercase()) {
+                Ok(()) => assert!(compiled),
+                Err(error) => assert_eq!(error.to_string(), format!("Converter not built with {} support", format)),
+            }
+        }
+        assert!(supported_output_formats().contains(&"PDF".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "tiff")]
+    fn tiff_inputs_are_read_with_the_tiff_feature() {
+        assert!(supported_input_formats().contains(&"TIFF".to_string()));
+        let mut tiff = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8).write_to(&mut std::io::Cursor::new(&mut tiff), image::ImageOutputFormat::Tiff).unwrap();
+        assert_eq!(check_input(&tiff, "image/tiff"), Ok(()));
+        assert_eq!(sniff_format(&tiff), Some("TIFF"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "jpeg", feature = "png"))]
+    fn color_types_are_read_from_headers() {
+        let encode = |img: image::DynamicImage, format: image::ImageOutputFormat| {
+            let mut data = Vec::new();
+            img.write_to(&mut std::io::Cursor::new(&mut data), format).unwrap();
+            data
+        };
+        let gray = image::DynamicImage::new_luma8(8, 8);
+        let rgb = image::DynamicImage::new_rgb8(8, 8);
+        assert_eq!(declared_color_type(&encode(rgb.clone(), image::ImageOutputFormat::Jpeg(80))).as_deref(), Some("rgb8"));
+        assert_eq!(declared_color_type(&encode(gray.clone(), image::ImageOutputFormat::Jpeg(80))).as_deref(), Some("grayscale"));
+        assert_eq!(declared_color_type(&encode(rgb, image::ImageOutputFormat::Png)).as_deref(), Some("rgb8"));
+        assert_eq!(declared_color_type(&encode(image::DynamicImage::new_rgba16(8, 8), image::ImageOutputFormat::Png)).as_deref(), Some("16-bit RGBA"));
+        assert_eq!(declared_color_type(&encode(gray, image::ImageOutputFormat::Png)).as_deref(), Some("grayscale"));
+        assert_eq!(declared_color_type(b"%PDF-1.4"), None);
+        assert_eq!(declared_layout(&encode(image::DynamicImage::new_luma8(8, 8), image::ImageOutputFormat::Png)), Some((ColorType::Grayscale, 8)));
+        assert_eq!(declared_layout(&encode(image::DynamicImage::new_rgb16(8, 8), image::ImageOutputFormat::Png)), Some((ColorType::Rgb, 48)));
+        assert_eq!(declared_layout(&encode(image::DynamicImage::new_rgb8(8, 8), image::ImageOutputFormat::Jpeg(80))), Some((ColorType::Rgb, 24)));
+    }
+}
@@ -0,0 +1,104 @@
+use super::spec::{DimensionsSpec, DocumentSpec};
+
+/// How comfortably an output sits inside the spec, from 0 (on a limit) to 1.
+///
+/// Each constraint band that applies is scored on its own:
+/// - a two-sided band scores 1 at its midpoint and falls linearly to 0 at either bound;
+/// - a one-sided band scores 0 at its bound and reaches 1 once the value is half the
+///   bound's magnitude away from it (a 100KB cap: 100KB scores 0, 75KB 0.5, 50KB 1);
+/// - an exact requirement (min equal to max) scores 1 when met and 0 otherwise.
+///
+/// The bands are file size (`size_kb`), each pixel axis (`pixels`, exact or range),
+/// width / height (`aspect_ratio.min`/`max`) and height / width
+/// (`aspect_ratio.height_to_width_*`). The score is the mean over the bands present,
+/// and 1 when none are. Dimension bands are skipped when `dimensions` is `None`.
+pub fn compliance_score(bytes: usize, dimensions: Option<&DimensionsSpec>, spec: &DocumentSpec) -> f32 {
+    let kb = |v: u32| v as f64 * 1024.0;
+    let mut scores = vec![band_score(bytes as f64, spec.size_kb.min.map(kb), Some(kb(spec.size_kb.max)))];
+
+    if let Some(dimensions) = dimensions {
+        let (width, height) = (dimensions.width as f64, dimensions.height as f64);
+        if let Some(pixels) = &spec.pixels {
+            let exact = pixels.width.zip(pixels.height);
+            let bound = |axis: Option<u32>, nested: Option<u32>| axis.or(nested).map(f64::from);
+            let (min_width, max_width, min_height, max_height) = match exact {
+                Some((w, h)) => (Some(w as f64), Some(w as f64), Some(h as f64), Some(h as f64)),
+                None => (
+                    bound(pixels.min_width, pixels.min.as_ref().map(|m| m.width)),
+                    bound(pixels.max_width, pixels.max.as_ref().map(|m| m.width)),
+                    bound(pixels.min_height, pixels.min.as_ref().map(|m| m.height)),
+                    bound(pixels.max_height, pixels.max.as_ref().map(|m| m.height)),
+                ),
+            };
+            scores.push(band_score(width, min_width, max_width));
+            scores.push(band_score(height, min_height, max_height));
+        }
+        if let Some(aspect) = &spec.aspect_ratio {
+            scores.push(band_score(width / height, aspect.min.map(f64::from), aspect.max.map(f64::from)));
+            scores.push(band_score(
+                height / width,
+                aspect.height_to_width_min.map(f64::from),
+                aspect.height_to_width_max.map(f64::from),
+            ));
+        }
+    }
+
+    let scores: Vec<f64> = scores.into_iter().flatten().collect();
+    if scores.is_empty() {
+        return 1.0;
+    }
+    (scores.iter().sum::<f64>() / scores.len() as f64) as f32
+}
+
+fn band_score(value: f64, min: Option<f64>, max: Option<f64>) -> Option<f64> {
+    // A zero lower bound constrains nothing and has no magnitude to scale by
+    let min = min.filter(|&min| min > 0.0);
+    let headroom = match (min, max) {
+        (None, None) => return None,
+        (Some(min), Some(max)) if max <= min => return Some(if value == min { 1.0 } else { 0.0 }),
+        (Some(min), Some(max)) => (value - min).min(max - value) / ((max - min) / 2.0),
+        (Some(min), None) => (value - min) / (min / 2.0),
+        (None, Some(max)) => (max - value) / (max / 2.0),
+    };
+    Some(headroom.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support::spec_from;
+
+    fn dims(width: f32, height: f32) -> DimensionsSpec {
+        DimensionsSpec { width, height }
+    }
+
+    #[test]
+    fn size_scores_by_headroom_under_the_cap() {
+        let spec = spec_from(serde_json::json!({ "size_kb": { "max": 100 } }));
+        assert_eq!(compliance_score(100 * 1024, None, &spec), 0.0);
+        assert_eq!(compliance_score(75 * 1024, None, &spec), 0.5);
+        assert_eq!(compliance_score(20 * 1024, None, &spec), 1.0);
+
+        // With a floor as well, the middle of the band is best
+        let spec = spec_from(serde_json::json!({ "size_kb": { "min": 20, "max": 100 } }));
+        assert_eq!(compliance_score(60 * 1024, None, &spec), 1.0);
+        assert_eq!(compliance_score(30 * 1024, None, &spec), 0.25);
+    }
+
+    #[test]
+    fn score_averages_every_applicable_band() {
+        let spec = spec_from(serde_json::json!({
+            "size_kb": { "max": 100 },
+            "pixels": { "min_width": 100, "max_width": 300 },
+            "aspect_ratio": { "min": 0.5, "max": 1.5 }
+        }));
+        // size 0.5, width 1, height unconstrained, ratio 1.0 is centered
+        assert_eq!(compliance_score(75 * 1024, Some(&dims(200.0, 200.0)), &spec), (0.5 + 1.0 + 1.0) / 3.0);
+        // Without dimensions only the size band counts
+        assert_eq!(compliance_score(75 * 1024, None, &spec), 0.5);
+
+        let exact = spec_from(serde_json::json!({ "size_kb": { "max": 100 }, "pixels": { "width": 200, "height": 250 } }));
+        assert_eq!(compliance_score(0, Some(&dims(200.0, 250.0)), &exact), 1.0);
+        assert_eq!(compliance_score(0, Some(&dims(200.0, 249.0)), &exact), 2.0 / 3.0);
+    }
+}
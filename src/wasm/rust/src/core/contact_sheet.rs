@@ -0,0 +1,137 @@
+use super::codecs::check_output;
+use super::font::{draw_text, GLYPH_ADVANCE, GLYPH_HEIGHT};
+use super::result::ConvertedFile;
+use super::ConvertError;
+
+// Margin around each thumbnail, which also separates it from its label
+const PADDING: u32 = 8;
+const CELL_SIZE_RANGE: (u32, u32) = (32, 1024);
+// Cells at least this large get labels at double size
+const LARGE_CELL: u32 = 160;
+const BACKGROUND: image::Rgb<u8> = image::Rgb([236, 236, 236]);
+const NAME_COLOR: image::Rgb<u8> = image::Rgb([32, 32, 32]);
+const DETAIL_COLOR: image::Rgb<u8> = image::Rgb([110, 110, 110]);
+
+/// Lay converted files out as a labelled grid for review before submitting: `columns`
+/// per row, each image fitted into a `cell_size` square on white and captioned with its
+/// `converted_name`, pixel dimensions and size. A short last row leaves its remaining
+/// cells blank. Outputs that are not images (PDFs) get a placeholder naming their
+/// format. Returns a PNG.
+pub fn contact_sheet(files: &[ConvertedFile], columns: u32, cell_size: u32) -> Result<Vec<u8>, ConvertError> {
+    if columns == 0 {
+        return Err(ConvertError::InvalidConfig("columns must be at least 1".to_string()));
+    }
+    if !(CELL_SIZE_RANGE.0..=CELL_SIZE_RANGE.1).contains(&cell_size) {
+        return Err(ConvertError::InvalidConfig(format!(
+            "cell_size must be between {} and {}, got {}",
+            CELL_SIZE_RANGE.0, CELL_SIZE_RANGE.1, cell_size
+        )));
+    }
+    if files.is_empty() {
+        return Err(ConvertError::Encode("A contact sheet needs at least one file".to_string()));
+    }
+    check_output("PNG")?;
+
+    let scale = if cell_size >= LARGE_CELL { 2 } else { 1 };
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    let (cell_width, cell_height) = (cell_size + 2 * PADDING, cell_size + 2 * PADDING + 2 * line_height);
+    let columns = columns.min(files.len() as u32);
+    let rows = (files.len() as u32).div_ceil(columns);
+    let max_chars = (cell_size / (GLYPH_ADVANCE * scale)) as usize;
+    let mut sheet = image::RgbImage::from_pixel(columns * cell_width, rows * cell_height, BACKGROUND);
+
+    for (index, file) in files.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let (left, top) = (column * cell_width + PADDING, row * cell_height + PADDING);
+
+        let mut panel = image::RgbaImage::from_pixel(cell_size, cell_size, image::Rgba([255, 255, 255, 255]));
+        let dimensions = file.decode_data().and_then(|data| image::load_from_memory(&data).ok()).map(|img| {
+            let thumbnail = img.thumbnail(cell_size, cell_size).to_rgba8();
+            let (x, y) = ((cell_size - thumbnail.width()) / 2, (cell_size - thumbnail.height()) / 2);
+            image::imageops::overlay(&mut panel, &thumbnail, x as i64, y as i64);
+            (img.width(), img.height())
+        });
+        let mut panel = image::DynamicImage::ImageRgba8(panel).to_rgb8();
+        let detail = match dimensions {
+            Some((width, height)) => format!("{}x{}, {}KB", width, height, file.size_kb),
+            None => {
+                let label = truncate(&file.format, max_chars);
+                let width = label.chars().count() as u32 * GLYPH_ADVANCE * scale;
+                let (x, y) = (cell_size.saturating_sub(width) / 2, (cell_size - GLYPH_HEIGHT * scale) / 2);
+                draw_text(&mut panel, x, y, &label, scale, DETAIL_COLOR);
+                format!("{}KB", file.size_kb)
+            }
+        };
+        image::imageops::replace(&mut sheet, &panel, left as i64, top as i64);
+
+        let label_top = top + cell_size + PADDING / 2;
+        draw_text(&mut sheet, left, label_top, &truncate(&file.converted_name, max_chars), scale, NAME_COLOR);
+        draw_text(&mut sheet, left, label_top + line_height, &truncate(&detail, max_chars), scale, DETAIL_COLOR);
+    }
+
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(sheet)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| ConvertError::Encode(e.to_string()))?;
+    Ok(png)
+}
+
+// Shorten from the middle so the extension (and any `_2` suffix) stays readable
+fn truncate(text: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= max_chars {
+        return text.to_string();
+    }
+    let kept = max_chars.saturating_sub(2);
+    let tail = kept / 2;
+    let head: String = chars[..kept - tail].iter().collect();
+    let end: String = chars[chars.len() - tail..].iter().collect();
+    format!("{}..{}", head, end)
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::events::NoopObserver;
+    use crate::core::pipeline::convert;
+    use crate::core::test_support::{config_from, encode_png, gradient};
+
+    #[test]
+    fn sheet_tiles_thumbnails_with_labels_and_a_short_last_row() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(120, 60)));
+        let config = config_from(serde_json::json!({}));
+        let photo = convert("scan.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let mut pdf = photo.clone();
+        pdf.format = "PDF".to_string();
+        pdf.data_url = "data:application/pdf;base64,JVBERi0xLjQ=".to_string();
+
+        let sheet = contact_sheet(&[photo.clone(), photo, pdf], 2, 64).unwrap();
+        let sheet = image::load_from_memory(&sheet).unwrap().to_rgb8();
+        // Cells are 64 + 2 × 8 wide and carry two 10px label lines below
+        assert_eq!(sheet.dimensions(), (160, 200));
+
+        // The thumbnail is letterboxed on white; its label sits underneath
+        assert_ne!(*sheet.get_pixel(40, 40), image::Rgb([255, 255, 255]));
+        assert_eq!(*sheet.get_pixel(40, 10), image::Rgb([255, 255, 255]));
+        let has_ink = |x0: u32, y0: u32, x1: u32, y1: u32| {
+            (y0..y1).any(|y| (x0..x1).any(|x| *sheet.get_pixel(x, y) == NAME_COLOR))
+        };
+        assert!(has_ink(8, 76, 72, 86));
+        // The PDF placeholder is labelled too, and the empty cell stays background
+        assert!((8..72).any(|x| (100..172).any(|y| *sheet.get_pixel(x, y) == DETAIL_COLOR)));
+        assert!(has_ink(8, 176, 72, 186));
+        assert!((88..152).all(|x| (100..200).all(|y| *sheet.get_pixel(x, y) == BACKGROUND)));
+
+        assert!(matches!(contact_sheet(&[], 2, 64), Err(ConvertError::Encode(_))));
+        assert_eq!(
+            contact_sheet(&[], 0, 64).unwrap_err().to_string(),
+            "Invalid config: columns must be at least 1"
+        );
+    }
+
+    #[test]
+    fn long_names_are_shortened_from_the_middle() {
+        assert_eq!(truncate("photo_scan.jpg", 20), "photo_scan.jpg");
+        assert_eq!(truncate("photo_candidate_signature_2.jpg", 12), "photo..2.jpg");
+    }
+}
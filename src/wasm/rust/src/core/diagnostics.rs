@@ -0,0 +1,185 @@
+use std::fmt;
+
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+
+use super::events::{LogEvent, LogEventKind, LogLevel};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct SelfTestReport {
+    pub version: String,
+    pub passed: bool,
+    pub codecs: Vec<CodecCheck>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct CodecCheck {
+    pub format: String,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+/// Identifies the converter build a report came from. Everything is fixed at compile
+/// time by build.rs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    pub profile: String,
+    pub features: Vec<String>,
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let features = if self.features.is_empty() { "none".to_string() } else { self.features.join(",") };
+        write!(f, "{} ({}, {}, features: {})", self.version, self.git_commit, self.profile, features)
+    }
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("SUMMA2_GIT_COMMIT").to_string(),
+        profile: env!("SUMMA2_BUILD_PROFILE").to_string(),
+        features: env!("SUMMA2_FEATURES").split(',').filter(|f| !f.is_empty()).map(str::to_string).collect(),
+    }
+}
+
+/// The event hosts log when the module loads, carrying `build_info` as the same
+/// string `version()` reports.
+pub fn startup_event(ts: f64) -> LogEvent {
+    let mut fields = serde_json::Map::new();
+    fields.insert("build".to_string(), serde_json::Value::String(build_info().to_string()));
+    LogEvent { ts, level: LogLevel::Info, event: LogEventKind::Startup, file: None, fields }
+}
+
+/// Which code wrote a converted file, so output that differs between machines can be
+/// traced to a codec version.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct CodecInfo {
+    /// Version of the `image` crate, which decodes and resizes every image input
+    pub image_version: String,
+    /// Crate that encoded the output: `image` for JPEG, `png` for PNG, or the host
+    /// encoder for formats built without one (`OffscreenCanvas` for WebP in browsers);
+    /// absent for PDFs, which are passed through unchanged
+    pub encoder: Option<String>,
+    pub encoder_version: Option<String>,
+}
+
+pub fn codec_info(target_format: &str) -> CodecInfo {
+    let image_version = env!("SUMMA2_IMAGE_VERSION").to_string();
+    let (encoder, encoder_version) = match target_format.to_uppercase().as_str() {
+        "JPEG" | "JPG" => (Some("image"), Some(image_version.clone())),
+        "PNG" => (Some("png"), Some(env!("SUMMA2_PNG_VERSION").to_string())),
+        _ => (None, None),
+    };
+    CodecInfo { image_version, encoder: encoder.map(str::to_string), encoder_version }
+}
+
+pub fn run_self_test() -> SelfTestReport {
+    let sample = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 16, |x, y| {
+        image::Rgb([(x * 16) as u8, (y * 16) as u8, 128])
+    }));
+    let formats = [
+        image::ImageFormat::Jpeg,
+        image::ImageFormat::Png,
+        // Written lossless, since image has no lossy WebP encoder without libwebp
+        #[cfg(feature = "webp")]
+        image::ImageFormat::WebP,
+    ];
+    let codecs: Vec<CodecCheck> = formats
+        .into_iter()
+        .filter(|format| format.writing_enabled())
+        .map(|format| {
+            let result = round_trip(&sample, format);
+            CodecCheck {
+                format: format!("{:?}", format).to_uppercase(),
+                passed: result.is_ok(),
+                error: result.err(),
+            }
+        })
+        .collect();
+    SelfTestReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        passed: codecs.iter().all(|c| c.passed),
+        codecs,
+    }
+}
+
+fn round_trip(sample: &image::DynamicImage, format: image::ImageFormat) -> Result<(), String> {
+    let mut encoded = Vec::new();
+    sample
+        .write_to(&mut std::io::Cursor::new(&mut encoded), format)
+        .map_err(|e| format!("encode failed: {}", e))?;
+    let decoded = image::load_from_memory_with_format(&encoded, format)
+        .map_err(|e| format!("decode failed: {}", e))?;
+    if decoded.dimensions() != sample.dimensions() {
+        return Err(format!("dimensions changed to {:?}", decoded.dimensions()));
+    }
+    // Lossy codecs only need to land near the original colors
+    let original = sample.to_rgb8();
+    let max_error = decoded
+        .to_rgb8()
+        .pixels()
+        .zip(original.pixels())
+        .flat_map(|(a, b)| a.0.iter().zip(b.0.iter()).map(|(x, y)| x.abs_diff(*y)).collect::<Vec<_>>())
+        .max()
+        .unwrap_or(0);
+    if max_error > 48 {
+        return Err(format!("pixel error {} too large", max_error));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn self_test_passes_for_compiled_codecs() {
+        let report = run_self_test();
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(report.version, env!("CARGO_PKG_VERSION"));
+        let formats: Vec<&str> = report.codecs.iter().map(|c| c.format.as_str()).collect();
+        let compiled = [("JPEG", cfg!(feature = "jpeg")), ("PNG", cfg!(feature = "png")), ("WEBP", cfg!(feature = "webp"))];
+        assert_eq!(formats, compiled.iter().filter(|(_, on)| *on).map(|(format, _)| *format).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn build_info_comes_from_the_build() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_commit.is_empty());
+        assert_eq!(info.profile, if cfg!(debug_assertions) { "debug" } else { "release" });
+        assert_eq!(info.features.contains(&"cli".to_string()), cfg!(feature = "cli"));
+
+        let info = BuildInfo { git_commit: "0123456789ab".to_string(), features: vec![], ..info };
+        assert_eq!(info.to_string(), format!("{} (0123456789ab, {}, features: none)", info.version, info.profile));
+    }
+
+    #[test]
+    fn startup_event_carries_the_build_string() {
+        let event = startup_event(1700000000000.0);
+        assert_eq!((event.level, event.event), (LogLevel::Info, LogEventKind::Startup));
+        assert_eq!(event.fields["build"], build_info().to_string());
+    }
+
+    #[test]
+    fn codec_info_names_the_encoder_per_format() {
+        let jpeg = codec_info("JPEG");
+        assert!(jpeg.image_version.starts_with("0.24."), "{:?}", jpeg);
+        assert_eq!(jpeg.encoder.as_deref(), Some("image"));
+        assert_eq!(jpeg.encoder_version, Some(jpeg.image_version.clone()));
+
+        let png = codec_info("PNG");
+        assert_eq!(png.encoder.as_deref(), Some("png"));
+        assert!(png.encoder_version.unwrap().starts_with("0.17."));
+
+        let pdf = codec_info("PDF");
+        assert_eq!((pdf.encoder, pdf.encoder_version), (None, None));
+    }
+}
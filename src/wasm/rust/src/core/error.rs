@@ -0,0 +1,164 @@
+use std::fmt;
+
+use super::result::EncodeAttempt;
+
+/// Why a conversion did not produce output. `Display` gives the message shown to users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvertError {
+    /// Stopped by the host at a stage boundary
+    Cancelled,
+    InvalidConfig(String),
+    InvalidOptions(String),
+    /// The spec allows no output format for the input and `on_missing_format` is `Fail`
+    NoTargetFormat,
+    UnsupportedFileType(String),
+    UnsupportedFormat(String),
+    /// The input could not be read by the host before conversion started
+    Read(String),
+    Decode(String),
+    Encode(String),
+    /// The output (or a passthrough input) does not meet the size requirements
+    Validation(String),
+    /// Decoding would likely exhaust the memory available to the host; sizes in bytes
+    InsufficientMemory { width: u32, height: u32, required: u64, available: u64 },
+    /// The input's header declares more pixels than `max_decoded_pixels`, so it was
+    /// refused before decoding
+    DecompressionBomb { width: u32, height: u32, max_pixels: u64 },
+    /// The input or target format, or PDF rasterization, needs a codec left out of this
+    /// build (cargo features)
+    CodecUnavailable(String),
+    /// `on_feature_loss: "Fail"` and converting would discard the listed source features
+    FeatureLoss { target_format: String, lost: Vec<String> },
+    /// `ink_color.on_mismatch: "fail"` and the signature ink is none of the allowed colors
+    InkColor { detected: String, rgb: [u8; 3], allowed: Vec<String> },
+    /// More inputs were passed to a batch call than `max_batch_size` allows
+    BatchTooLarge { count: usize, max: u32 },
+    /// No encode met the size limits, or `max_attempts` ran out first; `attempts` lists
+    /// every encode tried
+    SizeConstraints { message: String, attempts: Vec<EncodeAttempt> },
+    /// A PDF over `size_kb.max` that could not be brought under it. `best_kb` is the
+    /// smallest version made; `rasterize_dpi` is set when `pdf_rasterize_dpi` was tried,
+    /// with `rasterize_error` saying why the pages could not be rasterized
+    PdfTooLarge { size_kb: u32, max_kb: u32, best_kb: u32, rasterize_dpi: Option<u32>, rasterize_error: Option<String> },
+    /// The host could not write the output where it was asked to store it
+    Write(String),
+    /// Storing the output would exceed the host's storage quota
+    QuotaExceeded(String),
+    /// The host may not write to the place it was asked to store the output
+    PermissionDenied(String),
+    /// `pdf_a` is set but the PDF cannot be made PDF/A-1b without changing what it shows
+    PdfA(String),
+}
+
+impl ConvertError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, ConvertError::Cancelled)
+    }
+
+    /// The encodes tried before a `SizeConstraints` failure; empty for other errors.
+    pub fn attempts(&self) -> &[EncodeAttempt] {
+        match self {
+            ConvertError::SizeConstraints { attempts, .. } => attempts,
+            _ => &[],
+        }
+    }
+
+    /// Machine-readable code for errors callers are expected to handle specially.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            ConvertError::Cancelled => Some("cancelled"),
+            ConvertError::InsufficientMemory { .. } => Some("insufficient_memory"),
+            ConvertError::DecompressionBomb { .. } => Some("decompression_bomb"),
+            ConvertError::FeatureLoss { .. } => Some("feature_loss"),
+            ConvertError::CodecUnavailable(_) => Some("codec_unavailable"),
+            ConvertError::InkColor { .. } => Some("ink_color"),
+            ConvertError::NoTargetFormat => Some("no_target_format"),
+            ConvertError::BatchTooLarge { .. } => Some("batch_too_large"),
+            ConvertError::SizeConstraints { .. } => Some("size_constraints"),
+            ConvertError::PdfTooLarge { .. } => Some("pdf_too_large"),
+            ConvertError::QuotaExceeded(_) => Some("quota_exceeded"),
+            ConvertError::PermissionDenied(_) => Some("permission_denied"),
+            ConvertError::PdfA(_) => Some("pdf_a"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Cancelled => write!(f, "cancelled"),
+            ConvertError::InvalidConfig(message) => write!(f, "Invalid config: {}", message),
+            ConvertError::InvalidOptions(message) => write!(f, "Invalid options: {}", message),
+            ConvertError::NoTargetFormat => {
+                f.write_str("No target format: list one in format or image_formats, or set on_missing_format to Jpeg")
+            }
+            ConvertError::UnsupportedFileType(file_type) => write!(f, "Unsupported file type: {}", file_type),
+            ConvertError::UnsupportedFormat(message)
+            | ConvertError::Read(message)
+            | ConvertError::Decode(message)
+            | ConvertError::Encode(message)
+            | ConvertError::Validation(message)
+            | ConvertError::Write(message) => f.write_str(message),
+            ConvertError::QuotaExceeded(message) => write!(f, "Storage quota exceeded: {}", message),
+            ConvertError::PermissionDenied(message) => write!(f, "Permission denied: {}", message),
+            ConvertError::PdfA(reason) => write!(f, "Cannot make a PDF/A-1b copy: {}", reason),
+            ConvertError::InsufficientMemory { width, height, required, available } => write!(
+                f,
+                "Not enough memory to decode {}x{} image: needs about {}MB, {}MB available",
+                width,
+                height,
+                required.div_ceil(1024 * 1024),
+                available / (1024 * 1024)
+            ),
+            ConvertError::DecompressionBomb { width, height, max_pixels } => write!(
+                f,
+                "Refusing to decode {}x{} image: {} pixels (about {}MB decoded) is more than max_decoded_pixels {}",
+                width,
+                height,
+                *width as u64 * *height as u64,
+                (*width as u64 * *height as u64 * 4).div_ceil(1024 * 1024),
+                max_pixels
+            ),
+            ConvertError::CodecUnavailable(format) => write!(f, "Converter not built with {} support", format),
+            ConvertError::FeatureLoss { target_format, lost } => {
+                write!(f, "Converting to {} would lose: {}", target_format, lost.join(", "))
+            }
+            ConvertError::InkColor { detected, rgb: [r, g, b], allowed } => write!(
+                f,
+                "Signature ink looks {} (#{:02X}{:02X}{:02X}); allowed inks: {}",
+                detected,
+                r,
+                g,
+                b,
+                allowed.join(", ")
+            ),
+            ConvertError::BatchTooLarge { count, max } => write!(
+                f,
+                "{} files exceed max_batch_size {}; convert them in smaller batches or into an output directory",
+                count, max
+            ),
+            ConvertError::SizeConstraints { message, attempts } => {
+                write!(f, "{} after {} encodes", message, attempts.len())?;
+                for (index, attempt) in attempts.iter().enumerate() {
+                    let quality = attempt.quality.map(|quality| format!(" q{}", quality)).unwrap_or_default();
+                    let [width, height] = attempt.dimensions;
+                    write!(f, "{} {}{} {}x{} {}KB", if index == 0 { ":" } else { "," }, attempt.format, quality, width, height, attempt.size_kb)?;
+                }
+                Ok(())
+            }
+            ConvertError::PdfTooLarge { size_kb, max_kb, best_kb, rasterize_dpi, rasterize_error } => {
+                write!(f, "PDF file too large: {}KB, maximum allowed: {}KB; the smallest achieved was {}KB", size_kb, max_kb, best_kb)?;
+                match (rasterize_dpi, rasterize_error) {
+                    (None, _) => f.write_str(". Reduce the resolution of its page images, or set pdf_rasterize_dpi to rasterize the pages"),
+                    (Some(dpi), None) => write!(f, " with the pages rasterized at {} DPI. Reduce the resolution of its page images", dpi),
+                    (Some(_), Some(reason)) => {
+                        write!(f, " as the pages cannot be rasterized: {}. Reduce the resolution of its page images", reason)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+
+use super::timestamp::LocalTime;
+use super::ConvertError;
+
+// Ordered by severity, so `level >= threshold` filters
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+// Event names are part of the log schema consumed by dashboards; only ever add new ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum LogEventKind {
+    Read,
+    Decode,
+    Resize,
+    Posterize,
+    Trim,
+    EncodePass,
+    Encode,
+    PdfCheck,
+    Validate,
+    Converted,
+    Failed,
+    ColorConvert,
+    DocumentScan,
+    Tile,
+    FeatureLoss,
+    ColorCheck,
+    Rotate,
+    InkCheck,
+    Background,
+    ResolutionCheck,
+    FormatFallback,
+    CacheHit,
+    PdfRasterize,
+    History,
+    Timestamp,
+    Deskew,
+    Despeckle,
+    AutoContrast,
+    Gamma,
+    Animation,
+    ScannerEdges,
+    Transparency,
+    Config,
+    Presets,
+    Batch,
+    Startup,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct LogEvent {
+    pub ts: f64,
+    pub level: LogLevel,
+    pub event: LogEventKind,
+    pub file: Option<String>,
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressStage {
+    Reading,
+    Decoding,
+    Resizing,
+    Encoding,
+    Validating,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ProgressEvent {
+    pub file_name: String,
+    pub stage: ProgressStage,
+    pub pass: u32,
+    pub overall_percent: f32,
+    pub file_index: u32,
+    pub file_count: u32,
+}
+
+// Session-wide counters, updated on every conversion path including failures and cancellations
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct SessionStats {
+    pub files_attempted: u32,
+    pub files_succeeded: u32,
+    pub files_failed: u32,
+    pub files_cancelled: u32,
+    pub files_skipped: u32,
+    // serde-wasm-bindgen hands these to JS as plain numbers, exact up to 8PB
+    #[cfg_attr(feature = "typescript", ts(type = "number"))]
+    pub input_bytes: u64,
+    #[cfg_attr(feature = "typescript", ts(type = "number"))]
+    pub output_bytes: u64,
+    pub encode_passes: u32,
+    pub cache_hits: u32,
+}
+
+impl SessionStats {
+    pub fn record_failure(&mut self, error: &ConvertError) {
+        if error.is_cancelled() {
+            self.files_cancelled += 1;
+        } else {
+            self.files_failed += 1;
+        }
+    }
+}
+
+// Coarse per-file completion estimate. Encoding gets the widest band since it may
+// take several passes; later passes creep towards the top of the band.
+pub fn stage_percent(stage: ProgressStage, pass: u32) -> f32 {
+    match stage {
+        ProgressStage::Reading => 0.0,
+        ProgressStage::Decoding => 10.0,
+        ProgressStage::Resizing => 30.0,
+        ProgressStage::Encoding => 40.0 + 50.0 * (1.0 - 0.75f32.powi(pass.saturating_sub(1) as i32)),
+        ProgressStage::Validating => 95.0,
+    }
+}
+
+/// How a host watches and steers a conversion running in the core pipeline. Every
+/// hook defaults to doing nothing, so a host only implements what it surfaces.
+pub trait Observer {
+    /// A structured log event; `fields` is a JSON object.
+    fn event(&self, _level: LogLevel, _event: LogEventKind, _file: Option<&str>, _fields: serde_json::Value) {}
+
+    /// Readable text about a stage, sent as a debug-level `event` with a `message` field.
+    fn debug(&self, event: LogEventKind, file: Option<&str>, message: String) {
+        self.event(LogLevel::Debug, event, file, serde_json::json!({ "message": message }));
+    }
+
+    fn progress(&self, _file_name: &str, _stage: ProgressStage, _pass: u32) {}
+
+    fn record(&self, _update: &dyn Fn(&mut SessionStats)) {}
+
+    /// Bytes the host can still allocate, if it can tell. Consulted before decoding
+    /// when the config enables `memory_check`.
+    fn available_memory(&self) -> Option<u64> {
+        None
+    }
+
+    /// The host's local date and time, if it has a clock. Consulted when a
+    /// `timestamp_overlay` stamps the time of conversion.
+    fn local_time(&self) -> Option<LocalTime> {
+        None
+    }
+
+    /// Polled at every stage boundary; returning true stops with `ConvertError::Cancelled`.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Runs conversions unobserved and never cancels them.
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_event_schema_is_stable() {
+        let mut fields = serde_json::Map::new();
+        fields.insert("bytes".to_string(), serde_json::json!(2048));
+        let event = LogEvent {
+            ts: 1700000000000.0,
+            level: LogLevel::Debug,
+            event: LogEventKind::EncodePass,
+            file: Some("photo.jpg".to_string()),
+            fields,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"ts":1700000000000.0,"level":"debug","event":"encode_pass","file":"photo.jpg","fields":{"bytes":2048}}"#
+        );
+    }
+
+    #[test]
+    fn log_event_names_are_stable() {
+        let names: Vec<String> = [
+            LogEventKind::Read,
+            LogEventKind::Decode,
+            LogEventKind::Resize,
+            LogEventKind::Posterize,
+            LogEventKind::Trim,
+            LogEventKind::EncodePass,
+            LogEventKind::Encode,
+            LogEventKind::PdfCheck,
+            LogEventKind::Validate,
+            LogEventKind::Converted,
+            LogEventKind::Failed,
+            LogEventKind::ColorConvert,
+            LogEventKind::DocumentScan,
+            LogEventKind::Tile,
+            LogEventKind::FeatureLoss,
+            LogEventKind::ColorCheck,
+            LogEventKind::Rotate,
+            LogEventKind::InkCheck,
+            LogEventKind::Background,
+            LogEventKind::ResolutionCheck,
+            LogEventKind::FormatFallback,
+            LogEventKind::CacheHit,
+            LogEventKind::PdfRasterize,
+            LogEventKind::History,
+            LogEventKind::Timestamp,
+            LogEventKind::Deskew,
+            LogEventKind::Despeckle,
+            LogEventKind::AutoContrast,
+            LogEventKind::Gamma,
+            LogEventKind::Animation,
+            LogEventKind::ScannerEdges,
+            LogEventKind::Transparency,
+            LogEventKind::Config,
+            LogEventKind::Presets,
+            LogEventKind::Batch,
+            LogEventKind::Startup,
+        ]
+        .iter()
+        .map(|kind| serde_json::to_value(kind).unwrap().as_str().unwrap().to_string())
+        .collect();
+        assert_eq!(
+            names,
+            ["read", "decode", "resize", "posterize", "trim", "encode_pass", "encode", "pdf_check", "validate", "converted", "failed", "color_convert", "document_scan", "tile", "feature_loss", "color_check", "rotate", "ink_check", "background", "resolution_check", "format_fallback", "cache_hit", "pdf_rasterize", "history", "timestamp", "deskew", "despeckle", "auto_contrast", "gamma", "animation", "scanner_edges", "transparency", "config", "presets", "batch", "startup"]
+        );
+        let levels: Vec<serde_json::Value> = [LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error]
+            .iter()
+            .map(|level| serde_json::to_value(level).unwrap())
+            .collect();
+        assert_eq!(levels, serde_json::json!(["debug", "info", "warn", "error"]).as_array().unwrap().clone());
+    }
+
+    #[test]
+    fn debug_text_is_a_debug_level_event() {
+        struct Events(std::cell::RefCell<Vec<(LogLevel, LogEventKind, serde_json::Value)>>);
+        impl Observer for Events {
+            fn event(&self, level: LogLevel, event: LogEventKind, _file: Option<&str>, fields: serde_json::Value) {
+                self.0.borrow_mut().push((level, event, fields));
+            }
+        }
+        let events = Events(std::cell::RefCell::new(Vec::new()));
+        events.debug(LogEventKind::Resize, Some("photo.jpg"), "Target dimensions: 600x800".to_string());
+        assert_eq!(
+            events.0.into_inner(),
+            [(LogLevel::Debug, LogEventKind::Resize, serde_json::json!({ "message": "Target dimensions: 600x800" }))]
+        );
+    }
+
+    #[test]
+    fn stage_percent_increases_through_the_pipeline() {
+        let sequence = [
+            stage_percent(ProgressStage::Reading, 0),
+            stage_percent(ProgressStage::Decoding, 0),
+            stage_percent(ProgressStage::Resizing, 0),
+            stage_percent(ProgressStage::Encoding, 1),
+            stage_percent(ProgressStage::Encoding, 2),
+            stage_percent(ProgressStage::Encoding, 9),
+            stage_percent(ProgressStage::Encoding, 100),
+            stage_percent(ProgressStage::Validating, 0),
+        ];
+        assert!(sequence.windows(2).all(|w| w[0] <= w[1]), "{:?}", sequence);
+        assert!(sequence.iter().all(|p| (0.0..=100.0).contains(p)));
+    }
+
+    #[test]
+    fn stats_count_cancellations_separately_from_failures() {
+        let mut stats = SessionStats::default();
+        stats.record_failure(&ConvertError::Cancelled);
+        stats.record_failure(&ConvertError::Decode("Failed to load image: bad header".to_string()));
+        stats.record_failure(&ConvertError::Validation("File too large: 300KB, maximum allowed: 100KB".to_string()));
+        assert_eq!((stats.files_cancelled, stats.files_failed), (1, 2));
+    }
+}
@@ -0,0 +1,217 @@
+use super::features::riff_chunks;
+use super::timestamp::LocalTime;
+
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_IFD_TAG: u16 = 0x8769;
+const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+
+/// The EXIF orientation (1..=8) recorded by the camera, read from a JPEG APP1 segment,
+/// a PNG eXIf chunk or a WebP EXIF chunk. Only reported; pixels are never rotated by it.
+pub(crate) fn exif_orientation(data: &[u8]) -> Option<u8> {
+    let tiff = Tiff::parse(exif_tiff(data)?)?;
+    let entry = tiff.entry(tiff.ifd0()?, ORIENTATION_TAG)?;
+    u8::try_from(tiff.u16_at(entry + 8)?).ok().filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// The capture date from EXIF `DateTimeOriginal`, as `YYYY-MM-DD`. None when the tag is
+/// missing or does not hold a real calendar date (cameras without a set clock write
+/// zeros or spaces).
+pub(crate) fn exif_capture_date(data: &[u8]) -> Option<String> {
+    let date = date_time_original(data, 10)?;
+    let (year, month, day) = calendar_date(date)?;
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// The full EXIF `DateTimeOriginal`, in the camera's local time. None under the same
+/// conditions as `exif_capture_date`, or when the time of day is missing or invalid.
+pub(crate) fn exif_capture_time(data: &[u8]) -> Option<LocalTime> {
+    let date_time = date_time_original(data, 19)?;
+    let (year, month, day) = calendar_date(date_time)?;
+    let (hour, minute, second) = (digits(&date_time[11..13])?, digits(&date_time[14..16])?, digits(&date_time[17..19])?);
+    let separators = date_time[10] == b' ' && date_time[13] == b':' && date_time[16] == b':';
+    (separators && hour < 24 && minute < 60 && second < 61).then_some(LocalTime {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+        hour: hour as u8,
+        minute: minute as u8,
+        second: second as u8,
+    })
+}
+
+// The first `length` bytes of `DateTimeOriginal`, ASCII "YYYY:MM:DD HH:MM:SS\0"
+fn date_time_original(data: &[u8], length: usize) -> Option<&[u8]> {
+    let tiff = Tiff::parse(exif_tiff(data)?)?;
+    let exif_ifd = tiff.u32_at(tiff.entry(tiff.ifd0()?, EXIF_IFD_TAG)? + 8)? as usize;
+    let entry = tiff.entry(exif_ifd, DATE_TIME_ORIGINAL_TAG)?;
+    // Longer than four bytes, so the value sits at an offset
+    let offset = tiff.u32_at(entry + 8)? as usize;
+    tiff.data.get(offset..offset + length)
+}
+
+fn calendar_date(date: &[u8]) -> Option<(u32, u32, u32)> {
+    let (year, month, day) = (digits(&date[0..4])?, digits(&date[5..7])?, digits(&date[8..10])?);
+    let separators = date[4] == b':' && date[7] == b':';
+    (separators && year > 0 && (1..=12).contains(&month) && (1..=31).contains(&day)).then_some((year, month, day))
+}
+
+fn digits(field: &[u8]) -> Option<u32> {
+    field.iter().try_fold(0u32, |value, &b| b.is_ascii_digit().then(|| value * 10 + (b - b'0') as u32))
+}
+
+// The TIFF structure holding the EXIF tags, from whichever container the input uses
+fn exif_tiff(data: &[u8]) -> Option<&[u8]> {
+    if let Some(segments) = data.strip_prefix(b"\xff\xd8") {
+        jpeg_exif(segments)
+    } else if let Some(chunks) = data.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        png_exif(chunks)
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        let (_, body) = riff_chunks(&data[12..]).find(|(tag, _)| tag == b"EXIF")?;
+        // Some writers keep the JPEG-style header in front of the TIFF data
+        Some(body.strip_prefix(b"Exif\0\0").unwrap_or(body))
+    } else {
+        None
+    }
+}
+
+fn jpeg_exif(mut segments: &[u8]) -> Option<&[u8]> {
+    // Metadata segments all come before the first scan
+    while let [0xff, marker, rest @ ..] = segments {
+        match marker {
+            0xff => segments = &segments[1..],
+            0xda | 0xd9 => return None,
+            _ => {
+                let length = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?) as usize;
+                let body = rest.get(2..length)?;
+                if *marker == 0xe1 {
+                    if let Some(tiff) = body.strip_prefix(b"Exif\0\0") {
+                        return Some(tiff);
+                    }
+                }
+                segments = rest.get(length..)?;
+            }
+        }
+    }
+    None
+}
+
+fn png_exif(mut chunks: &[u8]) -> Option<&[u8]> {
+    while chunks.len() >= 8 {
+        let length = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+        let body = chunks.get(8..8 + length)?;
+        if &chunks[4..8] == b"eXIf" {
+            return Some(body);
+        }
+        chunks = chunks.get(8 + length + 4..)?;
+    }
+    None
+}
+
+// Byte-order-aware reads from a TIFF structure; offsets are from its start
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..4)? {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return None,
+        };
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn ifd0(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    // Offset of the 12-byte entry for `tag` in the IFD at `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let entries = self.u16_at(ifd)? as usize;
+        (0..entries).map(|index| ifd + 2 + index * 12).find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::test_support::{encode_png, gradient, jpeg_with_exif, tiff_with_capture_date};
+
+    // A TIFF header followed by IFD0 with a single SHORT orientation entry
+    fn tiff(orientation: u16, big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let mut out = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        out.extend_from_slice(&u32_bytes(8));
+        out.extend_from_slice(&u16_bytes(1));
+        out.extend_from_slice(&u16_bytes(ORIENTATION_TAG));
+        out.extend_from_slice(&u16_bytes(3));
+        out.extend_from_slice(&u32_bytes(1));
+        out.extend_from_slice(&u16_bytes(orientation));
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&u32_bytes(0));
+        out
+    }
+
+    #[test]
+    fn orientation_is_read_from_jpeg_app1() {
+        assert_eq!(exif_orientation(&jpeg_with_exif(&tiff(6, false))), Some(6));
+        assert_eq!(exif_orientation(&jpeg_with_exif(&tiff(8, true))), Some(8));
+        // Out-of-range values are ignored rather than passed on
+        assert_eq!(exif_orientation(&jpeg_with_exif(&tiff(9, false))), None);
+        assert_eq!(exif_orientation(&jpeg_with_exif(&[])), None);
+    }
+
+    #[test]
+    fn capture_date_is_read_and_checked() {
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:00"))).as_deref(), Some("2023-05-01"));
+        // Unset camera clocks and garbage give no date rather than a bogus one
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff_with_capture_date(b"0000:00:00 00:00:00"))), None);
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff_with_capture_date(b"    :  :     :  :  "))), None);
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff_with_capture_date(b"2023:13:01 09:30:00"))), None);
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff(6, false))), None);
+        assert_eq!(exif_capture_date(&jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:00")[..30])), None);
+
+        let time = exif_capture_time(&jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:07")));
+        assert_eq!(time, Some(LocalTime { year: 2023, month: 5, day: 1, hour: 9, minute: 30, second: 7 }));
+        assert_eq!(exif_capture_time(&jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 24:30:00"))), None);
+        assert_eq!(exif_capture_time(&jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:  "))), None);
+    }
+
+    #[test]
+    fn orientation_is_read_from_png_and_webp_chunks() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(4, 4)));
+        assert_eq!(exif_orientation(&png), None);
+
+        // Insert an eXIf chunk right after IHDR (8-byte signature + 25-byte IHDR chunk)
+        let body = tiff(3, true);
+        let mut with_exif = png[..33].to_vec();
+        with_exif.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        with_exif.extend_from_slice(b"eXIf");
+        with_exif.extend_from_slice(&body);
+        with_exif.extend_from_slice(&[0; 4]);
+        with_exif.extend_from_slice(&png[33..]);
+        assert_eq!(exif_orientation(&with_exif), Some(3));
+
+        let body = tiff(5, false);
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        webp.extend_from_slice(&10u32.to_le_bytes());
+        webp.extend_from_slice(&[0; 10]);
+        webp.extend_from_slice(b"EXIF");
+        webp.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        webp.extend_from_slice(&body);
+        assert_eq!(exif_orientation(&webp), Some(5));
+    }
+}
@@ -0,0 +1,116 @@
+use image::{DynamicImage, GenericImageView};
+
+use super::spec::PngProfile;
+
+/// Source information the target format cannot carry. Only single-frame JPEG and PNG are
+/// ever encoded (animated WebP inputs that may stay WebP are passed through before
+/// decoding); GIF and TIFF are not decodable in this build, so multi-page inputs fail at
+/// decode instead of reaching this check.
+pub(crate) fn lost_features(data: &[u8], img: &DynamicImage, target_format: &str, png_profile: Option<PngProfile>) -> Vec<String> {
+    let mut lost = Vec::new();
+    if let Some(frames) = animation_frames(data).filter(|&frames| frames > 1) {
+        lost.push(format!("{} animation frames (only the first is kept)", frames - 1));
+    }
+    let drops_alpha = match target_format.to_uppercase().as_str() {
+        "JPEG" | "JPG" => true,
+        "PNG" => png_profile.is_some_and(|profile| !profile.keeps_alpha()),
+        _ => false,
+    };
+    if drops_alpha && img.color().has_alpha() && img.pixels().any(|(_, _, pixel)| pixel[3] < 255) {
+        lost.push("alpha channel (transparent pixels)".to_string());
+    }
+    lost
+}
+
+// Frame count from the container, without decoding: the acTL chunk of an APNG or the
+// ANMF chunks of an animated WebP. None for anything else.
+pub(crate) fn animation_frames(data: &[u8]) -> Option<u32> {
+    if let Some(chunks) = data.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        return png_animation_frames(chunks);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        let frames = riff_chunks(&data[12..]).filter(|(tag, _)| tag == b"ANMF").count() as u32;
+        return (frames > 0).then_some(frames);
+    }
+    None
+}
+
+fn png_animation_frames(mut chunks: &[u8]) -> Option<u32> {
+    // acTL must precede the first IDAT
+    while chunks.len() >= 8 {
+        let length = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+        let tag = &chunks[4..8];
+        let body = chunks.get(8..8 + length)?;
+        match tag {
+            b"acTL" => return Some(u32::from_be_bytes(body.get(..4)?.try_into().ok()?)),
+            b"IDAT" => return None,
+            _ => chunks = chunks.get(8 + length + 4..)?,
+        }
+    }
+    None
+}
+
+pub(super) fn riff_chunks(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let tag: [u8; 4] = data.get(..4)?.try_into().ok()?;
+        let length = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+        let body = data.get(8..8 + length)?;
+        // Chunks are padded to even lengths
+        data = data.get(8 + length + (length & 1)..).unwrap_or(&[]);
+        Some((tag, body))
+    })
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::test_support::{encode_png, gradient};
+
+    fn with_actl(png: &[u8], frames: u32) -> Vec<u8> {
+        // Insert an acTL chunk right after IHDR (8-byte signature + 25-byte IHDR chunk)
+        let mut body = frames.to_be_bytes().to_vec();
+        body.extend_from_slice(&0u32.to_be_bytes());
+        let mut out = png[..33].to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(b"acTL");
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&png[33..]);
+        out
+    }
+
+    #[test]
+    fn animation_frames_are_read_from_containers() {
+        let png = encode_png(&DynamicImage::ImageRgb8(gradient(4, 4)));
+        assert_eq!(animation_frames(&png), None);
+        assert_eq!(animation_frames(&with_actl(&png, 12)), Some(12));
+
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        for tag in [b"VP8X", b"ANIM", b"ANMF", b"ANMF", b"ANMF"] {
+            webp.extend_from_slice(tag);
+            webp.extend_from_slice(&3u32.to_le_bytes());
+            webp.extend_from_slice(&[0; 4]);
+        }
+        assert_eq!(animation_frames(&webp), Some(3));
+        assert_eq!(animation_frames(b"not an image"), None);
+    }
+
+    #[test]
+    fn lost_features_depend_on_target_format() {
+        let opaque = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, image::Rgba([9, 9, 9, 255])));
+        let mut transparent = opaque.to_rgba8();
+        transparent.put_pixel(1, 1, image::Rgba([0, 0, 0, 0]));
+        let transparent = DynamicImage::ImageRgba8(transparent);
+        let png = encode_png(&transparent);
+
+        assert!(lost_features(&png, &opaque, "JPEG", None).is_empty());
+        assert_eq!(lost_features(&png, &transparent, "JPEG", None), ["alpha channel (transparent pixels)"]);
+        assert!(lost_features(&png, &transparent, "PNG", None).is_empty());
+        assert!(lost_features(&png, &transparent, "PNG", Some(PngProfile::Indexed8)).is_empty());
+        assert_eq!(lost_features(&png, &transparent, "PNG", Some(PngProfile::Grayscale8)), ["alpha channel (transparent pixels)"]);
+        assert_eq!(
+            lost_features(&with_actl(&png, 4), &transparent, "PNG", None),
+            ["3 animation frames (only the first is kept)"]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use super::pipeline::sha256_hex;
+use super::spec::DocumentSpec;
+
+/// A converted file kept in the host's conversion history, described without its bytes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct HistoryEntry {
+    /// Assigned by the store in the order entries were saved
+    pub id: u32,
+    pub converted_name: String,
+    pub document_type: String,
+    pub exam_type: String,
+    pub size_bytes: u32,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: f64,
+    /// `spec_hash` of the spec the file was converted to, so entries made under
+    /// requirements that have since changed can be told apart
+    pub spec_hash: String,
+}
+
+/// SHA-256 hex digest of a spec's JSON form.
+pub fn spec_hash(spec: &DocumentSpec) -> String {
+    sha256_hex(serde_json::to_string(spec).unwrap_or_default().as_bytes())
+}
+
+/// Ids of the entries to drop, oldest first, so that `incoming_bytes` more fit under
+/// `max_bytes`. The caller refuses files over `max_bytes` on their own.
+pub fn history_evictions(entries: &[HistoryEntry], incoming_bytes: u32, max_bytes: u32) -> Vec<u32> {
+    // Ids grow with every save, unlike timestamps, which follow the device clock
+    let mut oldest_first: Vec<&HistoryEntry> = entries.iter().collect();
+    oldest_first.sort_by_key(|entry| entry.id);
+    let mut total: u64 = entries.iter().map(|entry| entry.size_bytes as u64).sum::<u64>() + incoming_bytes as u64;
+    oldest_first
+        .into_iter()
+        .take_while(|entry| {
+            let over = total > max_bytes as u64;
+            total -= entry.size_bytes as u64;
+            over
+        })
+        .map(|entry| entry.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support::spec_from;
+
+    fn entry(id: u32, size_bytes: u32) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            converted_name: format!("photo_{}.jpg", id),
+            document_type: "photo".to_string(),
+            exam_type: "NEET".to_string(),
+            size_bytes,
+            timestamp: 1_700_000_000_000.0 - id as f64,
+            spec_hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_to_make_room() {
+        let entries = [entry(7, 300), entry(3, 200), entry(5, 400)];
+        assert_eq!(history_evictions(&entries, 100, 1000), Vec::<u32>::new());
+        assert_eq!(history_evictions(&entries, 150, 1000), [3]);
+        assert_eq!(history_evictions(&entries, 500, 1000), [3, 5]);
+        assert_eq!(history_evictions(&entries, 1000, 1000), [3, 5, 7]);
+        assert_eq!(history_evictions(&[], 1000, 1000), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn spec_hash_tells_specs_apart() {
+        let spec = |max_kb: u32| spec_from(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": max_kb } }));
+        assert_eq!(spec_hash(&spec(100)), spec_hash(&spec(100)));
+        assert_ne!(spec_hash(&spec(100)), spec_hash(&spec(200)));
+        assert_eq!(spec_hash(&spec(100)).len(), 64);
+    }
+}
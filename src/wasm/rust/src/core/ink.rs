@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use super::spec::{InkColor, InkColorSpec};
+
+/// The ink found on a signature and how it compared with `ink_color.allowed`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DetectedInk {
+    /// Name of the allowed range the ink fell in; otherwise the built-in color it looks
+    /// like, or "other"
+    pub color: String,
+    pub allowed: bool,
+    /// Dominant hue in degrees; absent when the ink is unsaturated (black or gray)
+    pub hue: Option<f32>,
+    /// Mean HSV saturation of the ink pixels, from 0 to 1
+    pub saturation: f32,
+    /// Mean ink color, for showing the user what was detected
+    pub rgb: [u8; 3],
+}
+
+const INK_MAX_EDGE: u32 = 512;
+// Ink must be this much darker than the paper; lighter strokes are antialiasing or stains
+const INK_CONTRAST: f32 = 0.7;
+// Below this mean saturation ink has no meaningful hue
+const SATURATED_INK: f32 = 0.25;
+// Fewer ink pixels than this fraction of the image means there is nothing to judge
+const MIN_INK_FRACTION: f32 = 0.001;
+const HUE_BINS: usize = 36;
+
+fn hue_saturation([r, g, b]: [f32; 3]) -> (f32, f32) {
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+    if chroma == 0.0 {
+        return (0.0, 0.0);
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    (hue, chroma / max)
+}
+
+/// Find the ink on a signature and match it against `spec.allowed`. Transparent pixels
+/// count as paper. Returns None when no ink stands out from the background.
+pub fn detect_ink(img: &image::DynamicImage, spec: &InkColorSpec) -> Option<DetectedInk> {
+    let sample = if img.width() > INK_MAX_EDGE || img.height() > INK_MAX_EDGE {
+        img.resize(INK_MAX_EDGE, INK_MAX_EDGE, image::imageops::FilterType::Nearest)
+    } else {
+        img.clone()
+    };
+    // Composite over white so transparent signature PNGs read as ink on paper
+    let pixels: Vec<[f32; 3]> = sample
+        .to_rgba8()
+        .pixels()
+        .map(|p| {
+            let alpha = p.0[3] as f32 / 255.0;
+            [0, 1, 2].map(|c| p.0[c] as f32 * alpha + 255.0 * (1.0 - alpha))
+        })
+        .collect();
+    let luma = |[r, g, b]: [f32; 3]| 0.299 * r + 0.587 * g + 0.114 * b;
+
+    // Paper is the 90th percentile brightness, so even dense signatures leave it intact
+    let mut lumas: Vec<f32> = pixels.iter().map(|&p| luma(p)).collect();
+    lumas.sort_by(f32::total_cmp);
+    let paper = *lumas.get(lumas.len() * 9 / 10)?;
+    let ink: Vec<[f32; 3]> = pixels.into_iter().filter(|&p| luma(p) < paper * INK_CONTRAST).collect();
+    if ink.is_empty() || (ink.len() as f32) < lumas.len() as f32 * MIN_INK_FRACTION {
+        return None;
+    }
+
+    let count = ink.len() as f32;
+    let mut sum = [0f32; 3];
+    let mut saturation_sum = 0f32;
+    let mut bins = [(0usize, 0f32, 0f32); HUE_BINS];
+    for &pixel in &ink {
+        (0..3).for_each(|c| sum[c] += pixel[c]);
+        let (hue, saturation) = hue_saturation(pixel);
+        saturation_sum += saturation;
+        if saturation >= SATURATED_INK {
+            // Hues are averaged as unit vectors so reds either side of 0° agree
+            let bin = &mut bins[(hue / 360.0 * HUE_BINS as f32) as usize % HUE_BINS];
+            bin.0 += 1;
+            bin.1 += hue.to_radians().cos();
+            bin.2 += hue.to_radians().sin();
+        }
+    }
+    let saturation = saturation_sum / count;
+    let hue = (saturation >= SATURATED_INK).then(|| {
+        // The busiest bin together with its neighbours
+        let peak = (0..HUE_BINS).max_by_key(|&i| bins[i].0).unwrap_or(0);
+        let (x, y) = [HUE_BINS - 1, 0, 1].iter().fold((0.0, 0.0), |(x, y), offset| {
+            let bin = bins[(peak + offset) % HUE_BINS];
+            (x + bin.1, y + bin.2)
+        });
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    });
+
+    let matched = spec.allowed.iter().filter_map(InkColor::range).find(|range| range.matches(hue, saturation));
+    let color = match &matched {
+        Some(range) => range.name.clone(),
+        None => InkColor::BUILT_IN
+            .iter()
+            .filter_map(|name| InkColor::Named(name.to_string()).range())
+            .find(|range| range.matches(hue, saturation))
+            .map_or_else(|| "other".to_string(), |range| range.name),
+    };
+    Some(DetectedInk {
+        color,
+        allowed: matched.is_some(),
+        hue,
+        saturation,
+        rgb: sum.map(|channel| (channel / count).round() as u8),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A few scribbled strokes in `ink` on off-white paper, antialiased at the edges
+    fn signature(ink: [u8; 3]) -> image::DynamicImage {
+        let paper = [246.0, 244.0, 238.0];
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 100, |x, y| {
+            let curve = 50.0 + 30.0 * (x as f32 / 20.0).sin();
+            let coverage = (2.5 - (y as f32 - curve).abs()).clamp(0.0, 1.0);
+            image::Rgb([0, 1, 2].map(|c| (ink[c] as f32 * coverage + paper[c] * (1.0 - coverage)).round() as u8))
+        }))
+    }
+
+    fn spec(allowed: serde_json::Value) -> InkColorSpec {
+        serde_json::from_value(serde_json::json!({ "allowed": allowed })).unwrap()
+    }
+
+    #[test]
+    fn blue_and_black_ink_are_told_apart_from_red() {
+        let spec = spec(serde_json::json!(["black", "blue"]));
+
+        let blue = detect_ink(&signature([30, 45, 150]), &spec).unwrap();
+        assert_eq!((blue.color.as_str(), blue.allowed), ("blue", true));
+        assert!((blue.hue.unwrap() - 232.5).abs() < 2.0, "{:?}", blue.hue);
+
+        let black = detect_ink(&signature([35, 35, 40]), &spec).unwrap();
+        assert_eq!((black.color.as_str(), black.allowed, black.hue), ("black", true, None));
+
+        let red = detect_ink(&signature([200, 25, 45]), &spec).unwrap();
+        assert_eq!((red.color.as_str(), red.allowed), ("red", false));
+        assert!(red.hue.unwrap() > 350.0, "{:?}", red.hue);
+        // Antialiased stroke edges lighten the mean a little
+        assert!(red.rgb.iter().zip([200u8, 25, 45]).all(|(&a, b)| a.abs_diff(b) < 25), "{:?}", red.rgb);
+
+        let violet = detect_ink(&signature([110, 40, 160]), &spec).unwrap();
+        assert_eq!((violet.color.as_str(), violet.allowed), ("other", false));
+    }
+
+    #[test]
+    fn blank_pages_and_transparent_backgrounds() {
+        let spec = spec(serde_json::json!(["blue"]));
+        let blank = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(100, 100, image::Rgb([250, 250, 250])));
+        assert_eq!(detect_ink(&blank, &spec), None);
+
+        let transparent = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(100, 100, |x, _| {
+            image::Rgba(if x % 10 == 0 { [20, 40, 170, 255] } else { [0, 0, 0, 0] })
+        }));
+        assert_eq!(detect_ink(&transparent, &spec).unwrap().color, "blue");
+    }
+}
@@ -0,0 +1,73 @@
+//! Platform-independent conversion pipeline. Everything here works on byte slices and
+//! plain structs; hosts (the wasm bindings) translate their own types at the edges and
+//! plug in through `Observer`.
+
+mod analysis;
+mod archive;
+mod background;
+mod batch;
+mod cache;
+mod codecs;
+mod compliance;
+mod contact_sheet;
+mod diagnostics;
+mod error;
+mod events;
+mod exif;
+mod features;
+mod font;
+mod history;
+mod ink;
+mod ops;
+mod options;
+mod orient;
+mod pdf;
+mod pipeline;
+mod presets;
+mod provenance;
+mod result;
+mod scan;
+mod spec;
+mod timestamp;
+mod validation;
+
+pub use analysis::{analyze, analyze_data, ImageAnalysis, LuminanceStats};
+pub use archive::{unique_name, zip_files};
+pub use background::{replace_background, BackgroundReport};
+pub use batch::{convert_one, count_outcome, single_result, Batch};
+pub use cache::ResultCache;
+pub use codecs::{needs_host_encoder, supported_input_formats, supported_output_formats};
+pub use compliance::compliance_score;
+pub use contact_sheet::contact_sheet;
+pub use diagnostics::{build_info, codec_info, run_self_test, startup_event, BuildInfo, CodecCheck, CodecInfo, SelfTestReport};
+pub use error::ConvertError;
+pub use history::{history_evictions, spec_hash, HistoryEntry};
+pub use events::{
+    stage_percent, LogEvent, LogEventKind, LogLevel, NoopObserver, Observer, ProgressEvent,
+    ProgressStage, SessionStats,
+};
+pub use ops::{adjust_gamma, clahe, colorfulness, convert_to_srgb, despeckle, posterize, stretch_contrast, trim_borders};
+pub use ink::{detect_ink, DetectedInk};
+pub use options::{validate_options, ConverterOptions};
+pub use pipeline::{
+    calculate_target_dimensions, conversion_violations, convert, convert_hashed, convert_to_target_bytes, convert_with_preview, convert_with_thumbnail, determine_target_format,
+    encode_to_size, encode_to_target, generate_converted_filename, get_mime_type, mime_type_from_name, plan, prepare_host_encode, sha256_hex, tile,
+    validate_conversion_result, EncodedImage, HostEncode,
+};
+pub use presets::{PresetLoadReport, PresetRegistry, RejectedPreset};
+pub use result::{
+    ConversionPlan, ConversionResult, ConvertedFile, DebugIntermediate, EncodeAttempt, FailedFile, FileCompletion, FormatFallback, Geometry,
+    Insets, PreviewPair, Region, SkippedFile, Thumbnail, ThumbnailPair, TilePosition, Transformation,
+};
+pub use scan::{deskew, detect_document, detect_scanner_edges, detect_skew, warp_perspective, Quad};
+pub use validation::{parse_data_url, validate_data, ValidationReport};
+pub use spec::{
+    parse_config, parse_size_kb, AspectRatioSpec, AutoContrast, AutoRotateSpec, AutoTrimSpec, BackgroundSpec, BorderSpec, CanvasSpec, ClaheSpec, ColorMismatchPolicy, ColorMode, ColorType, ConversionConfig, DespeckleSpec,
+    DctMethod, DensityUnit, DimensionsSpec, DocumentScanSpec, DocumentSpec, ExtensionCase, FeatureLossPolicy, FitMode,
+    InkColor, InkColorSpec, InkRange, MemoryCheckSpec, MissingFormatPolicy, PhysicalDimensionsSpec, PixelDimensions, PixelSpec, PngProfile, PrintOrientation,
+    OutputColorType, OverlayPosition, PerformanceProfile, QualityScale, ScannerEdgesSpec, SizeSpec, SourceProfile, TimestampOverlaySpec, TimestampSource,
+};
+pub use timestamp::{format_time, LocalTime};
+
+#[cfg(test)]
+pub(crate) mod test_support;
@@ -0,0 +1,946 @@
+use super::spec::{AutoTrimSpec, SourceProfile};
+
+fn within_tolerance(a: &image::Rgba<u8>, b: &image::Rgba<u8>, tolerance: u8) -> bool {
+    a.0.iter().zip(b.0.iter()).all(|(x, y)| x.abs_diff(*y) <= tolerance)
+}
+
+// Returns the (x, y, width, height) of the content inside a uniform border, or None when
+// there is nothing to trim. The border color is whatever at least three corners agree on,
+// so grey, cream or dark scanner beds are handled the same way as white margins.
+pub fn trim_borders(img: &image::DynamicImage, spec: &AutoTrimSpec) -> Option<(u32, u32, u32, u32)> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let corners = [
+        *rgba.get_pixel(0, 0),
+        *rgba.get_pixel(width - 1, 0),
+        *rgba.get_pixel(0, height - 1),
+        *rgba.get_pixel(width - 1, height - 1),
+    ];
+    let border = corners.iter().find(|candidate| {
+        corners.iter().filter(|c| within_tolerance(candidate, c, spec.tolerance)).count() >= 3
+    })?;
+
+    // Allow a sprinkle of scanner noise in otherwise uniform border lines
+    let is_border_row = |y: u32| {
+        let matching = (0..width).filter(|&x| within_tolerance(rgba.get_pixel(x, y), border, spec.tolerance)).count();
+        matching * 100 >= width as usize * 99
+    };
+    let is_border_col = |x: u32, top: u32, bottom: u32| {
+        let matching = (top..bottom).filter(|&y| within_tolerance(rgba.get_pixel(x, y), border, spec.tolerance)).count();
+        matching * 100 >= (bottom - top) as usize * 99
+    };
+
+    let top = (0..height).find(|&y| !is_border_row(y))?;
+    let bottom = (top..height).rev().find(|&y| !is_border_row(y))? + 1;
+    let left = (0..width).find(|&x| !is_border_col(x, top, bottom))?;
+    let right = (left..width).rev().find(|&x| !is_border_col(x, top, bottom))? + 1;
+
+    let (trimmed_width, trimmed_height) = (right - left, bottom - top);
+    if (trimmed_width, trimmed_height) == (width, height) {
+        return None;
+    }
+    if (trimmed_width as f32) < width as f32 * spec.min_retained
+        || (trimmed_height as f32) < height as f32 * spec.min_retained
+    {
+        return None;
+    }
+    Some((left, top, trimmed_width, trimmed_height))
+}
+
+fn posterize_channel(value: u8, levels: u8) -> u8 {
+    let steps = (levels - 1) as f32;
+    let index = (value as f32 * steps / 255.0).round();
+    (index * 255.0 / steps).round() as u8
+}
+
+// Posterize color channels only; alpha is left untouched. Grayscale inputs stay
+// grayscale since every channel maps through the same table.
+pub fn posterize(img: image::DynamicImage, levels: u8) -> image::DynamicImage {
+    use image::DynamicImage;
+
+    let table: Vec<u8> = (0..=255u8).map(|v| posterize_channel(v, levels)).collect();
+    match img {
+        DynamicImage::ImageLuma8(mut buf) => {
+            buf.pixels_mut().for_each(|p| p.0[0] = table[p.0[0] as usize]);
+            DynamicImage::ImageLuma8(buf)
+        }
+        DynamicImage::ImageLumaA8(mut buf) => {
+            buf.pixels_mut().for_each(|p| p.0[0] = table[p.0[0] as usize]);
+            DynamicImage::ImageLumaA8(buf)
+        }
+        DynamicImage::ImageRgb8(mut buf) => {
+            buf.pixels_mut()
+                .for_each(|p| p.0.iter_mut().for_each(|c| *c = table[*c as usize]));
+            DynamicImage::ImageRgb8(buf)
+        }
+        other => {
+            let mut buf = other.to_rgba8();
+            buf.pixels_mut()
+                .for_each(|p| p.0[..3].iter_mut().for_each(|c| *c = table[*c as usize]));
+            DynamicImage::ImageRgba8(buf)
+        }
+    }
+}
+
+// A palette of at most `max_colors` (up to 256) colors for the image, and the index of
+// each pixel's color in it. Images with few enough colors keep them exactly; others are
+// median-cut over a histogram of their colors at 5 bits a channel, splitting the box
+// with the most pixels times its widest channel range at its pixel median, and each
+// box's pixels averaging into its color.
+pub(crate) fn quantize(img: &image::RgbImage, max_colors: usize) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut exact: Vec<[u8; 3]> = Vec::new();
+    let mut indices = Vec::with_capacity(img.len() / 3);
+    for pixel in img.pixels() {
+        match exact.iter().position(|color| *color == pixel.0) {
+            Some(index) => indices.push(index as u8),
+            None if exact.len() == max_colors => break,
+            None => {
+                indices.push(exact.len() as u8);
+                exact.push(pixel.0);
+            }
+        }
+    }
+    if indices.len() == img.len() / 3 {
+        return (exact, indices);
+    }
+
+    let bucket = |color: [u8; 3]| ((color[0] as usize >> 3) << 10) | ((color[1] as usize >> 3) << 5) | (color[2] as usize >> 3);
+    let mut counts = vec![0u64; 1 << 15];
+    let mut sums = vec![[0u64; 3]; 1 << 15];
+    for pixel in img.pixels() {
+        let at = bucket(pixel.0);
+        counts[at] += 1;
+        (0..3).for_each(|channel| sums[at][channel] += pixel.0[channel] as u64);
+    }
+    let level = |at: usize, channel: usize| (at >> (10 - 5 * channel)) & 31;
+    let mut boxes: Vec<Vec<usize>> = vec![(0..1 << 15).filter(|&at| counts[at] > 0).collect()];
+    while boxes.len() < max_colors {
+        // (box, widest channel, score) of the box most worth splitting
+        let widest = |buckets: &[usize]| {
+            (0..3)
+                .map(|channel| {
+                    let levels = buckets.iter().map(|&at| level(at, channel));
+                    (channel, levels.clone().max().unwrap_or(0) - levels.min().unwrap_or(0))
+                })
+                .max_by_key(|&(_, range)| range)
+                .unwrap_or((0, 0))
+        };
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, buckets)| {
+                let (channel, range) = widest(buckets);
+                (index, channel, buckets.iter().map(|&at| counts[at]).sum::<u64>() * range as u64)
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+        else {
+            break;
+        };
+        let mut buckets = boxes.swap_remove(index);
+        buckets.sort_unstable_by_key(|&at| level(at, channel));
+        let half = buckets.iter().map(|&at| counts[at]).sum::<u64>() / 2;
+        let mut seen = 0;
+        let split = buckets
+            .iter()
+            .position(|&at| {
+                seen += counts[at];
+                seen >= half
+            })
+            .map_or(1, |at| at + 1)
+            .clamp(1, buckets.len() - 1);
+        let upper = buckets.split_off(split);
+        boxes.push(buckets);
+        boxes.push(upper);
+    }
+
+    let mut lookup = vec![0u8; 1 << 15];
+    let palette = boxes
+        .iter()
+        .enumerate()
+        .map(|(index, buckets)| {
+            buckets.iter().for_each(|&at| lookup[at] = index as u8);
+            let pixels: u64 = buckets.iter().map(|&at| counts[at]).sum();
+            [0, 1, 2].map(|channel| ((buckets.iter().map(|&at| sums[at][channel]).sum::<u64>() + pixels / 2) / pixels) as u8)
+        })
+        .collect();
+    (palette, img.pixels().map(|pixel| lookup[bucket(pixel.0)]).collect())
+}
+
+// Resize with alpha premultiplied, so the color of fully transparent pixels (often black)
+// does not bleed into the antialiased edge of a logo or stamp. Opaque images take the
+// plain path unless `linear_light` is set.
+//
+// With `linear_light` the color channels are decoded from sRGB before resampling and
+// encoded again after. Averaging sRGB values darkens fine bright detail: a one-pixel
+// black and white checkerboard halved comes out near 128, where the light it emits
+// averages to 188. Decoding costs a few times the plain resize, so it is opt-in.
+pub fn resize_exact_alpha(img: &image::DynamicImage, width: u32, height: u32, filter: image::imageops::FilterType, linear_light: bool) -> image::DynamicImage {
+    use image::DynamicImage;
+
+    let has_alpha = img.color().has_alpha();
+    if !has_alpha && !linear_light {
+        return img.resize_exact(width, height, filter);
+    }
+    let mut premultiplied = img.to_rgba32f();
+    for pixel in premultiplied.pixels_mut() {
+        let alpha = pixel.0[3];
+        pixel.0[..3].iter_mut().for_each(|c| *c = if linear_light { srgb_to_linear(*c) } else { *c } * alpha);
+    }
+    let mut resized = image::imageops::resize(&premultiplied, width, height, filter);
+    for pixel in resized.pixels_mut() {
+        // Lanczos overshoots; keep alpha in range before dividing by it
+        let alpha = pixel.0[3].clamp(0.0, 1.0);
+        pixel.0[3] = alpha;
+        pixel.0[..3].iter_mut().for_each(|c| {
+            let straight = if alpha > 0.0 { *c / alpha } else { 0.0 };
+            *c = if linear_light { linear_to_srgb(straight) as f32 / 255.0 } else { straight };
+        });
+    }
+    let resized = DynamicImage::ImageRgba32F(resized);
+    match (has_alpha, img.color().has_color()) {
+        (true, _) => DynamicImage::ImageRgba8(resized.to_rgba8()),
+        (false, true) => DynamicImage::ImageRgb8(resized.to_rgb8()),
+        (false, false) => DynamicImage::ImageLuma8(resized.to_luma8()),
+    }
+}
+
+// Halves both sides with a 2×2 box filter, the cheap pre-reduction for large
+// downscales. An odd last row or column is dropped. Alpha is premultiplied and
+// `linear_light` averages light, as in `resize_exact_alpha`.
+pub(crate) fn halve(img: &image::DynamicImage, linear_light: bool) -> image::DynamicImage {
+    use image::DynamicImage;
+
+    let rgba = img.to_rgba8();
+    let decode: Vec<f32> =
+        (0..=255u8).map(|value| if linear_light { srgb_to_linear(value as f32 / 255.0) } else { value as f32 / 255.0 }).collect();
+    let halved = image::RgbaImage::from_fn(rgba.width() / 2, rgba.height() / 2, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = rgba.get_pixel(2 * x + dx, 2 * y + dy).0;
+            let alpha = pixel[3] as f32 / 255.0;
+            (0..3).for_each(|channel| sum[channel] += decode[pixel[channel] as usize] * alpha);
+            sum[3] += alpha;
+        }
+        let mut halved = [0, 0, 0, (sum[3] / 4.0 * 255.0).round() as u8];
+        for channel in 0..3 {
+            let straight = if sum[3] > 0.0 { sum[channel] / sum[3] } else { 0.0 };
+            halved[channel] = if linear_light { linear_to_srgb(straight) } else { (straight * 255.0).round() as u8 };
+        }
+        image::Rgba(halved)
+    });
+    let halved = DynamicImage::ImageRgba8(halved);
+    match (img.color().has_alpha(), img.color().has_color()) {
+        (true, _) => halved,
+        (false, true) => DynamicImage::ImageRgb8(halved.to_rgb8()),
+        (false, false) => DynamicImage::ImageLuma8(halved.to_luma8()),
+    }
+}
+
+const COLORFULNESS_MAX_EDGE: u32 = 512;
+// Highest `colorfulness` still judged grayscale. JPEG chroma noise on gray scans stays
+// under about 4; a blue-ink signature covering a twentieth of a white page scores 25.
+pub(crate) const GRAYSCALE_MAX_COLORFULNESS: f32 = 10.0;
+
+// How far the pixels' chroma strays from a single tint. Chroma is taken on the opponent
+// channels rg = R - G and yb = (R + G) / 2 - B; the tint that best explains it as a
+// multiple of luminance is fitted by least squares and the RMS of what remains is the
+// score. A toned black-and-white scan (sepia, yellowed paper, a cool scanner cast) is
+// one tint scaled by brightness, so it scores near 0 however strong the tint is, while
+// a photograph mixes hues and scores tens. Neutral grays score 0.
+pub fn colorfulness(img: &image::DynamicImage) -> f32 {
+    if !img.color().has_color() {
+        return 0.0;
+    }
+    let sample = if img.width() > COLORFULNESS_MAX_EDGE || img.height() > COLORFULNESS_MAX_EDGE {
+        img.resize(COLORFULNESS_MAX_EDGE, COLORFULNESS_MAX_EDGE, image::imageops::FilterType::Nearest)
+    } else {
+        img.clone()
+    };
+    let rgb = sample.to_rgb8();
+    let opponent = |[r, g, b]: [f64; 3]| ((r + g + b) / 3.0, r - g, (r + g) / 2.0 - b);
+
+    let (mut luma_sq, mut rg_luma, mut yb_luma) = (0f64, 0f64, 0f64);
+    for pixel in rgb.pixels() {
+        let (luma, rg, yb) = opponent(pixel.0.map(f64::from));
+        luma_sq += luma * luma;
+        rg_luma += rg * luma;
+        yb_luma += yb * luma;
+    }
+    if luma_sq == 0.0 {
+        return 0.0;
+    }
+    let (rg_tint, yb_tint) = (rg_luma / luma_sq, yb_luma / luma_sq);
+
+    let residual: f64 = rgb
+        .pixels()
+        .map(|pixel| {
+            let (luma, rg, yb) = opponent(pixel.0.map(f64::from));
+            (rg - rg_tint * luma).powi(2) + (yb - yb_tint * luma).powi(2)
+        })
+        .sum();
+    (residual / (rgb.width() * rgb.height()) as f64).sqrt() as f32
+}
+
+// Luminance a pixel must fall below the paper by to count as ink. Scanner noise, dust
+// and show-through from the back of a duplex sheet stay well within it.
+pub(crate) const INK_CONTRAST: u8 = 48;
+
+// The paper's luminance: the 90th percentile, so gray or yellowed stock counts as paper
+pub(crate) fn paper_level(luma: &image::GrayImage) -> u8 {
+    let mut histogram = [0usize; 256];
+    luma.pixels().for_each(|pixel| histogram[pixel.0[0] as usize] += 1);
+    let total = luma.pixels().len();
+    let mut below = 0;
+    (0..=255u8)
+        .find(|&level| {
+            below += histogram[level as usize];
+            below * 10 >= total * 9
+        })
+        .unwrap_or(255)
+}
+
+// Share of the pixels that are ink, for telling blank pages from written ones
+pub(crate) fn ink_coverage(img: &image::DynamicImage) -> f32 {
+    let luma = img.to_luma8();
+    let total = luma.pixels().len();
+    if total == 0 {
+        return 0.0;
+    }
+    let threshold = paper_level(&luma).saturating_sub(INK_CONTRAST);
+    luma.pixels().filter(|pixel| pixel.0[0] < threshold).count() as f32 / total as f32
+}
+
+// Ink this close (in pixels, any direction) to other ink is part of the same mark, so a
+// fine stroke broken up by the scanner still counts as one
+const SPECK_GAP: i64 = 2;
+// Small marks this close to a larger one are the dot of an i or the end of a stroke,
+// not dust
+const SPECK_NEAR_STROKE: i64 = 8;
+
+/// Paint over isolated marks of at most `max_size` ink pixels, such as dust on the
+/// scanner glass, with the paper around them. Returns the image and how many marks were
+/// removed.
+pub fn despeckle(img: image::DynamicImage, max_size: u32) -> (image::DynamicImage, usize) {
+    let luma = img.to_luma8();
+    let (width, height) = (luma.width() as i64, luma.height() as i64);
+    let Some(threshold) = paper_level(&luma).checked_sub(INK_CONTRAST) else {
+        return (img, 0);
+    };
+    let ink: Vec<bool> = luma.pixels().map(|pixel| pixel.0[0] < threshold).collect();
+    let index = |x: i64, y: i64| (y * width + x) as usize;
+
+    // Marks: ink pixels linked to any ink within SPECK_GAP
+    let mut mark = vec![usize::MAX; ink.len()];
+    let mut marks: Vec<Vec<(i64, i64)>> = Vec::new();
+    for start in (0..ink.len()).filter(|&i| ink[i]) {
+        if mark[start] != usize::MAX {
+            continue;
+        }
+        mark[start] = marks.len();
+        let mut pixels = vec![(start as i64 % width, start as i64 / width)];
+        let mut next = 0;
+        while let Some(&(x, y)) = pixels.get(next) {
+            next += 1;
+            for ny in (y - SPECK_GAP).max(0)..=(y + SPECK_GAP).min(height - 1) {
+                for nx in (x - SPECK_GAP).max(0)..=(x + SPECK_GAP).min(width - 1) {
+                    if ink[index(nx, ny)] && mark[index(nx, ny)] == usize::MAX {
+                        mark[index(nx, ny)] = marks.len();
+                        pixels.push((nx, ny));
+                    }
+                }
+            }
+        }
+        marks.push(pixels);
+    }
+
+    // Summed-area table of the ink in large marks, to tell whether one is near a speck
+    let mut strokes = vec![0u32; ((width + 1) * (height + 1)) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let i = index(x, y);
+            let stroke = ink[i] && marks[mark[i]].len() > max_size as usize;
+            let at = |x: i64, y: i64| strokes[(y * (width + 1) + x) as usize];
+            strokes[((y + 1) * (width + 1) + x + 1) as usize] = stroke as u32 + at(x, y + 1) + at(x + 1, y) - at(x, y);
+        }
+    }
+    let near_stroke = |x: i64, y: i64| {
+        let (left, top) = ((x - SPECK_NEAR_STROKE).max(0), (y - SPECK_NEAR_STROKE).max(0));
+        let (right, bottom) = ((x + SPECK_NEAR_STROKE + 1).min(width), (y + SPECK_NEAR_STROKE + 1).min(height));
+        let at = |x: i64, y: i64| strokes[(y * (width + 1) + x) as usize] as i64;
+        at(right, bottom) - at(left, bottom) - at(right, top) + at(left, top) > 0
+    };
+
+    let specks: Vec<&Vec<(i64, i64)>> = marks
+        .iter()
+        .filter(|pixels| pixels.len() <= max_size as usize && !pixels.iter().any(|&(x, y)| near_stroke(x, y)))
+        .collect();
+    if specks.is_empty() {
+        return (img, 0);
+    }
+    let mut rgba = img.to_rgba8();
+    for pixels in &specks {
+        // The paper just outside the mark's box, past the soft edge a speck leaves
+        let (left, right) = (pixels.iter().map(|p| p.0).min().unwrap_or(0), pixels.iter().map(|p| p.0).max().unwrap_or(0));
+        let (top, bottom) = (pixels.iter().map(|p| p.1).min().unwrap_or(0), pixels.iter().map(|p| p.1).max().unwrap_or(0));
+        let (mut sum, mut count) = ([0u64; 3], 0u64);
+        for y in (top - 2).max(0)..=(bottom + 2).min(height - 1) {
+            for x in (left - 2).max(0)..=(right + 2).min(width - 1) {
+                let ring = x < left - 1 || x > right + 1 || y < top - 1 || y > bottom + 1;
+                if ring && !ink[index(x, y)] {
+                    let pixel = rgba.get_pixel(x as u32, y as u32).0;
+                    (0..3).for_each(|c| sum[c] += pixel[c] as u64);
+                    count += 1;
+                }
+            }
+        }
+        let paper = match count {
+            0 => [threshold + INK_CONTRAST; 3],
+            _ => sum.map(|total| ((total + count / 2) / count) as u8),
+        };
+        for &(x, y) in pixels.iter() {
+            for ny in (y - 1).max(0)..=(y + 1).min(height - 1) {
+                for nx in (x - 1).max(0)..=(x + 1).min(width - 1) {
+                    let pixel = rgba.get_pixel_mut(nx as u32, ny as u32);
+                    pixel.0[..3].copy_from_slice(&paper);
+                }
+            }
+        }
+    }
+    (with_layout_of(&img, rgba), specks.len())
+}
+
+// `rgba` in the channel layout of `original` (gray or color, with or without alpha)
+fn with_layout_of(original: &image::DynamicImage, rgba: image::RgbaImage) -> image::DynamicImage {
+    let grayscale = matches!(original, image::DynamicImage::ImageLuma8(_) | image::DynamicImage::ImageLumaA8(_));
+    let rgba = image::DynamicImage::ImageRgba8(rgba);
+    match (grayscale, original.color().has_alpha()) {
+        (true, false) => image::DynamicImage::ImageLuma8(rgba.to_luma8()),
+        (true, true) => image::DynamicImage::ImageLumaA8(rgba.to_luma_alpha8()),
+        (false, false) => image::DynamicImage::ImageRgb8(rgba.to_rgb8()),
+        (false, true) => rgba,
+    }
+}
+
+// Share of the pixels at each end of the histogram `stretch_contrast` lets clip
+const STRETCH_CLIP: f32 = 0.01;
+
+/// Map the darkest and lightest 1% of `img` to black and white, stretching every channel
+/// alike in between. Returns the luminance levels that became 0 and 255, or None when
+/// the image already spans them or is flat.
+pub fn stretch_contrast(img: image::DynamicImage) -> (image::DynamicImage, Option<(u8, u8)>) {
+    let luma = img.to_luma8();
+    let mut histogram = [0usize; 256];
+    luma.pixels().for_each(|pixel| histogram[pixel.0[0] as usize] += 1);
+    let clipped = (luma.len() as f32 * STRETCH_CLIP) as usize;
+    let past_clipped = |seen: &mut usize, level: u8| {
+        *seen += histogram[level as usize];
+        *seen > clipped
+    };
+    let (mut below, mut above) = (0, 0);
+    let low = (0..=255u8).find(|&level| past_clipped(&mut below, level));
+    let high = (0..=255u8).rev().find(|&level| past_clipped(&mut above, level));
+    let (Some(low), Some(high)) = (low, high) else {
+        return (img, None);
+    };
+    if high <= low + 1 || (low == 0 && high == 255) {
+        return (img, None);
+    }
+    let span = (high - low) as f32;
+    let table: Vec<u8> = (0..=255u8).map(|value| ((value.clamp(low, high) - low) as f32 * 255.0 / span).round() as u8).collect();
+    let mut rgba = img.to_rgba8();
+    rgba.pixels_mut().for_each(|pixel| pixel.0[..3].iter_mut().for_each(|channel| *channel = table[*channel as usize]));
+    (with_layout_of(&img, rgba), Some((low, high)))
+}
+
+/// Raise the linear light of every channel to `1 / gamma`, so a gamma above 1 lifts the
+/// mid-tones of a dark scan without clipping its paper or its ink. Alpha is kept.
+pub fn adjust_gamma(img: image::DynamicImage, gamma: f32) -> image::DynamicImage {
+    let table: Vec<u8> = (0..=255u8).map(|value| linear_to_srgb(srgb_to_linear(value as f32 / 255.0).powf(1.0 / gamma))).collect();
+    let mut rgba = img.to_rgba8();
+    rgba.pixels_mut().for_each(|pixel| pixel.0[..3].iter_mut().for_each(|channel| *channel = table[*channel as usize]));
+    with_layout_of(&img, rgba)
+}
+
+// Pixels within this many levels of the paper are paper, noise and all, and clear entirely
+const PAPER_NOISE: u8 = 16;
+
+/// `img` with its paper made transparent for `transparent_background`. Pixels near the
+/// paper's luminance clear entirely and ink at or below its median level stays opaque;
+/// the anti-aliased edge between fades in proportion, with the paper unblended from its
+/// color so it keeps no light fringe. Also returns the share of pixels cleared.
+pub fn clear_paper(img: &image::DynamicImage) -> (image::DynamicImage, f32) {
+    let luma = img.to_luma8();
+    let paper = paper_level(&luma);
+    let mut ink: Vec<u8> = luma.pixels().map(|pixel| pixel.0[0]).filter(|&level| level < paper.saturating_sub(INK_CONTRAST)).collect();
+    ink.sort_unstable();
+    let (paper, ink) = (paper as f32, ink.get(ink.len() / 2).copied().unwrap_or(0) as f32);
+    let clear = paper - PAPER_NOISE as f32;
+    let mut rgba = img.to_rgba8();
+    let mut cleared = 0;
+    for (pixel, level) in rgba.pixels_mut().zip(luma.pixels()) {
+        let alpha = ((clear - level.0[0] as f32) / (clear - ink).max(1.0)).clamp(0.0, 1.0);
+        if alpha == 0.0 {
+            // One color for all of it, which compresses far better than the paper's grain
+            *pixel = image::Rgba([0, 0, 0, 0]);
+            cleared += 1;
+            continue;
+        }
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as f32 - paper * (1.0 - alpha)) / alpha).round().clamp(0.0, 255.0) as u8;
+        }
+        pixel.0[3] = (pixel.0[3] as f32 * alpha).round() as u8;
+    }
+    (image::DynamicImage::ImageRgba8(rgba), cleared as f32 / luma.len().max(1) as f32)
+}
+
+/// Contrast-limited adaptive histogram equalization: every `tile_size` square gets its
+/// own equalization of luminance, with no level boosted past `clip_limit` times an even
+/// share, blended between neighbouring tiles so no seams show. Chroma is kept.
+pub fn clahe(img: image::DynamicImage, tile_size: u32, clip_limit: f32) -> image::DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width == 0 || height == 0 {
+        return img;
+    }
+    let luma = |pixel: &image::Rgba<u8>| (0.299 * pixel.0[0] as f32 + 0.587 * pixel.0[1] as f32 + 0.114 * pixel.0[2] as f32).round() as u8;
+    let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+
+    // One lookup table per tile, from its clipped histogram
+    let mut tables = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (left, top) = (column * tile_size, row * tile_size);
+            let (right, bottom) = ((left + tile_size).min(width), (top + tile_size).min(height));
+            let mut histogram = [0u32; 256];
+            for y in top..bottom {
+                for x in left..right {
+                    histogram[luma(rgba.get_pixel(x, y)) as usize] += 1;
+                }
+            }
+            let pixels = (right - left) * (bottom - top);
+            let limit = ((clip_limit * pixels as f32 / 256.0) as u32).max(1);
+            let excess: u32 = histogram.iter().map(|&count| count.saturating_sub(limit)).sum();
+            let mut cumulative = 0;
+            let table: Vec<u8> = (0..256)
+                .map(|level| {
+                    // The clipped excess is spread evenly, the remainder over the lowest levels
+                    cumulative += histogram[level].min(limit) + excess / 256 + u32::from((level as u32) < excess % 256);
+                    (cumulative as f32 * 255.0 / pixels as f32).round().min(255.0) as u8
+                })
+                .collect();
+            tables.push(table);
+        }
+    }
+
+    // Each pixel blends the tables of the four tiles whose centres surround it
+    let between = |position: u32, tiles: u32| {
+        let at = ((position as f32 + 0.5) / tile_size as f32 - 0.5).max(0.0);
+        let first = (at.floor() as u32).min(tiles - 1);
+        (first, (first + 1).min(tiles - 1), (at - first as f32).clamp(0.0, 1.0))
+    };
+    for y in 0..height {
+        let (top, bottom, down) = between(y, rows);
+        for x in 0..width {
+            let (left, right, across) = between(x, columns);
+            let pixel = rgba.get_pixel_mut(x, y);
+            let level = luma(pixel) as usize;
+            let at = |column: u32, row: u32| tables[(row * columns + column) as usize][level] as f32;
+            let upper = at(left, top) * (1.0 - across) + at(right, top) * across;
+            let lower = at(left, bottom) * (1.0 - across) + at(right, bottom) * across;
+            // Shifting every channel alike moves luminance and leaves chroma as it was
+            let shift = upper * (1.0 - down) + lower * down - level as f32;
+            pixel.0[..3].iter_mut().for_each(|channel| *channel = (*channel as f32 + shift).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    with_layout_of(&img, rgba)
+}
+
+// Side of the gray grid a perceptual hash is taken on, one bit per cell
+const PAGE_HASH_GRID: u32 = 16;
+
+/// Perceptual hash of a page: the image shrunk to a 16×16 gray grid, one bit per cell
+/// darker than the grid's mean. Only the layout of the ink survives, so a rescan of the
+/// same page (other exposure, JPEG noise, a degree or two of skew) flips a few bits
+/// while a page with other content flips a quarter or more.
+pub(crate) fn page_hash(img: &image::DynamicImage) -> [u64; 4] {
+    let grid = img.resize_exact(PAGE_HASH_GRID, PAGE_HASH_GRID, image::imageops::FilterType::Triangle).to_luma8();
+    let mean = grid.pixels().map(|cell| cell.0[0] as f32).sum::<f32>() / grid.len() as f32;
+    let mut hash = [0u64; 4];
+    for (bit, cell) in grid.pixels().enumerate() {
+        if (cell.0[0] as f32) < mean {
+            hash[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+    hash
+}
+
+// Share of the bits two page hashes agree on
+pub(crate) fn hash_similarity(a: &[u64; 4], b: &[u64; 4]) -> f32 {
+    let differing: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+    1.0 - differing as f32 / 256.0
+}
+
+// Linear source RGB to linear sRGB, both D65
+type Matrix = [[f32; 3]; 3];
+
+const ADOBE_RGB_TO_SRGB: Matrix = [
+    [1.398_355_7, -0.398_355_7, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -0.042_928_9, 1.042_928_9],
+];
+const DISPLAY_P3_TO_SRGB: Matrix = [
+    [1.224_940_1, -0.224_940_4, 0.0],
+    [-0.042_056_9, 1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+// Re-encode pixels from an assumed source color space into sRGB. Out-of-gamut colors
+// are clipped; alpha is left untouched and grayscale inputs stay grayscale, since
+// neutral colors map to neutral colors in every supported space.
+pub fn convert_to_srgb(img: image::DynamicImage, profile: SourceProfile) -> image::DynamicImage {
+    use image::DynamicImage;
+
+    let (matrix, decode): (&Matrix, fn(f32) -> f32) = match profile {
+        SourceProfile::Srgb => return img,
+        SourceProfile::AdobeRgb => (&ADOBE_RGB_TO_SRGB, |v| v.powf(563.0 / 256.0)),
+        SourceProfile::DisplayP3 => (&DISPLAY_P3_TO_SRGB, srgb_to_linear),
+    };
+    let linear: Vec<f32> = (0..=255u8).map(|v| decode(v as f32 / 255.0)).collect();
+    let gray: Vec<u8> = linear.iter().map(|&v| linear_to_srgb(v)).collect();
+    let convert = |rgb: &mut [u8]| {
+        let source = [linear[rgb[0] as usize], linear[rgb[1] as usize], linear[rgb[2] as usize]];
+        for (channel, row) in rgb.iter_mut().zip(matrix.iter()) {
+            *channel = linear_to_srgb(row[0] * source[0] + row[1] * source[1] + row[2] * source[2]);
+        }
+    };
+
+    match img {
+        DynamicImage::ImageLuma8(mut buf) => {
+            buf.pixels_mut().for_each(|p| p.0[0] = gray[p.0[0] as usize]);
+            DynamicImage::ImageLuma8(buf)
+        }
+        DynamicImage::ImageLumaA8(mut buf) => {
+            buf.pixels_mut().for_each(|p| p.0[0] = gray[p.0[0] as usize]);
+            DynamicImage::ImageLumaA8(buf)
+        }
+        DynamicImage::ImageRgb8(mut buf) => {
+            buf.pixels_mut().for_each(|p| convert(&mut p.0));
+            DynamicImage::ImageRgb8(buf)
+        }
+        other => {
+            let mut buf = other.to_rgba8();
+            buf.pixels_mut().for_each(|p| convert(&mut p.0[..3]));
+            DynamicImage::ImageRgba8(buf)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::spec::{default_trim_min_retained, default_trim_tolerance};
+    use crate::core::test_support::{dusty, fine_signature, gradient, scanned_paper};
+    use std::collections::HashSet;
+
+    #[test]
+    fn halving_averages_without_transparent_color_bleeding_in() {
+        // A red pixel next to a transparent black one stays red, at half coverage
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(5, 3, |x, _| {
+            if x % 2 == 0 { image::Rgba([255, 0, 0, 255]) } else { image::Rgba([0, 0, 0, 0]) }
+        }));
+        let halved = halve(&img, false);
+        assert_eq!((halved.width(), halved.height()), (2, 1));
+        assert_eq!(halved.to_rgba8().get_pixel(1, 0).0, [255, 0, 0, 128]);
+
+        let checks = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(4, 4, |x, y| image::Luma([if (x + y) % 2 == 0 { 255 } else { 0 }])));
+        assert_eq!(halve(&checks, false).to_luma8().get_pixel(0, 0).0, [128]);
+        assert_eq!(halve(&checks, true).to_luma8().get_pixel(0, 0).0, [188]);
+    }
+
+    fn bordered(border: [u8; 3], content: [u8; 3]) -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(100, 80, |x, y| {
+            if (10..90).contains(&x) && (15..65).contains(&y) {
+                image::Rgb([content[0], content[1], content[2].wrapping_add((x % 7) as u8)])
+            } else {
+                image::Rgb(border)
+            }
+        }))
+    }
+
+    fn trim_spec() -> AutoTrimSpec {
+        AutoTrimSpec { tolerance: default_trim_tolerance(), min_retained: default_trim_min_retained() }
+    }
+
+    #[test]
+    fn ink_coverage_sees_writing_but_not_scanner_noise() {
+        let blank = ink_coverage(&image::DynamicImage::ImageRgb8(scanned_paper(200, 280, 0)));
+        assert!(blank < 0.002, "{}", blank);
+        let written = ink_coverage(&image::DynamicImage::ImageRgb8(scanned_paper(200, 280, 1)));
+        assert!(written > 0.01, "{}", written);
+        assert_eq!(ink_coverage(&image::DynamicImage::ImageRgb8(image::RgbImage::new(0, 0))), 0.0);
+    }
+
+    #[test]
+    fn despeckle_removes_dust_but_not_hairline_strokes() {
+        let clean = fine_signature();
+        let (despeckled, specks) = despeckle(image::DynamicImage::ImageRgb8(dusty(&clean)), 8);
+        assert_eq!(specks, 3);
+        assert_eq!(despeckled.to_rgb8(), clean);
+
+        // The dot of the i is as small as the dust, but sits by the stroke
+        let (untouched, specks) = despeckle(image::DynamicImage::ImageRgb8(clean.clone()), 8);
+        assert_eq!((specks, untouched.to_rgb8()), (0, clean.clone()));
+
+        // Grayscale stays grayscale
+        let gray = image::DynamicImage::ImageRgb8(dusty(&clean)).to_luma8();
+        let (despeckled, specks) = despeckle(image::DynamicImage::ImageLuma8(gray), 8);
+        assert_eq!(specks, 3);
+        assert!(matches!(despeckled, image::DynamicImage::ImageLuma8(_)));
+    }
+
+    // A washed-out copy: `scanned_paper` squeezed into the levels 110 to 190
+    fn photocopy() -> image::RgbImage {
+        let mut copy = scanned_paper(240, 160, 4);
+        copy.pixels_mut().for_each(|pixel| pixel.0.iter_mut().for_each(|channel| *channel = (110.0 + *channel as f32 * 80.0 / 255.0) as u8));
+        copy
+    }
+
+    fn luma_range(img: &image::DynamicImage, columns: std::ops::Range<u32>) -> (u8, u8) {
+        let luma = img.to_luma8();
+        let levels: Vec<u8> = luma.enumerate_pixels().filter(|(x, _, _)| columns.contains(x)).map(|(_, _, pixel)| pixel.0[0]).collect();
+        (*levels.iter().min().unwrap(), *levels.iter().max().unwrap())
+    }
+
+    #[test]
+    fn stretch_contrast_spans_black_to_white() {
+        let (stretched, levels) = stretch_contrast(image::DynamicImage::ImageRgb8(photocopy()));
+        let (low, high) = levels.unwrap();
+        assert!(low < 130 && high > 180, "{} to {}", low, high);
+        let (darkest, lightest) = luma_range(&stretched, 0..240);
+        assert!(darkest == 0 && lightest >= 250, "{} to {}", darkest, lightest);
+
+        let crisp = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, _| image::Luma([if x < 32 { 0 } else { 255 }])));
+        assert_eq!(stretch_contrast(crisp).1, None);
+        let flat = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, image::Rgb([90, 90, 90])));
+        assert_eq!(stretch_contrast(flat).1, None);
+    }
+
+    #[test]
+    fn clearing_paper_keeps_ink_and_fades_its_edges() {
+        let mut signature = fine_signature();
+        // A softened edge pixel, halfway between the ink and the paper
+        signature.put_pixel(10, 10, image::Rgb([142, 142, 178]));
+        let (cleared, share) = clear_paper(&image::DynamicImage::ImageRgb8(signature));
+        let rgba = cleared.as_rgba8().unwrap();
+        assert_eq!(rgba.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(rgba.get_pixel(150, 100).0, [40, 40, 110, 255]);
+        let edge = rgba.get_pixel(10, 10).0;
+        assert!((40..255).contains(&edge[3]), "{:?}", edge);
+        // Unblended from the paper, the edge is darker than it looked on it
+        assert!(edge[0] < 142 && edge[2] < 178, "{:?}", edge);
+        assert!(share > 0.95 && share < 1.0, "{}", share);
+    }
+
+    #[test]
+    fn gamma_lifts_mid_tones_and_keeps_the_ends() {
+        let ramp = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(256, 1, |x, _| image::Luma([x as u8])));
+        let lifted = adjust_gamma(ramp.clone(), 1.4);
+        assert!(matches!(lifted, image::DynamicImage::ImageLuma8(_)));
+        let (before, after) = (ramp.to_luma8(), lifted.to_luma8());
+        assert_eq!((after.get_pixel(0, 0).0[0], after.get_pixel(255, 0).0[0]), (0, 255));
+        assert!(after.get_pixel(100, 0).0[0] > before.get_pixel(100, 0).0[0] + 15, "{:?}", after.get_pixel(100, 0));
+        assert!(after.pixels().zip(after.pixels().skip(1)).all(|(a, b)| a.0[0] <= b.0[0]));
+
+        let darkened = adjust_gamma(ramp.clone(), 0.5).to_luma8();
+        assert!(darkened.get_pixel(100, 0).0[0] < before.get_pixel(100, 0).0[0]);
+        assert_eq!(adjust_gamma(ramp, 1.0).to_luma8(), before);
+    }
+
+    #[test]
+    fn clahe_evens_out_uneven_lighting() {
+        // A dull, grainy surface lit from the left, so its right half is darker and flatter
+        let shaded = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(240, 160, |x, y| {
+            let grain = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761) >> 27;
+            let level = if x < 120 { 160 + grain } else { 70 + grain / 2 } as u8;
+            image::Rgb([level; 3])
+        }));
+        let (dark_before, light_before) = (luma_range(&shaded, 130..230), luma_range(&shaded, 10..110));
+        let equalized = clahe(shaded, 40, 2.0);
+        let (dark_after, light_after) = (luma_range(&equalized, 130..230), luma_range(&equalized, 10..110));
+        assert!(dark_after.1 - dark_after.0 > (dark_before.1 - dark_before.0) * 2, "{:?} became {:?}", dark_before, dark_after);
+        assert!(light_after.0.abs_diff(dark_after.0) < light_before.0.abs_diff(dark_before.0), "{:?} {:?}", light_after, dark_after);
+
+        // Only luminance moves, so a colored pixel keeps its channel differences
+        let tinted = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([120 + x as u8, 100 + x as u8, 80 + x as u8])));
+        let equalized = clahe(tinted, 16, 2.0).to_rgb8();
+        let pixel = equalized.get_pixel(40, 20).0;
+        assert_eq!((pixel[0] as i32 - pixel[1] as i32, pixel[1] as i32 - pixel[2] as i32), (20, 20));
+    }
+
+    #[test]
+    fn page_hashes_tell_pages_apart_but_not_exposures() {
+        let page = |lines: u32, gain: f32| {
+            let paper = scanned_paper(200, 280, lines);
+            image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 280, |x, y| {
+                image::Rgb(paper.get_pixel(x, y).0.map(|level| (level as f32 * gain) as u8))
+            }))
+        };
+        let hash = page_hash(&page(3, 1.0));
+        assert_eq!(hash_similarity(&hash, &page_hash(&page(3, 0.8))), 1.0);
+        assert!(hash_similarity(&hash, &page_hash(&page(5, 1.0))) < 0.8);
+    }
+
+    #[test]
+    fn trim_removes_grey_border() {
+        let img = bordered([128, 128, 128], [20, 40, 200]);
+        assert_eq!(trim_borders(&img, &trim_spec()), Some((10, 15, 80, 50)));
+    }
+
+    #[test]
+    fn trim_removes_cream_border_with_noise() {
+        let mut img = bordered([245, 235, 205], [60, 30, 30]).to_rgb8();
+        img.put_pixel(3, 4, image::Rgb([250, 240, 200]));
+        img.put_pixel(50, 2, image::Rgb([0, 0, 0]));
+        let img = image::DynamicImage::ImageRgb8(img);
+        assert_eq!(trim_borders(&img, &trim_spec()), Some((10, 15, 80, 50)));
+    }
+
+    #[test]
+    fn trim_respects_minimum_retained_size() {
+        let img = bordered([128, 128, 128], [20, 40, 200]);
+        let spec = AutoTrimSpec { min_retained: 0.9, ..trim_spec() };
+        assert_eq!(trim_borders(&img, &spec), None);
+    }
+
+    #[test]
+    fn trim_leaves_uniform_image_alone() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(50, 50, image::Rgb([200, 200, 200])));
+        assert_eq!(trim_borders(&img, &trim_spec()), None);
+    }
+
+    #[test]
+    fn posterize_limits_distinct_values_per_channel() {
+        let img = image::DynamicImage::ImageRgb8(gradient(256, 256));
+        for levels in [2u8, 4, 7, 16] {
+            let out = posterize(img.clone(), levels).to_rgb8();
+            for channel in 0..3 {
+                let distinct: HashSet<u8> = out.pixels().map(|p| p.0[channel]).collect();
+                assert!(distinct.len() <= levels as usize, "{} levels gave {:?}", levels, distinct);
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_keeps_few_colors_and_median_cuts_many() {
+        let flag = image::RgbImage::from_fn(6, 2, |x, _| image::Rgb(if x < 3 { [255, 153, 51] } else { [19, 136, 8] }));
+        let (palette, indices) = quantize(&flag, 2);
+        assert_eq!(palette, [[255, 153, 51], [19, 136, 8]]);
+        assert_eq!(indices, [0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 1, 1]);
+
+        let photo = crate::core::test_support::gradient(256, 256);
+        let (palette, indices) = quantize(&photo, 16);
+        assert_eq!((palette.len(), indices.len()), (16, 256 * 256));
+        let error = photo
+            .pixels()
+            .zip(&indices)
+            .map(|(pixel, &index)| (0..3).map(|channel| pixel.0[channel].abs_diff(palette[index as usize][channel]) as u64).sum::<u64>())
+            .sum::<u64>() as f64
+            / (3.0 * 256.0 * 256.0);
+        // A 16-color posterize of the same image is off by 32 a channel on average
+        assert!(error < 32.0, "{}", error);
+    }
+
+    #[test]
+    fn posterize_keeps_grayscale_inputs_grayscale() {
+        let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(256, 4, |x, _| {
+            image::Luma([x as u8])
+        }));
+        let out = posterize(img, 4);
+        assert!(matches!(out, image::DynamicImage::ImageLuma8(_)));
+        let distinct: HashSet<u8> = out.to_luma8().pixels().map(|p| p.0[0]).collect();
+        assert!(distinct.len() <= 4);
+        assert!(distinct.contains(&0) && distinct.contains(&255));
+    }
+
+    #[test]
+    fn toned_scans_score_as_grayscale_and_photos_as_color() {
+        // Sepia-toned page: every pixel is one warm tint scaled by brightness
+        let sepia = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 200, |x, y| {
+            let luma = if y % 20 < 3 { 30.0 } else { 80.0 + x as f32 * 0.8 };
+            image::Rgb([(luma * 1.07).min(255.0) as u8, (luma * 0.98) as u8, (luma * 0.82) as u8])
+        }));
+        // Black text on yellowed paper
+        let yellowed = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 200, |_, y| {
+            image::Rgb(if y % 10 < 2 { [20, 20, 20] } else { [245, 235, 200] })
+        }));
+        for toned in [&sepia, &yellowed] {
+            assert!(colorfulness(toned) < GRAYSCALE_MAX_COLORFULNESS, "{}", colorfulness(toned));
+        }
+
+        let portrait = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 200, |x, y| {
+            let (dx, dy) = (x as i32 - 100, y as i32 - 90);
+            image::Rgb(if dx * dx + dy * dy < 3600 {
+                [224, 172, 140]
+            } else if y > 160 {
+                [30, 40, 90]
+            } else {
+                [250, 250, 250]
+            })
+        }));
+        for photo in [&portrait, &image::DynamicImage::ImageRgb8(gradient(200, 200))] {
+            assert!(colorfulness(photo) > 3.0 * GRAYSCALE_MAX_COLORFULNESS, "{}", colorfulness(photo));
+        }
+        assert_eq!(colorfulness(&sepia.grayscale()), 0.0);
+    }
+
+    #[test]
+    fn srgb_source_is_left_unchanged() {
+        let img = image::DynamicImage::ImageRgb8(gradient(64, 64));
+        assert_eq!(convert_to_srgb(img.clone(), SourceProfile::Srgb), img);
+    }
+
+    #[test]
+    fn wide_gamut_sources_gain_saturation_and_keep_neutrals() {
+        for profile in [SourceProfile::AdobeRgb, SourceProfile::DisplayP3] {
+            let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(2, 1, |x, _| {
+                image::Rgb(if x == 0 { [200, 40, 40] } else { [128, 128, 128] })
+            }));
+            let out = convert_to_srgb(img, profile).to_rgb8();
+            let red = out.get_pixel(0, 0).0;
+            assert!(red[0] > 200 && red[1] < 40 && red[2] < 40, "{:?}: {:?}", profile, red);
+            let gray = out.get_pixel(1, 0).0;
+            assert!(gray[0].abs_diff(gray[1]) <= 1 && gray[1].abs_diff(gray[2]) <= 1, "{:?}: {:?}", profile, gray);
+        }
+    }
+
+    #[test]
+    fn source_profile_keeps_grayscale_and_alpha() {
+        let img = image::DynamicImage::ImageLumaA8(image::GrayAlphaImage::from_pixel(4, 4, image::LumaA([100, 7])));
+        let out = convert_to_srgb(img, SourceProfile::AdobeRgb);
+        let image::DynamicImage::ImageLumaA8(buf) = out else { panic!("grayscale input became color") };
+        assert_eq!(buf.get_pixel(0, 0).0[1], 7);
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use super::events::LogLevel;
+use super::ConvertError;
+
+/// How a converter behaves regardless of the document spec: logging, time limits and
+/// how failures are surfaced. Fixed when the converter is created.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ConverterOptions {
+    // Events below this level are not sent to the log callback or console
+    #[serde(default = "default_log_level")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub log_level: LogLevel,
+    // Also write structured events to the console as single-line JSON
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub json_console: bool,
+    // Wall-clock budget for one conversion call; a call over budget stops at the next
+    // stage boundary as if cancelled
+    pub timeout_ms: Option<u32>,
+    // Reject the returned promise with the error message instead of resolving to a
+    // result with `success: false`
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub reject_on_error: bool,
+    // Most files a batch call accepts without an output directory. `convert_files`,
+    // `convert_files_to_zip` and `contact_sheet` hold every output in memory until they
+    // resolve, so a dropped folder of photos can exhaust the tab; larger batches should be
+    // written to an output directory or split up
+    #[serde(default = "default_max_batch_size")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub max_batch_size: u32,
+    // Keep every successful conversion in the browser's IndexedDB so outputs survive a
+    // reload, dropping the oldest once they total more than this many bytes. Off when
+    // unset
+    pub history_max_bytes: Option<u32>,
+    // Keep recent outputs in memory, up to this many bytes of data URLs, so converting a
+    // file again under the same config returns the earlier result flagged `cache_hit`.
+    // Off when unset
+    pub result_cache_bytes: Option<u32>,
+}
+
+fn default_log_level() -> LogLevel {
+    LogLevel::Debug
+}
+
+fn default_max_batch_size() -> u32 {
+    200
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        ConverterOptions {
+            log_level: default_log_level(),
+            json_console: false,
+            timeout_ms: None,
+            reject_on_error: false,
+            max_batch_size: default_max_batch_size(),
+            history_max_bytes: None,
+            result_cache_bytes: None,
+        }
+    }
+}
+
+impl ConverterOptions {
+    /// Refuse a batch over `max_batch_size` before any input is read.
+    pub fn check_batch_size(&self, file_count: usize) -> Result<(), ConvertError> {
+        if file_count > self.max_batch_size as usize {
+            return Err(ConvertError::BatchTooLarge { count: file_count, max: self.max_batch_size });
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_options(options: &ConverterOptions) -> Result<(), ConvertError> {
+    if options.timeout_ms == Some(0) {
+        return Err(ConvertError::InvalidOptions("timeout_ms must be positive, got 0".to_string()));
+    }
+    if options.max_batch_size == 0 {
+        return Err(ConvertError::InvalidOptions("max_batch_size must be positive, got 0".to_string()));
+    }
+    if options.history_max_bytes == Some(0) {
+        return Err(ConvertError::InvalidOptions("history_max_bytes must be positive, got 0".to_string()));
+    }
+    if options.result_cache_bytes == Some(0) {
+        return Err(ConvertError::InvalidOptions("result_cache_bytes must be positive, got 0".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_default_when_omitted_and_are_validated() {
+        let options: ConverterOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, ConverterOptions::default());
+
+        let options: ConverterOptions = serde_json::from_value(serde_json::json!({ "log_level": "warn", "timeout_ms": 0 })).unwrap();
+        assert_eq!(options.log_level, LogLevel::Warn);
+        assert_eq!(
+            validate_options(&options).unwrap_err().to_string(),
+            "Invalid options: timeout_ms must be positive, got 0"
+        );
+        let options: ConverterOptions = serde_json::from_value(serde_json::json!({ "history_max_bytes": 0 })).unwrap();
+        assert_eq!(
+            validate_options(&options).unwrap_err().to_string(),
+            "Invalid options: history_max_bytes must be positive, got 0"
+        );
+        let options: ConverterOptions = serde_json::from_value(serde_json::json!({ "result_cache_bytes": 0 })).unwrap();
+        assert_eq!(
+            validate_options(&options).unwrap_err().to_string(),
+            "Invalid options: result_cache_bytes must be positive, got 0"
+        );
+    }
+
+    #[test]
+    fn batches_over_the_limit_are_refused() {
+        let options: ConverterOptions = serde_json::from_value(serde_json::json!({ "max_batch_size": 3 })).unwrap();
+        assert!(options.check_batch_size(3).is_ok());
+        let error = options.check_batch_size(4).unwrap_err();
+        assert_eq!(error.code(), Some("batch_too_large"));
+        assert_eq!(
+            error.to_string(),
+            "4 files exceed max_batch_size 3; convert them in smaller batches or into an output directory"
+        );
+        assert_eq!(ConverterOptions::default().max_batch_size, 200);
+        assert!(validate_options(&ConverterOptions { max_batch_size: 0, ..ConverterOptions::default() }).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Content-based orientation for `auto_rotate`. A signature or page photographed with
+//! the phone held sideways has no EXIF rotation to undo, so the writing itself is read:
+//! lines of writing make the ink profile across them ripple and the profile along them
+//! flat, left-aligned writing starts each line at the same place but ends it raggedly,
+//! and ascenders and capitals rise further above the writing than descenders drop below.
+
+use super::ops::{paper_level, INK_CONTRAST};
+
+// The sample the ink is measured on, so a 12MP photo costs the same as a thumbnail
+const SAMPLE_MAX_EDGE: u32 = 256;
+// Ink shares outside this range are not writing on paper: a blank sheet, or a photo
+// whose subject fills the frame
+const MIN_INK: f32 = 0.001;
+const MAX_INK: f32 = 0.3;
+// Rows (or columns) of ink needed before their starts and ends say anything
+const MIN_INK_LINES: usize = 4;
+
+/// The clockwise turn that brings the writing upright, and how sure that is from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Orientation {
+    pub degrees: u16,
+    pub confidence: f32,
+}
+
+/// How `img` must be turned for its writing to read upright, or None when it does not
+/// look like dark writing on light paper. `landscape` is the shape the spec expects the
+/// writing to have, when it says.
+pub(crate) fn detect_orientation(img: &image::DynamicImage, landscape: Option<bool>) -> Option<Orientation> {
+    let luma = img.thumbnail(SAMPLE_MAX_EDGE, SAMPLE_MAX_EDGE).to_luma8();
+    let (width, height) = (luma.width() as usize, luma.height() as usize);
+    let threshold = paper_level(&luma).checked_sub(INK_CONTRAST)?;
+    let ink: Vec<bool> = luma.pixels().map(|pixel| pixel.0[0] < threshold).collect();
+    let share = ink.iter().filter(|&&ink| ink).count() as f32 / ink.len().max(1) as f32;
+    if !(MIN_INK..=MAX_INK).contains(&share) {
+        return None;
+    }
+    let rows: Vec<Vec<usize>> = (0..height).map(|y| (0..width).filter(|&x| ink[y * width + x]).collect()).collect();
+    let columns: Vec<Vec<usize>> = (0..width).map(|x| (0..height).filter(|&y| ink[y * width + x]).collect()).collect();
+    let (row_profile, column_profile) = (trimmed_profile(&rows), trimmed_profile(&columns));
+
+    // How much more the writing looks like horizontal lines than vertical ones, from 0
+    // to 1: by the ripple of the profile across the lines, and by the shape of the ink
+    // against the one the spec expects. Each pulls away from an even 0.5 on its own, so
+    // a square block of writing leaves the ripple to decide.
+    let (row_ripple, column_ripple) = (variation(&row_profile), variation(&column_profile));
+    let ripple = if row_ripple + column_ripple > 0.0 { row_ripple / (row_ripple + column_ripple) } else { 0.5 };
+    let wide = column_profile.len() as f32 / (column_profile.len() + row_profile.len()) as f32;
+    let shape = match landscape {
+        Some(true) => wide,
+        Some(false) => 1.0 - wide,
+        None => 0.5,
+    };
+    let horizontal = (ripple + shape - 0.5).clamp(0.0, 1.0);
+
+    // Whether the writing reads from the near end of its lines with its top towards the
+    // near side (the top, or the right for writing turned clockwise), from 0 to 1
+    let (lines, near_start) = if horizontal >= 0.5 { (&rows, true) } else { (&columns, false) };
+    let across: Vec<f32> = match near_start {
+        true => rows.iter().enumerate().flat_map(|(y, row)| std::iter::repeat_n(y as f32, row.len())).collect(),
+        false => columns.iter().enumerate().flat_map(|(x, column)| std::iter::repeat_n((width - 1 - x) as f32, column.len())).collect(),
+    };
+    let start = (aligned_start(lines)? + rising(across) - 0.5).clamp(0.0, 1.0);
+    let degrees = match (near_start, start >= 0.5) {
+        (true, true) => 0,
+        (true, false) => 180,
+        // Lines running down from the top were turned clockwise, so turn them back
+        (false, true) => 270,
+        (false, false) => 90,
+    };
+    let confidence = ((horizontal - 0.5).abs() * 2.0).min((start - 0.5).abs() * 2.0);
+    Some(Orientation { degrees, confidence })
+}
+
+// Ink per line, from the first line with ink to the last
+fn trimmed_profile(lines: &[Vec<usize>]) -> Vec<f32> {
+    let first = lines.iter().position(|line| !line.is_empty()).unwrap_or(0);
+    let last = lines.iter().rposition(|line| !line.is_empty()).unwrap_or(0);
+    lines.get(first..=last).unwrap_or_default().iter().map(|line| line.len() as f32).collect()
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len().max(1) as f32
+}
+
+fn deviation(values: &[f32]) -> f32 {
+    let mean = mean(values);
+    (values.iter().map(|value| (value - mean).powi(2)).sum::<f32>() / values.len().max(1) as f32).sqrt()
+}
+
+// Coefficient of variation
+fn variation(profile: &[f32]) -> f32 {
+    let mean = mean(profile);
+    if mean == 0.0 { 0.0 } else { deviation(profile) / mean }
+}
+
+// How much more alike the lines' first ink positions are than their last ones, from 0
+// to 1, or None with too few lines of ink to tell
+fn aligned_start(lines: &[Vec<usize>]) -> Option<f32> {
+    let inked: Vec<&Vec<usize>> = lines.iter().filter(|line| !line.is_empty()).collect();
+    if inked.len() < MIN_INK_LINES {
+        return None;
+    }
+    let starts = deviation(&inked.iter().map(|line| line[0] as f32).collect::<Vec<_>>());
+    let ends = deviation(&inked.iter().map(|line| line[line.len() - 1] as f32).collect::<Vec<_>>());
+    Some(if starts + ends > 0.0 { ends / (starts + ends) } else { 0.5 })
+}
+
+// How far the ink's positions across the lines tail off towards low values, as ascenders
+// do above the writing, from 0 to 1: Pearson's median skewness, negated and halved
+fn rising(mut positions: Vec<f32>) -> f32 {
+    let (mean, deviation) = (mean(&positions), deviation(&positions));
+    if deviation == 0.0 {
+        return 0.5;
+    }
+    positions.sort_unstable_by(f32::total_cmp);
+    let median = positions[positions.len() / 2];
+    (0.5 + 1.5 * (median - mean) / deviation).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A left-aligned paragraph with a ragged right edge, as it would be photographed
+    // upright
+    fn paragraph() -> image::DynamicImage {
+        let lengths = [90, 70, 85, 55, 80, 62, 88, 40];
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 400, |x, y| {
+            let line = (y as usize).checked_sub(60).map(|y| (y / 30, y % 30));
+            let ink = line.is_some_and(|(line, within)| line < lengths.len() && within < 10 && x >= 30 && x < 30 + lengths[line] * 240 / 100);
+            image::Rgb(if ink { [30, 30, 40] } else { [238, 236, 230] })
+        }))
+    }
+
+    #[test]
+    fn sideways_and_upside_down_writing_is_turned_back() {
+        let upright = paragraph();
+        assert_eq!(detect_orientation(&upright, Some(false)).unwrap().degrees, 0);
+        for (turned, degrees) in [(upright.rotate90(), 270), (upright.rotate180(), 180), (upright.rotate270(), 90)] {
+            let orientation = detect_orientation(&turned, Some(false)).unwrap();
+            assert_eq!(orientation.degrees, degrees);
+            assert!(orientation.confidence > 0.5, "{:?}", orientation);
+        }
+        // Without an expected shape the lines alone decide
+        assert_eq!(detect_orientation(&upright.rotate90(), None).unwrap().degrees, 270);
+    }
+
+    #[test]
+    fn a_sideways_signature_is_read_by_its_ascenders() {
+        let signature = image::DynamicImage::ImageRgb8(crate::core::test_support::signature());
+        assert_eq!(detect_orientation(&signature, Some(true)).unwrap().degrees, 0);
+        for (turned, degrees) in [(signature.rotate90(), 270), (signature.rotate270(), 90)] {
+            let orientation = detect_orientation(&turned, Some(true)).unwrap();
+            assert_eq!(orientation.degrees, degrees);
+            assert!(orientation.confidence > 0.4, "{:?}", orientation);
+        }
+    }
+
+    #[test]
+    fn photos_and_blank_paper_are_not_read() {
+        let face = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 400, |x, y| {
+            let inside = (x as i32 - 150).pow(2) * 4 + (y as i32 - 200).pow(2) * 2 < 200 * 200;
+            image::Rgb(if inside { [196, 140, 110] } else { [250, 250, 250] })
+        }));
+        assert_eq!(detect_orientation(&face, Some(false)), None);
+        let blank = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(300, 400, image::Rgb([240, 240, 240])));
+        assert_eq!(detect_orientation(&blank, Some(false)), None);
+    }
+
+    #[test]
+    fn a_shapeless_blot_is_not_confident() {
+        let blot = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 300, |x, y| {
+            let inside = (x as i32 - 150).pow(2) + (y as i32 - 150).pow(2) < 40 * 40;
+            image::Rgb(if inside { [20, 20, 20] } else { [240, 240, 240] })
+        }));
+        assert!(detect_orientation(&blot, None).is_none_or(|orientation| orientation.confidence < 0.1));
+    }
+}
@@ -0,0 +1,779 @@
+//! Just enough PDF for `pdf_rasterize_dpi`, `pdf_a`, `max_pdf_colors` and upright pages.
+//! The page scans of an image-only PDF are found by scanning the raw bytes, and a new PDF
+//! is written from downscaled, palette-reduced or unchanged copies of them. There is no renderer in this build, so pages
+//! carrying text or vector drawings cannot be rebuilt.
+
+use pdf_writer::writers::OutputIntent;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, TextStr};
+use sha2::{Digest, Sha256};
+
+use super::ops::srgb_to_linear;
+
+/// A page that is one JPEG image, sized in points before its `/Rotate` is applied.
+pub(crate) struct ScannedPage<'a> {
+    pub jpeg: &'a [u8],
+    pub width: f32,
+    pub height: f32,
+    // Clockwise quarter turns viewers apply, in degrees
+    pub rotate: u16,
+}
+
+/// Each page's scan, in file order, or why the PDF is not a plain stack of scans.
+/// Scanners write pages and their images in the same order and usually give the
+/// MediaBox once on the page tree, so a lone MediaBox sizes every page.
+pub(crate) fn scanned_pages(data: &[u8]) -> Result<Vec<ScannedPage<'_>>, String> {
+    let pages = page_count(data);
+    if pages == 0 {
+        return Err("no pages were found".to_string());
+    }
+    let scans = jpeg_streams(data);
+    if scans.len() != pages {
+        return Err(format!(
+            "it has {} pages but {} JPEG scans, and only pages that are one scanned image each can be rasterized",
+            pages,
+            scans.len()
+        ));
+    }
+    let boxes = media_boxes(data);
+    let rotations = page_rotations(data);
+    scans
+        .into_iter()
+        .zip(rotations)
+        .enumerate()
+        .map(|(index, (jpeg, rotate))| {
+            let [width, height] = *boxes
+                .get(if boxes.len() == pages { index } else { 0 })
+                .ok_or_else(|| "no page size was found".to_string())?;
+            Ok(ScannedPage { jpeg, width, height, rotate })
+        })
+        .collect()
+}
+
+/// `(page, degrees)` for each page turned upright.
+pub(crate) type PageTurns = Vec<(u32, u16)>;
+
+/// The PDF rebuilt with each page's `/Rotate` turned into its content, so pages show
+/// upright in viewers that ignore the flag, together with the `(page, degrees)` turned,
+/// pages counted from 1. None when no page is rotated; the reason when rotated pages are
+/// not plain scans.
+pub(crate) fn normalize_rotation(data: &[u8]) -> Result<Option<(Vec<u8>, PageTurns)>, String> {
+    let rotated: PageTurns = (1..)
+        .zip(page_rotations(data))
+        .filter(|&(_, degrees)| degrees != 0)
+        .collect();
+    if rotated.is_empty() {
+        return Ok(None);
+    }
+    let pages = scanned_pages(data)?;
+    Ok(Some((rewrite(pages.iter())?, rotated)))
+}
+
+/// The scanned PDF without the given pages, counted from 1; the rest keep their order
+/// and are numbered afresh.
+pub(crate) fn remove_pages(data: &[u8], remove: &[u32]) -> Result<Vec<u8>, String> {
+    let pages = scanned_pages(data)?;
+    rewrite((1..).zip(&pages).filter(|(number, _)| !remove.contains(number)).map(|(_, page)| page))
+}
+
+// A new PDF of the scans, copied unchanged and each turned by its `/Rotate`
+fn rewrite<'a>(pages: impl Iterator<Item = &'a ScannedPage<'a>>) -> Result<Vec<u8>, String> {
+    let (mut scans, mut sizes, mut rotations) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, page) in pages.enumerate() {
+        let (width, height, _) =
+            jpeg_frame(page.jpeg).ok_or_else(|| format!("the scan on page {} is not a readable JPEG", index + 1))?;
+        scans.push((PageImage::Jpeg(page.jpeg.to_vec()), width, height));
+        sizes.push((page.width, page.height));
+        rotations.push(page.rotate);
+    }
+    Ok(write_pdf(&scans, &sizes, &rotations, false))
+}
+
+/// `data` with an incremental update that points its catalog at the XMP packet `xmp`,
+/// replacing any metadata it had, or why it cannot be added. The original bytes are kept
+/// as they are, so the update only needs a classic cross-reference table to chain onto.
+pub(crate) fn add_metadata(data: &[u8], xmp: &[u8]) -> Result<Vec<u8>, String> {
+    let startxref = data.windows(9).rposition(|window| window == b"startxref").ok_or("it has no startxref")?;
+    let prev = number_at(data, skip_whitespace(data, startxref + 9)).ok_or("its startxref is not a number")?;
+    if data.get(prev as usize..prev as usize + 4) != Some(b"xref") {
+        return Err("it uses a cross-reference stream".to_string());
+    }
+    let trailer = data[..startxref].windows(7).rposition(|window| window == b"trailer").ok_or("it has no trailer")?;
+    let trailer = &data[trailer..startxref];
+    if find(trailer, b"/Encrypt", 0).is_some() {
+        return Err("it is encrypted".to_string());
+    }
+    let size = find(trailer, b"/Size", 0).and_then(|at| number_at(trailer, skip_whitespace(trailer, at + 5))).ok_or("its trailer has no /Size")?;
+    let (root, generation) = reference_after(trailer, b"/Root").ok_or("its trailer has no /Root")?;
+
+    // The last definition of the catalog is the one in force
+    let header = format!("{} {} obj", root, generation);
+    let catalog_at = (0..data.len().saturating_sub(header.len()))
+        .rev()
+        .find(|&at| data[at..].starts_with(header.as_bytes()) && (at == 0 || !data[at - 1].is_ascii_digit()))
+        .ok_or("its catalog is missing")?;
+    let dict_start = find(data, b"<<", catalog_at).ok_or("its catalog is not a dictionary")?;
+    let dict_end = dict_end(data, dict_start).ok_or("its catalog is not a dictionary")?;
+    let mut catalog = data[dict_start..dict_end - 2].to_vec();
+    if let Some(at) = find(&catalog, b"/Metadata", 0) {
+        let end = find(&catalog, b"R", at).ok_or("its catalog's /Metadata is not a reference")?;
+        catalog.drain(at..=end);
+    }
+    catalog.extend(format!(" /Metadata {} 0 R >>", size).as_bytes());
+
+    let mut pdf = data.to_vec();
+    if pdf.last() != Some(&b'\n') {
+        pdf.push(b'\n');
+    }
+    let metadata_at = pdf.len();
+    pdf.extend(format!("{} 0 obj\n<< /Type /Metadata /Subtype /XML /Length {} >>\nstream\n", size, xmp.len()).as_bytes());
+    pdf.extend(xmp);
+    pdf.extend(b"\nendstream\nendobj\n");
+    let new_catalog_at = pdf.len();
+    pdf.extend(format!("{}\n", header).as_bytes());
+    pdf.extend(catalog);
+    pdf.extend(b"\nendobj\n");
+    let xref_at = pdf.len();
+    pdf.extend(
+        format!("xref\n{} 1\n{:010} {:05} n \n{} 1\n{:010} 00000 n \n", root, new_catalog_at, generation, size, metadata_at).as_bytes(),
+    );
+    let info = reference_after(trailer, b"/Info").map(|(info, generation)| format!(" /Info {} {} R", info, generation)).unwrap_or_default();
+    let id = find(trailer, b"/ID", 0)
+        .and_then(|at| Some(&trailer[at..=find(trailer, b"]", at)?]))
+        .map(|id| format!(" {}", String::from_utf8_lossy(id)))
+        .unwrap_or_default();
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root {} {} R /Prev {}{}{} >>\nstartxref\n{}\n%%EOF\n",
+            size + 1,
+            root,
+            generation,
+            prev,
+            info,
+            id,
+            xref_at
+        )
+        .as_bytes(),
+    );
+    Ok(pdf)
+}
+
+/// The PDF rebuilt as PDF/A-1b around its page scans, copied byte for byte, or why that
+/// cannot be done without changing what the pages show. Also returns notes on anything
+/// PDF/A forbids that was left out.
+pub(crate) fn rebuild_pdf_a(data: &[u8]) -> Result<(Vec<u8>, Vec<String>), String> {
+    if find(data, b"/Encrypt", 0).is_some() {
+        return Err("it is encrypted, which PDF/A forbids; remove its password first".to_string());
+    }
+    if page_count(data) == 0 {
+        return Err("no pages were found".to_string());
+    }
+    if find(data, b"/SMask", 0).is_some() {
+        return Err("its images have transparency masks, which PDF/A-1 forbids; flatten them first".to_string());
+    }
+    let pages = scanned_pages(data).map_err(|_| {
+        "its pages carry text or drawings besides scans, and their fonts cannot be embedded without a PDF renderer; \
+         export it as PDF/A-1b from the program that made it"
+            .to_string()
+    })?;
+    let scans = pages
+        .iter()
+        .enumerate()
+        .map(|(index, page)| match jpeg_frame(page.jpeg) {
+            Some((_, _, 4)) => Err(format!(
+                "page {} is a CMYK scan, which an sRGB output intent cannot hold; convert it to RGB first",
+                index + 1
+            )),
+            Some((width, height, _)) => Ok((PageImage::Jpeg(page.jpeg.to_vec()), width, height)),
+            None => Err(format!("the scan on page {} is not a readable JPEG", index + 1)),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let sizes: Vec<_> = pages.iter().map(|page| (page.width, page.height)).collect();
+    let rotations: Vec<_> = pages.iter().map(|page| page.rotate).collect();
+    let notes = [&b"/JavaScript"[..], b"/JS"]
+        .iter()
+        .any(|key| find(data, key, 0).is_some())
+        .then(|| "Removed JavaScript, which PDF/A forbids".to_string())
+        .into_iter()
+        .collect();
+    Ok((write_pdf(&scans, &sizes, &rotations, true), notes))
+}
+
+/// How a page's image is stored in a written PDF.
+pub(crate) enum PageImage {
+    /// A grayscale or RGB JPEG, embedded as it is
+    Jpeg(Vec<u8>),
+    /// One palette index per pixel, row by row, into at most 256 RGB colors; written
+    /// as an Indexed image, deflated
+    Indexed { palette: Vec<[u8; 3]>, indices: Vec<u8> },
+}
+
+/// A PDF with one page per image, each filling a page of the given size in points.
+/// The images are `(image, width_px, height_px)`. A page with a clockwise
+/// rotation in `rotations` is written turned, with the rotation drawn into its content
+/// instead of a `/Rotate` flag; pages past the end of `rotations` are upright. With
+/// `pdf_a` the file declares PDF/A-1b conformance, with the sRGB output intent and XMP
+/// metadata it needs.
+pub(crate) fn write_pdf(pages: &[(PageImage, u32, u32)], sizes: &[(f32, f32)], rotations: &[u16], pdf_a: bool) -> Vec<u8> {
+    let mut pdf = Pdf::new();
+    let catalog = Ref::new(1);
+    let tree = Ref::new(2);
+    let ids = |index: usize| {
+        let first = 3 + 3 * index as i32;
+        (Ref::new(first), Ref::new(first + 1), Ref::new(first + 2))
+    };
+    if pdf_a {
+        let (metadata, profile) = (ids(pages.len()).0, ids(pages.len()).1);
+        pdf.set_version(1, 4);
+        let mut hasher = Sha256::new();
+        pages.iter().for_each(|(image, _, _)| match image {
+            PageImage::Jpeg(jpeg) => hasher.update(jpeg),
+            PageImage::Indexed { indices, .. } => hasher.update(indices),
+        });
+        let id = hasher.finalize()[..16].to_vec();
+        pdf.set_file_id((id.clone(), id));
+        let mut document = pdf.catalog(catalog);
+        document.pages(tree).metadata(metadata);
+        document
+            .insert(Name(b"OutputIntents"))
+            .array()
+            .push()
+            .start::<OutputIntent>()
+            .subtype(pdf_writer::types::OutputIntentSubtype::PDFA)
+            .output_condition_identifier(TextStr("sRGB IEC61966-2.1"))
+            .dest_output_profile(profile);
+        document.finish();
+        pdf.metadata(metadata, PDF_A_XMP.as_bytes());
+        let icc = srgb_icc_profile();
+        pdf.icc_profile(profile, &icc).n(3);
+    } else {
+        pdf.catalog(catalog).pages(tree);
+    }
+    pdf.pages(tree).kids((0..pages.len()).map(|index| ids(index).0)).count(pages.len() as i32);
+    for (index, ((page_image, width_px, height_px), &(width, height))) in pages.iter().zip(sizes).enumerate() {
+        let (page_id, image_id, content_id) = ids(index);
+        // Maps the image's unit square onto the page, turned clockwise
+        let (media_box, placement) = match rotations.get(index).copied().unwrap_or(0) {
+            90 => ((height, width), [0.0, -width, height, 0.0, 0.0, width]),
+            180 => ((width, height), [-width, 0.0, 0.0, -height, width, height]),
+            270 => ((height, width), [0.0, width, -height, 0.0, height, 0.0]),
+            _ => ((width, height), [width, 0.0, 0.0, height, 0.0, 0.0]),
+        };
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, media_box.0, media_box.1)).parent(tree).contents(content_id);
+        page.resources().x_objects().pair(Name(b"Im0"), image_id);
+        page.finish();
+
+        match page_image {
+            PageImage::Jpeg(jpeg) => {
+                let mut image = pdf.image_xobject(image_id, jpeg);
+                image.filter(Filter::DctDecode);
+                image.width(*width_px as i32);
+                image.height(*height_px as i32);
+                if jpeg_frame(jpeg).is_some_and(|(_, _, components)| components == 1) {
+                    image.color_space().device_gray();
+                } else {
+                    image.color_space().device_rgb();
+                }
+                image.bits_per_component(8);
+                image.finish();
+            }
+            PageImage::Indexed { palette, indices } => {
+                let deflated = miniz_oxide::deflate::compress_to_vec_zlib(indices, 9);
+                let mut image = pdf.image_xobject(image_id, &deflated);
+                image.filter(Filter::FlateDecode);
+                image.width(*width_px as i32);
+                image.height(*height_px as i32);
+                let lookup: Vec<u8> = palette.iter().flatten().copied().collect();
+                image.color_space().indexed(Name(b"DeviceRGB"), palette.len() as i32 - 1, &lookup);
+                image.bits_per_component(8);
+                image.finish();
+            }
+        }
+
+        let mut content = Content::new();
+        content.save_state();
+        content.transform(placement);
+        content.x_object(Name(b"Im0"));
+        content.restore_state();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
+
+// PDF/A-1 identification; an Info dictionary would have to repeat every field, so the
+// producer is only named here
+const PDF_A_XMP: &str = concat!(
+    "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+    "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+    "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+    "<rdf:Description rdf:about=\"\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n",
+    "<pdf:Producer>summa2 document converter</pdf:Producer>\n",
+    "<pdfaid:part>1</pdfaid:part>\n",
+    "<pdfaid:conformance>B</pdfaid:conformance>\n",
+    "</rdf:Description>\n",
+    "</rdf:RDF>\n",
+    "</x:xmpmeta>\n",
+    "<?xpacket end=\"w\"?>",
+);
+
+// A version 2 display profile for sRGB: the D50-adapted primaries and white point and
+// the sRGB tone curve sampled 1024 times. The three channels share one curve.
+fn srgb_icc_profile() -> Vec<u8> {
+    fn s15_fixed16(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|value| ((value * 65536.0).round() as i32).to_be_bytes()).collect()
+    }
+    fn tag(signature: &[u8; 4], body: Vec<u8>) -> Vec<u8> {
+        [&signature[..], &[0; 4], &body].concat()
+    }
+    let name = b"sRGB IEC61966-2.1\0";
+    let description = [&(name.len() as u32).to_be_bytes()[..], name, &[0; 4 + 4 + 2 + 1 + 67]].concat();
+    let curve: Vec<u8> = (0..1024u32)
+        .flat_map(|step| ((srgb_to_linear(step as f32 / 1023.0) * 65535.0).round() as u16).to_be_bytes())
+        .collect();
+    let curve = tag(b"curv", [&1024u32.to_be_bytes()[..], &curve].concat());
+    let tags = [
+        (b"desc", tag(b"desc", description)),
+        (b"cprt", tag(b"text", b"No copyright, use freely\0".to_vec())),
+        (b"wtpt", tag(b"XYZ ", s15_fixed16(&[0.9642, 1.0, 0.8249]))),
+        (b"rXYZ", tag(b"XYZ ", s15_fixed16(&[0.4361, 0.2225, 0.0139]))),
+        (b"gXYZ", tag(b"XYZ ", s15_fixed16(&[0.3851, 0.7169, 0.0971]))),
+        (b"bXYZ", tag(b"XYZ ", s15_fixed16(&[0.1431, 0.0606, 0.7141]))),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data: Vec<u8> = Vec::new();
+    let mut placed: Vec<(&Vec<u8>, u32)> = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len() as u32;
+    for (signature, body) in &tags {
+        let offset = match placed.iter().find(|(placed, _)| *placed == body) {
+            Some(&(_, offset)) => offset,
+            None => {
+                let offset = data_start + data.len() as u32;
+                data.extend(body);
+                data.resize(data.len().next_multiple_of(4), 0);
+                placed.push((body, offset));
+                offset
+            }
+        };
+        table.extend([&signature[..], &offset.to_be_bytes(), &(body.len() as u32).to_be_bytes()].concat());
+    }
+
+    let size = data_start + data.len() as u32;
+    let mut header = Vec::with_capacity(128);
+    header.extend(size.to_be_bytes());
+    header.extend([0; 4]);
+    header.extend(0x0210_0000u32.to_be_bytes());
+    header.extend(b"mntrRGB XYZ ");
+    // 2000-01-01 00:00:00
+    header.extend([2000u16, 1, 1, 0, 0, 0].iter().flat_map(|field| field.to_be_bytes()));
+    header.extend(b"acsp");
+    header.extend([0; 28]);
+    header.extend(s15_fixed16(&[0.9642, 1.0, 0.8249]));
+    header.resize(128, 0);
+    [header, table, data].concat()
+}
+
+// Width, height and component count from a JPEG's frame header
+fn jpeg_frame(jpeg: &[u8]) -> Option<(u32, u32, u8)> {
+    let mut at = 2;
+    while at + 4 <= jpeg.len() {
+        if jpeg[at] != 0xff {
+            return None;
+        }
+        let marker = jpeg[at + 1];
+        if marker == 0xff {
+            at += 1;
+            continue;
+        }
+        // Start-of-frame markers, leaving out DHT, JPG and DAC in the same range
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let frame = jpeg.get(at + 4..at + 10)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Some((width, height, frame[5]));
+        }
+        at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+    }
+    None
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(needle.len()).position(|window| window == needle).map(|at| at + from)
+}
+
+fn number_at(data: &[u8], at: usize) -> Option<u64> {
+    let end = (at..=data.len()).find(|&end| !data.get(end).is_some_and(u8::is_ascii_digit))?;
+    std::str::from_utf8(&data[at..end]).ok()?.parse().ok()
+}
+
+// The object and generation numbers of the `N G R` reference following `key`
+fn reference_after(data: &[u8], key: &[u8]) -> Option<(u64, u64)> {
+    let at = skip_whitespace(data, find(data, key, 0)? + key.len());
+    let object = number_at(data, at)?;
+    let at = skip_whitespace(data, at + object.to_string().len());
+    let generation = number_at(data, at)?;
+    let at = skip_whitespace(data, at + generation.to_string().len());
+    (data.get(at) == Some(&b'R')).then_some((object, generation))
+}
+
+// Offset just past the `>>` closing the dictionary opened at `start`
+fn dict_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut at = start;
+    while at + 1 < data.len() {
+        match &data[at..at + 2] {
+            b"<<" => depth += 1,
+            b">>" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(at + 2);
+                }
+            }
+            _ => {
+                at += 1;
+                continue;
+            }
+        }
+        at += 2;
+    }
+    None
+}
+
+fn skip_whitespace(data: &[u8], mut at: usize) -> usize {
+    while data.get(at).is_some_and(u8::is_ascii_whitespace) {
+        at += 1;
+    }
+    at
+}
+
+fn page_count(data: &[u8]) -> usize {
+    typed(data, b"/Page").len()
+}
+
+// Offsets of the dictionaries with `/Type` `type_name`; /Page leaves out the /Pages tree nodes
+fn typed(data: &[u8], type_name: &[u8]) -> Vec<usize> {
+    let mut found_at = Vec::new();
+    let mut at = 0;
+    while let Some(found) = find(data, b"/Type", at) {
+        let value = skip_whitespace(data, found + 5);
+        let after = value + type_name.len();
+        if data.get(value..after) == Some(type_name) && !data.get(after).is_some_and(u8::is_ascii_alphanumeric) {
+            found_at.push(found);
+        }
+        at = found + 5;
+    }
+    found_at
+}
+
+// Each page's `/Rotate` in file order, as 0, 90, 180 or 270. Pages without their own
+// inherit it from the page tree; scanners that rotate every page set it once there.
+fn page_rotations(data: &[u8]) -> Vec<u16> {
+    let inherited = typed(data, b"/Pages").into_iter().find_map(|at| rotate_entry(object_around(data, at))).unwrap_or(0);
+    typed(data, b"/Page")
+        .into_iter()
+        .map(|at| rotate_entry(object_around(data, at)).unwrap_or(inherited))
+        .collect()
+}
+
+// The `/Rotate` of an object, normalized to a clockwise quarter turn; other angles are
+// invalid and viewers draw such pages upright
+fn rotate_entry(object: &[u8]) -> Option<u16> {
+    let found = find(object, b"/Rotate", 0)?;
+    let start = skip_whitespace(object, found + 7);
+    let end = (start..object.len()).find(|&at| !(object[at].is_ascii_digit() || object[at] == b'-' || object[at] == b'+'))?;
+    let degrees: i32 = std::str::from_utf8(&object[start..end]).ok()?.parse().ok()?;
+    Some(if degrees % 90 == 0 { degrees.rem_euclid(360) as u16 } else { 0 })
+}
+
+// The indirect object enclosing offset `at`
+fn object_around(data: &[u8], at: usize) -> &[u8] {
+    let start = data[..at].windows(4).rposition(|window| window == b" obj").unwrap_or(0);
+    let end = find(data, b"endobj", at).unwrap_or(data.len());
+    &data[start..end]
+}
+
+// Width and height of every literal MediaBox; indirect ones are skipped
+fn media_boxes(data: &[u8]) -> Vec<[f32; 2]> {
+    let mut boxes = Vec::new();
+    let mut at = 0;
+    while let Some(found) = find(data, b"/MediaBox", at) {
+        at = found + 9;
+        let open = skip_whitespace(data, at);
+        if data.get(open) != Some(&b'[') {
+            continue;
+        }
+        let Some(close) = find(data, b"]", open) else { break };
+        let numbers: Vec<f32> = std::str::from_utf8(&data[open + 1..close])
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+            .filter_map(|number| number.parse().ok())
+            .collect();
+        if let [left, bottom, right, top] = numbers[..] {
+            boxes.push([right - left, top - bottom]);
+        }
+    }
+    boxes
+}
+
+// Streams filtered with DCTDecode alone, which hold a plain JPEG file. A filter chain
+// leaves data that does not start with the JPEG marker, and is skipped.
+fn jpeg_streams(data: &[u8]) -> Vec<&[u8]> {
+    let mut streams = Vec::new();
+    let mut at = 0;
+    while let Some(found) = find(data, b"/DCTDecode", at) {
+        at = found + 10;
+        let Some(keyword) = find(data, b"stream", at) else { break };
+        let mut start = keyword + 6;
+        if data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find(data, b"endstream", start) else { break };
+        at = end + 9;
+        let body = &data[start..end];
+        let jpeg_end = body.windows(2).rposition(|window| window == [0xff, 0xd9]).map(|last| last + 2);
+        if let (true, Some(jpeg_end)) = (body.starts_with(&[0xff, 0xd8]), jpeg_end) {
+            streams.push(&body[..jpeg_end]);
+        }
+    }
+    streams
+}
+
+#[cfg(all(test, feature = "jpeg"))]
+mod tests {
+    use super::*;
+    use crate::core::test_support::{gradient, jpeg_pdf};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::DynamicImage::ImageRgb8(gradient(width, height))
+            .write_to(&mut std::io::Cursor::new(&mut out), image::ImageOutputFormat::Jpeg(80))
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn written_pdfs_read_back_as_scans() {
+        let (first, second) = (jpeg(40, 60), jpeg(30, 20));
+        let pdf = jpeg_pdf(&[(first.clone(), 40, 60), (second.clone(), 30, 20)], &[(200.0, 300.0), (150.0, 100.0)]);
+        let pages = scanned_pages(&pdf).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!((pages[0].jpeg, pages[0].width, pages[0].height), (first.as_slice(), 200.0, 300.0));
+        assert_eq!((pages[1].jpeg, pages[1].width, pages[1].height), (second.as_slice(), 150.0, 100.0));
+    }
+
+    #[test]
+    fn rotations_are_read_per_page_and_inherited_from_the_tree() {
+        let pdf = b"1 0 obj << /Type /Pages /Kids [2 0 R 3 0 R 4 0 R] /Rotate 180 >> endobj\n\
+            2 0 obj << /Type /Page /Parent 1 0 R /Rotate 90 >> endobj\n\
+            3 0 obj << /Type /Page /Parent 1 0 R >> endobj\n\
+            4 0 obj << /Type /Page /Parent 1 0 R /Rotate -90 >> endobj\n\
+            5 0 obj << /Type /Page /Parent 1 0 R /Rotate 45 >> endobj\n";
+        assert_eq!(page_rotations(pdf), [90, 180, 270, 0]);
+        assert_eq!(page_rotations(b"2 0 obj << /Type /Page >> endobj"), [0]);
+    }
+
+    #[test]
+    fn rotated_scans_are_turned_upright() {
+        let (first, second, third) = (jpeg(40, 60), jpeg(30, 20), jpeg(20, 20));
+        let scans = [(first.clone(), 40, 60), (second.clone(), 30, 20), (third.clone(), 20, 20)];
+        let sizes = [(200.0, 300.0), (150.0, 100.0), (100.0, 100.0)];
+        let upright = jpeg_pdf(&scans, &sizes);
+        assert!(normalize_rotation(&upright).unwrap().is_none());
+
+        // The first page turned a quarter clockwise, the second a quarter back
+        let mut turns = [&b"/Rotate 90 "[..], b"/Rotate -90 ", b""].into_iter();
+        let mut rotated = Vec::new();
+        for (index, part) in upright.split(|&byte| byte == b'\n').enumerate() {
+            if index > 0 {
+                rotated.push(b'\n');
+            }
+            if part.ends_with(b"/Type /Page") {
+                rotated.extend_from_slice(turns.next().unwrap());
+            }
+            rotated.extend_from_slice(part);
+        }
+        let (pdf, turned) = normalize_rotation(&rotated).unwrap().unwrap();
+        assert_eq!(turned, [(1, 90), (2, 270)]);
+        assert_eq!(page_rotations(&pdf), [0, 0, 0]);
+        let pages = scanned_pages(&pdf).unwrap();
+        assert_eq!((pages[0].jpeg, pages[0].width, pages[0].height), (first.as_slice(), 300.0, 200.0));
+        assert_eq!((pages[1].jpeg, pages[1].width, pages[1].height), (second.as_slice(), 100.0, 150.0));
+        assert_eq!((pages[2].jpeg, pages[2].width, pages[2].height), (third.as_slice(), 100.0, 100.0));
+        assert!(find(&pdf, b"0 -200 300 0 0 200 cm", 0).is_some() && find(&pdf, b"0 150 -100 0 100 0 cm", 0).is_some());
+
+        // Rotated pages that are not scans cannot be redrawn
+        let text = b"2 0 obj << /Type /Page /Rotate 90 /Contents 3 0 R >> endobj";
+        assert!(normalize_rotation(text).unwrap_err().starts_with("it has 1 pages but 0 JPEG scans"));
+    }
+
+    // The PDF/A-1b rules a file of page scans can break, checked on its raw bytes
+    fn pdf_a_violations(pdf: &[u8]) -> Vec<&'static str> {
+        let has = |needle: &[u8]| find(pdf, needle, 0).is_some();
+        let mut violations = Vec::new();
+        if !pdf.starts_with(b"%PDF-1.4\n%") || pdf[10..14].iter().any(|&byte| byte < 128) {
+            violations.push("header must be PDF 1.4 with a binary comment");
+        }
+        if !has(b"/ID [<") {
+            violations.push("trailer must carry a file ID");
+        }
+        let metadata = find(pdf, b"/Type /Metadata", 0).map(|at| &pdf[at..find(pdf, b">>", at).unwrap()]);
+        if !has(b"<pdfaid:part>1</pdfaid:part>") || !has(b"<pdfaid:conformance>B</pdfaid:conformance>") || metadata.is_none() {
+            violations.push("XMP metadata must declare PDF/A-1b");
+        }
+        if metadata.is_some_and(|dict| find(dict, b"/Filter", 0).is_some()) {
+            violations.push("metadata stream must not be filtered");
+        }
+        if !has(b"/S /GTS_PDFA1") || !has(b"/DestOutputProfile") {
+            violations.push("needs a GTS_PDFA1 output intent with a profile");
+        }
+        match find(pdf, b"acsp", 0).and_then(|at| pdf.get(at - 36..)) {
+            Some(profile) if profile[8] == 2 && &profile[12..24] == b"mntrRGB XYZ " => {
+                let size = u32::from_be_bytes(profile[..4].try_into().unwrap()) as usize;
+                let count = u32::from_be_bytes(profile[128..132].try_into().unwrap()) as usize;
+                let tags = (0..count).map(|index| &profile[132 + 12 * index..144 + 12 * index]);
+                let in_bounds = tags.clone().all(|entry| {
+                    let offset = u32::from_be_bytes(entry[4..8].try_into().unwrap()) as usize;
+                    offset + u32::from_be_bytes(entry[8..12].try_into().unwrap()) as usize <= size
+                });
+                let required = [b"desc", b"cprt", b"wtpt", b"rXYZ", b"gXYZ", b"bXYZ", b"rTRC", b"gTRC", b"bTRC"];
+                if !in_bounds || !required.iter().all(|signature| tags.clone().any(|entry| &entry[..4] == *signature)) {
+                    violations.push("output profile tags are incomplete");
+                }
+            }
+            _ => violations.push("output profile must be an ICC v2 RGB display profile"),
+        }
+        for (forbidden, violation) in [
+            (&b"/Encrypt"[..], "encryption is forbidden"),
+            (b"/JavaScript", "JavaScript is forbidden"),
+            (b"/JS ", "JavaScript is forbidden"),
+            (b"/SMask", "soft masks are forbidden"),
+            (b"/Transparency", "transparency groups are forbidden"),
+            (b"/Interpolate true", "image interpolation is forbidden"),
+            (b"/EmbeddedFile", "embedded files are forbidden"),
+            (b"/DeviceCMYK", "CMYK needs a CMYK output intent"),
+        ] {
+            if has(forbidden) {
+                violations.push(violation);
+            }
+        }
+        if has(b"/Font") && !has(b"/FontFile") {
+            violations.push("fonts must be embedded");
+        }
+        if !pdf.trim_ascii_end().ends_with(b"%%EOF") {
+            violations.push("must end with %%EOF");
+        }
+        violations
+    }
+
+    #[test]
+    fn image_pdfs_are_rebuilt_as_pdf_a_with_their_scans_unchanged() {
+        let color = jpeg(40, 60);
+        let mut gray = Vec::new();
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(30, 20, |x, _| image::Luma([x as u8 * 8])))
+            .write_to(&mut std::io::Cursor::new(&mut gray), image::ImageOutputFormat::Jpeg(80))
+            .unwrap();
+        let plain = jpeg_pdf(&[(color.clone(), 40, 60), (gray.clone(), 30, 20)], &[(200.0, 300.0), (150.0, 100.0)]);
+        assert!(!pdf_a_violations(&plain).is_empty());
+
+        let (rebuilt, notes) = rebuild_pdf_a(&plain).unwrap();
+        assert_eq!(pdf_a_violations(&rebuilt), Vec::<&str>::new());
+        assert!(notes.is_empty());
+        let pages = scanned_pages(&rebuilt).unwrap();
+        assert_eq!((pages[0].jpeg, pages[0].width, pages[0].height), (color.as_slice(), 200.0, 300.0));
+        assert_eq!((pages[1].jpeg, pages[1].width, pages[1].height), (gray.as_slice(), 150.0, 100.0));
+        assert!(find(&rebuilt, b"/DeviceGray", 0).is_some());
+
+        // Scripts are left behind
+        let scripted = [&plain[..], b"9 0 obj << /S /JavaScript /JS (app.alert(1)) >> endobj\n"].concat();
+        let (rebuilt, notes) = rebuild_pdf_a(&scripted).unwrap();
+        assert_eq!(pdf_a_violations(&rebuilt), Vec::<&str>::new());
+        assert_eq!(notes, ["Removed JavaScript, which PDF/A forbids"]);
+    }
+
+    #[test]
+    fn indexed_pages_are_deflated_with_their_palette() {
+        let page = PageImage::Indexed { palette: vec![[255, 255, 255], [0, 0, 128]], indices: vec![0, 1, 1, 0] };
+        let pdf = write_pdf(&[(page, 2, 2)], &[(100.0, 100.0)], &[], true);
+        assert_eq!(pdf_a_violations(&pdf), Vec::<&str>::new());
+        assert!(find(&pdf, b"/ColorSpace [/Indexed /DeviceRGB 1 <FFFFFF000080>]", 0).is_some());
+        let image = find(&pdf, b"/FlateDecode", 0).unwrap();
+        let start = find(&pdf, b"stream\n", image).unwrap() + 7;
+        let end = find(&pdf, b"\nendstream", start).unwrap();
+        assert_eq!(miniz_oxide::inflate::decompress_to_vec_zlib(&pdf[start..end]).unwrap(), [0, 1, 1, 0]);
+        // No longer a plain stack of JPEG scans
+        assert!(scanned_pages(&pdf).is_err());
+    }
+
+    #[test]
+    fn metadata_is_added_in_an_incremental_update() {
+        let plain = jpeg_pdf(&[(jpeg(40, 60), 40, 60)], &[(200.0, 300.0)]);
+        let updated = add_metadata(&plain, b"<x:xmpmeta>first</x:xmpmeta>").unwrap();
+        assert_eq!(updated[..plain.len()], plain[..]);
+        let update = &updated[plain.len()..];
+        let prev = plain.windows(9).rposition(|window| window == b"startxref").unwrap();
+        let prev = number_at(&plain, skip_whitespace(&plain, prev + 9)).unwrap();
+        assert!(find(update, format!("/Prev {}", prev).as_bytes(), 0).is_some());
+        assert_eq!(scanned_pages(&updated).unwrap().len(), 1);
+
+        // Every xref entry points at its object, and a second update replaces the first's metadata
+        let updated = add_metadata(&updated, b"<x:xmpmeta>second</x:xmpmeta>").unwrap();
+        let xref = updated.windows(6).rposition(|window| window == b"\nxref\n").unwrap() + 1;
+        let lines: Vec<&[u8]> = updated[xref..].split(|&byte| byte == b'\n').collect();
+        for subsection in [1, 3] {
+            let object = number_at(lines[subsection], 0).unwrap();
+            let offset = number_at(lines[subsection + 1], 0).unwrap() as usize;
+            assert!(updated[offset..].starts_with(format!("{} ", object).as_bytes()));
+        }
+        let catalog = &updated[number_at(lines[2], 0).unwrap() as usize..];
+        let catalog = &catalog[..find(catalog, b"endobj", 0).unwrap()];
+        assert_eq!(catalog.windows(9).filter(|window| *window == b"/Metadata").count(), 1);
+        assert!(find(&updated, b"second", 0).is_some());
+
+        let (pdf_a, _) = rebuild_pdf_a(&plain).unwrap();
+        assert_eq!(pdf_a_violations(&add_metadata(&pdf_a, PDF_A_XMP.as_bytes()).unwrap()), Vec::<&str>::new());
+
+        let xref_stream = b"%PDF-1.5\n1 0 obj << /Type /XRef /Size 2 >> stream\nendstream endobj\nstartxref\n9\n%%EOF";
+        assert_eq!(add_metadata(xref_stream, b"").unwrap_err(), "it uses a cross-reference stream");
+    }
+
+    #[test]
+    fn pdfs_that_would_change_are_not_made_pdf_a() {
+        let plain = jpeg_pdf(&[(jpeg(40, 60), 40, 60)], &[(200.0, 300.0)]);
+        let error = |pdf: &[u8]| rebuild_pdf_a(pdf).unwrap_err();
+        assert_eq!(
+            error(&[&plain[..], b"trailer << /Encrypt 9 0 R >>"].concat()),
+            "it is encrypted, which PDF/A forbids; remove its password first"
+        );
+        assert_eq!(
+            error(&[&plain[..], b"9 0 obj << /SMask 10 0 R >> endobj"].concat()),
+            "its images have transparency masks, which PDF/A-1 forbids; flatten them first"
+        );
+        let text = b"%PDF-1.4\n1 0 obj << /Type /Pages /Kids [2 0 R] /Count 1 /MediaBox [0 0 595 842] >> endobj\n\
+            2 0 obj << /Type /Page /Parent 1 0 R /Contents 3 0 R >> endobj\n";
+        assert!(error(text).starts_with("its pages carry text or drawings besides scans"));
+
+        // A four-component frame header marks a CMYK scan
+        let mut cmyk = jpeg(40, 60);
+        let frame = cmyk.windows(2).position(|marker| marker == [0xff, 0xc0]).unwrap();
+        cmyk[frame + 9] = 4;
+        let plain = jpeg_pdf(&[(cmyk, 40, 60)], &[(200.0, 300.0)]);
+        assert_eq!(error(&plain), "page 1 is a CMYK scan, which an sRGB output intent cannot hold; convert it to RGB first");
+    }
+
+    #[test]
+    fn pages_without_a_scan_are_refused() {
+        let text = b"%PDF-1.4\n1 0 obj << /Type /Pages /Kids [2 0 R] /Count 1 /MediaBox [0 0 595 842] >> endobj\n\
+            2 0 obj << /Type /Page /Parent 1 0 R /Contents 3 0 R >> endobj\n";
+        assert_eq!(
+            scanned_pages(text).err().unwrap(),
+            "it has 1 pages but 0 JPEG scans, and only pages that are one scanned image each can be rasterized"
+        );
+        assert_eq!(scanned_pages(b"%PDF-1.4\n").err().unwrap(), "no pages were found");
+    }
+}
@@ -0,0 +1,5270 @@
+use std::cell::RefCell;
+
+use base64::Engine;
+#[cfg(feature = "jpeg")]
+use image::codecs::jpeg::{PixelDensity, PixelDensityUnit};
+#[cfg(feature = "jpeg")]
+use super::spec::DensityUnit;
+#[cfg(any(feature = "jpeg", feature = "png"))]
+use super::spec::PngProfile;
+use image::GenericImageView;
+use sha2::{Digest, Sha256};
+
+use super::background::{replace_background, BackgroundReport};
+use super::codecs::{check_decoded_size, check_input, check_output, declared_color_type, declared_layout, needs_host_encoder, sniff_format};
+use super::compliance::compliance_score;
+use super::diagnostics::{codec_info, CodecInfo};
+use super::events::{LogEventKind, LogLevel, Observer, ProgressStage, SessionStats};
+use super::exif::{exif_capture_date, exif_capture_time, exif_orientation};
+use super::features::{animation_frames, lost_features};
+use super::ink::{detect_ink, DetectedInk};
+use super::pdf::{normalize_rotation, rebuild_pdf_a, remove_pages, scanned_pages, write_pdf, PageImage, ScannedPage};
+use super::ops::{
+    adjust_gamma, clahe, clear_paper, colorfulness, convert_to_srgb, despeckle, halve, hash_similarity, ink_coverage, page_hash, posterize, quantize, resize_exact_alpha, stretch_contrast, trim_borders, GRAYSCALE_MAX_COLORFULNESS,
+};
+use super::orient::{detect_orientation, Orientation};
+use super::scan::{deskew, detect_document, detect_scanner_edges, detect_skew, warp_perspective};
+use super::result::{
+    ConversionPlan, ConvertedFile, DebugIntermediate, EncodeAttempt, FormatFallback, Geometry, Insets, PreviewPair, Thumbnail, ThumbnailPair,
+    TilePosition, Transformation,
+};
+use super::spec::{
+    default_clahe_clip_limit, default_clahe_tile_size, parse_rgb, AutoContrast, BackgroundSpec, CanvasSpec, ColorMismatchPolicy, ColorMode, ColorType, ConversionConfig, DimensionsSpec, DocumentSpec, ExtensionCase,
+    FeatureLossPolicy, FitMode, MemoryCheckSpec, MissingFormatPolicy, PixelRange, PrintOrientation, SizeSpec, SourceProfile,
+    TimestampSource,
+};
+use super::timestamp::{draw_timestamp, format_time, LocalTime};
+use super::provenance::Provenance;
+use super::ConvertError;
+
+pub(crate) const MAX_DEBUG_INTERMEDIATES: usize = 8;
+// JPEG qualities `pdf_rasterize_dpi` tries for the page scans, best first
+#[cfg(feature = "pdf-raster")]
+const RASTERIZE_QUALITIES: [u8; 5] = [85, 70, 55, 40, 25];
+const DEBUG_PREVIEW_MAX_EDGE: u32 = 400;
+const DEFAULT_BLANK_PAGE_MAX_INK: f32 = 0.002;
+const DEFAULT_DUPLICATE_PAGE_MIN_SIMILARITY: f32 = 0.9;
+const DEFAULT_DESKEW_MAX_ANGLE: f32 = 10.0;
+// Tilts smaller than this are within the measurement's own error and left as they are
+const MIN_DESKEW_ANGLE: f32 = 0.2;
+// Page scans are checked for blankness and repeats decoded at about this size, enough to
+// see writing
+#[cfg(feature = "jpeg")]
+const BLANK_CHECK_EDGE: u16 = 256;
+// Longest edge an aspect correction may grow an axis to when the spec sets no pixel
+// maximum for it; a panorama against a portrait ratio would otherwise come out tens of
+// thousands of pixels tall. Matches the canvas size limit of common browsers.
+const MAX_ASPECT_EDGE: u32 = 16_384;
+
+// Output of the image/PDF pipelines before validation and packaging
+struct ConvertedData {
+    data: Vec<u8>,
+    dimensions: Option<DimensionsSpec>,
+    transformations: Vec<Transformation>,
+    debug: Vec<DebugIntermediate>,
+    colorfulness: Option<f32>,
+    ink: Option<DetectedInk>,
+    background: Option<BackgroundReport>,
+    warnings: Vec<String>,
+    geometry: Option<Geometry>,
+}
+
+// Decoded image after every pixel stage, ready to encode
+struct PreparedImage {
+    img: image::DynamicImage,
+    // Decoded size, before any stage
+    source: (u32, u32),
+    transformations: Vec<Transformation>,
+    debug: Vec<DebugIntermediate>,
+    colorfulness: Option<f32>,
+    ink: Option<DetectedInk>,
+    background: Option<BackgroundReport>,
+    warnings: Vec<String>,
+}
+
+/// Result of the quality loop: the encoded bytes and, for JPEG, the quality that fit.
+#[derive(Debug, Clone)]
+pub struct EncodedImage {
+    pub data: Vec<u8>,
+    pub quality: Option<u8>,
+}
+
+fn check_cancelled(observer: &dyn Observer) -> Result<(), ConvertError> {
+    if observer.is_cancelled() {
+        return Err(ConvertError::Cancelled);
+    }
+    Ok(())
+}
+
+// Wraps the caller's observer for one conversion to enforce `max_attempts`. Every encode
+// loop reports an EncodePass event and polls for cancellation before the next encode, so
+// the budget collects its trace from the former and stops the loops through the latter.
+struct EncodeBudget<'a> {
+    inner: &'a dyn Observer,
+    max_attempts: u32,
+    attempts: RefCell<Vec<EncodeAttempt>>,
+}
+
+impl<'a> EncodeBudget<'a> {
+    fn new(inner: &'a dyn Observer, max_attempts: u32) -> Self {
+        EncodeBudget { inner, max_attempts, attempts: RefCell::new(Vec::new()) }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.attempts.borrow().len() >= self.max_attempts as usize
+    }
+
+    // Attaches the encode trace to failures that come from not fitting the size limits.
+    // A cancellation the caller did not ask for is the budget running out.
+    fn explain(&self, error: ConvertError) -> ConvertError {
+        let attempts = self.attempts.borrow().clone();
+        match error {
+            ConvertError::Cancelled if !self.inner.is_cancelled() && self.exhausted() => ConvertError::SizeConstraints {
+                message: format!("Size limits not met within max_attempts {}", self.max_attempts),
+                attempts,
+            },
+            ConvertError::Encode(message) if !attempts.is_empty() => ConvertError::SizeConstraints { message, attempts },
+            error => error,
+        }
+    }
+}
+
+impl Observer for EncodeBudget<'_> {
+    fn event(&self, level: LogLevel, event: LogEventKind, file: Option<&str>, fields: serde_json::Value) {
+        if event == LogEventKind::EncodePass {
+            let number = |key: &str| fields[key].as_u64().unwrap_or(0);
+            self.attempts.borrow_mut().push(EncodeAttempt {
+                format: fields["format"].as_str().unwrap_or_default().to_string(),
+                quality: fields["quality"].as_u64().map(|quality| quality as u8),
+                dimensions: [number("width") as u32, number("height") as u32],
+                size_kb: (number("bytes") / 1024) as u32,
+            });
+        }
+        self.inner.event(level, event, file, fields);
+    }
+
+    fn progress(&self, file_name: &str, stage: ProgressStage, pass: u32) {
+        self.inner.progress(file_name, stage, pass);
+    }
+
+    fn record(&self, update: &dyn Fn(&mut SessionStats)) {
+        self.inner.record(update);
+    }
+
+    fn available_memory(&self) -> Option<u64> {
+        self.inner.available_memory()
+    }
+
+    fn local_time(&self) -> Option<LocalTime> {
+        self.inner.local_time()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled() || self.exhausted()
+    }
+}
+
+/// Convert one input to the configured target spec. `file_type` is the input MIME type.
+pub fn convert(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<ConvertedFile, ConvertError> {
+    convert_hashed(file_name, file_type, data, &sha256_hex(data), config, observer)
+}
+
+/// `convert` for an input whose `sha256_hex` the caller already took, such as a batch
+/// checking it against the completed inputs.
+pub fn convert_hashed(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    input_hash: &str,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<ConvertedFile, ConvertError> {
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+    observer.debug(LogEventKind::Decode, Some(file_name), format!("Converting file: {} ({}) for {}", file_name, file_type, config.document_type));
+
+    // Determine target format from spec
+    let target_format = determine_target_format(file_type, &config.target_spec)?;
+    let budget = EncodeBudget::new(observer, config.max_attempts);
+    convert_to(file_name, file_type, data, input_hash, target_format, Vec::new(), config, &budget)
+}
+
+// `convert` from `target_format` on, after the `format_fallbacks` already made, counting
+// encodes against `budget`
+#[allow(clippy::too_many_arguments)]
+fn convert_to(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    input_hash: &str,
+    mut target_format: String,
+    mut format_fallbacks: Vec<FormatFallback>,
+    config: &ConversionConfig,
+    budget: &EncodeBudget,
+) -> Result<ConvertedFile, ConvertError> {
+    let observer = budget.inner;
+
+    // Convert based on file type and specifications
+    let passthrough = file_type.starts_with("image/").then(|| animation_passthrough(data, &target_format, &config.target_spec)).flatten();
+    let converted = if let Some(Ok(frames)) = passthrough {
+        target_format = "WEBP".to_string();
+        kept_animation(file_name, data, frames, observer)
+    } else if file_type.starts_with("image/") {
+        // Later allowed formats are only tried when the one before cannot fit the size cap,
+        // and never in place of the PNG that holds a transparent background
+        let allowed = config.target_spec.allowed_image_formats();
+        let start = match config.target_spec.transparent_background {
+            true => allowed.len(),
+            false => allowed.iter().position(|format| *format == target_format).map_or(allowed.len(), |index| index + 1),
+        };
+        let mut remaining = allowed[start..].iter().filter(|format| check_output(format).is_ok());
+        let mut converted = loop {
+            let attempt = convert_image(file_name, data, file_type, &target_format, config, budget);
+            let reason = match &attempt {
+                // The JPEG quality loop gives up with an encode error once it bottoms out
+                Err(error @ ConvertError::Encode(_)) => Some(error.to_string()),
+                Ok(converted) if converted.data.len() > (config.target_spec.size_kb.max * 1024) as usize => {
+                    validate_conversion_result(&converted.data, &converted.dimensions, &config.target_spec)
+                        .err()
+                        .map(|error| error.to_string())
+                }
+                _ => None,
+            };
+            let (Some(reason), Some(next)) = (reason, remaining.next()) else {
+                break attempt.map_err(|error| budget.explain(error))?;
+            };
+            let next = next.to_string();
+            observer.event(
+                LogLevel::Warn,
+                LogEventKind::FormatFallback,
+                Some(file_name),
+                serde_json::json!({ "from": target_format, "to": next, "reason": reason }),
+            );
+            format_fallbacks.push(FormatFallback { format: std::mem::replace(&mut target_format, next), reason });
+        };
+        if let Some(Err(warning)) = passthrough {
+            converted.warnings.insert(0, warning);
+        }
+        converted
+    } else if file_type == "application/pdf" {
+        let provenance = config.embed_metadata.then(|| Provenance::new(config, observer));
+        convert_pdf(file_name, data, &config.target_spec, config.max_decoded_pixels, config.return_best_effort, provenance.as_ref(), observer)?
+    } else {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    };
+    finish_conversion(file_name, data, input_hash, &target_format, converted, format_fallbacks, budget.attempts.take(), codec_info(&target_format), config, observer)
+}
+
+// Whether an animated WebP input is kept whole, since no encoder here writes frames:
+// Some(Ok(frames)) when the spec allows WebP output and the file already meets size_kb,
+// Some(Err(warning)) when only its first frame will be converted, and None for inputs
+// that are not animated WebPs
+fn animation_passthrough(data: &[u8], target_format: &str, spec: &DocumentSpec) -> Option<Result<u32, String>> {
+    let frames = animation_frames(data).filter(|&frames| frames > 1 && sniff_format(data) == Some("WEBP"))?;
+    if !spec.allowed_image_formats().iter().any(|format| format.eq_ignore_ascii_case("WEBP")) {
+        return Some(Err(format!(
+            "The animated WebP has {} frames, but {} cannot hold animation, so only the first was converted",
+            frames, target_format
+        )));
+    }
+    Some(match conversion_violations(data, &None, spec).into_iter().next() {
+        Some(violation) => Err(format!("The animated WebP could not be kept whole ({}), so only its first frame was converted", violation)),
+        None => Ok(frames),
+    })
+}
+
+// An animated WebP returned byte for byte; only its size is checked
+fn kept_animation(file_name: &str, data: &[u8], frames: u32, observer: &dyn Observer) -> ConvertedData {
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Animation,
+        Some(file_name),
+        serde_json::json!({ "frames": frames, "bytes": data.len() }),
+    );
+    ConvertedData {
+        data: data.to_vec(),
+        dimensions: None,
+        geometry: None,
+        transformations: vec![Transformation::KeepAnimation { frames }],
+        debug: vec![],
+        colorfulness: None,
+        ink: None,
+        background: None,
+        warnings: vec![],
+    }
+}
+
+// Validates the output of `convert` and packages it. `attempts` is the size loop's trace,
+// attached when the output misses the size limits.
+#[allow(clippy::too_many_arguments)]
+fn finish_conversion(
+    file_name: &str,
+    data: &[u8],
+    input_hash: &str,
+    target_format: &str,
+    converted: ConvertedData,
+    format_fallbacks: Vec<FormatFallback>,
+    attempts: Vec<EncodeAttempt>,
+    codec_info: CodecInfo,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<ConvertedFile, ConvertError> {
+    let converted_data = converted.data;
+    let final_dimensions = converted.dimensions;
+
+    // Validate final result against specifications
+    check_cancelled(observer)?;
+    observer.progress(file_name, ProgressStage::Validating, 0);
+    let violations = if config.return_best_effort {
+        conversion_violations(&converted_data, &final_dimensions, &config.target_spec)
+    } else {
+        validate_conversion_result(&converted_data, &final_dimensions, &config.target_spec).map_err(|error| {
+            // Pixel violations are not the size search's doing, so only size misses carry its trace
+            let size_kb = (converted_data.len() / 1024) as u32;
+            let size = &config.target_spec.size_kb;
+            match error {
+                ConvertError::Validation(message)
+                    if !attempts.is_empty() && (size_kb > size.max || size.min.is_some_and(|min| size_kb < min)) =>
+                {
+                    ConvertError::SizeConstraints { message, attempts }
+                }
+                error => error,
+            }
+        })?;
+        vec![]
+    };
+    if violations.is_empty() {
+        observer.event(
+            LogLevel::Info,
+            LogEventKind::Validate,
+            Some(file_name),
+            serde_json::json!({ "bytes": converted_data.len(), "passed": true }),
+        );
+    } else {
+        observer.event(
+            LogLevel::Warn,
+            LogEventKind::Validate,
+            Some(file_name),
+            serde_json::json!({ "bytes": converted_data.len(), "passed": false, "violations": violations }),
+        );
+    }
+
+    // Generate new filename
+    let converted_name = converted_filename(file_name, data, target_format, config);
+
+    // Create data URL
+    let mime_type = output_mime_type(target_format, &config.target_spec);
+    let base64_data = base64::engine::general_purpose::STANDARD.encode(&converted_data);
+    let data_url = format!("data:{};base64,{}", mime_type, base64_data);
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Converted,
+        Some(file_name),
+        serde_json::json!({
+            "format": target_format,
+            "input_bytes": data.len(),
+            "output_bytes": converted_data.len(),
+        }),
+    );
+
+    observer.record(&|stats| stats.output_bytes += converted_data.len() as u64);
+    let compliance_score = compliance_score(converted_data.len(), final_dimensions.as_ref(), &config.target_spec);
+    let (color_type, bit_depth) = declared_layout(&converted_data).unzip();
+    Ok(ConvertedFile {
+        original_name: file_name.to_string(),
+        converted_name,
+        document_type: config.document_type.clone(),
+        format: target_format.to_string(),
+        size_kb: (converted_data.len() / 1024) as u32,
+        size_bytes: converted_data.len() as u32,
+        dimensions: final_dimensions,
+        color_type,
+        bit_depth,
+        data_url,
+        applied_spec: config.target_spec.clone(),
+        input_hash: input_hash.to_string(),
+        transformations: converted.transformations,
+        compliance_score,
+        compliant: violations.is_empty(),
+        violations,
+        codec_info,
+        format_fallbacks,
+        warnings: converted.warnings,
+        geometry: converted.geometry,
+        colorfulness: converted.colorfulness,
+        ink: converted.ink,
+        background: converted.background,
+        debug: converted.debug,
+        tile: None,
+        cache_hit: false,
+        path: None,
+    })
+}
+
+/// An image input whose target format has no encoder in this build (WebP), decoded and
+/// run through every pixel stage, waiting for the host to encode it with its own
+/// encoder. The host encodes `rgba` at each `next_quality` and hands the bytes to
+/// `push` until `next_quality` returns None, then calls `finish`. The qualities are
+/// those `encode_to_size` would try; `flexible_dimensions` rescaling is not applied.
+pub struct HostEncode {
+    pub target_format: String,
+    pub rgba: image::RgbaImage,
+    file_type: String,
+    prepared: PreparedImage,
+    // Qualities still to try, lowest first so the next one pops off the end
+    qualities: Vec<u8>,
+    max_bytes: usize,
+    max_attempts: u32,
+    attempts: Vec<EncodeAttempt>,
+    encoded: Option<EncodedImage>,
+}
+
+/// Start converting an image input whose target format needs a host encoder, running
+/// every stage before encoding. None, before anything is decoded, when the target
+/// format has an encoder in this build, the input is not an image or it is an animated
+/// WebP `convert` passes through whole; `convert` it.
+pub fn prepare_host_encode(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<Option<HostEncode>, ConvertError> {
+    if !file_type.starts_with("image/") {
+        return Ok(None);
+    }
+    let target_format = determine_target_format(file_type, &config.target_spec)?;
+    let passthrough = animation_passthrough(data, &target_format, &config.target_spec);
+    if !needs_host_encoder(&target_format) || matches!(passthrough, Some(Ok(_))) {
+        return Ok(None);
+    }
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+    observer.debug(LogEventKind::Decode, Some(file_name), format!("Converting file: {} ({}) for {} with a host {} encoder", file_name, file_type, config.document_type, target_format));
+    let mut prepared = prepare_image(file_name, data, file_type, &target_format, config, observer)?;
+    if let Some(Err(warning)) = passthrough {
+        prepared.warnings.insert(0, warning);
+    }
+    let img = std::mem::take(&mut prepared.img);
+    let mut qualities = match &config.target_spec.quality_ladder {
+        Some(ladder) => ladder_rungs(ladder),
+        None => config.target_spec.performance_profile.qualities(),
+    };
+    qualities.reverse();
+    Ok(Some(HostEncode {
+        target_format,
+        rgba: img.into_rgba8(),
+        file_type: file_type.to_string(),
+        prepared,
+        qualities,
+        max_bytes: (config.target_spec.size_kb.max * 1024) as usize,
+        max_attempts: config.max_attempts,
+        attempts: Vec::new(),
+        encoded: None,
+    }))
+}
+
+impl HostEncode {
+    /// The quality (1-100) to encode at next, or None once an encode fits under
+    /// `size_kb.max`, every quality has been tried or `max_attempts` is spent. Counts
+    /// the pass and stops with `ConvertError::Cancelled` when the host has cancelled.
+    pub fn next_quality(&self, file_name: &str, observer: &dyn Observer) -> Result<Option<u8>, ConvertError> {
+        check_cancelled(observer)?;
+        let fits = self.encoded.as_ref().is_some_and(|encoded| encoded.data.len() <= self.max_bytes);
+        if fits || self.attempts.len() >= self.max_attempts as usize {
+            return Ok(None);
+        }
+        let Some(&quality) = self.qualities.last() else {
+            return Ok(None);
+        };
+        let pass = self.attempts.len() as u32 + 1;
+        observer.record(&|stats| stats.encode_passes += 1);
+        observer.progress(file_name, ProgressStage::Encoding, pass);
+        Ok(Some(quality))
+    }
+
+    /// The host's encode at the quality `next_quality` returned.
+    pub fn push(&mut self, file_name: &str, data: Vec<u8>, observer: &dyn Observer) {
+        let Some(quality) = self.qualities.pop() else {
+            return;
+        };
+        let (width, height) = self.rgba.dimensions();
+        observer.event(
+            LogLevel::Debug,
+            LogEventKind::EncodePass,
+            Some(file_name),
+            serde_json::json!({
+                "format": self.target_format,
+                "quality": quality,
+                "bytes": data.len(),
+                "max_bytes": self.max_bytes,
+                "width": width,
+                "height": height,
+            }),
+        );
+        self.attempts.push(EncodeAttempt {
+            format: self.target_format.clone(),
+            quality: Some(quality),
+            dimensions: [width, height],
+            size_kb: (data.len() / 1024) as u32,
+        });
+        self.encoded = Some(EncodedImage { data, quality: Some(quality) });
+    }
+
+    /// Validate and package the last encode as `convert` would. `encoder` names the
+    /// host encoder in `codec_info`; host encoders are not pinned to a version the way
+    /// the compiled ones are, so the output carries a warning that it may differ
+    /// between hosts. An encode over `size_kb.max` falls back to the spec's later
+    /// allowed formats as in `convert`.
+    pub fn finish(
+        mut self,
+        file_name: &str,
+        data: &[u8],
+        encoder: &str,
+        config: &ConversionConfig,
+        observer: &dyn Observer,
+    ) -> Result<ConvertedFile, ConvertError> {
+        let Some(encoded) = self.encoded.take() else {
+            return Err(ConvertError::Encode(format!("The host never encoded {}", self.target_format)));
+        };
+        if encoded.data.len() > self.max_bytes && (!config.return_best_effort || self.next_format(config).is_some()) {
+            let message = if self.qualities.is_empty() {
+                "Cannot compress image to meet size requirements".to_string()
+            } else {
+                format!("Size limits not met within max_attempts {}", self.max_attempts)
+            };
+            let error = ConvertError::SizeConstraints { message, attempts: self.attempts.clone() };
+            return self.fall_back(file_name, data, error, config, observer);
+        }
+        let (width, height) = self.rgba.dimensions();
+        observer.event(
+            LogLevel::Info,
+            LogEventKind::Encode,
+            Some(file_name),
+            serde_json::json!({
+                "format": self.target_format,
+                "bytes": encoded.data.len(),
+                "width": width,
+                "height": height,
+                "encoder": encoder,
+            }),
+        );
+        let PreparedImage { source, mut transformations, debug, colorfulness, ink, background, mut warnings, .. } = self.prepared;
+        transformations.push(Transformation::Encode {
+            format: self.target_format.clone(),
+            quality: encoded.quality,
+            bytes: encoded.data.len(),
+        });
+        warnings.push(format!(
+            "Encoded to {} by {}, which may give different bytes in other browsers or versions",
+            self.target_format, encoder
+        ));
+        let provenance = config.embed_metadata.then(|| Provenance::new(config, observer));
+        let encoded_data = embed_provenance(encoded.data, &self.target_format, provenance.as_ref(), &mut transformations, &mut warnings);
+        let converted = ConvertedData {
+            data: encoded_data,
+            dimensions: Some(DimensionsSpec { width: width as f32, height: height as f32 }),
+            geometry: Geometry::trace(source, &transformations),
+            transformations,
+            debug,
+            colorfulness,
+            ink,
+            background,
+            warnings,
+        };
+        let codec_info = CodecInfo { encoder: Some(encoder.to_string()), encoder_version: None, ..codec_info(&self.target_format) };
+        finish_conversion(file_name, data, &sha256_hex(data), &self.target_format, converted, vec![], self.attempts, codec_info, config, observer)
+    }
+
+    /// Give up on the host encoder after `error`, carrying on with the spec's next
+    /// allowed format through `convert`'s own encoders and recording the fallback. Fails
+    /// with `error` when no later format is left.
+    pub fn fall_back(
+        self,
+        file_name: &str,
+        data: &[u8],
+        error: ConvertError,
+        config: &ConversionConfig,
+        observer: &dyn Observer,
+    ) -> Result<ConvertedFile, ConvertError> {
+        let Some(next) = self.next_format(config) else {
+            return Err(error);
+        };
+        let reason = error.to_string();
+        observer.event(
+            LogLevel::Warn,
+            LogEventKind::FormatFallback,
+            Some(file_name),
+            serde_json::json!({ "from": self.target_format, "to": next, "reason": reason }),
+        );
+        let budget = EncodeBudget::new(observer, config.max_attempts);
+        budget.attempts.replace(self.attempts);
+        let fallback = FormatFallback { format: self.target_format, reason };
+        convert_to(file_name, &self.file_type, data, &sha256_hex(data), next, vec![fallback], config, &budget)
+    }
+
+    // The allowed format `convert` would move on to from this one, as in its fallback loop
+    fn next_format(&self, config: &ConversionConfig) -> Option<String> {
+        if config.target_spec.transparent_background {
+            return None;
+        }
+        let allowed = config.target_spec.allowed_image_formats();
+        let start = allowed.iter().position(|format| *format == self.target_format)? + 1;
+        allowed[start..].iter().find(|format| check_output(format).is_ok()).map(|format| format.to_string())
+    }
+}
+
+// Each tile is a separate encode and data URL, so small tile sizes on large images
+// are refused rather than left to run the tab out of memory
+const MAX_TILES: u64 = 4096;
+
+/// Resize an image input per the spec, then slice it into `tile_size`×`tile_size`
+/// tiles in row-major order. Tiles on the right and bottom edges are cut short rather
+/// than padded. Each tile is encoded under `size_kb.max` on its own (`size_kb.min` is
+/// not enforced) and carries its grid position in `tile`; debug intermediates are not
+/// attached. Fails with `InvalidConfig` when the grid would hold more than 4096 tiles.
+pub fn tile(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    tile_size: u32,
+    observer: &dyn Observer,
+) -> Result<Vec<ConvertedFile>, ConvertError> {
+    if tile_size == 0 {
+        return Err(ConvertError::InvalidConfig("tile_size must be at least 1".to_string()));
+    }
+    if !file_type.starts_with("image/") {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    }
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+    let target_format = determine_target_format(file_type, &config.target_spec)?;
+    let prepared = prepare_image(file_name, data, file_type, &target_format, config, observer)?;
+
+    let (width, height) = prepared.img.dimensions();
+    let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+    let count = columns as u64 * rows as u64;
+    if count > MAX_TILES {
+        return Err(ConvertError::InvalidConfig(format!(
+            "tile_size {} cuts the {}x{} image into {} tiles, over the {} limit",
+            tile_size, width, height, count, MAX_TILES
+        )));
+    }
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Tile,
+        Some(file_name),
+        serde_json::json!({ "width": width, "height": height, "tile_size": tile_size, "rows": rows, "columns": columns }),
+    );
+
+    let base_name = converted_filename(file_name, data, &target_format, config);
+    let (stem, extension) = base_name.rsplit_once('.').unwrap_or((&base_name, ""));
+    let mime_type = output_mime_type(&target_format, &config.target_spec);
+    let max_size_bytes = (config.target_spec.size_kb.max * 1024) as usize;
+    let input_hash = sha256_hex(data);
+    let mut tiles = Vec::with_capacity(count as usize);
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * tile_size, row * tile_size);
+            let (tile_width, tile_height) = (tile_size.min(width - x), tile_size.min(height - y));
+            let converted_name = format!("{}_r{}_c{}.{}", stem, row, column, extension);
+            let img = prepared.img.crop_imm(x, y, tile_width, tile_height);
+            let tile_crop = Transformation::Crop { x, y, width: tile_width, height: tile_height };
+            let encoded = encode_to_size(&converted_name, &img, &target_format, max_size_bytes, &config.target_spec, observer)?;
+            let dimensions = Some(DimensionsSpec { width: tile_width as f32, height: tile_height as f32 });
+            // Edge tiles can be arbitrarily small, so only the cap applies
+            if encoded.data.len() > max_size_bytes {
+                return Err(ConvertError::Validation(format!(
+                    "Tile {} too large: {}KB, maximum allowed: {}KB",
+                    converted_name,
+                    encoded.data.len() / 1024,
+                    config.target_spec.size_kb.max
+                )));
+            }
+
+            let mut transformations = prepared.transformations.clone();
+            transformations.push(Transformation::Encode {
+                format: target_format.clone(),
+                quality: encoded.quality,
+                bytes: encoded.data.len(),
+            });
+            observer.record(&|stats| stats.output_bytes += encoded.data.len() as u64);
+            let (color_type, bit_depth) = declared_layout(&encoded.data).unzip();
+            tiles.push(ConvertedFile {
+                original_name: file_name.to_string(),
+                converted_name,
+                document_type: config.document_type.clone(),
+                format: target_format.clone(),
+                size_kb: (encoded.data.len() / 1024) as u32,
+                size_bytes: encoded.data.len() as u32,
+                dimensions,
+                color_type,
+                bit_depth,
+                data_url: format!(
+                    "data:{};base64,{}",
+                    mime_type,
+                    base64::engine::general_purpose::STANDARD.encode(&encoded.data)
+                ),
+                applied_spec: config.target_spec.clone(),
+                input_hash: input_hash.clone(),
+                transformations,
+                // Dimension bands describe the whole image, so tiles are scored on size alone
+                compliance_score: compliance_score(encoded.data.len(), None, &config.target_spec),
+                compliant: true,
+                violations: vec![],
+                codec_info: codec_info(&target_format),
+                format_fallbacks: vec![],
+                warnings: prepared.warnings.clone(),
+                geometry: Geometry::trace(prepared.source, &[&prepared.transformations[..], &[tile_crop]].concat()),
+                colorfulness: prepared.colorfulness,
+                ink: prepared.ink.clone(),
+                background: prepared.background.clone(),
+                debug: vec![],
+                tile: Some(TilePosition { row, column, x, y }),
+                cache_hit: false,
+                path: None,
+            });
+        }
+    }
+    Ok(tiles)
+}
+
+/// Convert a transparent image input into two outputs from one decode: `preview`, a JPEG
+/// with the transparent pixels composited onto white (`background.required` when set),
+/// and `original`, a PNG that keeps the alpha channel. Each is sized and validated against
+/// the spec like a `convert` output, whether or not the spec lists JPEG and PNG.
+pub fn convert_with_preview(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<PreviewPair, ConvertError> {
+    if !file_type.starts_with("image/") {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    }
+    if config.target_spec.png_profile.is_some_and(|profile| !profile.keeps_alpha()) {
+        return Err(ConvertError::InvalidConfig("png_profile drops the alpha channel the original PNG keeps".to_string()));
+    }
+    if let Some(color_type) = config.target_spec.output_color_type {
+        return Err(ConvertError::InvalidConfig(format!(
+            "output_color_type {} cannot hold both the JPEG preview and the transparent PNG original",
+            color_type.name()
+        )));
+    }
+    if config.target_spec.required_layout().is_some() {
+        return Err(ConvertError::InvalidConfig(
+            "required_color_type and required_bit_depth cannot hold both the JPEG preview and the transparent PNG original".to_string(),
+        ));
+    }
+    check_output("JPEG")?;
+    check_output("PNG")?;
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+
+    let original = prepare_image(file_name, data, file_type, "PNG", config, observer)?;
+    let color = pad_color(&config.target_spec);
+    let preview = PreparedImage {
+        img: flatten(&original.img, color),
+        source: original.source,
+        transformations: [
+            original.transformations.clone(),
+            vec![Transformation::Flatten { color: format!("#{:02X}{:02X}{:02X}", color[0], color[1], color[2]) }],
+        ]
+        .concat(),
+        debug: vec![],
+        colorfulness: original.colorfulness,
+        ink: original.ink.clone(),
+        background: original.background.clone(),
+        warnings: original.warnings.clone(),
+    };
+    let input_hash = sha256_hex(data);
+    let mut outputs = [("JPEG", preview), ("PNG", original)].into_iter().map(|(format, prepared)| {
+        let budget = EncodeBudget::new(observer, config.max_attempts);
+        let converted = encode_prepared(file_name, prepared, format, config, &budget).map_err(|error| budget.explain(error))?;
+        finish_conversion(file_name, data, &input_hash, format, converted, vec![], budget.attempts.take(), codec_info(format), config, observer)
+    });
+    let preview = outputs.next().unwrap()?;
+    let original = outputs.next().unwrap()?;
+    Ok(PreviewPair { preview, original })
+}
+
+// Thumbnails are for looking at, not for size limits
+const THUMBNAIL_QUALITY: u8 = 80;
+
+/// Convert an image input as `convert` does, and from the same decode a `thumbnail` of the
+/// prepared image no larger than `thumb_max_edge` on either side, in the output format.
+/// The thumbnail shows the output's orientation, crop and colors; it is never enlarged
+/// and takes no part in validation. Unlike `convert`, no later allowed format is tried
+/// when the first cannot fit the size cap.
+pub fn convert_with_thumbnail(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    thumb_max_edge: u32,
+    observer: &dyn Observer,
+) -> Result<ThumbnailPair, ConvertError> {
+    if thumb_max_edge == 0 {
+        return Err(ConvertError::InvalidConfig("thumb_max_edge must be at least 1".to_string()));
+    }
+    if !file_type.starts_with("image/") {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    }
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+    let target_format = determine_target_format(file_type, &config.target_spec)?;
+    let prepared = prepare_image(file_name, data, file_type, &target_format, config, observer)?;
+
+    let (width, height) = prepared.img.dimensions();
+    let small = if width.max(height) > thumb_max_edge { prepared.img.thumbnail(thumb_max_edge, thumb_max_edge) } else { prepared.img.clone() };
+    let thumbnail = Thumbnail {
+        data_url: format!(
+            "data:{};base64,{}",
+            output_mime_type(&target_format, &config.target_spec),
+            base64::engine::general_purpose::STANDARD.encode(encode_once(&small, &target_format, THUMBNAIL_QUALITY, &config.target_spec)?)
+        ),
+        width: small.width(),
+        height: small.height(),
+    };
+
+    let budget = EncodeBudget::new(observer, config.max_attempts);
+    let converted = encode_prepared(file_name, prepared, &target_format, config, &budget).map_err(|error| budget.explain(error))?;
+    let converted = finish_conversion(file_name, data, &sha256_hex(data), &target_format, converted, vec![], budget.attempts.take(), codec_info(&target_format), config, observer)?;
+    Ok(ThumbnailPair { converted, thumbnail })
+}
+
+// `img` composited over an opaque `color` canvas
+fn flatten(img: &image::DynamicImage, color: [u8; 3]) -> image::DynamicImage {
+    if !img.color().has_alpha() {
+        return img.clone();
+    }
+    let (width, height) = img.dimensions();
+    let mut canvas = image::RgbaImage::from_pixel(width, height, image::Rgba([color[0], color[1], color[2], 255]));
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), 0, 0);
+    image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(canvas).to_rgb8())
+}
+
+// Never shrink below this edge while searching for a size target
+const MIN_TARGET_EDGE: u32 = 16;
+
+/// Convert an image input as `convert` does, but size the output by `target_bytes`
+/// instead of `size_kb`: the highest JPEG quality that fits, and when even quality 1 is
+/// too large (or the target is PNG) the image is scaled down by a fifth at a time until
+/// it fits. For size sliders, where `size_bytes` of the result shows what was achieved.
+pub fn convert_to_target_bytes(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+    target_bytes: usize,
+    observer: &dyn Observer,
+) -> Result<ConvertedFile, ConvertError> {
+    if !file_type.starts_with("image/") {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    }
+    observer.record(&|stats| stats.input_bytes += data.len() as u64);
+    check_cancelled(observer)?;
+    // The target replaces the size band, so results are scored and reported against it
+    let spec = DocumentSpec {
+        size_kb: SizeSpec { min: None, max: target_bytes.div_ceil(1024) as u32 },
+        ..config.target_spec.clone()
+    };
+    let target_format = determine_target_format(file_type, &spec)?;
+    let budget = EncodeBudget::new(observer, config.max_attempts);
+    let prepared = prepare_image(file_name, data, file_type, &target_format, config, observer)?;
+    let source = prepared.source;
+    let mut transformations = prepared.transformations;
+    let mut img = prepared.img;
+    let warnings = prepared.warnings;
+
+    let encoded = loop {
+        let attempt = encode_to_target(file_name, &img, &target_format, target_bytes, &spec, &budget);
+        if let Some(encoded) = attempt.map_err(|error| budget.explain(error))? {
+            break encoded;
+        }
+        let (width, height) = img.dimensions();
+        if width.min(height) <= MIN_TARGET_EDGE {
+            let error = ConvertError::Encode(format!("Cannot compress image to {} bytes", target_bytes));
+            return Err(budget.explain(error));
+        }
+        let (to_width, to_height) = ((width * 4 / 5).max(1), (height * 4 / 5).max(1));
+        observer.event(
+            LogLevel::Info,
+            LogEventKind::Resize,
+            Some(file_name),
+            serde_json::json!({ "from": [width, height], "to": [to_width, to_height] }),
+        );
+        img = resample(&img, to_width, to_height, &spec);
+        transformations.push(Transformation::Resize { from: [width, height], to: [to_width, to_height] });
+    };
+
+    let (width, height) = img.dimensions();
+    let dimensions = Some(DimensionsSpec { width: width as f32, height: height as f32 });
+    transformations.push(Transformation::Encode {
+        format: target_format.clone(),
+        quality: encoded.quality,
+        bytes: encoded.data.len(),
+    });
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Converted,
+        Some(file_name),
+        serde_json::json!({
+            "format": target_format,
+            "input_bytes": data.len(),
+            "output_bytes": encoded.data.len(),
+            "target_bytes": target_bytes,
+        }),
+    );
+    observer.record(&|stats| stats.output_bytes += encoded.data.len() as u64);
+    let (color_type, bit_depth) = declared_layout(&encoded.data).unzip();
+    Ok(ConvertedFile {
+        original_name: file_name.to_string(),
+        converted_name: converted_filename(file_name, data, &target_format, config),
+        document_type: config.document_type.clone(),
+        format: target_format.clone(),
+        size_kb: (encoded.data.len() / 1024) as u32,
+        size_bytes: encoded.data.len() as u32,
+        compliance_score: compliance_score(encoded.data.len(), dimensions.as_ref(), &spec),
+        compliant: true,
+        violations: vec![],
+        codec_info: codec_info(&target_format),
+        format_fallbacks: vec![],
+        warnings,
+        geometry: Geometry::trace(source, &transformations),
+        color_type,
+        bit_depth,
+        dimensions,
+        data_url: format!(
+            "data:{};base64,{}",
+            output_mime_type(&target_format, &spec),
+            base64::engine::general_purpose::STANDARD.encode(&encoded.data)
+        ),
+        applied_spec: spec,
+        input_hash: sha256_hex(data),
+        transformations,
+        colorfulness: prepared.colorfulness,
+        ink: prepared.ink,
+        background: prepared.background,
+        debug: prepared.debug,
+        tile: None,
+        cache_hit: false,
+        path: None,
+    })
+}
+
+/// Describe what `convert` would do with an input, reading only the image header.
+pub fn plan(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    config: &ConversionConfig,
+) -> Result<ConversionPlan, ConvertError> {
+    let spec = &config.target_spec;
+    let target_format = determine_target_format(file_type, spec)?;
+    let max_size_bytes = (spec.size_kb.max * 1024) as usize;
+    let mut transformations = Vec::new();
+    let mut notes = Vec::new();
+
+    let (source_dimensions, target_dimensions) = if file_type.starts_with("image/") {
+        check_input(data, file_type)?;
+        let (width, height) = image::io::Reader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .map_err(|e| ConvertError::Decode(format!("Failed to read image header: {}", e)))?
+            .into_dimensions()
+            .map_err(|e| ConvertError::Decode(format!("Failed to read image header: {}", e)))?;
+        if config.assume_source_profile != SourceProfile::Srgb {
+            transformations.push(Transformation::ColorConvert { from: config.assume_source_profile });
+        }
+        if spec.document_scan.is_some() {
+            notes.push("document_scan is decided after decoding and may flatten and crop the source first".to_string());
+        }
+        if spec.auto_rotate.is_some() {
+            notes.push("auto_rotate is decided after decoding and may turn the source a quarter or half turn first".to_string());
+        }
+        if spec.deskew {
+            notes.push("deskew is decided after decoding and may straighten the source and crop its corners first".to_string());
+        }
+        if spec.scanner_edges.is_some() {
+            notes.push("scanner_edges is decided after decoding and may cut dark frames off the sides of the source first".to_string());
+        }
+        if spec.gamma.is_some_and(|gamma| gamma != 1.0) {
+            notes.push("gamma is applied after decoding and changes the tones, not the size".to_string());
+        }
+        if spec.color_mode.is_some_and(|mode| mode != ColorMode::Any) {
+            notes.push("color_mode is checked after decoding and may reject the source or convert it to grayscale".to_string());
+        }
+        if let Some(background) = spec.background.as_ref().filter(|background| background.auto_replace) {
+            notes.push(format!("background is checked after decoding and may be repainted in {}", background.required));
+        }
+        if spec.auto_trim.is_some() {
+            notes.push("auto_trim is decided after decoding and may shrink the source first".to_string());
+        }
+        if spec.auto_contrast.is_some() {
+            notes.push("auto_contrast is applied after decoding, on the trimmed source before resizing".to_string());
+        }
+        if let Some(Ok(frames)) = animation_passthrough(data, &target_format, spec) {
+            notes.push(format!("the animated WebP already meets size_kb, so it is returned unchanged with its {} frames", frames));
+        }
+        if spec.flexible_dimensions {
+            notes.push("flexible_dimensions may rescale the image while encoding to land inside size_kb".to_string());
+        }
+        let (cropped_width, cropped_height) = match square_crop(width, height, spec) {
+            Some(crop @ Transformation::Crop { width, height, .. }) => {
+                transformations.push(crop);
+                (width, height)
+            }
+            _ => (width, height),
+        };
+        let (target_width, target_height) = match &spec.canvas {
+            Some(canvas) => (canvas.width, canvas.height),
+            None => calculate_target_dimensions(cropped_width, cropped_height, spec)?,
+        };
+        transformations.extend(layout_steps((cropped_width, cropped_height), (target_width, target_height), spec));
+        if let Some(levels) = spec.posterize_levels {
+            transformations.push(Transformation::Posterize { levels });
+        }
+        if spec.despeckle.is_some() {
+            notes.push("despeckle is decided after resizing and may paint over isolated specks".to_string());
+        }
+        if spec.transparent_background && target_format == "PNG" && looks_like_signature(&config.document_type) {
+            transformations.push(Transformation::TransparentBackground);
+        }
+        let (target_width, target_height) = if needs_rotation(target_width, target_height, spec.print_orientation) {
+            transformations.push(Transformation::Rotate { degrees: 90 });
+            (target_height, target_width)
+        } else {
+            (target_width, target_height)
+        };
+        transformations.push(Transformation::Encode {
+            format: target_format.clone(),
+            quality: None,
+            bytes: max_size_bytes,
+        });
+        (Some([width, height]), Some([target_width, target_height]))
+    } else if file_type == "application/pdf" {
+        if data.len() > max_size_bytes {
+            notes.push(match spec.pdf_rasterize_dpi {
+                Some(dpi) => format!(
+                    "PDF is {}KB, above {}KB; its pages will be rasterized at {} DPI if they are scans, losing selectable text",
+                    data.len() / 1024,
+                    spec.size_kb.max,
+                    dpi
+                ),
+                None => format!("PDF is {}KB and will be rejected above {}KB", data.len() / 1024, spec.size_kb.max),
+            });
+        }
+        match normalize_rotation(data) {
+            Ok(Some((_, rotated))) => {
+                transformations.extend(rotated.into_iter().map(|(page, degrees)| Transformation::PageRotate { page, degrees }))
+            }
+            Ok(None) => {}
+            Err(reason) => notes.push(format!("Rotated pages will be left for the viewer to turn: {}", reason)),
+        }
+        if spec.pdf_a {
+            if let Err(reason) = rebuild_pdf_a(data) {
+                notes.push(ConvertError::PdfA(reason).to_string());
+            }
+        }
+        if let Some(colors) = spec.max_pdf_colors {
+            match scanned_pages(data) {
+                Ok(pages) => transformations.push(Transformation::ReduceColors { pages: pages.len() as u32, colors }),
+                Err(reason) => notes.push(format!("Cannot reduce the PDF to {} colors: {}", colors, reason)),
+            }
+        }
+        (None, None)
+    } else {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    };
+
+    Ok(ConversionPlan {
+        original_name: file_name.to_string(),
+        converted_name: converted_filename(file_name, data, &target_format, config),
+        source_format: file_type.to_string(),
+        source_dimensions,
+        source_orientation: exif_orientation(data),
+        target_format,
+        target_dimensions,
+        max_size_kb: spec.size_kb.max,
+        transformations,
+        notes,
+    })
+}
+
+fn convert_image(
+    file_name: &str,
+    data: &[u8],
+    original_format: &str,
+    target_format: &str,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<ConvertedData, ConvertError> {
+    observer.debug(LogEventKind::Decode, Some(file_name), format!("Converting image from {} to {} with specifications", original_format, target_format));
+    let prepared = prepare_image(file_name, data, original_format, target_format, config, observer)?;
+    encode_prepared(file_name, prepared, target_format, config, observer)
+}
+
+// Encodes a prepared image under the size cap, or inside the band for `flexible_dimensions`
+fn encode_prepared(
+    file_name: &str,
+    prepared: PreparedImage,
+    target_format: &str,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<ConvertedData, ConvertError> {
+    let PreparedImage { img: mut processed_img, source, mut transformations, debug, colorfulness, ink, background, mut warnings } = prepared;
+
+    // The provenance goes in after encoding, so the size loop leaves room for it
+    let provenance = config.embed_metadata.then(|| Provenance::new(config, observer));
+    let reserved = provenance.as_ref().and_then(|provenance| provenance.overhead(target_format)).unwrap_or(0);
+
+    // Convert to target format with quality optimization
+    let max_size_bytes = ((config.target_spec.size_kb.max * 1024) as usize).saturating_sub(reserved);
+    let result = if config.target_spec.flexible_dimensions {
+        encode_in_band(file_name, &processed_img, target_format, &config.target_spec, reserved, observer).map(|(img, encoded, resize)| {
+            processed_img = img;
+            transformations.extend(resize);
+            encoded
+        })
+    } else {
+        encode_to_size(file_name, &processed_img, target_format, max_size_bytes, &config.target_spec, observer)
+    };
+    let (target_width, target_height) = processed_img.dimensions();
+    let encoded = match result {
+        // The quality loop gave up; keep an encode at its lowest quality for review
+        Err(ConvertError::Encode(message)) if config.return_best_effort => {
+            observer.debug(LogEventKind::Encode, Some(file_name), format!("{}, keeping the lowest quality as a best effort", message));
+            let quality = config.target_spec.quality_ladder.iter().flatten().min().copied().unwrap_or(10);
+            let data = encode_once(&processed_img, target_format, quality, &config.target_spec)?;
+            EncodedImage { data, quality: Some(quality) }
+        }
+        result => result?,
+    };
+    let output = encoded.data;
+
+    let final_dimensions = Some(DimensionsSpec {
+        width: target_width as f32,
+        height: target_height as f32,
+    });
+
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Encode,
+        Some(file_name),
+        serde_json::json!({
+            "format": target_format,
+            "bytes": output.len(),
+            "width": target_width,
+            "height": target_height,
+        }),
+    );
+    transformations.push(Transformation::Encode {
+        format: target_format.to_string(),
+        quality: encoded.quality,
+        bytes: output.len(),
+    });
+    let output = embed_provenance(output, target_format, provenance.as_ref(), &mut transformations, &mut warnings);
+    Ok(ConvertedData {
+        data: output,
+        dimensions: final_dimensions,
+        geometry: Geometry::trace(source, &transformations),
+        transformations,
+        debug,
+        colorfulness,
+        ink,
+        background,
+        warnings,
+    })
+}
+
+// `data` with the provenance added for `embed_metadata`, or unchanged with a warning
+// saying why it could not be
+fn embed_provenance(
+    data: Vec<u8>,
+    format: &str,
+    provenance: Option<&Provenance>,
+    transformations: &mut Vec<Transformation>,
+    warnings: &mut Vec<String>,
+) -> Vec<u8> {
+    match provenance.map(|provenance| provenance.embed(&data, format)) {
+        Some(Ok(embedded)) => {
+            transformations.push(Transformation::EmbedMetadata { bytes: embedded.len() - data.len() });
+            embedded
+        }
+        Some(Err(reason)) => {
+            warnings.push(format!("Metadata was not embedded: {}", reason));
+            data
+        }
+        None => data,
+    }
+}
+
+// Decodes and runs every pixel stage up to (not including) encoding
+fn prepare_image(
+    file_name: &str,
+    data: &[u8],
+    file_type: &str,
+    target_format: &str,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<PreparedImage, ConvertError> {
+    let spec = &config.target_spec;
+    let debug_intermediates = config.debug_intermediates;
+
+    check_decoded_size(data, config.max_decoded_pixels)?;
+    if let Some(memory_spec) = &config.memory_check {
+        check_decode_memory(data, memory_spec, observer)?;
+    }
+
+    // Load image
+    observer.progress(file_name, ProgressStage::Decoding, 0);
+    check_input(data, file_type)?;
+    let img = image::load_from_memory(data)
+        .map_err(|e| ConvertError::Decode(format!("Failed to load image: {}", e)))?;
+
+    let (decoded_width, decoded_height) = img.dimensions();
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Decode,
+        Some(file_name),
+        serde_json::json!({ "width": decoded_width, "height": decoded_height }),
+    );
+    let lost = lost_features(data, &img, target_format, config.target_spec.png_layout());
+    if !lost.is_empty() {
+        if config.on_feature_loss == FeatureLossPolicy::Fail {
+            return Err(ConvertError::FeatureLoss { target_format: target_format.to_string(), lost });
+        }
+        observer.event(
+            LogLevel::Warn,
+            LogEventKind::FeatureLoss,
+            Some(file_name),
+            serde_json::json!({ "target_format": target_format, "lost": lost }),
+        );
+    }
+    let mut transformations = Vec::new();
+    let mut debug = Vec::new();
+    capture_intermediate(debug_intermediates, &mut debug, "decoded", &img, None);
+
+    // Bring untagged wide-gamut inputs into sRGB before any pixel-level processing
+    let img = match config.assume_source_profile {
+        SourceProfile::Srgb => img,
+        profile => {
+            observer.event(
+                LogLevel::Info,
+                LogEventKind::ColorConvert,
+                Some(file_name),
+                serde_json::json!({ "from": profile, "to": SourceProfile::Srgb }),
+            );
+            let converted = convert_to_srgb(img, profile);
+            let transformation = Transformation::ColorConvert { from: profile };
+            capture_intermediate(debug_intermediates, &mut debug, "color_converted", &converted, Some(&transformation));
+            transformations.push(transformation);
+            converted
+        }
+    };
+
+    // Correct the scanner's tone curve first, so every later step sees the page as it was
+    let img = match spec.gamma.filter(|&gamma| gamma != 1.0) {
+        Some(gamma) => {
+            observer.event(LogLevel::Info, LogEventKind::Gamma, Some(file_name), serde_json::json!({ "gamma": gamma }));
+            let corrected = adjust_gamma(img, gamma);
+            let transformation = Transformation::Gamma { gamma };
+            capture_intermediate(debug_intermediates, &mut debug, "gamma", &corrected, Some(&transformation));
+            transformations.push(transformation);
+            corrected
+        }
+        None => img,
+    };
+
+    // Judge color on sRGB pixels, before trimming can crop a colored border away
+    let mut measured_colorfulness = None;
+    let img = match spec.color_mode {
+        Some(mode) => {
+            let measured = colorfulness(&img);
+            measured_colorfulness = Some(measured);
+            let is_color = measured > GRAYSCALE_MAX_COLORFULNESS;
+            let matches = match mode {
+                ColorMode::Color => is_color,
+                ColorMode::Grayscale => !is_color,
+                ColorMode::Any => true,
+            };
+            observer.event(
+                if matches { LogLevel::Info } else { LogLevel::Warn },
+                LogEventKind::ColorCheck,
+                Some(file_name),
+                serde_json::json!({ "required": mode, "colorfulness": measured, "matches": matches }),
+            );
+            match spec.on_color_mismatch {
+                _ if matches => img,
+                ColorMismatchPolicy::Fail => {
+                    return Err(ConvertError::Validation(format!(
+                        "Image is {} (colorfulness {:.1}) but the spec requires {}",
+                        if is_color { "in color" } else { "grayscale" },
+                        measured,
+                        if is_color { "grayscale" } else { "color" }
+                    )));
+                }
+                ColorMismatchPolicy::Warn => {
+                    img
+                }
+                ColorMismatchPolicy::Convert => {
+                    observer.debug(LogEventKind::ColorCheck, Some(file_name), format!("Converting image with colorfulness {:.1} to grayscale", measured));
+                    let gray = img.grayscale();
+                    capture_intermediate(debug_intermediates, &mut debug, "grayscale", &gray, Some(&Transformation::Grayscale));
+                    transformations.push(Transformation::Grayscale);
+                    gray
+                }
+            }
+        }
+        None => img,
+    };
+
+    // Signatures in the wrong pen are a common late rejection
+    let ink = spec.ink_color.as_ref().and_then(|ink_spec| detect_ink(&img, ink_spec));
+    if let (Some(ink_spec), Some(ink)) = (&spec.ink_color, &ink) {
+        observer.event(
+            if ink.allowed { LogLevel::Info } else { LogLevel::Warn },
+            LogEventKind::InkCheck,
+            Some(file_name),
+            serde_json::json!(ink),
+        );
+        if !ink.allowed && ink_spec.on_mismatch == ColorMismatchPolicy::Fail {
+            return Err(ConvertError::InkColor {
+                detected: ink.color.clone(),
+                rgb: ink.rgb,
+                allowed: ink_spec.allowed.iter().filter_map(|color| color.range()).map(|range| range.name).collect(),
+            });
+        }
+    }
+
+    // Flatten a photographed page and drop the desk around it
+    let img = match spec.document_scan.as_ref().and_then(|scan_spec| detect_document(&img, scan_spec)) {
+        Some(corners) => {
+            let flattened = warp_perspective(&img, &corners);
+            let (width, height) = flattened.dimensions();
+            observer.event(
+                LogLevel::Info,
+                LogEventKind::DocumentScan,
+                Some(file_name),
+                serde_json::json!({ "corners": corners, "width": width, "height": height }),
+            );
+            let transformation = Transformation::Perspective { corners, width, height };
+            capture_intermediate(debug_intermediates, &mut debug, "flattened", &flattened, Some(&transformation));
+            transformations.push(transformation);
+            flattened
+        }
+        None => img,
+    };
+
+    // Paper turned under a level phone carries no EXIF rotation, so the writing is read
+    let mut warnings = Vec::new();
+    let orientation = spec.auto_rotate.as_ref().and_then(|_| detect_orientation(&img, expected_landscape(spec)));
+    let img = match (&spec.auto_rotate, orientation) {
+        (Some(rotate_spec), Some(Orientation { degrees, confidence })) if degrees != 0 => {
+            let confident = confidence >= rotate_spec.min_confidence;
+            observer.event(
+                if confident { LogLevel::Info } else { LogLevel::Warn },
+                LogEventKind::Rotate,
+                Some(file_name),
+                serde_json::json!({ "degrees": degrees, "confidence": confidence, "applied": confident }),
+            );
+            if confident {
+                let rotated = match degrees {
+                    90 => img.rotate90(),
+                    180 => img.rotate180(),
+                    _ => img.rotate270(),
+                };
+                let transformation = Transformation::Rotate { degrees };
+                capture_intermediate(debug_intermediates, &mut debug, "upright", &rotated, Some(&transformation));
+                transformations.push(transformation);
+                rotated
+            } else {
+                warnings.push(format!(
+                    "The writing may be turned {}° from upright, but at confidence {:.2} the image was left as it is",
+                    degrees, confidence
+                ));
+                img
+            }
+        }
+        _ => img,
+    };
+
+    // Straighten a page photographed slightly off level, before its borders are judged
+    let img = match spec.deskew.then(|| detect_skew(&img)).flatten() {
+        Some(degrees) if degrees.abs() >= MIN_DESKEW_ANGLE => {
+            let max_angle = spec.deskew_max_angle.unwrap_or(DEFAULT_DESKEW_MAX_ANGLE);
+            let applied = degrees.abs() <= max_angle;
+            observer.event(
+                if applied { LogLevel::Info } else { LogLevel::Warn },
+                LogEventKind::Deskew,
+                Some(file_name),
+                serde_json::json!({ "degrees": degrees, "max_angle": max_angle, "applied": applied }),
+            );
+            if applied {
+                let straightened = deskew(&img, degrees);
+                let (width, height) = straightened.dimensions();
+                let transformation = Transformation::Deskew { degrees, width, height };
+                capture_intermediate(debug_intermediates, &mut debug, "deskewed", &straightened, Some(&transformation));
+                transformations.push(transformation);
+                straightened
+            } else {
+                warnings.push(format!(
+                    "The page looks tilted by {:.1}°, more than the {}° deskew_max_angle, so it was left as it is",
+                    degrees, max_angle
+                ));
+                img
+            }
+        }
+        _ => img,
+    };
+
+    // Cut the scanner bed and skew wedges off a scan, so trimming sees only the page
+    let img = match &spec.scanner_edges {
+        Some(edges_spec) => {
+            let (width, height) = img.dimensions();
+            let Insets { left, top, right, bottom } = detect_scanner_edges(&img, edges_spec.tolerance);
+            let widest = (left.max(right) as f32 / width as f32).max(top.max(bottom) as f32 / height as f32);
+            if widest == 0.0 {
+                img
+            } else {
+                let applied = widest <= edges_spec.max_crop;
+                observer.event(
+                    if applied { LogLevel::Info } else { LogLevel::Warn },
+                    LogEventKind::ScannerEdges,
+                    Some(file_name),
+                    serde_json::json!({ "left": left, "top": top, "right": right, "bottom": bottom, "applied": applied }),
+                );
+                if applied {
+                    let cut = img.crop_imm(left, top, width - left - right, height - top - bottom);
+                    let transformation = Transformation::RemoveEdges { left, top, right, bottom };
+                    capture_intermediate(debug_intermediates, &mut debug, "edges_removed", &cut, Some(&transformation));
+                    transformations.push(transformation);
+                    cut
+                } else {
+                    warnings.push(format!(
+                        "A frame reaching {:.0}% into the scan was found, more than the {:.0}% scanner_edges.max_crop, so it was left as it is",
+                        widest * 100.0,
+                        edges_spec.max_crop * 100.0
+                    ));
+                    img
+                }
+            }
+        }
+        None => img,
+    };
+
+    // Crop away uniform scanner/background borders before sizing
+    let img = match &spec.auto_trim {
+        Some(trim_spec) => match trim_borders(&img, trim_spec) {
+            Some((x, y, width, height)) => {
+                observer.event(
+                    LogLevel::Info,
+                    LogEventKind::Trim,
+                    Some(file_name),
+                    serde_json::json!({ "x": x, "y": y, "width": width, "height": height }),
+                );
+                let trimmed = img.crop_imm(x, y, width, height);
+                let transformation = Transformation::Trim { x, y, width, height };
+                capture_intermediate(debug_intermediates, &mut debug, "trimmed", &trimmed, Some(&transformation));
+                transformations.push(transformation);
+                trimmed
+            }
+            None => img,
+        },
+        None => img,
+    };
+
+    // Restore contrast on the final frame, so trimmed borders do not skew the histogram, and
+    // before the backdrop is judged and repainted in its required color
+    let img = match spec.auto_contrast {
+        Some(mode) => {
+            let (contrasted, fields) = match mode {
+                AutoContrast::Stretch => match stretch_contrast(img) {
+                    (stretched, Some((low, high))) => (stretched, Some(serde_json::json!({ "mode": mode, "low": low, "high": high }))),
+                    (unchanged, None) => (unchanged, None),
+                },
+                AutoContrast::Clahe => {
+                    let (tile_size, clip_limit) = spec
+                        .clahe
+                        .as_ref()
+                        .map_or((default_clahe_tile_size(), default_clahe_clip_limit()), |clahe| (clahe.tile_size, clahe.clip_limit));
+                    if looks_like_photo(&config.document_type) {
+                        warnings.push(format!(
+                            "auto_contrast \"clahe\" evens out each part of a {} on its own and can shift skin tones; \"stretch\" keeps them",
+                            config.document_type
+                        ));
+                    }
+                    let fields = serde_json::json!({ "mode": mode, "tile_size": tile_size, "clip_limit": clip_limit });
+                    (clahe(img, tile_size, clip_limit), Some(fields))
+                }
+            };
+            if let Some(fields) = fields {
+                observer.event(LogLevel::Info, LogEventKind::AutoContrast, Some(file_name), fields);
+                let transformation = Transformation::AutoContrast { mode };
+                capture_intermediate(debug_intermediates, &mut debug, "contrast", &contrasted, Some(&transformation));
+                transformations.push(transformation);
+            }
+            contrasted
+        }
+        None => img,
+    };
+
+    // Check the backdrop once the frame is final, and repaint it at full resolution so the
+    // resize smooths the feathered edge
+    let (img, background) = match &spec.background {
+        Some(background_spec) => {
+            let (replaced, report) = replace_background(img, background_spec);
+            observer.event(
+                if report.matches || report.replaced { LogLevel::Info } else { LogLevel::Warn },
+                LogEventKind::Background,
+                Some(file_name),
+                serde_json::json!(report),
+            );
+            if report.replaced {
+                let transformation = Transformation::ReplaceBackground { color: background_spec.required.clone() };
+                capture_intermediate(debug_intermediates, &mut debug, "background", &replaced, Some(&transformation));
+                transformations.push(transformation);
+            }
+            (replaced, Some(report))
+        }
+        None => (img, None),
+    };
+
+    // Square output is cut to shape rather than stretched
+    let img = match square_crop(img.width(), img.height(), spec) {
+        Some(transformation @ Transformation::Crop { x, y, width, height }) => {
+            observer.debug(LogEventKind::Resize, Some(file_name), format!("Cropping {}x{} image to a {}px square", img.width(), img.height(), width));
+            let cropped = img.crop_imm(x, y, width, height);
+            capture_intermediate(debug_intermediates, &mut debug, "cropped", &cropped, Some(&transformation));
+            transformations.push(transformation);
+            cropped
+        }
+        _ => img,
+    };
+    let (original_width, original_height) = img.dimensions();
+
+    // Calculate target dimensions based on specifications; a canvas fixes them outright
+    let (target_width, target_height) = match &spec.canvas {
+        Some(canvas) => (canvas.width, canvas.height),
+        None => calculate_target_dimensions(original_width, original_height, spec)?,
+    };
+
+    observer.debug(LogEventKind::Resize, Some(file_name), format!("Target dimensions: {}x{}", target_width, target_height));
+
+    // Judge the detail the source brings to the print size; resizing up adds pixels but
+    // not resolution
+    let physical_size = spec.physical_size(original_width, original_height);
+    if let (Some(min_dpi), Some((width, height, units_per_inch))) = (spec.min_effective_dpi, physical_size) {
+        let effective_dpi =
+            (original_width as f32 / width).min(original_height as f32 / height) * units_per_inch;
+        let sufficient = effective_dpi >= min_dpi as f32;
+        observer.event(
+            if sufficient { LogLevel::Info } else { LogLevel::Warn },
+            LogEventKind::ResolutionCheck,
+            Some(file_name),
+            serde_json::json!({ "effective_dpi": effective_dpi, "min_effective_dpi": min_dpi }),
+        );
+        if !sufficient && spec.on_low_resolution == ColorMismatchPolicy::Fail {
+            return Err(ConvertError::Validation(format!(
+                "Image has {:.0} DPI of detail at the required print size, minimum required: {} DPI",
+                effective_dpi, min_dpi
+            )));
+        }
+    }
+
+    // Resize image if necessary
+    check_cancelled(observer)?;
+    observer.progress(file_name, ProgressStage::Resizing, 0);
+    let mut processed_img = img;
+    for transformation in layout_steps((original_width, original_height), (target_width, target_height), spec) {
+        processed_img = match &transformation {
+            Transformation::Resize { from, to } => {
+                observer.event(
+                    LogLevel::Info,
+                    LogEventKind::Resize,
+                    Some(file_name),
+                    serde_json::json!({ "from": from, "to": to }),
+                );
+                let resized = if spec.integer_scaling {
+                    processed_img.resize_exact(to[0], to[1], image::imageops::FilterType::Nearest)
+                } else {
+                    resample(&processed_img, to[0], to[1], spec)
+                };
+                capture_intermediate(debug_intermediates, &mut debug, "resized", &resized, Some(&transformation));
+                resized
+            }
+            &Transformation::Crop { x, y, width, height } => {
+                observer.debug(LogEventKind::Resize, Some(file_name), format!("Cropping resized image to {}x{}", width, height));
+                let cropped = processed_img.crop_imm(x, y, width, height);
+                capture_intermediate(debug_intermediates, &mut debug, "cropped", &cropped, Some(&transformation));
+                cropped
+            }
+            Transformation::Pad { left, top, right, bottom, color } => {
+                let (width, height) = (processed_img.width() + left + right, processed_img.height() + top + bottom);
+                observer.debug(LogEventKind::Resize, Some(file_name), format!("Padding resized image to {}x{} with {}", width, height, color));
+                let color = parse_rgb(color).unwrap_or_else(|| pad_color(spec));
+                let padded = pad(&processed_img, width, height, *left, *top, color);
+                capture_intermediate(debug_intermediates, &mut debug, "padded", &padded, Some(&transformation));
+                padded
+            }
+            Transformation::Border { width, color } => {
+                observer.debug(LogEventKind::Resize, Some(file_name), format!("Framing image with a {}px {} border", width, color));
+                let color = parse_rgb(color).unwrap_or([0, 0, 0]);
+                let framed = pad(&processed_img, processed_img.width() + 2 * width, processed_img.height() + 2 * width, *width, *width, color);
+                capture_intermediate(debug_intermediates, &mut debug, "bordered", &framed, Some(&transformation));
+                framed
+            }
+            _ => processed_img,
+        };
+        transformations.push(transformation);
+    }
+
+    // Reduce each channel to a fixed number of levels for quantized submissions
+    let processed_img = match spec.posterize_levels {
+        Some(levels) => {
+            observer.event(
+                LogLevel::Info,
+                LogEventKind::Posterize,
+                Some(file_name),
+                serde_json::json!({ "levels": levels }),
+            );
+            let posterized = posterize(processed_img, levels);
+            let transformation = Transformation::Posterize { levels };
+            capture_intermediate(debug_intermediates, &mut debug, "posterized", &posterized, Some(&transformation));
+            transformations.push(transformation);
+            posterized
+        }
+        None => processed_img,
+    };
+
+    // Dust is judged at the output size, where it would be encoded
+    let processed_img = match &spec.despeckle {
+        Some(despeckle_spec) => {
+            let (despeckled, specks) = despeckle(processed_img, despeckle_spec.max_size);
+            if specks > 0 {
+                observer.event(
+                    LogLevel::Info,
+                    LogEventKind::Despeckle,
+                    Some(file_name),
+                    serde_json::json!({ "specks": specks, "max_size": despeckle_spec.max_size }),
+                );
+                let transformation = Transformation::Despeckle { specks: specks as u32 };
+                capture_intermediate(debug_intermediates, &mut debug, "despeckled", &despeckled, Some(&transformation));
+                transformations.push(transformation);
+            }
+            despeckled
+        }
+        None => processed_img,
+    };
+
+    // Clear the paper once the specks are gone, so none are left floating on nothing
+    let processed_img = match spec.transparent_background && target_format.eq_ignore_ascii_case("PNG") {
+        true if looks_like_signature(&config.document_type) => {
+            let (transparent, cleared) = clear_paper(&processed_img);
+            observer.event(LogLevel::Info, LogEventKind::Transparency, Some(file_name), serde_json::json!({ "cleared": cleared }));
+            let transformation = Transformation::TransparentBackground;
+            capture_intermediate(debug_intermediates, &mut debug, "transparent", &transparent, Some(&transformation));
+            transformations.push(transformation);
+            transparent
+        }
+        true => {
+            warnings.push(format!("transparent_background is for signatures, so the {} was left opaque", config.document_type));
+            processed_img
+        }
+        false => processed_img,
+    };
+
+    // Turn the finished image to the orientation the print form expects
+    let (width, height) = processed_img.dimensions();
+    let processed_img = if needs_rotation(width, height, spec.print_orientation) {
+        observer.event(
+            LogLevel::Info,
+            LogEventKind::Rotate,
+            Some(file_name),
+            serde_json::json!({ "degrees": 90, "to": spec.print_orientation }),
+        );
+        let rotated = processed_img.rotate90();
+        let transformation = Transformation::Rotate { degrees: 90 };
+        capture_intermediate(debug_intermediates, &mut debug, "rotated", &rotated, Some(&transformation));
+        transformations.push(transformation);
+        rotated
+    } else {
+        processed_img
+    };
+
+    // Stamp last, so the text is upright and sized for the pixels that are sent
+    let processed_img = match &spec.timestamp_overlay {
+        Some(stamp) => {
+            let time = match stamp.source {
+                TimestampSource::Now => observer
+                    .local_time()
+                    .ok_or_else(|| ConvertError::Validation("timestamp_overlay needs the current time, which this host cannot tell".to_string()))?,
+                TimestampSource::Exif => exif_capture_time(data).ok_or_else(|| {
+                    ConvertError::Validation(format!("{} has no EXIF capture time for timestamp_overlay to stamp", file_name))
+                })?,
+            };
+            let text = format_time(&stamp.format, &time).map_err(|placeholder| {
+                ConvertError::InvalidConfig(format!("timestamp_overlay.format has an unknown placeholder {}", placeholder))
+            })?;
+            observer.event(
+                LogLevel::Info,
+                LogEventKind::Timestamp,
+                Some(file_name),
+                serde_json::json!({ "text": text, "source": stamp.source }),
+            );
+            let stamped = draw_timestamp(&processed_img, &text, stamp);
+            let transformation = Transformation::Timestamp { text };
+            capture_intermediate(debug_intermediates, &mut debug, "timestamped", &stamped, Some(&transformation));
+            transformations.push(transformation);
+            stamped
+        }
+        None => processed_img,
+    };
+
+    Ok(PreparedImage {
+        img: processed_img,
+        source: (decoded_width, decoded_height),
+        transformations,
+        debug,
+        colorfulness: measured_colorfulness,
+        ink,
+        background,
+        warnings,
+    })
+}
+
+// Lossless reductions for `png_optimize`. Alpha survives every one of them: indexed
+// images carry it in a tRNS chunk, and only fully opaque images lose the channel.
+#[cfg(feature = "png")]
+fn write_optimized_png(rgba: &image::RgbaImage) -> Result<Vec<u8>, ConvertError> {
+    let encode_error = |e: png::EncodingError| ConvertError::Encode(format!("PNG encoding failed: {}", e));
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, rgba.width(), rgba.height());
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+
+    let opaque = rgba.pixels().all(|p| p.0[3] == u8::MAX);
+    let data = match palette_indices(rgba) {
+        Some((palette, indices)) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect::<Vec<u8>>());
+            if !opaque {
+                encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<u8>>());
+            }
+            indices
+        }
+        None if opaque => {
+            encoder.set_color(png::ColorType::Rgb);
+            rgba.pixels().flat_map(|p| [p.0[0], p.0[1], p.0[2]]).collect()
+        }
+        None => {
+            encoder.set_color(png::ColorType::Rgba);
+            rgba.as_raw().clone()
+        }
+    };
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    writer.write_image_data(&data).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)?;
+    Ok(output)
+}
+
+// The byte layout a `png_profile` fixes: its color type at 8 bits, the same filter on
+// every row (none for indexed color, where it rarely pays, Paeth otherwise) and best
+// compression. Nothing depends on the image content, so equal pixels give equal bytes.
+#[cfg(feature = "png")]
+fn write_profiled_png(img: &image::DynamicImage, profile: PngProfile) -> Result<Vec<u8>, ConvertError> {
+    let encode_error = |e: png::EncodingError| ConvertError::Encode(format!("PNG encoding failed: {}", e));
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, img.width(), img.height());
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::NonAdaptive);
+    let (color, filter, data) = match profile {
+        PngProfile::Grayscale8 => (png::ColorType::Grayscale, png::FilterType::Paeth, img.to_luma8().into_raw()),
+        PngProfile::Rgb8 => (png::ColorType::Rgb, png::FilterType::Paeth, img.to_rgb8().into_raw()),
+        PngProfile::Rgba8 => (png::ColorType::Rgba, png::FilterType::Paeth, img.to_rgba8().into_raw()),
+        PngProfile::Indexed8 => {
+            let (palette, indices) = palette_indices(&img.to_rgba8()).ok_or_else(|| {
+                ConvertError::Encode(
+                    "png_profile Indexed8 allows at most 256 colors; set posterize_levels to reduce them".to_string(),
+                )
+            })?;
+            encoder.set_palette(palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect::<Vec<u8>>());
+            if palette.iter().any(|color| color[3] < u8::MAX) {
+                encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<u8>>());
+            }
+            (png::ColorType::Indexed, png::FilterType::NoFilter, indices)
+        }
+    };
+    encoder.set_color(color);
+    encoder.set_filter(filter);
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    writer.write_image_data(&data).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)?;
+    Ok(output)
+}
+
+// Adam7's passes, each as its first column and row, then its column and row steps
+#[cfg(feature = "png")]
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] =
+    [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+#[cfg(feature = "png")]
+fn interlaced_if_asked(png: Vec<u8>, spec: &DocumentSpec) -> Result<Vec<u8>, ConvertError> {
+    if spec.png_interlaced { interlace_png(&png) } else { Ok(png) }
+}
+
+// `png` with its pixels regrouped into the seven Adam7 passes, for `png_interlaced`. The
+// png crate only writes non-interlaced images, so the written pixels are read back and
+// deflated again, pass by pass; every chunk but IHDR's interlace flag and the image data
+// is kept as it was. The passes filter worse than whole rows, so the file grows.
+#[cfg(feature = "png")]
+fn interlace_png(png: &[u8]) -> Result<Vec<u8>, ConvertError> {
+    let failed = |reason: String| ConvertError::Encode(format!("PNG interlacing failed: {}", reason));
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(|e| failed(e.to_string()))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(|e| failed(e.to_string()))?;
+    if frame.bit_depth != png::BitDepth::Eight {
+        return Err(failed(format!("{:?}-bit samples are not supported", frame.bit_depth)));
+    }
+    let (width, height, stride) = (frame.width as usize, frame.height as usize, frame.line_size);
+    let bpp = frame.color_type.samples();
+    // Palette indices are not filtered, as in `write_profiled_png`
+    let paeth = frame.color_type != png::ColorType::Indexed;
+
+    let mut filtered = Vec::with_capacity(pixels.len() + height * 2);
+    for (first_x, first_y, step_x, step_y) in ADAM7_PASSES {
+        let mut previous: Vec<u8> = Vec::new();
+        for y in (first_y..height).step_by(step_y) {
+            let row: Vec<u8> = (first_x..width).step_by(step_x).flat_map(|x| &pixels[y * stride + x * bpp..][..bpp]).copied().collect();
+            if row.is_empty() {
+                break;
+            }
+            previous.resize(row.len(), 0);
+            filtered.push(if paeth { 4 } else { 0 });
+            for i in 0..row.len() {
+                let predicted = match paeth {
+                    true => paeth_predictor(
+                        if i >= bpp { row[i - bpp] } else { 0 },
+                        previous[i],
+                        if i >= bpp { previous[i - bpp] } else { 0 },
+                    ),
+                    false => 0,
+                };
+                filtered.push(row[i].wrapping_sub(predicted));
+            }
+            previous = row;
+        }
+    }
+
+    let mut output = png[..8].to_vec();
+    let mut chunk = |kind: &[u8], data: &[u8]| {
+        output.extend((data.len() as u32).to_be_bytes());
+        let start = output.len();
+        output.extend(kind);
+        output.extend(data);
+        output.extend(crc32fast::hash(&output[start..]).to_be_bytes());
+    };
+    let (mut rest, mut wrote_data) = (&png[8..], false);
+    while let Some((header, body)) = rest.split_first_chunk::<8>() {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let (kind, data) = (&header[4..], body.get(..length).ok_or_else(|| failed("truncated chunk".to_string()))?);
+        match kind {
+            b"IHDR" => chunk(kind, &[data.get(..12).ok_or_else(|| failed("short IHDR".to_string()))?, &[1]].concat()),
+            b"IDAT" if !wrote_data => {
+                chunk(kind, &miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 9));
+                wrote_data = true;
+            }
+            b"IDAT" => {}
+            _ => chunk(kind, data),
+        }
+        rest = body.get(length + 4..).unwrap_or_default();
+    }
+    Ok(output)
+}
+
+#[cfg(feature = "png")]
+fn paeth_predictor(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let (to_left, to_above, to_upper_left) = ((estimate - left as i16).abs(), (estimate - above as i16).abs(), (estimate - upper_left as i16).abs());
+    if to_left <= to_above && to_left <= to_upper_left {
+        left
+    } else if to_above <= to_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+// The image's colors as a palette plus one index per pixel, or None above 256 colors
+#[cfg(feature = "png")]
+fn palette_indices(rgba: &image::RgbaImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut palette = Vec::new();
+    let mut lookup = std::collections::HashMap::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(&index) => index,
+            None if palette.len() == 256 => return None,
+            None => {
+                let index = palette.len() as u8;
+                lookup.insert(pixel.0, index);
+                palette.push(pixel.0);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some((palette, indices))
+}
+
+#[cfg(feature = "jpeg")]
+fn jpeg_density(spec: &DocumentSpec) -> PixelDensity {
+
+    let dpi = spec.resolution_px_per_inch.unwrap_or(150) as f32;
+    let (density, unit) = match spec.density_unit {
+        DensityUnit::None => return PixelDensity::default(),
+        DensityUnit::Inch => (dpi, PixelDensityUnit::Inches),
+        DensityUnit::Cm => (dpi / 2.54, PixelDensityUnit::Centimeters),
+    };
+    let density = density.round().clamp(1.0, u16::MAX as f32) as u16;
+    PixelDensity { density: (density, density), unit }
+}
+
+// Compares the RGBA buffer a decode would allocate with the host's memory estimate. The
+// check is advisory: unreadable headers and hosts without an estimate pass through.
+fn check_decode_memory(data: &[u8], spec: &MemoryCheckSpec, observer: &dyn Observer) -> Result<(), ConvertError> {
+    let available = match spec.available_mb {
+        Some(mb) => Some(mb as u64 * 1024 * 1024),
+        None => observer.available_memory(),
+    };
+    let dimensions = image::io::Reader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    let (Some(available), Some((width, height))) = (available, dimensions) else {
+        return Ok(());
+    };
+
+    let required = width as u64 * height as u64 * 4;
+    if required as f64 > available as f64 * spec.max_fraction as f64 {
+        return Err(ConvertError::InsufficientMemory { width, height, required, available });
+    }
+    Ok(())
+}
+
+// Largest linear growth `flexible_dimensions` may apply to reach the size floor; flat
+// images barely grow in bytes however far they are scaled up
+const MAX_BAND_UPSCALE: f64 = 4.0;
+
+// The `flexible_dimensions` size solver: the highest quality that fits `size_kb.max`,
+// found by bisection, with the image scaled down when none fits and up when even the
+// best fit is under `size_kb.min`. Once one scale has come out too small and another
+// too large, the scale is bisected between them. Scaling stays inside the spec's pixel
+// limits. `reserved_bytes` of the band are left for what is added after encoding.
+// Returns the image actually encoded and its resize, if any.
+fn encode_in_band(
+    file_name: &str,
+    img: &image::DynamicImage,
+    target_format: &str,
+    spec: &DocumentSpec,
+    reserved_bytes: usize,
+    observer: &dyn Observer,
+) -> Result<(image::DynamicImage, EncodedImage, Option<Transformation>), ConvertError> {
+    let max_bytes = ((spec.size_kb.max * 1024) as usize).saturating_sub(reserved_bytes);
+    let min_bytes = spec.size_kb.min.map_or(0, |kb| kb as usize * 1024).saturating_sub(reserved_bytes);
+    let top_quality = spec.quality_ladder.iter().flatten().max().copied().unwrap_or(100);
+    let (source_width, source_height) = img.dimensions();
+    let (lowest, highest) = scale_limits(source_width, source_height, spec);
+    let highest = highest.min(MAX_BAND_UPSCALE);
+    let source = img;
+    let mut img = img.clone();
+    // Scales relative to the prepared image: the current one, the largest known to come
+    // out under the floor and the smallest known to overshoot the cap
+    let (mut scale, mut under, mut over) = (1.0f64, None::<f64>, None::<f64>);
+
+    loop {
+        let (width, height) = img.dimensions();
+        let next = match encode_to_target(file_name, &img, target_format, max_bytes, spec, observer)? {
+            Some(encoded) if encoded.data.len() >= min_bytes => {
+                // Every pass resamples the prepared image, so only the net resize is recorded
+                let resize = (scale != 1.0).then_some(Transformation::Resize {
+                    from: [source_width, source_height],
+                    to: [width, height],
+                });
+                return Ok((img, encoded, resize));
+            }
+            Some(encoded) if encoded.quality.is_some_and(|quality| quality < top_quality) => {
+                return Err(ConvertError::Encode(format!(
+                    "No quality lands between {}KB and {}KB at {}x{}; widen size_kb",
+                    min_bytes / 1024,
+                    spec.size_kb.max,
+                    width,
+                    height
+                )));
+            }
+            Some(encoded) => {
+                under = Some(scale);
+                // Bytes grow roughly with the pixel count
+                let wanted = scale * (min_bytes as f64 / encoded.data.len().max(1) as f64).sqrt() * 1.05;
+                over.map_or(wanted.min(highest).max(scale), |over| (scale + over) / 2.0)
+            }
+            None => {
+                over = Some(scale);
+                under.map_or((scale * 0.8).max(lowest).min(scale), |under| (under + scale) / 2.0)
+            }
+        };
+        let (to_width, to_height) = (
+            ((source_width as f64 * next).round() as u32).max(1),
+            ((source_height as f64 * next).round() as u32).max(1),
+        );
+        if (to_width, to_height) == (width, height) {
+            return Err(ConvertError::Encode(match (under, over) {
+                (Some(_), Some(_)) => format!(
+                    "No size between {}KB and {}KB is reachable by rescaling; widen size_kb",
+                    min_bytes / 1024,
+                    spec.size_kb.max
+                ),
+                (None, _) => format!("Cannot compress image to {}KB even at the smallest allowed dimensions", spec.size_kb.max),
+                (_, None) => format!(
+                    "Image stays under {}KB even at full quality and the largest allowed dimensions",
+                    min_bytes / 1024
+                ),
+            }));
+        }
+        observer.event(
+            LogLevel::Info,
+            LogEventKind::Resize,
+            Some(file_name),
+            serde_json::json!({ "from": [width, height], "to": [to_width, to_height] }),
+        );
+        img = resample(source, to_width, to_height, spec);
+        scale = next;
+    }
+}
+
+// Resamples to exactly `width`×`height`, halving first for downscales by more than
+// `pre_reduce_above`
+fn resample(img: &image::DynamicImage, width: u32, height: u32, spec: &DocumentSpec) -> image::DynamicImage {
+    let (threshold, filter, linear_light) = (spec.pre_reduce_threshold(), spec.performance_profile.resize_filter(), spec.linear_light());
+    let factor = (img.width() as f32 / width as f32).min(img.height() as f32 / height as f32);
+    if threshold == 0.0 || factor <= threshold {
+        return resize_exact_alpha(img, width, height, filter, linear_light);
+    }
+    let mut reduced = halve(img, linear_light);
+    while reduced.width() / 2 >= width * 2 && reduced.height() / 2 >= height * 2 {
+        reduced = halve(&reduced, linear_light);
+    }
+    resize_exact_alpha(&reduced, width, height, filter, linear_light)
+}
+
+// The (smallest, largest) factor a `width`×`height` image can be scaled by while staying
+// inside the spec's pixel ranges, total pixel limits and MIN_TARGET_EDGE..=MAX_ASPECT_EDGE
+fn scale_limits(width: u32, height: u32, spec: &DocumentSpec) -> (f64, f64) {
+    let range = spec.pixels.as_ref().and_then(|pixels| pixels.range().ok()).unwrap_or(PixelRange::UNBOUNDED);
+    let (width, height) = (width as f64, height as f64);
+    let (minimum, budget) = total_pixel_limits(spec);
+    let lowest = [
+        range.min_width as f64 / width,
+        range.min_height as f64 / height,
+        MIN_TARGET_EDGE as f64 / width.min(height),
+        minimum.map_or(0.0, |minimum| (minimum / (width * height)).sqrt()),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max);
+    let highest = [
+        range.max_width.min(MAX_ASPECT_EDGE) as f64 / width,
+        range.max_height.min(MAX_ASPECT_EDGE) as f64 / height,
+        budget.map_or(f64::INFINITY, |budget| (budget / (width * height)).sqrt()),
+    ]
+    .into_iter()
+    .fold(f64::INFINITY, f64::min);
+    (lowest, highest)
+}
+
+/// Encode `img`, lowering JPEG quality from 90 in steps of 10 (or as the spec's
+/// `performance_profile` steps it, or down its `quality_ladder`) until the output fits
+/// in `max_size_bytes`. PNG is lossless and encoded once; validation catches oversize output.
+/// `spec` supplies the encoder settings (the JFIF density of JPEG output); the size budget
+/// is passed separately so callers can tighten it.
+pub fn encode_to_size(
+    file_name: &str,
+    img: &image::DynamicImage,
+    target_format: &str,
+    max_size_bytes: usize,
+    spec: &DocumentSpec,
+    observer: &dyn Observer,
+) -> Result<EncodedImage, ConvertError> {
+    let is_jpeg = matches!(target_format.to_uppercase().as_str(), "JPEG" | "JPG");
+    if let (true, Some(ladder)) = (is_jpeg, &spec.quality_ladder) {
+        return encode_on_ladder(file_name, img, target_format, max_size_bytes, ladder, spec, observer);
+    }
+    let qualities = spec.performance_profile.qualities();
+    let mut pass = 0;
+
+    let (output, quality) = loop {
+        check_cancelled(observer)?;
+        let quality = qualities[pass];
+        pass += 1;
+        observer.record(&|stats| stats.encode_passes += 1);
+        observer.progress(file_name, ProgressStage::Encoding, pass as u32);
+        let output = encode_once(img, target_format, quality, spec)?;
+        observer.event(
+            LogLevel::Debug,
+            LogEventKind::EncodePass,
+            Some(file_name),
+            serde_json::json!({
+                "format": target_format,
+                "quality": is_jpeg.then_some(quality),
+                "bytes": output.len(),
+                "max_bytes": max_size_bytes,
+                "width": img.width(),
+                "height": img.height(),
+            }),
+        );
+        if !is_jpeg {
+            // An alpha channel can take a signature past a cap its opaque scan fit, so a
+            // transparent one gets the smallest lossless PNG before it is given up on
+            #[cfg(feature = "png")]
+            if output.len() > max_size_bytes && spec.transparent_background && spec.png_layout().is_none() && !spec.png_optimized() {
+                observer.debug(LogEventKind::EncodePass, Some(file_name), format!("Transparent PNG too large ({}KB), recompressing", output.len() / 1024));
+                observer.record(&|stats| stats.encode_passes += 1);
+                let smaller = interlaced_if_asked(write_optimized_png(&img.to_rgba8())?, spec)?;
+                if smaller.len() < output.len() {
+                    break (smaller, quality);
+                }
+            }
+            break (output, quality); // PNG doesn't support quality adjustment
+        }
+
+        // Check size constraints
+        if output.len() <= max_size_bytes {
+            break (output, quality);
+        }
+
+        // Reduce quality and try again
+        if pass == qualities.len() {
+            return Err(ConvertError::Encode("Cannot compress image to meet size requirements".to_string()));
+        }
+    };
+
+    Ok(EncodedImage {
+        data: output,
+        quality: is_jpeg.then_some(quality),
+    })
+}
+
+// The size loop for a spec with `quality_ladder`: each rung from highest to lowest,
+// stopping at the first that fits
+fn encode_on_ladder(
+    file_name: &str,
+    img: &image::DynamicImage,
+    target_format: &str,
+    max_size_bytes: usize,
+    ladder: &[u8],
+    spec: &DocumentSpec,
+    observer: &dyn Observer,
+) -> Result<EncodedImage, ConvertError> {
+    let rungs = ladder_rungs(ladder);
+    for (index, &quality) in rungs.iter().enumerate() {
+        check_cancelled(observer)?;
+        let pass = index as u32 + 1;
+        observer.record(&|stats| stats.encode_passes += 1);
+        observer.progress(file_name, ProgressStage::Encoding, pass);
+        let output = encode_once(img, target_format, quality, spec)?;
+        observer.event(
+            LogLevel::Debug,
+            LogEventKind::EncodePass,
+            Some(file_name),
+            serde_json::json!({
+                "format": target_format,
+                "quality": quality,
+                "bytes": output.len(),
+                "max_bytes": max_size_bytes,
+                "width": img.width(),
+                "height": img.height(),
+                "rung": pass,
+            }),
+        );
+        if output.len() <= max_size_bytes {
+            return Ok(EncodedImage { data: output, quality: Some(quality) });
+        }
+    }
+    Err(ConvertError::Encode(format!(
+        "Cannot compress image to meet size requirements at the lowest quality_ladder quality {}",
+        rungs.last().copied().unwrap_or_default()
+    )))
+}
+
+// Ladder qualities from highest to lowest, without repeats
+fn ladder_rungs(ladder: &[u8]) -> Vec<u8> {
+    let mut rungs = ladder.to_vec();
+    rungs.sort_unstable_by(|a, b| b.cmp(a));
+    rungs.dedup();
+    rungs
+}
+
+/// Encode `img` at the highest JPEG quality whose output fits in `target_bytes`, found
+/// by bisecting 1..=100, so the result lands just under the target rather than a whole
+/// quality step below it. With `quality_ladder` only its qualities are tried. PNG is
+/// encoded once. None when nothing fits at this size.
+pub fn encode_to_target(
+    file_name: &str,
+    img: &image::DynamicImage,
+    target_format: &str,
+    target_bytes: usize,
+    spec: &DocumentSpec,
+    observer: &dyn Observer,
+) -> Result<Option<EncodedImage>, ConvertError> {
+    let is_jpeg = matches!(target_format.to_uppercase().as_str(), "JPEG" | "JPG");
+    let mut pass = 0;
+    let mut encode = |quality: u8| {
+        check_cancelled(observer)?;
+        pass += 1;
+        observer.record(&|stats| stats.encode_passes += 1);
+        observer.progress(file_name, ProgressStage::Encoding, pass);
+        let output = encode_once(img, target_format, quality, spec)?;
+        observer.event(
+            LogLevel::Debug,
+            LogEventKind::EncodePass,
+            Some(file_name),
+            serde_json::json!({
+                "format": target_format,
+                "quality": is_jpeg.then_some(quality),
+                "bytes": output.len(),
+                "max_bytes": target_bytes,
+                "width": img.width(),
+                "height": img.height(),
+            }),
+        );
+        Ok::<_, ConvertError>(output)
+    };
+
+    if !is_jpeg {
+        let output = encode(100)?;
+        return Ok((output.len() <= target_bytes).then_some(EncodedImage { data: output, quality: None }));
+    }
+    if let Some(ladder) = &spec.quality_ladder {
+        for quality in ladder_rungs(ladder) {
+            let output = encode(quality)?;
+            if output.len() <= target_bytes {
+                return Ok(Some(EncodedImage { data: output, quality: Some(quality) }));
+            }
+        }
+        return Ok(None);
+    }
+    let (mut low, mut high, mut best) = (1u8, 100u8, None);
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let output = encode(quality)?;
+        if output.len() <= target_bytes {
+            best = Some(EncodedImage { data: output, quality: Some(quality) });
+            low = quality + 1;
+        } else if quality == 1 {
+            break;
+        } else {
+            high = quality - 1;
+        }
+    }
+    Ok(best)
+}
+
+// A single encode; `quality` only applies to JPEG
+// Without the JPEG encoder `quality` and `spec` go unused and only PNG replaces the empty
+// output buffer, and without any encoder every format is rejected before it is written
+#[cfg_attr(not(feature = "jpeg"), allow(unreachable_code, unused_assignments, unused_mut, unused_variables))]
+fn encode_once(
+    img: &image::DynamicImage,
+    target_format: &str,
+    quality: u8,
+    spec: &DocumentSpec,
+) -> Result<Vec<u8>, ConvertError> {
+    let mut output = Vec::new();
+    match target_format.to_uppercase().as_str() {
+        #[cfg(feature = "jpeg")]
+        "JPEG" | "JPG" => {
+            let quality = spec.quality_scale.native_quality(quality);
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut output, quality);
+            encoder.set_pixel_density(jpeg_density(spec));
+            let encoded = match spec.required_layout() {
+                Some(PngProfile::Grayscale8) => encoder.encode_image(&img.to_luma8()),
+                _ => encoder.encode_image(&img.to_rgb8()),
+            };
+            encoded.map_err(|e| ConvertError::Encode(format!("JPEG encoding failed: {}", e)))?;
+        }
+        #[cfg(feature = "png")]
+        "PNG" => {
+            let png = match spec.png_layout() {
+                Some(profile) => write_profiled_png(img, profile)?,
+                None if spec.png_optimized() => write_optimized_png(&img.to_rgba8())?,
+                None => {
+                    use image::ImageEncoder;
+                    let rgba_img = img.to_rgba8();
+                    let mut png = Vec::new();
+                    let encoder = image::codecs::png::PngEncoder::new(&mut png);
+                    encoder.write_image(
+                        rgba_img.as_raw(),
+                        rgba_img.width(),
+                        rgba_img.height(),
+                        image::ColorType::Rgba8,
+                    ).map_err(|e| ConvertError::Encode(format!("PNG encoding failed: {}", e)))?;
+                    png
+                }
+            };
+            output = interlaced_if_asked(png, spec)?;
+        }
+        _ => {
+            check_output(target_format)?;
+            return Err(ConvertError::UnsupportedFormat(format!("Unsupported target format: {}", target_format)));
+        }
+    }
+    Ok(output)
+}
+
+fn convert_pdf(
+    file_name: &str,
+    data: &[u8],
+    spec: &DocumentSpec,
+    max_decoded_pixels: u64,
+    best_effort: bool,
+    provenance: Option<&Provenance>,
+    observer: &dyn Observer,
+) -> Result<ConvertedData, ConvertError> {
+    check_cancelled(observer)?;
+
+    let max_size_bytes = (spec.size_kb.max * 1024) as usize;
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::PdfCheck,
+        Some(file_name),
+        serde_json::json!({
+            "bytes": data.len(),
+            "max_bytes": max_size_bytes,
+            "within_limit": data.len() <= max_size_bytes,
+        }),
+    );
+
+    // Portals that render previews themselves often ignore /Rotate, so scanned pages get
+    // their rotation drawn in
+    let mut transformations = Vec::new();
+    let mut warnings = Vec::new();
+    let upright = match normalize_rotation(data) {
+        Ok(Some((pdf, rotated))) => {
+            observer.debug(LogEventKind::PdfCheck, Some(file_name), format!("Turned {} rotated PDF pages upright", rotated.len()));
+            transformations.extend(rotated.into_iter().map(|(page, degrees)| Transformation::PageRotate { page, degrees }));
+            Some(pdf)
+        }
+        Ok(None) => None,
+        Err(reason) => {
+            warnings.push(format!("Rotated pages were left for the viewer to turn: {}", reason));
+            None
+        }
+    };
+    let data = upright.as_deref().unwrap_or(data);
+
+    // Duplex scanners leave the backs of single-sided sheets in as blank pages, and
+    // applicants scan the same certificate twice
+    let thumbnails: Vec<Option<image::DynamicImage>> =
+        scanned_pages(data).map(|pages| pages.iter().map(|page| scan_thumbnail(page.jpeg, max_decoded_pixels)).collect()).unwrap_or_default();
+    let blank = blank_pages(&thumbnails, spec.blank_page_max_ink.unwrap_or(DEFAULT_BLANK_PAGE_MAX_INK));
+    let min_similarity = spec.duplicate_page_min_similarity.unwrap_or(DEFAULT_DUPLICATE_PAGE_MIN_SIMILARITY);
+    let duplicates = duplicate_pages(&thumbnails, &blank, min_similarity);
+    let mut remove = Vec::new();
+    let page_list = blank.iter().map(u32::to_string).collect::<Vec<_>>().join(", ");
+    match (blank.is_empty(), spec.remove_blank_pages) {
+        (true, _) => {}
+        (false, true) if blank.len() < thumbnails.len() => {
+            observer.debug(LogEventKind::PdfCheck, Some(file_name), format!("Removing blank PDF pages {}", page_list));
+            remove.extend(&blank);
+            transformations.push(Transformation::RemoveBlankPages { pages: blank });
+        }
+        (false, true) => warnings.push("Every page looks blank, so none were removed".to_string()),
+        (false, false) => warnings.push(format!("Blank pages found: {}; set remove_blank_pages to drop them", page_list)),
+    }
+    if !duplicates.is_empty() {
+        let pairs = duplicates.iter().map(|(page, of)| format!("{} (like {})", page, of)).collect::<Vec<_>>().join(", ");
+        if spec.remove_duplicate_pages {
+            observer.debug(LogEventKind::PdfCheck, Some(file_name), format!("Removing duplicate PDF pages {}", pairs));
+            let later: Vec<u32> = duplicates.iter().map(|&(page, _)| page).collect();
+            remove.extend(&later);
+            transformations.push(Transformation::RemoveDuplicatePages { pages: later });
+        } else {
+            warnings.push(format!("Pages that repeat an earlier one: {}; set remove_duplicate_pages to drop them", pairs));
+        }
+    }
+    let kept = if remove.is_empty() {
+        None
+    } else {
+        remove.sort_unstable();
+        Some(remove_pages(data, &remove).map_err(ConvertError::Decode)?)
+    };
+    let data = kept.as_deref().unwrap_or(data);
+
+    // PDFs are passed through as they are, or for `pdf_a` as a PDF/A copy of their
+    // scans; the only way to shrink one is rasterizing it
+    let rebuilt = if spec.pdf_a {
+        let (pdf, notes) = rebuild_pdf_a(data).map_err(ConvertError::PdfA)?;
+        observer.debug(LogEventKind::PdfCheck, Some(file_name), format!("Rebuilt PDF as PDF/A-1b: {}KB", pdf.len() / 1024));
+        let pages = scanned_pages(&pdf).map_or(0, |pages| pages.len() as u32);
+        transformations.push(Transformation::PdfA { pages });
+        warnings.extend(notes);
+        Some(pdf)
+    } else {
+        None
+    };
+    let data = rebuilt.as_deref().unwrap_or(data);
+    let reduced = match spec.max_pdf_colors {
+        Some(colors) => {
+            let (pdf, pages) = reduce_pdf_colors(file_name, data, colors, spec.pdf_a, max_decoded_pixels, observer)?;
+            transformations.push(Transformation::ReduceColors { pages, colors });
+            warnings.push(format!(
+                "The PDF's page images were reduced to at most {} colors for max_pdf_colors, so they may look posterized",
+                colors
+            ));
+            Some(pdf)
+        }
+        None => None,
+    };
+    let data = reduced.as_deref().unwrap_or(data);
+    // Added as an incremental update, before the size check so it counts
+    let embedded = embed_provenance(data.to_vec(), "PDF", provenance, &mut transformations, &mut warnings);
+    let data = embedded.as_slice();
+    let passthrough = ConvertedData {
+        data: data.to_vec(),
+        dimensions: None,
+        geometry: None,
+        transformations,
+        debug: vec![],
+        colorfulness: None,
+        ink: None,
+        background: None,
+        warnings,
+    };
+    if data.len() <= max_size_bytes {
+        return Ok(passthrough);
+    }
+    let too_large = |best_kb: usize, rasterize_error: Option<String>| ConvertError::PdfTooLarge {
+        size_kb: (data.len() / 1024) as u32,
+        max_kb: spec.size_kb.max,
+        best_kb: best_kb as u32,
+        rasterize_dpi: spec.pdf_rasterize_dpi,
+        rasterize_error,
+    };
+    let Some(dpi) = spec.pdf_rasterize_dpi else {
+        // An oversized PDF passes through unchanged as a best effort; validation flags it
+        return if best_effort { Ok(passthrough) } else { Err(too_large(data.len() / 1024, None)) };
+    };
+    let pages = match scanned_pages(data) {
+        Ok(pages) => pages,
+        Err(reason) => {
+            observer.debug(LogEventKind::PdfRasterize, Some(file_name), format!("Cannot rasterize PDF: {}", reason));
+            return if best_effort { Ok(passthrough) } else { Err(too_large(data.len() / 1024, Some(reason))) };
+        }
+    };
+    let mut rasterized = rasterize_pdf(file_name, &pages, dpi, max_size_bytes, spec, max_decoded_pixels, observer)?;
+    let earlier = passthrough.transformations.iter().filter(|step| !matches!(step, Transformation::EmbedMetadata { .. }));
+    rasterized.transformations.splice(0..0, earlier.cloned());
+    rasterized.warnings.splice(0..0, passthrough.warnings.iter().cloned());
+    rasterized.data = embed_provenance(rasterized.data, "PDF", provenance, &mut rasterized.transformations, &mut rasterized.warnings);
+    if rasterized.data.len() <= max_size_bytes || (best_effort && rasterized.data.len() < data.len()) {
+        Ok(rasterized)
+    } else if best_effort {
+        Ok(passthrough)
+    } else {
+        Err(too_large(rasterized.data.len() / 1024, None))
+    }
+}
+
+// The scanned PDF rewritten with each page scan reduced to a palette of at most
+// `colors` colors and deflated losslessly, with its page count
+fn reduce_pdf_colors(
+    file_name: &str,
+    data: &[u8],
+    colors: u16,
+    pdf_a: bool,
+    max_decoded_pixels: u64,
+    observer: &dyn Observer,
+) -> Result<(Vec<u8>, u32), ConvertError> {
+    let pages = scanned_pages(data)
+        .map_err(|reason| ConvertError::Decode(format!("Cannot reduce the PDF to {} colors: {}", colors, reason)))?;
+    let mut images = Vec::new();
+    for page in &pages {
+        check_cancelled(observer)?;
+        check_input(page.jpeg, "image/jpeg")?;
+        check_decoded_size(page.jpeg, max_decoded_pixels)?;
+        let img = image::load_from_memory(page.jpeg)
+            .map_err(|e| ConvertError::Decode(format!("Failed to load PDF page scan: {}", e)))?
+            .to_rgb8();
+        let (palette, indices) = quantize(&img, colors as usize);
+        images.push((PageImage::Indexed { palette, indices }, img.width(), img.height()));
+    }
+    observer.event(
+        LogLevel::Info,
+        LogEventKind::Posterize,
+        Some(file_name),
+        serde_json::json!({ "format": "PDF", "pages": pages.len(), "colors": colors }),
+    );
+    let sizes: Vec<_> = pages.iter().map(|page| (page.width, page.height)).collect();
+    let rotations: Vec<_> = pages.iter().map(|page| page.rotate).collect();
+    Ok((write_pdf(&images, &sizes, &rotations, pdf_a), pages.len() as u32))
+}
+
+// Numbers (from 1) of the pages whose scan has at most `max_ink` ink. Scans that could
+// not be decoded count as written.
+fn blank_pages(thumbnails: &[Option<image::DynamicImage>], max_ink: f32) -> Vec<u32> {
+    (1..)
+        .zip(thumbnails)
+        .filter(|(_, thumbnail)| thumbnail.as_ref().is_some_and(|thumbnail| ink_coverage(thumbnail) <= max_ink))
+        .map(|(number, _)| number)
+        .collect()
+}
+
+// (page, earlier page it repeats) for each page whose hash agrees with an earlier one's
+// on at least `min_similarity` of its bits, numbered from 1. Blank pages all look alike
+// and are left to the blank check; scans that could not be decoded repeat nothing.
+fn duplicate_pages(thumbnails: &[Option<image::DynamicImage>], blank: &[u32], min_similarity: f32) -> Vec<(u32, u32)> {
+    let hashes: Vec<Option<[u64; 4]>> = (1..)
+        .zip(thumbnails)
+        .map(|(number, thumbnail)| thumbnail.as_ref().filter(|_| !blank.contains(&number)).map(page_hash))
+        .collect();
+    let mut duplicates = Vec::new();
+    for (later, hash) in hashes.iter().enumerate() {
+        let Some(hash) = hash else {
+            continue;
+        };
+        let earlier = hashes[..later].iter().position(|other| other.is_some_and(|other| hash_similarity(hash, &other) >= min_similarity));
+        if let Some(earlier) = earlier {
+            duplicates.push((later as u32 + 1, earlier as u32 + 1));
+        }
+    }
+    duplicates
+}
+
+// The JPEG decoded at an eighth, quarter or half of its size, whichever comes closest
+// to `BLANK_CHECK_EDGE` without going under; the DCT scaling skips most of the decode.
+// Scans over `max_decoded_pixels` are not decoded.
+#[cfg(feature = "jpeg")]
+fn scan_thumbnail(jpeg: &[u8], max_decoded_pixels: u64) -> Option<image::DynamicImage> {
+    check_decoded_size(jpeg, max_decoded_pixels).ok()?;
+    let mut decoder = image::codecs::jpeg::JpegDecoder::new(std::io::Cursor::new(jpeg)).ok()?;
+    decoder.scale(BLANK_CHECK_EDGE, BLANK_CHECK_EDGE).ok()?;
+    image::DynamicImage::from_decoder(decoder).ok()
+}
+
+#[cfg(not(feature = "jpeg"))]
+fn scan_thumbnail(_jpeg: &[u8], _max_decoded_pixels: u64) -> Option<image::DynamicImage> {
+    None
+}
+
+// Rebuilds a scanned PDF for `pdf_rasterize_dpi`, lowering JPEG quality until it fits in
+// `max_size_bytes`. Gives the smallest attempt when none fits.
+#[cfg(feature = "pdf-raster")]
+fn rasterize_pdf(
+    file_name: &str,
+    pages: &[ScannedPage],
+    dpi: u32,
+    max_size_bytes: usize,
+    spec: &DocumentSpec,
+    max_decoded_pixels: u64,
+    observer: &dyn Observer,
+) -> Result<ConvertedData, ConvertError> {
+    check_output("JPEG")?;
+    let images = pages
+        .iter()
+        .map(|page| {
+            check_input(page.jpeg, "image/jpeg")?;
+            check_decoded_size(page.jpeg, max_decoded_pixels)?;
+            let img = image::load_from_memory(page.jpeg)
+                .map_err(|e| ConvertError::Decode(format!("Failed to load PDF page scan: {}", e)))?;
+            // Scans are only ever scaled down to the DPI, never up
+            let (width, height) = img.dimensions();
+            let scale = (page.width / 72.0 * dpi as f32 / width as f32).min(page.height / 72.0 * dpi as f32 / height as f32);
+            Ok(if scale < 1.0 {
+                let to = (((width as f32 * scale).round() as u32).max(1), ((height as f32 * scale).round() as u32).max(1));
+                resample(&img, to.0, to.1, spec)
+            } else {
+                img
+            })
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+    let sizes: Vec<_> = pages.iter().map(|page| (page.width, page.height)).collect();
+    let rotations: Vec<_> = pages.iter().map(|page| page.rotate).collect();
+
+    let mut output = (Vec::new(), 0);
+    for (index, &quality) in RASTERIZE_QUALITIES.iter().enumerate() {
+        check_cancelled(observer)?;
+        observer.record(&|stats| stats.encode_passes += 1);
+        observer.progress(file_name, ProgressStage::Encoding, index as u32 + 1);
+        let scans = images
+            .iter()
+            .map(|img| Ok((PageImage::Jpeg(encode_once(img, "JPEG", quality, spec)?), img.width(), img.height())))
+            .collect::<Result<Vec<_>, ConvertError>>()?;
+        let pdf = write_pdf(&scans, &sizes, &rotations, spec.pdf_a);
+        observer.event(
+            LogLevel::Debug,
+            LogEventKind::EncodePass,
+            Some(file_name),
+            serde_json::json!({
+                "format": "PDF",
+                "quality": quality,
+                "bytes": pdf.len(),
+                "max_bytes": max_size_bytes,
+                "dpi": dpi,
+            }),
+        );
+        let fits = pdf.len() <= max_size_bytes;
+        output = (pdf, quality);
+        if fits {
+            break;
+        }
+    }
+    let (pdf, quality) = output;
+
+    observer.event(
+        LogLevel::Warn,
+        LogEventKind::PdfRasterize,
+        Some(file_name),
+        serde_json::json!({ "pages": pages.len(), "dpi": dpi, "quality": quality, "bytes": pdf.len() }),
+    );
+    let transformations = vec![
+        Transformation::Rasterize { pages: pages.len() as u32, dpi },
+        Transformation::Encode { format: "PDF".to_string(), quality: Some(quality), bytes: pdf.len() },
+    ];
+    Ok(ConvertedData {
+        data: pdf,
+        dimensions: None,
+        geometry: None,
+        transformations,
+        debug: vec![],
+        colorfulness: None,
+        ink: None,
+        background: None,
+        warnings: vec![format!(
+            "The PDF was rasterized at {} DPI to fit the size limit, so its text can no longer be selected or searched",
+            dpi
+        )],
+    })
+}
+
+#[cfg(not(feature = "pdf-raster"))]
+fn rasterize_pdf(
+    _file_name: &str,
+    _pages: &[ScannedPage],
+    _dpi: u32,
+    _max_size_bytes: usize,
+    _spec: &DocumentSpec,
+    _max_decoded_pixels: u64,
+    _observer: &dyn Observer,
+) -> Result<ConvertedData, ConvertError> {
+    Err(ConvertError::CodecUnavailable("PDF rasterization".to_string()))
+}
+
+// The (minimum, maximum) total pixel counts set by `pixels` and `max_megapixels`
+fn total_pixel_limits(spec: &DocumentSpec) -> (Option<f64>, Option<f64>) {
+    let pixel_spec = spec.pixels.as_ref();
+    let budget = spec
+        .max_megapixels
+        .map(|megapixels| (megapixels as f64 * 1_000_000.0).floor())
+        .into_iter()
+        .chain(pixel_spec.and_then(|p| p.max_total_pixels).map(f64::from))
+        .reduce(f64::min);
+    (pixel_spec.and_then(|p| p.min_total_pixels).map(f64::from), budget)
+}
+
+pub fn calculate_target_dimensions(
+    original_width: u32,
+    original_height: u32,
+    spec: &DocumentSpec,
+) -> Result<(u32, u32), ConvertError> {
+    let mut target_width = original_width;
+    let mut target_height = original_height;
+    let mut range = PixelRange::UNBOUNDED;
+
+    // Apply pixel constraints
+    if let Some(pixel_spec) = &spec.pixels {
+        // Exact dimensions
+        if let (Some(width), Some(height)) = (pixel_spec.width, pixel_spec.height) {
+            target_width = width;
+            target_height = height;
+        }
+        // Range constraints
+        else {
+            range = pixel_spec.range().map_err(ConvertError::InvalidConfig)?;
+            target_width = target_width.clamp(range.min_width, range.max_width);
+            target_height = target_height.clamp(range.min_height, range.max_height);
+        }
+    }
+
+    // Apply aspect ratio constraints. Each bound moves one axis as far as needed; when
+    // that would leave the pixel range (or pass MAX_ASPECT_EDGE), the other axis gives
+    // way instead, so no intermediate size exceeds the maximums. A bound given without
+    // its partner is one-sided.
+    if let Some(aspect_spec) = &spec.aspect_ratio {
+        let max_width = range.max_width.min(MAX_ASPECT_EDGE).max(range.min_width);
+        let max_height = range.max_height.min(MAX_ASPECT_EDGE).max(range.min_height);
+        let (width, height) = (target_width as f64, target_height as f64);
+        let min_ratio = aspect_spec.min.map_or(0.0, f64::from);
+        let max_ratio = aspect_spec.max.map_or(f64::INFINITY, f64::from);
+        if width / height < min_ratio {
+            target_width = ceil_px(height * min_ratio);
+            if target_width > max_width {
+                target_width = max_width;
+                target_height = floor_px(max_width as f64 / min_ratio);
+            }
+        } else if width / height > max_ratio {
+            target_height = ceil_px(width / max_ratio);
+            if target_height > max_height {
+                target_height = max_height;
+                target_width = floor_px(max_height as f64 * max_ratio);
+            }
+        }
+
+        let (width, height) = (target_width as f64, target_height as f64);
+        let min_hw_ratio = aspect_spec.height_to_width_min.map_or(0.0, f64::from);
+        let max_hw_ratio = aspect_spec.height_to_width_max.map_or(f64::INFINITY, f64::from);
+        if height / width < min_hw_ratio {
+            target_height = ceil_px(width * min_hw_ratio);
+            if target_height > max_height {
+                target_height = max_height;
+                target_width = floor_px(max_height as f64 / min_hw_ratio);
+            }
+        } else if height / width > max_hw_ratio {
+            target_height = floor_px(width * max_hw_ratio);
+            if target_height < range.min_height {
+                target_height = range.min_height;
+                target_width = ceil_px(range.min_height as f64 / max_hw_ratio).min(max_width);
+            }
+        }
+
+        // Ranges too tight for the ratio keep the range
+        target_width = target_width.clamp(range.min_width, range.max_width);
+        target_height = target_height.clamp(range.min_height, range.max_height);
+    }
+
+    // Apply dimension constraints (convert cm/mm to pixels assuming 150 DPI)
+    let dpi = spec.resolution_px_per_inch.unwrap_or(150) as f32;
+
+    if let Some((width, height, units_per_inch)) = spec.physical_size(original_width, original_height) {
+        let pixels_per_unit = dpi / units_per_inch;
+        target_width = (width * pixels_per_unit) as u32;
+        target_height = (height * pixels_per_unit) as u32;
+    }
+
+    // Apply total pixel budgets last so they win over any looser box constraint
+    let (minimum, budget) = total_pixel_limits(spec);
+    target_width = target_width.max(1);
+    target_height = target_height.max(1);
+    let total = target_width as f64 * target_height as f64;
+    if let Some(budget) = budget.filter(|&budget| total > budget) {
+        let scale = (budget / total).sqrt();
+        target_width = (target_width as f64 * scale).floor() as u32;
+        target_height = (target_height as f64 * scale).floor() as u32;
+    } else if let Some(minimum) = minimum.filter(|&minimum| total < minimum) {
+        let scale = (minimum / total).sqrt();
+        target_width = (target_width as f64 * scale).ceil() as u32;
+        target_height = (target_height as f64 * scale).ceil() as u32;
+    }
+
+    // Any ratio rounding above is settled here: both sides take the shorter one, kept
+    // inside the pixel ranges
+    if spec.pixels.as_ref().is_some_and(|pixels| pixels.square) {
+        let side = target_width.min(target_height).clamp(
+            range.min_width.max(range.min_height),
+            range.max_width.min(range.max_height).max(range.min_width.max(range.min_height)),
+        );
+        target_width = side;
+        target_height = side;
+    }
+
+    Ok((target_width.max(1), target_height.max(1)))
+}
+
+// Aspect ratios arrive as f32, so products meant to be whole pixels land a hair to either
+// side; snap those before rounding in the direction the bound needs
+fn snap_px(x: f64) -> Option<u32> {
+    let nearest = x.round();
+    ((x - nearest).abs() <= nearest * 1e-6).then_some(nearest as u32)
+}
+
+fn ceil_px(x: f64) -> u32 {
+    snap_px(x).unwrap_or(x.ceil() as u32)
+}
+
+fn floor_px(x: f64) -> u32 {
+    snap_px(x).unwrap_or(x.floor() as u32)
+}
+
+pub fn validate_conversion_result(
+    data: &[u8],
+    dimensions: &Option<DimensionsSpec>,
+    spec: &DocumentSpec,
+) -> Result<(), ConvertError> {
+    if let Some(violation) = conversion_violations(data, dimensions, spec).into_iter().next() {
+        return Err(ConvertError::Validation(violation));
+    }
+    Ok(())
+}
+
+/// Every size and pixel limit of `spec` the output breaks, in the order
+/// `validate_conversion_result` checks them.
+pub fn conversion_violations(data: &[u8], dimensions: &Option<DimensionsSpec>, spec: &DocumentSpec) -> Vec<String> {
+    let mut violations = Vec::new();
+    let size_kb = (data.len() / 1024) as u32;
+    if let Some(min_size) = spec.size_kb.min.filter(|&min| size_kb < min) {
+        violations.push(format!("File too small: {}KB, minimum required: {}KB", size_kb, min_size));
+    }
+    if size_kb > spec.size_kb.max {
+        violations.push(format!("File too large: {}KB, maximum allowed: {}KB", size_kb, spec.size_kb.max));
+    }
+
+    // Total pixels and shape; PDFs carry no pixel dimensions
+    if let (Some(dimensions), Some(pixel_spec)) = (dimensions, &spec.pixels) {
+        if pixel_spec.square && dimensions.width != dimensions.height {
+            violations.push(format!(
+                "Image is {}x{} but the spec requires a square image",
+                dimensions.width, dimensions.height
+            ));
+        }
+        let total = dimensions.width as u64 * dimensions.height as u64;
+        if let Some(max_total) = pixel_spec.max_total_pixels.filter(|&max| total > max as u64) {
+            violations.push(format!(
+                "Too many pixels: {}x{} is {} pixels, maximum allowed: {}",
+                dimensions.width, dimensions.height, total, max_total
+            ));
+        }
+        if let Some(min_total) = pixel_spec.min_total_pixels.filter(|&min| total < min as u64) {
+            violations.push(format!(
+                "Too few pixels: {}x{} is {} pixels, minimum required: {}",
+                dimensions.width, dimensions.height, total, min_total
+            ));
+        }
+    }
+
+    // What the backend reads is the header, so that is what is checked
+    if let Some(required) = spec.output_color_type.filter(|_| sniff_format(data) != Some("PDF")) {
+        match declared_color_type(data) {
+            Some(found) if found == required.name() => {}
+            found => violations.push(format!(
+                "Output is {} but output_color_type requires {}",
+                found.as_deref().unwrap_or("of an unknown color type"),
+                required.name()
+            )),
+        }
+    }
+    if (spec.required_color_type.is_some() || spec.required_bit_depth.is_some()) && sniff_format(data) != Some("PDF") {
+        let (color_type, bit_depth) = declared_layout(data).unzip();
+        if let Some(required) = spec.required_color_type.filter(|&required| color_type != Some(required)) {
+            violations.push(format!(
+                "Output is {} but required_color_type is {}",
+                color_type.map_or("of an unknown color type", ColorType::name),
+                required.name()
+            ));
+        }
+        if let Some(required) = spec.required_bit_depth.filter(|&required| bit_depth != Some(required)) {
+            violations.push(match bit_depth {
+                Some(bits) => format!("Output is {}-bit but required_bit_depth is {}", bits, required),
+                None => format!("Output is of an unknown bit depth but required_bit_depth is {}", required),
+            });
+        }
+    }
+    violations
+}
+
+pub fn determine_target_format(file_type: &str, spec: &DocumentSpec) -> Result<String, ConvertError> {
+    let preferred_format = if file_type.starts_with("image/") {
+        // For images, prefer the first allowed format, or PNG for the transparency of
+        // `transparent_background`
+        let allowed = spec.allowed_image_formats();
+        match allowed.first() {
+            Some(_) if spec.transparent_background && allowed.contains(&"PNG") => "PNG".to_string(),
+            Some(format) => format.to_string(),
+            None if spec.on_missing_format == MissingFormatPolicy::Jpeg => "JPEG".to_string(),
+            None => return Err(ConvertError::NoTargetFormat),
+        }
+    } else if file_type == "application/pdf" {
+        if spec.allowed_document_formats().contains(&"PDF") {
+            "PDF".to_string()
+        } else {
+            return Err(ConvertError::UnsupportedFormat(
+                "PDF format not supported for this document type".to_string(),
+            ));
+        }
+    } else {
+        return Err(ConvertError::UnsupportedFileType(file_type.to_string()));
+    };
+
+    check_output(&preferred_format)?;
+    Ok(preferred_format)
+}
+
+pub fn generate_converted_filename(
+    original_name: &str,
+    target_format: &str,
+    document_type: &str,
+    extension_case: ExtensionCase,
+) -> String {
+    let base_name = original_name.split('.').next().unwrap_or(original_name);
+    // Use document type in filename for clarity
+    format!("{}_{}.{}", document_type, base_name, output_extension(original_name, target_format, extension_case))
+}
+
+fn output_extension(original_name: &str, target_format: &str, extension_case: ExtensionCase) -> String {
+    let extension = match target_format.to_uppercase().as_str() {
+        "JPEG" | "JPG" => "jpg",
+        "PNG" => "png",
+        "WEBP" => "webp",
+        "PDF" => "pdf",
+        _ => "bin",
+    };
+    let uppercase = match extension_case {
+        ExtensionCase::Lower => false,
+        ExtensionCase::Upper => true,
+        ExtensionCase::PreserveInput => original_name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.chars().any(|c| c.is_ascii_alphabetic()) && !ext.chars().any(|c| c.is_ascii_lowercase()))
+            .unwrap_or(false),
+    };
+    if uppercase { extension.to_uppercase() } else { extension.to_string() }
+}
+
+// The config's `filename_template` filled in for this input, or the default name
+pub(crate) fn converted_filename(file_name: &str, data: &[u8], target_format: &str, config: &ConversionConfig) -> String {
+    let Some(template) = &config.filename_template else {
+        return generate_converted_filename(file_name, target_format, &config.document_type, config.extension_case);
+    };
+    let base_name = file_name.split('.').next().unwrap_or(file_name);
+    let mut stem = String::new();
+    let mut rest = template.as_str();
+    while let Some((literal, tail)) = rest.split_once('{') {
+        let Some((placeholder, after)) = tail.split_once('}') else {
+            break;
+        };
+        stem.push_str(literal);
+        match placeholder {
+            "document_type" => stem.push_str(&config.document_type),
+            "capture_date" => match exif_capture_date(data) {
+                Some(date) => stem.push_str(&date),
+                None => stem.push_str(base_name),
+            },
+            _ => stem.push_str(base_name),
+        }
+        rest = after;
+    }
+    stem.push_str(rest);
+    format!("{}.{}", stem, output_extension(file_name, target_format, config.extension_case))
+}
+
+pub fn get_mime_type(format: &str) -> &'static str {
+    match format.to_uppercase().as_str() {
+        "JPEG" | "JPG" => "image/jpeg",
+        "PNG" => "image/png",
+        "WEBP" => "image/webp",
+        "PDF" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+// The MIME type an output is labelled with: `output_mime_override`, or the format's own
+fn output_mime_type<'a>(format: &str, spec: &'a DocumentSpec) -> &'a str {
+    spec.output_mime_override.as_deref().unwrap_or(get_mime_type(format))
+}
+
+/// MIME type for an input, from its file extension. Browsers supply this for `File`s;
+/// native hosts use it so both builds route inputs the same way.
+pub fn mime_type_from_name(file_name: &str) -> &'static str {
+    let extension = file_name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// How `from` becomes the `to` output: placed on the canvas or fitted, inside the border
+// when the spec has one, which then frames it out to `to`
+fn layout_steps(from: (u32, u32), to: (u32, u32), spec: &DocumentSpec) -> Vec<Transformation> {
+    let border = spec.border.as_ref().map_or(0, |border| border.width_px);
+    let inner = (to.0.saturating_sub(2 * border).max(1), to.1.saturating_sub(2 * border).max(1));
+    let mut steps = match &spec.canvas {
+        Some(canvas) => canvas_steps(from, &CanvasSpec { width: inner.0, height: inner.1, ..canvas.clone() }),
+        None => fit_steps(from, inner, spec),
+    };
+    if let Some(border) = &spec.border {
+        steps.push(Transformation::Border { width: border.width_px, color: border.color.to_uppercase() });
+    }
+    steps
+}
+
+// How `from` reaches exactly `to` under `fit_mode`: a resize, then for `Cover` a crop of
+// the overflow or for `Pad` margins around it. `integer_scaling` resizes by a whole
+// factor and pads whatever remains. Odd remainders put the extra pixel on the right or
+// bottom.
+fn fit_steps(from: (u32, u32), to: (u32, u32), spec: &DocumentSpec) -> Vec<Transformation> {
+    let (width_scale, height_scale) = (to.0 as f64 / from.0 as f64, to.1 as f64 / from.1 as f64);
+    let scaled = |scale: f64| ((from.0 as f64 * scale).round() as u32, (from.1 as f64 * scale).round() as u32);
+    let padded = |(width, height): (u32, u32)| {
+        let (width, height) = (width.clamp(1, to.0), height.clamp(1, to.1));
+        let (left, top) = ((to.0 - width) / 2, (to.1 - height) / 2);
+        let [red, green, blue] = pad_color(spec);
+        let pad = Transformation::Pad {
+            left,
+            top,
+            right: to.0 - width - left,
+            bottom: to.1 - height - top,
+            color: format!("#{:02X}{:02X}{:02X}", red, green, blue),
+        };
+        ((width, height), ((width, height) != to).then_some(pad))
+    };
+    let (resized, extra) = match spec.fit_mode {
+        _ if spec.integer_scaling => padded(integer_scaled(from, to)),
+        FitMode::Stretch => (to, None),
+        FitMode::Cover => {
+            let (width, height) = scaled(width_scale.max(height_scale));
+            let (width, height) = (width.max(to.0), height.max(to.1));
+            let crop = Transformation::Crop { x: (width - to.0) / 2, y: (height - to.1) / 2, width: to.0, height: to.1 };
+            ((width, height), ((width, height) != to).then_some(crop))
+        }
+        FitMode::Pad => padded(scaled(width_scale.min(height_scale))),
+    };
+    let resize = (resized != from).then_some(Transformation::Resize { from: [from.0, from.1], to: [resized.0, resized.1] });
+    resize.into_iter().chain(extra).collect()
+}
+
+// How `from` lands on `canvas`: resized to fit within `scale` of it, then padded out to
+// the canvas with the image at its percentage position
+fn canvas_steps(from: (u32, u32), canvas: &CanvasSpec) -> Vec<Transformation> {
+    let fit = (canvas.width as f64 * canvas.scale as f64 / from.0 as f64)
+        .min(canvas.height as f64 * canvas.scale as f64 / from.1 as f64);
+    let width = ((from.0 as f64 * fit).round() as u32).clamp(1, canvas.width);
+    let height = ((from.1 as f64 * fit).round() as u32).clamp(1, canvas.height);
+    let left = ((canvas.width - width) as f32 * canvas.x_percent / 100.0).round() as u32;
+    let top = ((canvas.height - height) as f32 * canvas.y_percent / 100.0).round() as u32;
+    let resize = ((width, height) != from).then_some(Transformation::Resize { from: [from.0, from.1], to: [width, height] });
+    let pad = ((width, height) != (canvas.width, canvas.height)).then(|| Transformation::Pad {
+        left,
+        top,
+        right: canvas.width - width - left,
+        bottom: canvas.height - height - top,
+        color: canvas.background.to_uppercase(),
+    });
+    resize.into_iter().chain(pad).collect()
+}
+
+// The largest size within `to` that is `from` multiplied or divided by a whole number,
+// so nearest-neighbour sampling keeps every QR or barcode module the same width
+fn integer_scaled(from: (u32, u32), to: (u32, u32)) -> (u32, u32) {
+    if to.0 >= from.0 && to.1 >= from.1 {
+        let factor = (to.0 / from.0).min(to.1 / from.1);
+        (from.0 * factor, from.1 * factor)
+    } else {
+        let divisor = from.0.div_ceil(to.0).max(from.1.div_ceil(to.1));
+        (from.0.div_ceil(divisor), from.1.div_ceil(divisor))
+    }
+}
+
+fn pad_color(spec: &DocumentSpec) -> [u8; 3] {
+    spec.background.as_ref().and_then(BackgroundSpec::required_rgb).unwrap_or([255, 255, 255])
+}
+
+// `img` on a `width`×`height` canvas of `color` with its top-left corner at (`left`, `top`)
+fn pad(img: &image::DynamicImage, width: u32, height: u32, left: u32, top: u32, color: [u8; 3]) -> image::DynamicImage {
+    if img.color().has_alpha() {
+        let mut canvas = image::RgbaImage::from_pixel(width, height, image::Rgba([color[0], color[1], color[2], 255]));
+        image::imageops::replace(&mut canvas, &img.to_rgba8(), left as i64, top as i64);
+        image::DynamicImage::ImageRgba8(canvas)
+    } else {
+        let mut canvas = image::RgbImage::from_pixel(width, height, image::Rgb(color));
+        image::imageops::replace(&mut canvas, &img.to_rgb8(), left as i64, top as i64);
+        image::DynamicImage::ImageRgb8(canvas)
+    }
+}
+
+// The centered square crop `pixels.square` needs, if the image is not square already
+fn square_crop(width: u32, height: u32, spec: &DocumentSpec) -> Option<Transformation> {
+    let square = spec.pixels.as_ref().is_some_and(|pixels| pixels.square);
+    let side = width.min(height);
+    (square && width != height).then(|| Transformation::Crop {
+        x: (width - side) / 2,
+        y: (height - side) / 2,
+        width: side,
+        height: side,
+    })
+}
+
+// Document types that are pictures of a person, by name: "photo", "passport_photo"
+fn looks_like_photo(document_type: &str) -> bool {
+    document_type.to_ascii_lowercase().contains("photo")
+}
+
+// Document types that are signatures, by name: "signature", "sign", "thumb_sign"
+fn looks_like_signature(document_type: &str) -> bool {
+    document_type.to_ascii_lowercase().contains("sign")
+}
+
+// Whether the spec wants landscape (true) or portrait (false) output, by its exact pixel
+// or print size or else its aspect ratio bounds; None when it is square or open
+fn expected_landscape(spec: &DocumentSpec) -> Option<bool> {
+    let pixels = spec.pixels.as_ref().and_then(|pixels| Some((pixels.width? as f32, pixels.height? as f32)));
+    let printed = [&spec.dimensions_cm, &spec.dimensions_mm].into_iter().flatten().find_map(|size| Some((size.width?, size.height?)));
+    let ratio = match pixels.or(printed) {
+        Some((width, height)) => width / height,
+        None => {
+            let (lowest, highest) = spec.aspect_ratio.as_ref()?.width_to_height_bounds();
+            if lowest > 1.0 {
+                lowest
+            } else if highest < 1.0 {
+                highest
+            } else {
+                return None;
+            }
+        }
+    };
+    (ratio != 1.0).then_some(ratio > 1.0)
+}
+
+fn needs_rotation(width: u32, height: u32, orientation: PrintOrientation) -> bool {
+    match orientation {
+        PrintOrientation::Portrait => width > height,
+        PrintOrientation::Landscape => height > width,
+        PrintOrientation::Auto => false,
+    }
+}
+
+fn capture_intermediate(
+    enabled: bool,
+    debug: &mut Vec<DebugIntermediate>,
+    stage: &str,
+    img: &image::DynamicImage,
+    transformation: Option<&Transformation>,
+) {
+    if !enabled || debug.len() >= MAX_DEBUG_INTERMEDIATES {
+        return;
+    }
+    let preview = img.thumbnail(DEBUG_PREVIEW_MAX_EDGE, DEBUG_PREVIEW_MAX_EDGE);
+    let mut png = Vec::new();
+    if preview.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).is_err() {
+        return;
+    }
+    debug.push(DebugIntermediate {
+        stage: stage.to_string(),
+        width: img.width(),
+        height: img.height(),
+        transformation: transformation.cloned(),
+        data_url: format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(&png)),
+    });
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::events::NoopObserver;
+    use crate::core::pdf::scanned_pages;
+    use crate::core::result::Region;
+    #[cfg(feature = "webp")]
+    use crate::core::test_support::animated_webp;
+    use crate::core::test_support::{
+        config_from, dusty, encode_png, fine_signature, gradient, jpeg_pdf, jpeg_with_exif, scanned_paper, signature, spec_from, tiff_with_capture_date, tilted_paper,
+    };
+    use std::cell::Cell;
+
+    #[test]
+    fn max_megapixels_caps_total_pixels_preserving_aspect() {
+        let spec = spec_from(serde_json::json!({ "max_megapixels": 2.0 }));
+        let (width, height) = calculate_target_dimensions(4000, 3000, &spec).unwrap();
+        assert!(width * height <= 2_000_000, "{}x{}", width, height);
+        assert!(width * height > 1_990_000, "{}x{}", width, height);
+        assert!(((width as f32 / height as f32) - 4.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn total_pixel_bounds_scale_preserving_aspect() {
+        // A 400x750 image passes per-axis limits but not a 0.3 megapixel cap
+        let spec = spec_from(serde_json::json!({ "pixels": { "max_width": 1000, "max_total_pixels": 300_000 } }));
+        let (width, height) = calculate_target_dimensions(400, 750, &spec).unwrap();
+        assert!(width * height <= 300_000 && width * height > 298_000, "{}x{}", width, height);
+        assert!(((width as f32 / height as f32) - 400.0 / 750.0).abs() < 0.01);
+
+        // The tighter of max_megapixels and max_total_pixels applies
+        let spec = spec_from(serde_json::json!({ "max_megapixels": 0.2, "pixels": { "max_total_pixels": 300_000 } }));
+        let (width, height) = calculate_target_dimensions(400, 750, &spec).unwrap();
+        assert!(width * height <= 200_000, "{}x{}", width, height);
+
+        let spec = spec_from(serde_json::json!({ "pixels": { "min_total_pixels": 120_000 } }));
+        let (width, height) = calculate_target_dimensions(200, 300, &spec).unwrap();
+        assert!(width * height >= 120_000 && width * height < 122_000, "{}x{}", width, height);
+        assert_eq!(calculate_target_dimensions(400, 750, &spec).unwrap(), (400, 750));
+    }
+
+    #[test]
+    fn validation_checks_total_pixels() {
+        let spec = spec_from(serde_json::json!({
+            "size_kb": { "max": 50 },
+            "pixels": { "min_total_pixels": 1000, "max_total_pixels": 300_000 }
+        }));
+        let dimensions = |width: f32, height: f32| Some(DimensionsSpec { width, height });
+        assert!(validate_conversion_result(&[0; 1024], &dimensions(400.0, 700.0), &spec).is_ok());
+        assert!(validate_conversion_result(&[0; 1024], &None, &spec).is_ok());
+        assert_eq!(
+            validate_conversion_result(&[0; 1024], &dimensions(401.0, 750.0), &spec).unwrap_err().to_string(),
+            "Too many pixels: 401x750 is 300750 pixels, maximum allowed: 300000"
+        );
+        assert!(validate_conversion_result(&[0; 1024], &dimensions(20.0, 20.0), &spec).is_err());
+    }
+
+    #[test]
+    fn max_megapixels_combines_with_pixel_box() {
+        // The box is tighter than the budget here, so the box wins
+        let spec = spec_from(serde_json::json!({
+            "max_megapixels": 2.0,
+            "pixels": { "max_width": 1000, "max_height": 750 }
+        }));
+        assert_eq!(calculate_target_dimensions(4000, 3000, &spec).unwrap(), (1000, 750));
+
+        // Small inputs are left untouched
+        let spec = spec_from(serde_json::json!({ "max_megapixels": 2.0 }));
+        assert_eq!(calculate_target_dimensions(800, 600, &spec).unwrap(), (800, 600));
+    }
+
+    #[test]
+    fn sha256_hex_matches_known_digest() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn debug_intermediates_are_capped_and_downscaled() {
+        let img = image::DynamicImage::ImageRgb8(gradient(1200, 600));
+        let mut debug = Vec::new();
+        for _ in 0..MAX_DEBUG_INTERMEDIATES + 3 {
+            capture_intermediate(true, &mut debug, "resized", &img, None);
+        }
+        assert_eq!(debug.len(), MAX_DEBUG_INTERMEDIATES);
+        assert_eq!((debug[0].width, debug[0].height), (1200, 600));
+
+        let encoded = debug[0].data_url.strip_prefix("data:image/png;base64,").unwrap();
+        let png = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+        let preview = image::load_from_memory(&png).unwrap();
+        assert_eq!(preview.dimensions(), (400, 200));
+    }
+
+    #[test]
+    fn debug_intermediates_are_off_by_default() {
+        let mut debug = Vec::new();
+        capture_intermediate(false, &mut debug, "decoded", &image::DynamicImage::new_rgb8(4, 4), None);
+        assert!(debug.is_empty());
+    }
+
+    #[test]
+    fn filename_extension_case() {
+        let name = |original: &str, case| generate_converted_filename(original, "JPEG", "photo", case);
+        assert_eq!(name("scan.PNG", ExtensionCase::Lower), "photo_scan.jpg");
+        assert_eq!(name("scan.png", ExtensionCase::Upper), "photo_scan.JPG");
+        assert_eq!(name("IMG_001.JPG", ExtensionCase::PreserveInput), "photo_IMG_001.JPG");
+        assert_eq!(name("IMG_001.Jpg", ExtensionCase::PreserveInput), "photo_IMG_001.jpg");
+        assert_eq!(name("no_extension", ExtensionCase::PreserveInput), "photo_no_extension.jpg");
+    }
+
+    #[test]
+    fn plan_mirrors_transformations_from_header() {
+        let config = config_from(serde_json::json!({
+            "pixels": { "width": 276, "height": 354 },
+            "posterize_levels": 8
+        }));
+        let png = encode_png(&image::DynamicImage::new_rgb8(600, 800));
+        let plan = plan("me.png", "image/png", &png, &config).unwrap();
+        assert_eq!(plan.source_dimensions, Some([600, 800]));
+        assert_eq!(plan.target_dimensions, Some([276, 354]));
+        assert_eq!(plan.converted_name, "photo_me.jpg");
+        assert_eq!(
+            plan.transformations,
+            vec![
+                Transformation::Resize { from: [600, 800], to: [276, 354] },
+                Transformation::Posterize { levels: 8 },
+                Transformation::Encode { format: "JPEG".to_string(), quality: None, bytes: 100 * 1024 },
+            ]
+        );
+    }
+
+    #[test]
+    fn exact_pixel_spec_overrides_source_dimensions() {
+        let spec = spec_from(serde_json::json!({ "pixels": { "width": 276, "height": 354 } }));
+        assert_eq!(calculate_target_dimensions(1200, 900, &spec).unwrap(), (276, 354));
+    }
+
+    #[test]
+    fn pixel_ranges_clamp_each_axis() {
+        let spec = spec_from(serde_json::json!({
+            "pixels": { "min_width": 200, "max_width": 500, "min_height": 300, "max_height": 400 }
+        }));
+        assert_eq!(calculate_target_dimensions(100, 1000, &spec).unwrap(), (200, 400));
+
+        // The nested min/max form is equivalent
+        let spec = spec_from(serde_json::json!({
+            "pixels": { "min": { "width": 200, "height": 300 }, "max": { "width": 500, "height": 400 } }
+        }));
+        assert_eq!(calculate_target_dimensions(100, 1000, &spec).unwrap(), (200, 400));
+    }
+
+    #[test]
+    fn physical_dimensions_use_resolution() {
+        let spec = spec_from(serde_json::json!({
+            "dimensions_cm": { "width": 3.5, "height": 4.5 },
+            "resolution_px_per_inch": 300
+        }));
+        assert_eq!(calculate_target_dimensions(1000, 1000, &spec).unwrap(), (413, 531));
+
+        // 150 DPI when no resolution is given
+        let spec = spec_from(serde_json::json!({ "dimensions_mm": { "width": 35.0, "height": 45.0 } }));
+        assert_eq!(calculate_target_dimensions(1000, 1000, &spec).unwrap(), (206, 265));
+
+        // Height only: the width follows the source's 3:4 proportions
+        let spec = spec_from(serde_json::json!({ "dimensions_cm": { "height": 4.5 }, "resolution_px_per_inch": 200 }));
+        assert_eq!(calculate_target_dimensions(600, 800, &spec).unwrap(), (265, 354));
+        assert_eq!(calculate_target_dimensions(800, 800, &spec).unwrap(), (354, 354));
+    }
+
+    #[test]
+    fn aspect_ratio_bounds_adjust_one_axis() {
+        let spec = spec_from(serde_json::json!({ "aspect_ratio": { "min": 0.7, "max": 0.8 } }));
+        assert_eq!(calculate_target_dimensions(1000, 1000, &spec).unwrap(), (1000, 1250));
+        assert_eq!(calculate_target_dimensions(500, 1000, &spec).unwrap(), (700, 1000));
+    }
+
+    #[test]
+    fn one_sided_aspect_bounds_apply() {
+        let spec = spec_from(serde_json::json!({ "aspect_ratio": { "height_to_width_max": 0.2 } }));
+        assert_eq!(calculate_target_dimensions(1, 4, &spec).unwrap(), (5, 1));
+        let spec = spec_from(serde_json::json!({ "aspect_ratio": { "min": 0.75 } }));
+        assert_eq!(calculate_target_dimensions(300, 1000, &spec).unwrap(), (750, 1000));
+    }
+
+    #[test]
+    fn aspect_correction_stays_inside_pixel_range() {
+        // Widening to the ratio would pass max_width, so the height shrinks instead
+        let spec = spec_from(serde_json::json!({
+            "pixels": { "max_width": 1 },
+            "aspect_ratio": { "min": 0.2, "max": 0.2 }
+        }));
+        assert_eq!(calculate_target_dimensions(1, 1291, &spec).unwrap(), (1, 5));
+    }
+
+    #[test]
+    fn panorama_against_portrait_ratio_stays_bounded() {
+        // A 10:1 panorama against a 3:4 photo spec lands on the spec's box
+        let spec = spec_from(serde_json::json!({
+            "pixels": { "max_width": 600, "max_height": 800 },
+            "aspect_ratio": { "min": 0.75, "max": 0.75 }
+        }));
+        assert_eq!(calculate_target_dimensions(4000, 400, &spec).unwrap(), (600, 800));
+
+        // Without pixel maximums the grown axis stops at MAX_ASPECT_EDGE and the width
+        // gives way, instead of the height reaching 40000
+        let spec = spec_from(serde_json::json!({ "aspect_ratio": { "min": 0.75, "max": 0.75 } }));
+        assert_eq!(calculate_target_dimensions(30000, 3000, &spec).unwrap(), (12288, 16384));
+    }
+
+    #[test]
+    fn inverted_pixel_range_is_an_error() {
+        let spec = spec_from(serde_json::json!({ "pixels": { "min_width": 1417, "max_width": 1 } }));
+        assert_eq!(
+            calculate_target_dimensions(1, 1, &spec).unwrap_err(),
+            ConvertError::InvalidConfig("pixels min width 1417 exceeds max width 1".to_string())
+        );
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        stats: Cell<SessionStats>,
+        cancelled: bool,
+    }
+
+    impl Observer for Recorder {
+        fn record(&self, update: &dyn Fn(&mut SessionStats)) {
+            let mut stats = self.stats.get();
+            update(&mut stats);
+            self.stats.set(stats);
+        }
+
+        fn is_cancelled(&self) -> bool {
+            self.cancelled
+        }
+    }
+
+    // Pseudo-random noise compresses poorly, so JPEG size tracks quality closely
+    fn noise(width: u32, height: u32) -> image::DynamicImage {
+        let mut state = 0x2545_f491u32;
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |_, _| {
+            let mut next = || {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            };
+            image::Rgb([next(), next(), next()])
+        }))
+    }
+
+    #[test]
+    fn quality_loop_steps_down_until_output_fits() {
+        let img = noise(128, 128);
+        let first = encode_to_size("noise.png", &img, "JPEG", usize::MAX, &spec_from(serde_json::json!({})), &NoopObserver).unwrap();
+        assert_eq!(first.quality, Some(90));
+
+        let recorder = Recorder::default();
+        let budget = first.data.len() * 3 / 4;
+        let encoded = encode_to_size("noise.png", &img, "JPEG", budget, &spec_from(serde_json::json!({})), &recorder).unwrap();
+        assert!(encoded.data.len() <= budget, "{} > {}", encoded.data.len(), budget);
+        assert!(encoded.quality.unwrap() < 90);
+        assert!(recorder.stats.get().encode_passes >= 2);
+    }
+
+    #[test]
+    fn quality_loop_gives_up_below_minimum_quality() {
+        let recorder = Recorder::default();
+        let result = encode_to_size("noise.png", &noise(128, 128), "JPEG", 64, &spec_from(serde_json::json!({})), &recorder);
+        assert_eq!(
+            result.unwrap_err(),
+            ConvertError::Encode("Cannot compress image to meet size requirements".to_string())
+        );
+        assert!(recorder.stats.get().encode_passes >= 8);
+    }
+
+    #[test]
+    fn quality_ladder_only_uses_its_rungs() {
+        let img = noise(128, 128);
+        let spec = spec_from(serde_json::json!({ "quality_ladder": [45, 85, 65, 75, 55] }));
+        let rung_75 = encode_once(&img, "JPEG", 75, &spec).unwrap().len();
+        let recorder = Recorder::default();
+        let encoded = encode_to_size("noise.png", &img, "JPEG", rung_75, &spec, &recorder).unwrap();
+        assert_eq!(encoded.quality, Some(75));
+        assert_eq!(recorder.stats.get().encode_passes, 2);
+        // Bisection would land just under the budget; the ladder stays on a rung
+        let encoded = encode_to_target("noise.png", &img, "JPEG", rung_75 + 1, &spec, &NoopObserver).unwrap().unwrap();
+        assert_eq!(encoded.quality, Some(75));
+
+        assert_eq!(
+            encode_to_size("noise.png", &img, "JPEG", 64, &spec, &NoopObserver).unwrap_err().to_string(),
+            "Cannot compress image to meet size requirements at the lowest quality_ladder quality 45"
+        );
+        assert!(encode_to_target("noise.png", &img, "JPEG", 64, &spec, &NoopObserver).unwrap().is_none());
+    }
+
+    #[test]
+    fn quality_scale_maps_qualities_before_encoding() {
+        let photoshop = spec_from(serde_json::json!({ "quality_scale": "Photoshop", "quality_ladder": [50] }));
+        let encoded = encode_to_size("noise.jpg", &noise(64, 64), "JPEG", 1 << 20, &photoshop, &NoopObserver).unwrap();
+        // Reported on the Photoshop scale, encoded at its libjpeg equivalent
+        assert_eq!(encoded.quality, Some(50));
+        assert_eq!(encoded.data, encode_once(&noise(64, 64), "JPEG", 72, &spec_from(serde_json::json!({}))).unwrap());
+    }
+
+    #[test]
+    fn quality_loop_encodes_png_once() {
+        let recorder = Recorder::default();
+        let encoded = encode_to_size("noise.png", &noise(64, 64), "PNG", 1, &spec_from(serde_json::json!({})), &recorder).unwrap();
+        assert_eq!(encoded.quality, None);
+        assert_eq!(recorder.stats.get().encode_passes, 1);
+    }
+
+    #[test]
+    fn quality_loop_stops_when_cancelled() {
+        let recorder = Recorder { cancelled: true, ..Recorder::default() };
+        let result = encode_to_size("noise.png", &noise(64, 64), "JPEG", usize::MAX, &spec_from(serde_json::json!({})), &recorder);
+        assert_eq!(result.unwrap_err(), ConvertError::Cancelled);
+        assert_eq!(recorder.stats.get().encode_passes, 0);
+    }
+
+    #[test]
+    fn conversions_cancelled_mid_run_stop_after_the_current_pass() {
+        // Cancels as soon as the first encode attempt has been counted
+        struct CancelAfterFirstPass(Recorder);
+        impl Observer for CancelAfterFirstPass {
+            fn record(&self, update: &dyn Fn(&mut SessionStats)) {
+                self.0.record(update);
+            }
+
+            fn is_cancelled(&self) -> bool {
+                self.0.stats.get().encode_passes >= 1
+            }
+        }
+
+        let png = encode_png(&noise(128, 128));
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 8 } }));
+        // Uncancelled, the size limit takes several passes
+        let recorder = Recorder::default();
+        let _ = convert("noise.png", "image/png", &png, &config, &recorder);
+        assert!(recorder.stats.get().encode_passes > 1);
+        let observer = CancelAfterFirstPass(Recorder::default());
+        let error = convert("noise.png", "image/png", &png, &config, &observer).unwrap_err();
+        assert_eq!(error, ConvertError::Cancelled);
+        assert_eq!(error.code(), Some("cancelled"));
+        assert_eq!(observer.0.stats.get().encode_passes, 1);
+    }
+
+    #[test]
+    fn quality_loop_rejects_unknown_format() {
+        let result = encode_to_size("noise.png", &noise(8, 8), "GIF", usize::MAX, &spec_from(serde_json::json!({})), &NoopObserver);
+        assert!(matches!(result, Err(ConvertError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn target_search_finds_the_highest_quality_that_fits() {
+        let img = noise(128, 128);
+        let spec = spec_from(serde_json::json!({}));
+        let budget = 20_000;
+        let encoded = encode_to_target("noise.png", &img, "JPEG", budget, &spec, &NoopObserver).unwrap().unwrap();
+        let quality = encoded.quality.unwrap();
+        assert!(encoded.data.len() <= budget);
+        assert!(encode_once(&img, "JPEG", quality + 1, &spec).unwrap().len() > budget);
+        assert!(encode_to_target("noise.png", &img, "JPEG", 64, &spec, &NoopObserver).unwrap().is_none());
+    }
+
+    #[test]
+    fn target_bytes_override_the_size_band_and_shrink_when_needed() {
+        let png = encode_png(&noise(64, 64));
+        let config = config_from(serde_json::json!({ "size_kb": { "max": 1 } }));
+        let converted = convert_to_target_bytes("noise.png", "image/png", &png, &config, 6000, &NoopObserver).unwrap();
+        assert!(converted.size_bytes <= 6000 && converted.size_bytes > 1024, "{}", converted.size_bytes);
+        assert_eq!(converted.size_bytes as usize, converted.decode_data().unwrap().len());
+        assert_eq!(converted.applied_spec.size_kb.max, 6);
+
+        // Lossless output can only get smaller by scaling down
+        let config = config_from(serde_json::json!({ "format": ["PNG"] }));
+        let converted = convert_to_target_bytes("noise.png", "image/png", &png, &config, 4000, &NoopObserver).unwrap();
+        assert!(converted.size_bytes <= 4000);
+        assert!(converted.transformations.iter().any(|t| matches!(t, Transformation::Resize { .. })));
+        assert!(converted.dimensions.unwrap().width < 64.0);
+    }
+
+    #[test]
+    fn required_color_type_and_bit_depth_are_written_and_checked() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 48)));
+        let converted = convert("photo.png", "image/png", &png, &config_from(serde_json::json!({ "format": ["PNG"] })), &NoopObserver).unwrap();
+        assert_eq!((converted.color_type, converted.bit_depth), (Some(ColorType::Rgba), Some(32)));
+
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "required_bit_depth": 24 }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!((converted.color_type, converted.bit_depth), (Some(ColorType::Rgb), Some(24)));
+        assert!(converted.compliant);
+
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "required_color_type": "grayscale", "required_bit_depth": 8 }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!((converted.color_type, converted.bit_depth), (Some(ColorType::Grayscale), Some(8)));
+        assert!(converted.compliant, "{:?}", converted.violations);
+
+        // Outputs written some other way are reported
+        let rgba = encode_png(&image::DynamicImage::ImageRgba8(image::RgbaImage::new(8, 8)));
+        let spec = config_from(serde_json::json!({ "format": ["PNG"], "required_color_type": "rgb", "required_bit_depth": 24 })).target_spec;
+        assert_eq!(
+            conversion_violations(&rgba, &None, &spec),
+            vec!["Output is rgba but required_color_type is rgb", "Output is 32-bit but required_bit_depth is 24"]
+        );
+    }
+
+    #[test]
+    fn output_mime_override_relabels_the_data_url_only() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 48)));
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "output_mime_override": "image/jpg" }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(converted.data_url.starts_with("data:image/jpg;base64,"), "{}", &converted.data_url[..32]);
+        assert_eq!(converted.format, "JPEG");
+        assert_eq!(image::guess_format(&converted.decode_data().unwrap()).unwrap(), image::ImageFormat::Jpeg);
+        let thumbnail = convert_with_thumbnail("photo.png", "image/png", &png, &config, 16, &NoopObserver).unwrap().thumbnail;
+        assert!(thumbnail.data_url.starts_with("data:image/jpg;base64,"));
+    }
+
+    #[test]
+    fn formats_fall_back_in_order_when_one_cannot_fit() {
+        let png = encode_png(&noise(128, 128));
+        let config = config_from(serde_json::json!({ "format": ["PNG", "JPEG"], "size_kb": { "max": 40 } }));
+        let converted = convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.format, "JPEG");
+        assert_eq!(converted.converted_name, "photo_noise.jpg");
+        assert!(converted.data_url.starts_with("data:image/jpeg;base64,"));
+        assert_eq!(converted.format_fallbacks.len(), 1);
+        assert_eq!(converted.format_fallbacks[0].format, "PNG");
+        assert!(converted.format_fallbacks[0].reason.starts_with("File too large: "), "{}", converted.format_fallbacks[0].reason);
+
+        // When the last allowed format cannot fit either, its own error is reported
+        let config = config_from(serde_json::json!({ "format": ["PNG", "JPEG"], "size_kb": { "max": 1 } }));
+        let error = convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert!(
+            matches!(&error, ConvertError::SizeConstraints { message, .. } if message == "Cannot compress image to meet size requirements"),
+            "{:?}",
+            error
+        );
+        // A format that fits first time records no fallbacks
+        let config = config_from(serde_json::json!({ "format": ["JPEG", "PNG"], "size_kb": { "max": 40 } }));
+        assert!(convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap().format_fallbacks.is_empty());
+    }
+
+    #[test]
+    fn best_effort_returns_the_closest_output_flagged_non_compliant() {
+        let png = encode_png(&noise(128, 128));
+        let mut config = config_from(serde_json::json!({ "format": ["JPEG", "PDF"], "size_kb": { "max": 1 } }));
+        assert!(matches!(convert("noise.png", "image/png", &png, &config, &NoopObserver), Err(ConvertError::SizeConstraints { .. })));
+
+        config.return_best_effort = true;
+        let converted = convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(!converted.compliant);
+        assert_eq!(converted.violations, [format!("File too large: {}KB, maximum allowed: 1KB", converted.size_kb)]);
+        assert!(converted.transformations.contains(&Transformation::Encode {
+            format: "JPEG".to_string(),
+            quality: Some(10),
+            bytes: converted.size_bytes as usize,
+        }));
+        let json = serde_json::to_value(&converted).unwrap();
+        assert_eq!((&json["compliant"], json["violations"].as_array().unwrap().len()), (&serde_json::json!(false), 1));
+
+        // An oversized PDF comes back unchanged
+        let pdf = [b"%PDF-1.4\n".as_slice(), &[b' '; 2048]].concat();
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.decode_data().unwrap(), pdf);
+        assert_eq!(converted.violations, ["File too large: 2KB, maximum allowed: 1KB"]);
+
+        // Outputs within the spec stay compliant with no violations listed
+        let config = config_from(serde_json::json!({ "size_kb": { "max": 200 } }));
+        let converted = convert("noise.png", "image/png", &png, &ConversionConfig { return_best_effort: true, ..config }, &NoopObserver).unwrap();
+        assert!(converted.compliant && converted.violations.is_empty());
+        assert!(serde_json::to_value(&converted).unwrap().get("violations").is_none());
+    }
+
+    #[test]
+    fn performance_profile_sets_the_quality_steps() {
+        let png = encode_png(&noise(128, 128));
+        let qualities = |profile: &str| {
+            let config = config_from(serde_json::json!({ "size_kb": { "max": 1 }, "performance_profile": profile }));
+            let error = convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+            error.attempts().iter().map(|attempt| attempt.quality.unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(qualities("Fast"), [90, 70, 50, 30, 10]);
+        assert_eq!(qualities("Balanced"), [90, 80, 70, 60, 50, 40, 30, 20, 10]);
+        assert_eq!(qualities("Best")[..3], [90, 85, 80]);
+
+        // Best also shrinks few-color PNGs to indexed color, unless the spec says otherwise
+        let flat = encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| image::Rgb([(x / 16 * 60) as u8; 3]))));
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"], "performance_profile": "Best" }));
+        let color_type = |config: &ConversionConfig| convert("flat.png", "image/png", &flat, config, &NoopObserver).unwrap().decode_data().unwrap()[25];
+        assert_eq!(color_type(&config), 3);
+        config.target_spec.png_optimize = Some(false);
+        assert_eq!(color_type(&config), 6);
+    }
+
+    #[test]
+    fn size_failures_report_their_encodes_within_max_attempts() {
+        let png = encode_png(&noise(128, 128));
+        let config = config_from(serde_json::json!({ "size_kb": { "max": 1 } }));
+        let error = convert("noise.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error.code(), Some("size_constraints"));
+        let attempts = error.attempts();
+        assert_eq!(attempts[0], EncodeAttempt { format: "JPEG".to_string(), quality: Some(90), dimensions: [128, 128], size_kb: attempts[0].size_kb });
+        assert!(attempts.windows(2).all(|pair| pair[1].quality < pair[0].quality && pair[1].size_kb <= pair[0].size_kb));
+        assert!(error.to_string().starts_with(&format!(
+            "Cannot compress image to meet size requirements after {} encodes: JPEG q90 128x128 {}KB, JPEG q80 ",
+            attempts.len(),
+            attempts[0].size_kb
+        )));
+
+        // The budget stops the search early and counts every format tried
+        let capped = ConversionConfig { max_attempts: 3, ..config_from(serde_json::json!({ "format": ["PNG", "JPEG"], "size_kb": { "max": 1 } })) };
+        let error = convert("noise.png", "image/png", &png, &capped, &NoopObserver).unwrap_err();
+        let formats: Vec<_> = error.attempts().iter().map(|attempt| attempt.format.as_str()).collect();
+        assert_eq!(formats, ["PNG", "JPEG", "JPEG"]);
+        assert!(error.to_string().starts_with("Size limits not met within max_attempts 3 after 3 encodes: PNG 128x128 "), "{}", error);
+        let error = convert_to_target_bytes("noise.png", "image/png", &png, &capped, 500, &NoopObserver).unwrap_err();
+        assert_eq!(error.attempts().len(), 3);
+
+        // A cancellation from the caller stays a cancellation
+        struct Cancelled;
+        impl Observer for Cancelled {
+            fn is_cancelled(&self) -> bool {
+                true
+            }
+        }
+        assert_eq!(convert("noise.png", "image/png", &png, &capped, &Cancelled).unwrap_err(), ConvertError::Cancelled);
+    }
+
+    #[test]
+    fn host_encoders_run_the_quality_loop() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(120, 160)));
+        let config = config_from(serde_json::json!({ "format": ["WEBP"], "size_kb": { "max": 5 } }));
+        assert!(prepare_host_encode("photo.png", "image/png", &png, &config_from(serde_json::json!({})), &NoopObserver).unwrap().is_none());
+
+        // A stand-in encoder whose output grows with quality: 100 bytes per step
+        let run = |config: &ConversionConfig| {
+            let mut encode = prepare_host_encode("photo.png", "image/png", &png, config, &NoopObserver).unwrap().unwrap();
+            assert_eq!(encode.rgba.dimensions(), (120, 160));
+            let mut qualities = Vec::new();
+            while let Some(quality) = encode.next_quality("photo.png", &NoopObserver).unwrap() {
+                qualities.push(quality);
+                encode.push("photo.png", vec![0; quality as usize * 100], &NoopObserver);
+            }
+            (qualities, encode.finish("photo.png", &png, "TestCanvas", config, &NoopObserver))
+        };
+        let (qualities, converted) = run(&config);
+        assert_eq!(qualities, [90, 80, 70, 60, 50]);
+        let converted = converted.unwrap();
+        assert_eq!((converted.format.as_str(), converted.size_bytes, converted.converted_name.as_str()), ("WEBP", 5000, "photo_photo.webp"));
+        assert!(converted.data_url.starts_with("data:image/webp;base64,"));
+        assert_eq!(converted.codec_info.encoder.as_deref(), Some("TestCanvas"));
+        assert_eq!(converted.warnings, ["Encoded to WEBP by TestCanvas, which may give different bytes in other browsers or versions"]);
+        assert!(matches!(converted.transformations.last(), Some(Transformation::Encode { quality: Some(50), bytes: 5000, .. })));
+
+        let capped = ConversionConfig { max_attempts: 2, ..config.clone() };
+        let (qualities, error) = run(&capped);
+        assert_eq!(qualities, [90, 80]);
+        let error = error.unwrap_err();
+        assert_eq!(error.attempts().len(), 2);
+        assert!(error.to_string().starts_with("Size limits not met within max_attempts 2 after 2 encodes: WEBP q90 120x160 8KB"), "{}", error);
+    }
+
+    #[test]
+    fn host_encodes_fall_back_to_later_formats() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(120, 160)));
+        let config = config_from(serde_json::json!({ "format": ["WEBP", "JPEG"], "size_kb": { "max": 5 } }));
+
+        // An encoder that never gets under the cap
+        let mut encode = prepare_host_encode("photo.png", "image/png", &png, &config, &NoopObserver).unwrap().unwrap();
+        while encode.next_quality("photo.png", &NoopObserver).unwrap().is_some() {
+            encode.push("photo.png", vec![0; 8000], &NoopObserver);
+        }
+        let converted = encode.finish("photo.png", &png, "TestCanvas", &config, &NoopObserver).unwrap();
+        assert_eq!((converted.format.as_str(), converted.converted_name.as_str()), ("JPEG", "photo_photo.jpg"));
+        assert!(converted.size_bytes <= 5 * 1024 && converted.codec_info.encoder.as_deref() != Some("TestCanvas"));
+        assert_eq!(converted.format_fallbacks.len(), 1);
+        assert_eq!(converted.format_fallbacks[0].format, "WEBP");
+        assert!(converted.format_fallbacks[0].reason.starts_with("Cannot compress image to meet size requirements"));
+
+        // An encoder that fails outright
+        let encode = prepare_host_encode("photo.png", "image/png", &png, &config, &NoopObserver).unwrap().unwrap();
+        let error = ConvertError::Encode("This browser cannot encode image/webp".to_string());
+        let converted = encode.fall_back("photo.png", &png, error.clone(), &config, &NoopObserver).unwrap();
+        assert_eq!(converted.format, "JPEG");
+        assert_eq!(converted.format_fallbacks, [FormatFallback { format: "WEBP".to_string(), reason: error.to_string() }]);
+
+        // With nothing left to fall back to, the host's error stands
+        let webp_only = config_from(serde_json::json!({ "format": ["WEBP"], "size_kb": { "max": 5 } }));
+        let encode = prepare_host_encode("photo.png", "image/png", &png, &webp_only, &NoopObserver).unwrap().unwrap();
+        assert_eq!(encode.fall_back("photo.png", &png, error.clone(), &webp_only, &NoopObserver).unwrap_err(), error);
+    }
+
+    #[test]
+    #[cfg(feature = "webp")]
+    fn animated_webps_pass_through_when_webp_is_allowed() {
+        let webp = animated_webp(3, 4000);
+        let config = config_from(serde_json::json!({ "format": ["PNG", "WEBP"], "size_kb": { "max": 10 } }));
+        let converted = convert("sign.webp", "image/webp", &webp, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations, [Transformation::KeepAnimation { frames: 3 }]);
+        assert_eq!((converted.format.as_str(), converted.converted_name.as_str()), ("WEBP", "photo_sign.webp"));
+        assert_eq!(converted.decode_data().unwrap(), webp);
+        assert!(converted.warnings.is_empty() && converted.compliant);
+        let config = config_from(serde_json::json!({ "format": ["WEBP"], "size_kb": { "max": 10 } }));
+        assert!(prepare_host_encode("sign.webp", "image/webp", &webp, &config, &NoopObserver).unwrap().is_none());
+
+        // Too large to keep, the first frame goes to the host encoder like any still image
+        let large = animated_webp(3, 20_000);
+        let mut encode = prepare_host_encode("sign.webp", "image/webp", &large, &config, &NoopObserver).unwrap().unwrap();
+        encode.next_quality("sign.webp", &NoopObserver).unwrap().unwrap();
+        encode.push("sign.webp", vec![0; 100], &NoopObserver);
+        let converted = encode.finish("sign.webp", &large, "TestCanvas", &config, &NoopObserver).unwrap();
+        assert_eq!(
+            converted.warnings[0],
+            "The animated WebP could not be kept whole (File too large: 19KB, maximum allowed: 10KB), so only its first frame was converted"
+        );
+
+        // PNG cannot hold the frames at all
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 10 } }));
+        let converted = convert("sign.webp", "image/webp", &webp, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.format, "PNG");
+        assert_eq!(converted.warnings, ["The animated WebP has 3 frames, but PNG cannot hold animation, so only the first was converted"]);
+    }
+
+    #[test]
+    #[cfg(feature = "pdf-raster")]
+    fn oversized_scanned_pdfs_are_rasterized_as_a_last_resort() {
+        // Two distinct A4 pages, each a 620x877 (75 DPI) scan
+        let scan = |seed: u32| {
+            let mut jpeg = Vec::new();
+            image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(620, 877, |x, y| {
+                let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729) ^ seed).wrapping_mul(2654435761) >> 24;
+                image::Rgb([noise as u8, ((x + 128 * seed) % 256) as u8, (y % 256) as u8])
+            }))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(95))
+            .unwrap();
+            (jpeg, 620, 877)
+        };
+        let pdf = jpeg_pdf(&[scan(1), scan(2)], &[(595.0, 842.0), (595.0, 842.0)]);
+        let mut config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 150 }, "pdf_rasterize_dpi": 50 }));
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert!(pdf.len() > 150 * 1024 && converted.size_kb <= 150, "{}", converted.size_kb);
+        assert_eq!(converted.transformations[0], Transformation::Rasterize { pages: 2, dpi: 50 });
+        assert_eq!(converted.warnings.len(), 1);
+        // Page sizes carry over and the scans are now 50 DPI
+        let output = converted.decode_data().unwrap();
+        let pages = scanned_pages(&output).unwrap();
+        assert_eq!((pages.len(), pages[1].width, pages[1].height), (2, 595.0, 842.0));
+        assert_eq!(image::load_from_memory(pages[0].jpeg).unwrap().dimensions(), (413, 584));
+
+        // Out of reach even rasterized: the error gives the smallest size made
+        config.target_spec.size_kb.max = 5;
+        let error = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap_err();
+        let ConvertError::PdfTooLarge { best_kb, rasterize_dpi: Some(50), rasterize_error: None, .. } = error else {
+            panic!("{:?}", error);
+        };
+        assert!(best_kb > 5 && (best_kb as usize) < pdf.len() / 1024);
+
+        // Scans over the config's decode budget are refused rather than rasterized
+        let budget = std::mem::replace(&mut config.max_decoded_pixels, 620 * 877 - 1);
+        let error = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error, ConvertError::DecompressionBomb { width: 620, height: 877, max_pixels: 620 * 877 - 1 });
+        config.max_decoded_pixels = budget;
+
+        // Pages that are not scans cannot be rasterized
+        let text = [b"%PDF-1.4\n2 0 obj << /Type /Page /MediaBox [0 0 595 842] >> endobj\n".as_slice(), &[b' '; 8192]].concat();
+        assert_eq!(
+            convert("marks.pdf", "application/pdf", &text, &config, &NoopObserver).unwrap_err().to_string(),
+            "PDF file too large: 8KB, maximum allowed: 5KB; the smallest achieved was 8KB as the pages cannot be rasterized: \
+             it has 1 pages but 0 JPEG scans, and only pages that are one scanned image each can be rasterized. Reduce the \
+             resolution of its page images"
+        );
+    }
+
+    #[test]
+    fn blank_pdf_pages_are_reported_or_removed() {
+        let page = |lines: u32| {
+            let mut jpeg = Vec::new();
+            image::DynamicImage::ImageRgb8(scanned_paper(620, 877, lines))
+                .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(75))
+                .unwrap();
+            (jpeg, 620, 877)
+        };
+        let scans = [page(5), page(0), page(3), page(0)];
+        let pdf = jpeg_pdf(&scans, &[(595.0, 842.0); 4]);
+        let mut config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 500 } }));
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.decode_data().unwrap(), pdf);
+        assert_eq!(converted.warnings, ["Blank pages found: 2, 4; set remove_blank_pages to drop them"]);
+
+        config.target_spec.remove_blank_pages = true;
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations, [Transformation::RemoveBlankPages { pages: vec![2, 4] }]);
+        let output = converted.decode_data().unwrap();
+        let pages = scanned_pages(&output).unwrap();
+        assert_eq!(pages.iter().map(|page| page.jpeg).collect::<Vec<_>>(), [scans[0].0.as_slice(), scans[2].0.as_slice()]);
+
+        // A stricter threshold, and a document that is nothing but blank pages
+        config.target_spec.blank_page_max_ink = Some(0.0);
+        assert!(convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap().transformations.is_empty());
+        config.target_spec.blank_page_max_ink = None;
+        let empty = jpeg_pdf(&[page(0), page(0)], &[(595.0, 842.0); 2]);
+        let converted = convert("marks.pdf", "application/pdf", &empty, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.warnings, ["Every page looks blank, so none were removed"]);
+    }
+
+    #[test]
+    fn repeated_pdf_pages_are_reported_or_removed() {
+        let jpeg = |img: image::RgbImage| {
+            let mut jpeg = Vec::new();
+            image::DynamicImage::ImageRgb8(img).write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(75)).unwrap();
+            (jpeg, 620, 877)
+        };
+        // The same sheet fed in again 2° askew and scanned a little darker
+        let sheet = scanned_paper(620, 877, 3);
+        let (sin, cos) = 2f32.to_radians().sin_cos();
+        let rescan = image::RgbImage::from_fn(620, 877, |x, y| {
+            let (dx, dy) = (x as f32 - 310.0, y as f32 - 438.0);
+            let (from_x, from_y) = ((cos * dx - sin * dy + 310.0).clamp(0.0, 619.0), (sin * dx + cos * dy + 438.0).clamp(0.0, 876.0));
+            image::Rgb(sheet.get_pixel(from_x as u32, from_y as u32).0.map(|level| (level as f32 * 0.9) as u8))
+        });
+        let scans = [jpeg(sheet.clone()), jpeg(rescan), jpeg(scanned_paper(620, 877, 6)), jpeg(scanned_paper(620, 877, 0)), jpeg(sheet), jpeg(scanned_paper(620, 877, 0))];
+        let pdf = jpeg_pdf(&scans, &[(595.0, 842.0); 6]);
+        let mut config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 500 } }));
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.decode_data().unwrap(), pdf);
+        assert_eq!(
+            converted.warnings,
+            [
+                "Blank pages found: 4, 6; set remove_blank_pages to drop them",
+                "Pages that repeat an earlier one: 2 (like 1), 5 (like 1); set remove_duplicate_pages to drop them",
+            ]
+        );
+
+        config.target_spec.remove_duplicate_pages = true;
+        config.target_spec.remove_blank_pages = true;
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(
+            converted.transformations,
+            [Transformation::RemoveBlankPages { pages: vec![4, 6] }, Transformation::RemoveDuplicatePages { pages: vec![2, 5] }]
+        );
+        let output = converted.decode_data().unwrap();
+        let pages = scanned_pages(&output).unwrap();
+        assert_eq!(pages.iter().map(|page| page.jpeg).collect::<Vec<_>>(), [scans[0].0.as_slice(), scans[2].0.as_slice()]);
+
+        // Demanding identical hashes lets the skewed rescan through
+        config.target_spec.duplicate_page_min_similarity = Some(1.0);
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[1], Transformation::RemoveDuplicatePages { pages: vec![5] });
+    }
+
+    #[test]
+    fn rotated_pdf_scans_come_out_upright() {
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgb8(gradient(60, 80))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let pdf = jpeg_pdf(&[(jpeg.clone(), 60, 80)], &[(595.0, 842.0)]);
+        let page_type = pdf.windows(12).position(|window| window == b"/Type /Page\n").unwrap() + 11;
+        let rotated = [&pdf[..page_type], b" /Rotate 270", &pdf[page_type..]].concat();
+        let config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 100 } }));
+        let converted = convert("marks.pdf", "application/pdf", &rotated, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations, [Transformation::PageRotate { page: 1, degrees: 270 }]);
+        let output = converted.decode_data().unwrap();
+        let page = &scanned_pages(&output).unwrap()[0];
+        assert_eq!((page.jpeg, page.width, page.height, page.rotate), (jpeg.as_slice(), 842.0, 595.0, 0));
+        assert_eq!(
+            plan("marks.pdf", "application/pdf", &rotated, &config).unwrap().transformations,
+            [Transformation::PageRotate { page: 1, degrees: 270 }]
+        );
+
+        // Unrotated PDFs still pass through byte for byte
+        assert_eq!(convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap().decode_data().unwrap(), pdf);
+    }
+
+    #[test]
+    fn max_pdf_colors_reduces_scans_to_a_palette() {
+        let jpeg = |img: image::RgbImage| {
+            let mut jpeg = Vec::new();
+            image::DynamicImage::ImageRgb8(img).write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(90)).unwrap();
+            (jpeg, 60, 80)
+        };
+        let pdf = jpeg_pdf(&[jpeg(gradient(60, 80)), jpeg(scanned_paper(60, 80, 3))], &[(595.0, 842.0); 2]);
+        let config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 100 }, "max_pdf_colors": 8 }));
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations, [Transformation::ReduceColors { pages: 2, colors: 8 }]);
+        assert_eq!(
+            converted.warnings,
+            ["The PDF's page images were reduced to at most 8 colors for max_pdf_colors, so they may look posterized"]
+        );
+        let output = converted.decode_data().unwrap();
+        let indexed = b"/ColorSpace [/Indexed /DeviceRGB ";
+        let palettes: Vec<u8> = output.windows(indexed.len()).enumerate().filter(|(_, window)| window == indexed).map(|(at, _)| output[at + indexed.len()]).collect();
+        assert_eq!(palettes, b"77");
+        assert!(!output.windows(10).any(|window| window == b"/DCTDecode"));
+        assert_eq!(
+            plan("marks.pdf", "application/pdf", &pdf, &config).unwrap().transformations,
+            [Transformation::ReduceColors { pages: 2, colors: 8 }]
+        );
+
+        // Page scans are held to the config's decode budget like image inputs
+        let config = ConversionConfig { max_decoded_pixels: 60 * 80 - 1, ..config };
+        let error = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error, ConvertError::DecompressionBomb { width: 60, height: 80, max_pixels: 60 * 80 - 1 });
+
+        let text = b"%PDF-1.4\n2 0 obj << /Type /Page /MediaBox [0 0 595 842] /Contents 3 0 R >> endobj\n";
+        assert_eq!(
+            convert("marks.pdf", "application/pdf", text, &config, &NoopObserver).unwrap_err().to_string(),
+            "Cannot reduce the PDF to 8 colors: it has 1 pages but 0 JPEG scans, and only pages that are one scanned image each can be rasterized"
+        );
+    }
+
+    #[test]
+    fn pdf_a_rebuilds_scanned_pdfs_and_refuses_the_rest() {
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgb8(gradient(60, 80))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let pdf = jpeg_pdf(&[(jpeg.clone(), 60, 80)], &[(595.0, 842.0)]);
+        let config = config_from(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 100 }, "pdf_a": true }));
+        let converted = convert("marks.pdf", "application/pdf", &pdf, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations, [Transformation::PdfA { pages: 1 }]);
+        let output = converted.decode_data().unwrap();
+        assert!(output.starts_with(b"%PDF-1.4") && output.windows(10).any(|window| window == b"/GTS_PDFA1"));
+        assert_eq!(scanned_pages(&output).unwrap()[0].jpeg, jpeg.as_slice());
+
+        let text = b"%PDF-1.4\n2 0 obj << /Type /Page /MediaBox [0 0 595 842] /Contents 3 0 R >> endobj\n";
+        let error = convert("marks.pdf", "application/pdf", text, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error.code(), Some("pdf_a"));
+        let plan = plan("marks.pdf", "application/pdf", text, &config).unwrap();
+        assert!(plan.notes.iter().any(|note| note.starts_with("Cannot make a PDF/A-1b copy: its pages carry text")), "{:?}", plan.notes);
+    }
+
+    #[test]
+    fn violations_list_every_broken_limit() {
+        let spec = spec_from(serde_json::json!({ "size_kb": { "max": 1 }, "pixels": { "square": true, "max_total_pixels": 100 } }));
+        let dimensions = Some(DimensionsSpec { width: 20.0, height: 10.0 });
+        assert_eq!(
+            conversion_violations(&[0; 2048], &dimensions, &spec),
+            [
+                "File too large: 2KB, maximum allowed: 1KB",
+                "Image is 20x10 but the spec requires a square image",
+                "Too many pixels: 20x10 is 200 pixels, maximum allowed: 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn flexible_dimensions_rescale_into_the_size_band() {
+        let resized = |converted: &ConvertedFile| {
+            converted.transformations.iter().find_map(|t| match t {
+                Transformation::Resize { from, to } => Some((*from, *to)),
+                _ => None,
+            })
+        };
+        // Even the lowest quality is over the cap at full size, so the image shrinks
+        // Each scale tried runs its own quality search, so the default encode budget is too tight
+        let flexible = |spec: serde_json::Value| ConversionConfig { max_attempts: 100, ..config_from(spec) };
+        let png = encode_png(&noise(128, 128));
+        let shrink = flexible(serde_json::json!({ "size_kb": { "min": 1, "max": 2 }, "flexible_dimensions": true }));
+        let converted = convert("noise.png", "image/png", &png, &shrink, &NoopObserver).unwrap();
+        let (from, to) = resized(&converted).unwrap();
+        assert!(from == [128, 128] && to[0] < 128, "{:?}", to);
+        assert!((1024..=2048).contains(&converted.size_bytes), "{}", converted.size_bytes);
+        assert_eq!(converted.dimensions.unwrap().width, to[0] as f32);
+
+        // Full quality is under the floor, so the image grows
+        let small = encode_png(&noise(24, 24));
+        let grow = flexible(serde_json::json!({ "size_kb": { "min": 6, "max": 40 }, "flexible_dimensions": true }));
+        let converted = convert("noise.png", "image/png", &small, &grow, &NoopObserver).unwrap();
+        assert!(resized(&converted).unwrap().1[0] > 24);
+        assert!(converted.size_kb >= 6);
+        assert!(converted.transformations.iter().any(|t| matches!(t, Transformation::Encode { quality: Some(100), .. })));
+        let mut fixed = grow.clone();
+        fixed.target_spec.flexible_dimensions = false;
+        assert!(matches!(
+            convert("noise.png", "image/png", &small, &fixed, &NoopObserver),
+            Err(ConvertError::SizeConstraints { message, .. }) if message.starts_with("File too small: ")
+        ));
+
+        // A flat image barely grows in bytes, so the floor is out of reach
+        let flat = encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(32, 32, image::Rgb([200, 200, 200]))));
+        let floor = flexible(serde_json::json!({ "size_kb": { "min": 50, "max": 100 }, "flexible_dimensions": true }));
+        assert!(matches!(
+            convert("flat.png", "image/png", &flat, &floor, &NoopObserver),
+            Err(ConvertError::SizeConstraints { message, .. })
+                if message == "Image stays under 50KB even at full quality and the largest allowed dimensions"
+        ));
+    }
+
+    #[test]
+    fn validation_checks_size_bounds() {
+        let spec = spec_from(serde_json::json!({ "size_kb": { "min": 10, "max": 50 } }));
+        assert!(validate_conversion_result(&[0; 20 * 1024], &None, &spec).is_ok());
+        assert_eq!(
+            validate_conversion_result(&[0; 5 * 1024], &None, &spec).unwrap_err().to_string(),
+            "File too small: 5KB, minimum required: 10KB"
+        );
+        assert_eq!(
+            validate_conversion_result(&[0; 51 * 1024], &None, &spec).unwrap_err().to_string(),
+            "File too large: 51KB, maximum allowed: 50KB"
+        );
+        // Sizes are compared in whole kilobytes
+        assert!(validate_conversion_result(&[0; 50 * 1024 + 1023], &None, &spec).is_ok());
+    }
+
+    #[test]
+    fn filename_uses_document_type_and_base_name() {
+        assert_eq!(generate_converted_filename("scan.png", "JPEG", "photo", ExtensionCase::Lower), "photo_scan.jpg");
+        assert_eq!(generate_converted_filename("marks.2023.pdf", "PDF", "marksheet", ExtensionCase::Lower), "marksheet_marks.pdf");
+        assert_eq!(generate_converted_filename("sign.jpg", "PNG", "signature", ExtensionCase::Lower), "signature_sign.png");
+        assert_eq!(generate_converted_filename("odd.tif", "TIFF", "photo", ExtensionCase::Lower), "photo_odd.bin");
+    }
+
+    #[test]
+    fn filename_template_fills_in_the_capture_date() {
+        let dated = jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:00"));
+        let undated = jpeg_with_exif(&tiff_with_capture_date(b"2023:00:00 00:00:00"));
+        let mut config = config_from(serde_json::json!({}));
+        config.filename_template = Some("{document_type}_{capture_date}".to_string());
+        let name = |data: &[u8], config: &ConversionConfig| convert("IMG_0042.JPG", "image/jpeg", data, config, &NoopObserver).unwrap().converted_name;
+        assert_eq!(name(&dated, &config), "photo_2023-05-01.jpg");
+        // A malformed date falls back to the input's name instead of failing
+        assert_eq!(name(&undated, &config), "photo_IMG_0042.jpg");
+
+        config.extension_case = ExtensionCase::PreserveInput;
+        config.filename_template = Some("{capture_date}-{name}".to_string());
+        assert_eq!(name(&dated, &config), "2023-05-01-IMG_0042.JPG");
+        assert_eq!(plan("IMG_0042.JPG", "image/jpeg", &dated, &config).unwrap().converted_name, "2023-05-01-IMG_0042.JPG");
+    }
+
+    #[test]
+    fn convert_produces_resized_jpeg() {
+        let config = config_from(serde_json::json!({ "pixels": { "width": 276, "height": 354 } }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(600, 800)));
+        let converted = convert("me.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.converted_name, "photo_me.jpg");
+        assert_eq!(converted.format, "JPEG");
+        assert_eq!(converted.codec_info, codec_info("JPEG"));
+        assert!(converted.data_url.starts_with("data:image/jpeg;base64,"));
+        assert_eq!(converted.input_hash, sha256_hex(&png));
+        let dimensions = converted.dimensions.unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (276.0, 354.0));
+        assert!(matches!(converted.transformations.last(), Some(Transformation::Encode { quality: Some(90), .. })));
+    }
+
+    #[test]
+    fn convert_rejects_unsupported_inputs() {
+        let config = config_from(serde_json::json!({}));
+        assert_eq!(
+            convert("notes.txt", "text/plain", b"hello", &config, &NoopObserver).unwrap_err(),
+            ConvertError::UnsupportedFileType("text/plain".to_string())
+        );
+        assert_eq!(
+            convert("marks.pdf", "application/pdf", b"%PDF-1.4", &config, &NoopObserver).unwrap_err().to_string(),
+            "PDF format not supported for this document type"
+        );
+    }
+
+    #[test]
+    fn images_and_pdfs_draw_from_their_own_format_lists() {
+        // The flat list serves both, with PDF only for PDF inputs
+        let mixed = spec_from(serde_json::json!({ "format": ["PDF", "JPEG"] }));
+        assert_eq!(determine_target_format("image/png", &mixed).unwrap(), "JPEG");
+        assert_eq!(determine_target_format("application/pdf", &mixed).unwrap(), "PDF");
+
+        let split = spec_from(serde_json::json!({
+            "format": ["JPEG"],
+            "image_formats": ["PNG"],
+            "document_formats": ["PDF"],
+        }));
+        assert_eq!(determine_target_format("image/jpeg", &split).unwrap(), "PNG");
+        assert_eq!(determine_target_format("application/pdf", &split).unwrap(), "PDF");
+
+        let pdf_only = spec_from(serde_json::json!({ "format": ["PDF"] }));
+        assert_eq!(determine_target_format("image/png", &pdf_only).unwrap_err(), ConvertError::NoTargetFormat);
+        let jpeg_fallback = spec_from(serde_json::json!({ "format": ["PDF"], "on_missing_format": "Jpeg" }));
+        assert_eq!(determine_target_format("image/png", &jpeg_fallback).unwrap(), "JPEG");
+        let no_pdf = spec_from(serde_json::json!({ "format": ["PDF"], "image_formats": ["PNG"], "document_formats": [] }));
+        assert!(determine_target_format("application/pdf", &no_pdf).is_err());
+    }
+
+    #[test]
+    fn jpeg_density_is_written_in_the_configured_unit() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(32, 32)));
+        // JFIF APP0 follows SOI: units at byte 13, then big-endian X and Y density
+        let jfif = |spec: serde_json::Value| {
+            let data = convert("scan.png", "image/png", &png, &config_from(spec), &NoopObserver).unwrap().decode_data().unwrap();
+            assert_eq!(&data[6..11], b"JFIF\0");
+            (data[13], u16::from_be_bytes([data[14], data[15]]), u16::from_be_bytes([data[16], data[17]]))
+        };
+
+        assert_eq!(jfif(serde_json::json!({})), (0, 1, 1));
+        assert_eq!(jfif(serde_json::json!({ "density_unit": "Inch", "resolution_px_per_inch": 300 })), (1, 300, 300));
+        assert_eq!(jfif(serde_json::json!({ "density_unit": "Cm", "resolution_px_per_inch": 300 })), (2, 118, 118));
+        assert_eq!(jfif(serde_json::json!({ "density_unit": "Cm" })), (2, 59, 59));
+    }
+
+    #[test]
+    fn tile_slices_resized_image_with_short_edge_tiles() {
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 200 }, "pixels": { "width": 250, "height": 120 } }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(500, 240)));
+        let tiles = tile("scan.png", "image/png", &png, &config, 100, &NoopObserver).unwrap();
+
+        let layout: Vec<(u32, u32, u32, u32, f32, f32)> = tiles
+            .iter()
+            .map(|t| {
+                let p = t.tile.unwrap();
+                let d = t.dimensions.as_ref().unwrap();
+                (p.row, p.column, p.x, p.y, d.width, d.height)
+            })
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (0, 0, 0, 0, 100.0, 100.0),
+                (0, 1, 100, 0, 100.0, 100.0),
+                (0, 2, 200, 0, 50.0, 100.0),
+                (1, 0, 0, 100, 100.0, 20.0),
+                (1, 1, 100, 100, 100.0, 20.0),
+                (1, 2, 200, 100, 50.0, 20.0),
+            ]
+        );
+        assert_eq!(tiles[5].converted_name, "photo_scan_r1_c2.png");
+        assert!(matches!(tiles[0].transformations[0], Transformation::Resize { to: [250, 120], .. }));
+
+        // Tiles are exact crops of the resized image
+        let whole = convert("scan.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let whole = image::load_from_memory(&whole.decode_data().unwrap()).unwrap();
+        let edge = image::load_from_memory(&tiles[5].decode_data().unwrap()).unwrap();
+        assert_eq!(edge.to_rgba8(), whole.crop_imm(200, 100, 50, 20).to_rgba8());
+
+        assert_eq!(
+            tile("scan.png", "image/png", &png, &config, 0, &NoopObserver).unwrap_err().to_string(),
+            "Invalid config: tile_size must be at least 1"
+        );
+        assert_eq!(
+            tile("scan.png", "image/png", &png, &config, 1, &NoopObserver).unwrap_err().to_string(),
+            "Invalid config: tile_size 1 cuts the 250x120 image into 30000 tiles, over the 4096 limit"
+        );
+        assert!(tile("marks.pdf", "application/pdf", b"%PDF-1.4", &config, 100, &NoopObserver).is_err());
+    }
+
+    #[test]
+    fn assumed_source_profile_is_converted_first() {
+        let mut config = config_from(serde_json::json!({ "pixels": { "width": 32, "height": 32 } }));
+        config.assume_source_profile = serde_json::from_value(serde_json::json!("AdobeRGB")).unwrap();
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 64)));
+
+        let converted = convert("scan.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::ColorConvert { from: SourceProfile::AdobeRgb });
+        let plan = plan("scan.png", "image/png", &png, &config).unwrap();
+        assert_eq!(plan.transformations[0], Transformation::ColorConvert { from: SourceProfile::AdobeRgb });
+
+        config.assume_source_profile = SourceProfile::Srgb;
+        let converted = convert("scan.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(matches!(converted.transformations[0], Transformation::Resize { .. }));
+    }
+
+    #[test]
+    fn document_scan_reports_detected_corners() {
+        let config = config_from(serde_json::json!({ "document_scan": {} }));
+        let photo = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(300, 200, |x, y| {
+            let on_page = (50..250).contains(&x) && (40..170).contains(&y);
+            image::Rgb(if on_page { [235, 235, 225] } else { [30, 30, 30] })
+        }));
+        let converted = convert("desk.png", "image/png", &encode_png(&photo), &config, &NoopObserver).unwrap();
+        match &converted.transformations[0] {
+            Transformation::Perspective { corners, width, height } => {
+                assert_eq!(*corners, [[50, 40], [249, 40], [249, 169], [50, 169]]);
+                assert_eq!((*width, *height), (199, 129));
+            }
+            other => panic!("expected a perspective correction, got {:?}", other),
+        }
+    }
+
+    struct LowMemory(u64);
+
+    impl Observer for LowMemory {
+        fn available_memory(&self) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn memory_check_rejects_oversized_decodes() {
+        let mut config = config_from(serde_json::json!({}));
+        config.memory_check = serde_json::from_value(serde_json::json!({})).unwrap();
+        let png = encode_png(&image::DynamicImage::new_rgb8(1000, 1000));
+
+        // 4MB of pixels against 6MB available, half of which may be used
+        let error = convert("big.png", "image/png", &png, &config, &LowMemory(6 * 1024 * 1024)).unwrap_err();
+        assert_eq!(
+            error,
+            ConvertError::InsufficientMemory { width: 1000, height: 1000, required: 4_000_000, available: 6 * 1024 * 1024 }
+        );
+        assert_eq!(error.code(), Some("insufficient_memory"));
+        assert!(convert("big.png", "image/png", &png, &config, &LowMemory(16 * 1024 * 1024)).is_ok());
+    }
+
+    #[test]
+    fn decompression_bombs_are_refused_before_decoding() {
+        // A real 1x1 PNG whose header claims 100000x100000, with the header's CRC fixed up
+        let mut png = encode_png(&image::DynamicImage::new_rgb8(1, 1));
+        png[16..24].copy_from_slice(&[100_000u32.to_be_bytes(), 100_000u32.to_be_bytes()].concat());
+        let crc = crc32fast::hash(&png[12..29]);
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+        assert!(png.len() < 100);
+
+        let mut config = config_from(serde_json::json!({}));
+        let error = convert("bomb.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error, ConvertError::DecompressionBomb { width: 100_000, height: 100_000, max_pixels: 128 * 1024 * 1024 });
+        assert_eq!(error.code(), Some("decompression_bomb"));
+        assert_eq!(
+            error.to_string(),
+            "Refusing to decode 100000x100000 image: 10000000000 pixels (about 38147MB decoded) is more than max_decoded_pixels 134217728"
+        );
+
+        // The budget is the config's to set
+        config.max_decoded_pixels = 1000 * 1000 - 1;
+        let png = encode_png(&image::DynamicImage::new_rgb8(1000, 1000));
+        assert!(matches!(convert("big.png", "image/png", &png, &config, &NoopObserver), Err(ConvertError::DecompressionBomb { .. })));
+        config.max_decoded_pixels = 1000 * 1000;
+        assert!(convert("big.png", "image/png", &png, &config, &NoopObserver).is_ok());
+    }
+
+    #[test]
+    fn memory_check_is_advisory_without_an_estimate() {
+        let mut config = config_from(serde_json::json!({}));
+        config.memory_check = serde_json::from_value(serde_json::json!({})).unwrap();
+        let png = encode_png(&image::DynamicImage::new_rgb8(1000, 1000));
+        assert!(convert("big.png", "image/png", &png, &config, &NoopObserver).is_ok());
+
+        // An explicit figure overrides whatever the host reports
+        config.memory_check = serde_json::from_value(serde_json::json!({ "available_mb": 2 })).unwrap();
+        assert!(matches!(
+            convert("big.png", "image/png", &png, &config, &LowMemory(u64::MAX)),
+            Err(ConvertError::InsufficientMemory { .. })
+        ));
+        config.memory_check = None;
+        assert!(convert("big.png", "image/png", &png, &config, &LowMemory(0)).is_ok());
+    }
+
+    #[derive(Default)]
+    struct Warnings(std::cell::RefCell<Vec<serde_json::Value>>);
+
+    impl Observer for Warnings {
+        fn event(&self, level: LogLevel, event: LogEventKind, _file: Option<&str>, fields: serde_json::Value) {
+            if level == LogLevel::Warn && event == LogEventKind::FeatureLoss {
+                self.0.borrow_mut().push(fields);
+            }
+        }
+    }
+
+    #[test]
+    fn feature_loss_warns_by_default_and_fails_when_strict() {
+        let mut img = image::RgbaImage::from_pixel(32, 32, image::Rgba([200, 10, 10, 255]));
+        img.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+        let png = encode_png(&image::DynamicImage::ImageRgba8(img));
+        let mut config = config_from(serde_json::json!({}));
+
+        let warnings = Warnings::default();
+        assert!(convert("logo.png", "image/png", &png, &config, &warnings).is_ok());
+        assert_eq!(
+            warnings.0.take(),
+            [serde_json::json!({ "target_format": "JPEG", "lost": ["alpha channel (transparent pixels)"] })]
+        );
+
+        config.on_feature_loss = FeatureLossPolicy::Fail;
+        let error = convert("logo.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error.to_string(), "Converting to JPEG would lose: alpha channel (transparent pixels)");
+        assert_eq!(error.code(), Some("feature_loss"));
+
+        // PNG keeps the alpha channel, so nothing is lost
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"] }));
+        config.on_feature_loss = FeatureLossPolicy::Fail;
+        assert!(convert("logo.png", "image/png", &png, &config, &NoopObserver).is_ok());
+    }
+
+    #[test]
+    fn color_mode_rejects_or_converts_mismatched_sources() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 64)));
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"], "color_mode": "grayscale" }));
+        let error = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert!(error.to_string().starts_with("Image is in color (colorfulness "), "{}", error);
+
+        config.target_spec.on_color_mismatch = ColorMismatchPolicy::Convert;
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(converted.colorfulness.unwrap() > GRAYSCALE_MAX_COLORFULNESS);
+        assert!(converted.transformations.contains(&Transformation::Grayscale));
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap();
+        assert!(output.to_rgb8().pixels().all(|p| p.0[0] == p.0[1] && p.0[1] == p.0[2]));
+
+        // A grayscale source satisfies the grayscale requirement but not the color one
+        let gray = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 64)).grayscale());
+        let converted = convert("scan.png", "image/png", &gray, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.colorfulness, Some(0.0));
+        assert!(!converted.transformations.contains(&Transformation::Grayscale));
+        config.target_spec.color_mode = Some(ColorMode::Color);
+        config.target_spec.on_color_mismatch = ColorMismatchPolicy::Warn;
+        assert!(convert("scan.png", "image/png", &gray, &config, &NoopObserver).is_ok());
+        config.target_spec.on_color_mismatch = ColorMismatchPolicy::Fail;
+        assert_eq!(
+            convert("scan.png", "image/png", &gray, &config, &NoopObserver).unwrap_err().to_string(),
+            "Image is grayscale (colorfulness 0.0) but the spec requires color"
+        );
+    }
+
+    #[test]
+    fn auto_rotate_turns_sideways_signatures_upright() {
+        let sideways = encode_png(&image::DynamicImage::ImageRgb8(signature()).rotate90());
+        let mut config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "pixels": { "width": 300, "height": 200 },
+            "fit_mode": "Pad",
+            "auto_rotate": {},
+        }));
+        let converted = convert("sign.png", "image/png", &sideways, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::Rotate { degrees: 270 });
+        assert!(converted.warnings.is_empty());
+        // Upright, the signature fills the frame without padding
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output, signature());
+
+        config.target_spec.auto_rotate.as_mut().unwrap().min_confidence = 0.95;
+        let converted = convert("sign.png", "image/png", &sideways, &config, &NoopObserver).unwrap();
+        assert!(!converted.transformations.iter().any(|step| matches!(step, Transformation::Rotate { .. })));
+        assert_eq!(converted.warnings.len(), 1);
+        assert!(converted.warnings[0].starts_with("The writing may be turned 270° from upright, but at confidence 0.4"), "{:?}", converted.warnings);
+
+        // A portrait on a plain backdrop is not read as writing
+        let portrait = encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 300, |x, y| {
+            let face = (x as i32 - 100).pow(2) * 3 + (y as i32 - 140).pow(2) * 2 < 140 * 140;
+            image::Rgb(if face { [200, 150, 120] } else { [250, 250, 250] })
+        })));
+        let converted = convert("photo.png", "image/png", &portrait, &config, &NoopObserver).unwrap();
+        assert!(converted.warnings.is_empty() && !converted.transformations.iter().any(|step| matches!(step, Transformation::Rotate { .. })));
+    }
+
+    #[test]
+    fn deskew_straightens_slightly_tilted_pages() {
+        let tilted = encode_png(&image::DynamicImage::ImageRgb8(tilted_paper(600, 800, 8, 3.0)));
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 2000 }, "deskew": true }));
+        let converted = convert("page.png", "image/png", &tilted, &config, &NoopObserver).unwrap();
+        let Transformation::Deskew { degrees, width, height } = converted.transformations[0] else {
+            panic!("{:?}", converted.transformations);
+        };
+        assert!((degrees - 3.0).abs() <= 0.2, "{}", degrees);
+        assert!(width < 600 && height < 800);
+        assert!(converted.warnings.is_empty());
+
+        // A tilt past the limit is more likely a misreading, so it is only reported
+        config.target_spec.deskew_max_angle = Some(2.0);
+        let converted = convert("page.png", "image/png", &tilted, &config, &NoopObserver).unwrap();
+        assert!(!converted.transformations.iter().any(|step| matches!(step, Transformation::Deskew { .. })));
+        assert_eq!(converted.warnings.len(), 1);
+        assert!(converted.warnings[0].starts_with("The page looks tilted by 3."), "{:?}", converted.warnings);
+
+        // A level page is left untouched
+        let level = encode_png(&image::DynamicImage::ImageRgb8(scanned_paper(600, 800, 8)));
+        let converted = convert("page.png", "image/png", &level, &config, &NoopObserver).unwrap();
+        assert!(converted.warnings.is_empty());
+        assert!(!converted.transformations.iter().any(|step| matches!(step, Transformation::Deskew { .. })));
+    }
+
+    #[test]
+    fn scanner_edges_are_cut_before_sizing_unless_they_reach_too_far() {
+        let page = scanned_paper(600, 800, 8);
+        let scan = |bed: u32| {
+            encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(600, 800, |x, y| {
+                if x < bed || y >= 800 - bed / 2 { image::Rgb([20, 22, 26]) } else { *page.get_pixel(x, y) }
+            })))
+        };
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 2000 }, "scanner_edges": {} }));
+        let converted = convert("scan.png", "image/png", &scan(60), &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::RemoveEdges { left: 60, top: 0, right: 0, bottom: 30 });
+        let dimensions = converted.dimensions.unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (540.0, 770.0));
+        assert!(converted.warnings.is_empty());
+
+        // A quarter of the width is past the 15% limit, so the frame is only reported
+        let converted = convert("scan.png", "image/png", &scan(150), &config, &NoopObserver).unwrap();
+        assert!(!converted.transformations.iter().any(|step| matches!(step, Transformation::RemoveEdges { .. })));
+        assert_eq!(converted.dimensions.unwrap().width, 600.0);
+        assert_eq!(converted.warnings.len(), 1);
+        assert!(converted.warnings[0].starts_with("A frame reaching 25% into the scan"), "{:?}", converted.warnings);
+    }
+
+    #[test]
+    fn transparent_background_clears_the_paper_behind_signatures() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(signature()));
+        let mut config = config_from(serde_json::json!({ "format": ["JPEG", "PNG"], "size_kb": { "max": 100 }, "transparent_background": true }));
+        config.document_type = "signature".to_string();
+        let converted = convert("sign.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.format, "PNG");
+        assert_eq!(converted.transformations[0], Transformation::TransparentBackground);
+        let rgba = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgba8();
+        assert_eq!(rgba.get_pixel(0, 0).0[3], 0);
+        assert_eq!(rgba.get_pixel(50, 100).0, [20, 30, 120, 255]);
+        assert_eq!(plan("sign.png", "image/png", &png, &config).unwrap().transformations[0], Transformation::TransparentBackground);
+
+        // Other documents keep their background
+        config.document_type = "photo".to_string();
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.format, "PNG");
+        assert!(!converted.transformations.contains(&Transformation::TransparentBackground));
+        assert_eq!(converted.warnings, ["transparent_background is for signatures, so the photo was left opaque"]);
+    }
+
+    #[test]
+    fn despeckle_cleans_dust_off_signatures() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(dusty(&fine_signature())));
+        let mut config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "pixels": { "width": 300, "height": 200 },
+            "despeckle": {},
+        }));
+        let converted = convert("sign.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::Despeckle { specks: 3 });
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output, fine_signature());
+
+        // Only the single-pixel speck is small enough
+        config.target_spec.despeckle.as_mut().unwrap().max_size = 1;
+        let converted = convert("sign.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::Despeckle { specks: 1 });
+    }
+
+    #[test]
+    fn auto_contrast_restores_washed_out_copies() {
+        let mut copy = scanned_paper(240, 160, 4);
+        copy.pixels_mut().for_each(|pixel| pixel.0.iter_mut().for_each(|channel| *channel = 110 + *channel / 3));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(copy));
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 }, "auto_contrast": "stretch" }));
+        config.document_type = "marksheet".to_string();
+        let converted = convert("copy.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::AutoContrast { mode: AutoContrast::Stretch });
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_luma8();
+        assert!(output.pixels().any(|pixel| pixel.0[0] < 20));
+        assert!(converted.warnings.is_empty());
+
+        // Tile by tile equalization is only a caution for photos of people
+        config.target_spec.auto_contrast = Some(AutoContrast::Clahe);
+        let converted = convert("copy.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::AutoContrast { mode: AutoContrast::Clahe });
+        assert!(converted.warnings.is_empty());
+        config.document_type = "passport_photo".to_string();
+        let converted = convert("copy.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.warnings.len(), 1);
+        assert!(converted.warnings[0].starts_with("auto_contrast \"clahe\" evens out each part of a passport_photo"), "{:?}", converted.warnings);
+    }
+
+    #[test]
+    fn gamma_lifts_dark_scans_before_contrast() {
+        let mut dark = scanned_paper(240, 160, 4);
+        dark.pixels_mut().for_each(|pixel| pixel.0.iter_mut().for_each(|channel| *channel = (*channel as u32 * 3 / 5) as u8));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(dark.clone()));
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 }, "gamma": 1.4, "auto_contrast": "stretch" }));
+        let converted = convert("dark.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!(converted.transformations[0], Transformation::Gamma { gamma: 1.4 });
+        assert!(matches!(converted.transformations[1], Transformation::AutoContrast { .. }));
+        let paper = |img: &image::RgbImage| img.get_pixel(2, 2).0[0];
+        config.target_spec.auto_contrast = None;
+        let converted = convert("dark.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert!(paper(&output) > paper(&dark) + 20, "{} from {}", paper(&output), paper(&dark));
+
+        // A gamma of exactly 1 skips the pass
+        config.target_spec.gamma = Some(1.0);
+        let converted = convert("dark.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(!converted.transformations.iter().any(|step| matches!(step, Transformation::Gamma { .. })));
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output, dark);
+    }
+
+    #[test]
+    fn print_orientation_rotates_the_finished_image() {
+        let source = image::DynamicImage::ImageRgb8(gradient(120, 80));
+        let png = encode_png(&source);
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "pixels": { "width": 60, "height": 40 },
+            "print_orientation": "Portrait",
+        }));
+        let converted = convert("form.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let dimensions = converted.dimensions.as_ref().unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (40.0, 60.0));
+        let steps = converted.transformations.len();
+        assert_eq!(converted.transformations[steps - 2], Transformation::Rotate { degrees: 90 });
+
+        // Resized first, then turned clockwise
+        let expected = source.resize_exact(60, 40, image::imageops::FilterType::Lanczos3).rotate90();
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap();
+        assert_eq!(output.to_rgb8(), expected.to_rgb8());
+
+        let planned = plan("form.png", "image/png", &png, &config).unwrap();
+        assert_eq!(planned.target_dimensions, Some([40, 60]));
+
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "print_orientation": "Landscape" }));
+        let converted = convert("form.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(!converted.transformations.iter().any(|t| matches!(t, Transformation::Rotate { .. })));
+    }
+
+    struct Clock(LocalTime);
+
+    impl Observer for Clock {
+        fn local_time(&self) -> Option<LocalTime> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn timestamp_overlay_stamps_the_finished_image() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(600, 400, image::Rgb([255, 255, 255]))));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "pixels": { "width": 300, "height": 200 },
+            "timestamp_overlay": { "format": "%d.%m.%Y %H:%M", "position": "TopLeft" },
+        }));
+        let clock = Clock(LocalTime { year: 2024, month: 3, day: 9, hour: 7, minute: 5, second: 42 });
+        let converted = convert("photo.png", "image/png", &png, &config, &clock).unwrap();
+        assert!(matches!(converted.transformations.last(), Some(Transformation::Encode { .. })));
+        let steps = converted.transformations.len();
+        assert_eq!(converted.transformations[steps - 2], Transformation::Timestamp { text: "09.03.2024 07:05".to_string() });
+
+        // Same pixel size, with only the top-left corner drawn over
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output.dimensions(), (300, 200));
+        assert_ne!(*output.get_pixel(4, 4), image::Rgb([255, 255, 255]));
+        assert!(output.enumerate_pixels().filter(|(_, _, pixel)| pixel.0 != [255, 255, 255]).all(|(x, y, _)| x < 150 && y < 20));
+
+        assert_eq!(
+            convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap_err().to_string(),
+            "timestamp_overlay needs the current time, which this host cannot tell"
+        );
+
+        let config = config_from(serde_json::json!({ "timestamp_overlay": { "format": "%Y-%m-%d", "source": "Exif" } }));
+        let jpeg = jpeg_with_exif(&tiff_with_capture_date(b"2023:05:01 09:30:00"));
+        let converted = convert("photo.jpg", "image/jpeg", &jpeg, &config, &NoopObserver).unwrap();
+        assert!(converted.transformations.contains(&Transformation::Timestamp { text: "2023-05-01".to_string() }));
+        assert_eq!(
+            convert("photo.png", "image/png", &png, &config, &clock).unwrap_err().to_string(),
+            "photo.png has no EXIF capture time for timestamp_overlay to stamp"
+        );
+    }
+
+    #[test]
+    fn embedded_metadata_counts_against_the_size_cap() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 300)));
+        let config = ConversionConfig { embed_metadata: true, ..config_from(serde_json::json!({ "size_kb": { "max": 12 } })) };
+        let clock = Clock(LocalTime { year: 2024, month: 3, day: 9, hour: 7, minute: 5, second: 42 });
+        let converted = convert("photo.png", "image/png", &png, &config, &clock).unwrap();
+        assert!(converted.size_bytes <= 12 * 1024);
+        let output = converted.decode_data().unwrap();
+        let comment = format!("exam=NEET; document=photo; software=summa2 {}; creation_time=2024-03-09T07:05:42", env!("CARGO_PKG_VERSION"));
+        assert!(output.windows(comment.len()).any(|window| window == comment.as_bytes()));
+        let steps = converted.transformations.len();
+        let (Transformation::Encode { bytes: encoded, .. }, Transformation::EmbedMetadata { bytes: embedded }) =
+            (&converted.transformations[steps - 2], &converted.transformations[steps - 1])
+        else {
+            panic!("{:?}", converted.transformations);
+        };
+        assert_eq!(encoded + embedded, converted.size_bytes as usize);
+        assert_eq!(*embedded, comment.len() + 4);
+
+        // Without a clock the conversion time is left out
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let output = converted.decode_data().unwrap();
+        assert!(!output.windows(13).any(|window| window == b"creation_time"));
+
+        let pdf = jpeg_pdf(&[(converted.decode_data().unwrap(), 300, 300)], &[(200.0, 200.0)]);
+        let config = ConversionConfig { embed_metadata: true, ..config_from(serde_json::json!({ "format": ["PDF"] })) };
+        let converted = convert("photo.pdf", "application/pdf", &pdf, &config, &clock).unwrap();
+        let output = converted.decode_data().unwrap();
+        assert!(output.starts_with(&pdf) && output.windows(12).any(|window| window == b"MetadataDate"));
+        assert_eq!(converted.transformations, [Transformation::EmbedMetadata { bytes: output.len() - pdf.len() }]);
+
+        let xref_stream = b"%PDF-1.5\n1 0 obj << /Type /XRef /Size 2 >> stream\nendstream endobj\nstartxref\n9\n%%EOF";
+        let converted = convert("photo.pdf", "application/pdf", xref_stream, &config, &clock).unwrap();
+        assert_eq!(converted.warnings, ["Metadata was not embedded: it uses a cross-reference stream"]);
+    }
+
+    #[test]
+    fn signature_in_a_disallowed_ink_is_rejected_unless_warning() {
+        let signature = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 80, |x, y| {
+            image::Rgb(if y.abs_diff(40 + x / 20) < 2 { [190, 20, 40] } else { [250, 250, 250] })
+        }));
+        let png = encode_png(&signature);
+        let mut config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "ink_color": { "allowed": ["black", "blue"] },
+        }));
+        let error = convert("sign.png", "image/png", &png, &config, &NoopObserver).unwrap_err();
+        assert_eq!(error.code(), Some("ink_color"));
+        assert_eq!(error.to_string(), "Signature ink looks red (#BE1428); allowed inks: black, blue");
+
+        config.target_spec.ink_color.as_mut().unwrap().on_mismatch = ColorMismatchPolicy::Warn;
+        let converted = convert("sign.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let ink = converted.ink.unwrap();
+        assert_eq!((ink.color.as_str(), ink.allowed), ("red", false));
+    }
+
+    #[test]
+    fn square_output_is_cropped_to_exact_dimensions() {
+        let config = config_from(serde_json::json!({
+            "pixels": { "max_width": 350, "max_height": 350, "square": true },
+            "aspect_ratio": { "min": 0.9, "max": 1.1 },
+        }));
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(700, 703)));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let dimensions = converted.dimensions.as_ref().unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (350.0, 350.0));
+        assert_eq!(converted.transformations[0], Transformation::Crop { x: 0, y: 1, width: 700, height: 700 });
+        let decoded = image::load_from_memory(&converted.decode_data().unwrap()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (350, 350));
+
+        let plan = plan("photo.png", "image/png", &png, &config).unwrap();
+        assert_eq!(plan.target_dimensions, Some([350, 350]));
+
+        let off_by_one = Some(DimensionsSpec { width: 349.0, height: 350.0 });
+        assert_eq!(
+            validate_conversion_result(&[0; 1024], &off_by_one, &config.target_spec).unwrap_err().to_string(),
+            "Image is 349x350 but the spec requires a square image"
+        );
+    }
+
+    #[test]
+    fn fit_modes_reach_exact_dimensions_without_stretching() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 200)));
+        let config = |fit_mode: &str| {
+            config_from(serde_json::json!({
+                "format": ["PNG"],
+                "size_kb": { "max": 500 },
+                "pixels": { "width": 150, "height": 151 },
+                "fit_mode": fit_mode,
+                "background": { "required": "#3050C0" },
+            }))
+        };
+
+        // The odd pixel of vertical margin goes to the bottom
+        let padded = convert("photo.png", "image/png", &png, &config("Pad"), &NoopObserver).unwrap();
+        let pad = Transformation::Pad { left: 0, top: 25, right: 0, bottom: 26, color: "#3050C0".to_string() };
+        assert_eq!(padded.transformations[..2], [Transformation::Resize { from: [300, 200], to: [150, 100] }, pad]);
+        let img = image::load_from_memory(&padded.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (150, 151));
+        assert_eq!(img.get_pixel(75, 24).0, [0x30, 0x50, 0xC0]);
+        assert_eq!(img.get_pixel(75, 125).0, [0x30, 0x50, 0xC0]);
+        assert_ne!(img.get_pixel(75, 25).0, [0x30, 0x50, 0xC0]);
+
+        let covered = convert("photo.png", "image/png", &png, &config("Cover"), &NoopObserver).unwrap();
+        let crop = Transformation::Crop { x: 38, y: 0, width: 150, height: 151 };
+        assert_eq!(covered.transformations[..2], [Transformation::Resize { from: [300, 200], to: [227, 151] }, crop]);
+        let dimensions = covered.dimensions.as_ref().unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (150.0, 151.0));
+
+        let plan = plan("photo.png", "image/png", &png, &config("Cover")).unwrap();
+        assert_eq!(plan.transformations[..2], covered.transformations[..2]);
+    }
+
+    #[test]
+    fn border_frames_the_image_inside_the_pixel_size() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 400)));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "size_kb": { "max": 500 },
+            "pixels": { "width": 150, "height": 200 },
+            "border": { "width_px": 3, "color": "#102030" },
+        }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let border = Transformation::Border { width: 3, color: "#102030".to_string() };
+        assert_eq!(converted.transformations[..2], [Transformation::Resize { from: [300, 400], to: [144, 194] }, border]);
+        let img = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (150, 200));
+        for (x, y) in [(0, 0), (2, 100), (75, 197), (149, 199)] {
+            assert_eq!(img.get_pixel(x, y).0, [0x10, 0x20, 0x30], "({}, {})", x, y);
+        }
+        assert_ne!(img.get_pixel(3, 100).0, [0x10, 0x20, 0x30]);
+
+        // The frame counts as margin when mapping the output back
+        let geometry = converted.geometry.unwrap();
+        assert_eq!(geometry.pad, Insets { left: 3, top: 3, right: 3, bottom: 3 });
+        assert_eq!(geometry.crop, Region { x: 0.0, y: 0.0, width: 300.0, height: 400.0 });
+        assert_eq!(plan("photo.png", "image/png", &png, &config).unwrap().transformations[..2], converted.transformations[..2]);
+    }
+
+    #[test]
+    fn geometry_maps_the_output_back_onto_the_source() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 200)));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "size_kb": { "max": 500 },
+            "pixels": { "width": 150, "height": 151 },
+            "fit_mode": "Cover",
+        }));
+        // The crop after resizing falls between source pixels
+        let geometry = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap().geometry.unwrap();
+        assert_eq!((geometry.source, geometry.rotation, geometry.pad), ([300, 200], 0, Insets { left: 0, top: 0, right: 0, bottom: 0 }));
+        let crop = geometry.crop;
+        assert!((crop.x - 50.22).abs() < 0.01 && (crop.width - 198.24).abs() < 0.01, "{:?}", crop);
+        assert_eq!((crop.y, crop.height), (0.0, 200.0));
+        assert!((geometry.scale[0] - 227.0 / 300.0).abs() < 1e-6 && (geometry.scale[1] - 151.0 / 200.0).abs() < 1e-6);
+
+        // Margins added before the print rotation turn with the picture
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 300)));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "size_kb": { "max": 500 },
+            "pixels": { "width": 200, "height": 150 },
+            "fit_mode": "Pad",
+            "print_orientation": "Portrait",
+        }));
+        let geometry = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap().geometry.unwrap();
+        assert_eq!(geometry.crop, Region { x: 0.0, y: 0.0, width: 300.0, height: 300.0 });
+        assert_eq!((geometry.rotation, geometry.scale), (90, [0.5, 0.5]));
+        assert_eq!(geometry.pad, Insets { left: 0, top: 25, right: 0, bottom: 25 });
+
+        // A turn after cropping maps the crop back through it
+        let turned = [
+            Transformation::Rotate { degrees: 90 },
+            Transformation::Crop { x: 10, y: 20, width: 30, height: 40 },
+        ];
+        let geometry = Geometry::trace((100, 60), &turned).unwrap();
+        assert_eq!(geometry.crop, Region { x: 20.0, y: 20.0, width: 40.0, height: 30.0 });
+        let edges = [Transformation::RemoveEdges { left: 12, top: 0, right: 3, bottom: 5 }];
+        assert_eq!(Geometry::trace((100, 60), &edges).unwrap().crop, Region { x: 12.0, y: 0.0, width: 85.0, height: 55.0 });
+        assert_eq!(Geometry::trace((100, 60), &[Transformation::Deskew { degrees: 2.0, width: 90, height: 50 }]), None);
+    }
+
+    #[test]
+    fn integer_scaling_keeps_code_modules_crisp() {
+        // A QR-like pattern of 2px modules
+        let code = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(42, 42, |x, y| {
+            image::Luma([if (x / 2 * 7 + y / 2 * 13) % 5 < 2 { 0 } else { 255 }])
+        }));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "size_kb": { "max": 500 },
+            "pixels": { "width": 200, "height": 200 },
+            "integer_scaling": true,
+        }));
+        let converted = convert("code.png", "image/png", &encode_png(&code), &config, &NoopObserver).unwrap();
+        let pad = Transformation::Pad { left: 16, top: 16, right: 16, bottom: 16, color: "#FFFFFF".to_string() };
+        assert_eq!(converted.transformations[..2], [Transformation::Resize { from: [42, 42], to: [168, 168] }, pad]);
+
+        // Every output pixel copies exactly one source pixel: no blurred module edges
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_luma8();
+        assert_eq!(output.dimensions(), (200, 200));
+        let source = code.to_luma8();
+        for (x, y, pixel) in output.enumerate_pixels().filter(|(x, y, _)| (16..184).contains(x) && (16..184).contains(y)) {
+            assert_eq!(*pixel, *source.get_pixel((x - 16) / 4, (y - 16) / 4), "({}, {})", x, y);
+        }
+
+        assert_eq!(integer_scaled((300, 300), (100, 100)), (100, 100));
+        assert_eq!(integer_scaled((300, 200), (140, 140)), (100, 67));
+    }
+
+    #[test]
+    fn linear_light_resize_keeps_fine_bright_detail_bright() {
+        let checkerboard = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb(if (x + y) % 2 == 0 { [255; 3] } else { [0; 3] })
+        }));
+        let png = encode_png(&checkerboard);
+        let mean_level = |linear_light: bool| {
+            let config = config_from(serde_json::json!({
+                "format": ["PNG"],
+                "size_kb": { "max": 500 },
+                "pixels": { "width": 16, "height": 16 },
+                "linear_light_resize": linear_light,
+            }));
+            let converted = convert("checks.png", "image/png", &png, &config, &NoopObserver).unwrap();
+            let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_luma8();
+            output.pixels().map(|p| p.0[0] as f32).sum::<f32>() / output.len() as f32
+        };
+        // Half the light is white, which sRGB encodes as 188; averaging the codes gives 128
+        let (default, linear) = (mean_level(false), mean_level(true));
+        assert!((default - 128.0).abs() < 10.0, "{}", default);
+        assert!((linear - 188.0).abs() < 10.0, "{}", linear);
+    }
+
+    #[test]
+    fn large_downscales_are_halved_before_resampling() {
+        use image::imageops::FilterType::Lanczos3;
+        let source = image::DynamicImage::ImageRgb8(gradient(1203, 901));
+        let spec = |threshold: serde_json::Value| spec_from(serde_json::json!({ "pre_reduce_above": threshold }));
+
+        // 1203 / 100 is over the default 3: two halvings leave the last pass 3 times to go
+        let resampled = resample(&source, 100, 75, &spec(serde_json::Value::Null));
+        assert_eq!(resampled.dimensions(), (100, 75));
+        let halved = halve(&halve(&source, false), false);
+        assert_eq!(halved.dimensions(), (300, 225));
+        assert_eq!(resampled.to_rgb8(), resize_exact_alpha(&halved, 100, 75, Lanczos3, false).to_rgb8());
+
+        // At or under the threshold, or with it at 0, there is one pass
+        let direct = resize_exact_alpha(&source, 100, 75, Lanczos3, false).to_rgb8();
+        assert_eq!(resample(&source, 100, 75, &spec(serde_json::json!(0))).to_rgb8(), direct);
+        assert_eq!(resample(&source, 100, 75, &spec(serde_json::json!(13))).to_rgb8(), direct);
+        assert_eq!(resample(&source, 401, 300, &spec(serde_json::Value::Null)).to_rgb8(), resize_exact_alpha(&source, 401, 300, Lanczos3, false).to_rgb8());
+
+        // Through the pipeline the output still has the exact pixel size
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 500 }, "pixels": { "width": 97, "height": 61 } }));
+        let converted = convert("big.png", "image/png", &encode_png(&source), &config, &NoopObserver).unwrap();
+        let dimensions = converted.dimensions.unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (97.0, 61.0));
+    }
+
+    #[test]
+    fn canvas_places_the_image_by_percentage() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(300, 200)));
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "size_kb": { "max": 500 },
+            "pixels": { "width": 400, "height": 300 },
+            "canvas": { "width": 400, "height": 300, "background": "#20a040", "x_percent": 100, "y_percent": 25, "scale": 0.5 },
+        }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        // Fitted inside 200x150, then pushed to the right edge and a quarter of the way down
+        let pad = Transformation::Pad { left: 200, top: 42, right: 0, bottom: 125, color: "#20A040".to_string() };
+        assert_eq!(converted.transformations[..2], [Transformation::Resize { from: [300, 200], to: [200, 133] }, pad]);
+        let img = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (400, 300));
+        assert_eq!(img.get_pixel(199, 100).0, [0x20, 0xA0, 0x40]);
+        assert_eq!(img.get_pixel(300, 41).0, [0x20, 0xA0, 0x40]);
+        assert_ne!(img.get_pixel(300, 42).0, [0x20, 0xA0, 0x40]);
+
+        let plan = plan("photo.png", "image/png", &png, &config).unwrap();
+        assert_eq!(plan.transformations[..2], converted.transformations[..2]);
+        assert_eq!(plan.target_dimensions, Some([400, 300]));
+    }
+
+    #[test]
+    fn low_effective_dpi_fails_despite_upscaling() {
+        let mut config = config_from(serde_json::json!({
+            "dimensions_cm": { "width": 3.5, "height": 4.5 },
+            "resolution_px_per_inch": 200,
+            "min_effective_dpi": 200,
+        }));
+        // Upscaled to the right pixel size, but only ~109 DPI of real detail
+        let small = encode_png(&image::DynamicImage::ImageRgb8(gradient(150, 193)));
+        assert_eq!(
+            convert("photo.png", "image/png", &small, &config, &NoopObserver).unwrap_err().to_string(),
+            "Image has 109 DPI of detail at the required print size, minimum required: 200 DPI"
+        );
+        let large = encode_png(&image::DynamicImage::ImageRgb8(gradient(600, 772)));
+        assert!(convert("photo.png", "image/png", &large, &config, &NoopObserver).is_ok());
+
+        config.target_spec.on_low_resolution = ColorMismatchPolicy::Warn;
+        let converted = convert("photo.png", "image/png", &small, &config, &NoopObserver).unwrap();
+        let dimensions = converted.dimensions.unwrap();
+        assert_eq!((dimensions.width, dimensions.height), (275.0, 354.0));
+    }
+
+    #[test]
+    fn off_white_background_is_replaced_before_resizing() {
+        let photo = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 240, |x, y| {
+            let (dx, dy) = (x as i32 - 100, y as i32 - 100);
+            image::Rgb(if dx * dx + dy * dy < 2500 || y > 180 { [80, 50, 40] } else { [230, 225, 200] })
+        }));
+        let png = encode_png(&photo);
+        let config = config_from(serde_json::json!({
+            "format": ["PNG"],
+            "pixels": { "width": 100, "height": 120 },
+            "background": { "required": "#FFFFFF", "auto_replace": true },
+        }));
+        let converted = convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert!(converted.background.as_ref().unwrap().replaced);
+        assert_eq!(
+            converted.transformations[0],
+            Transformation::ReplaceBackground { color: "#FFFFFF".to_string() }
+        );
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgb8();
+        assert_eq!(output.get_pixel(5, 5).0, [255, 255, 255]);
+
+        let planned = plan("photo.png", "image/png", &png, &config).unwrap();
+        assert!(planned.notes.iter().any(|note| note.starts_with("background is checked")));
+    }
+
+    // An antialiased red disc on a fully transparent (black) canvas, as logo exports come
+    fn transparent_logo(size: u32) -> image::DynamicImage {
+        let radius = size as f32 * 0.4;
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size, size, |x, y| {
+            let distance = ((x as f32 - size as f32 / 2.0).powi(2) + (y as f32 - size as f32 / 2.0).powi(2)).sqrt();
+            let alpha = (radius - distance + 0.5).clamp(0.0, 1.0);
+            if alpha == 0.0 {
+                image::Rgba([0, 0, 0, 0])
+            } else {
+                image::Rgba([220, 30, 30, (alpha * 255.0).round() as u8])
+            }
+        }))
+    }
+
+    #[test]
+    fn transparent_png_keeps_alpha_and_edge_color_through_resize() {
+        let png = encode_png(&transparent_logo(128));
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "pixels": { "width": 48, "height": 48 } }));
+        let converted = convert("logo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        let output = image::load_from_memory(&converted.decode_data().unwrap()).unwrap().to_rgba8();
+        assert_eq!(output.dimensions(), (48, 48));
+        assert_eq!(output.get_pixel(0, 0).0[3], 0);
+        assert_eq!(output.get_pixel(24, 24).0, [220, 30, 30, 255]);
+
+        // Partly transparent edge pixels stay red instead of darkening toward the black canvas
+        let edge: Vec<_> = output.pixels().filter(|p| (1..255).contains(&p.0[3])).collect();
+        assert!(!edge.is_empty());
+        for pixel in edge {
+            assert!(pixel.0[0].abs_diff(220) <= 8 && pixel.0[1].abs_diff(30) <= 8, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn preview_is_composited_on_white_and_original_keeps_alpha() {
+        let png = encode_png(&transparent_logo(64));
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 50 } }));
+        let PreviewPair { preview, original } = convert_with_preview("logo.png", "image/png", &png, &config, &NoopObserver).unwrap();
+        assert_eq!((preview.format.as_str(), original.format.as_str()), ("JPEG", "PNG"));
+        assert!(preview.compliant && original.compliant);
+        assert_eq!(preview.transformations[0], Transformation::Flatten { color: "#FFFFFF".to_string() });
+
+        // Corners were transparent: white in the preview, still transparent in the original
+        let jpeg = image::load_from_memory(&preview.decode_data().unwrap()).unwrap().to_rgb8();
+        assert!(jpeg.get_pixel(0, 0).0.iter().all(|&c| c >= 250), "{:?}", jpeg.get_pixel(0, 0));
+        let center = jpeg.get_pixel(32, 32).0;
+        assert!(center[0].abs_diff(220) <= 8 && center[1].abs_diff(30) <= 8, "{:?}", center);
+        let png = image::load_from_memory(&original.decode_data().unwrap()).unwrap().to_rgba8();
+        assert_eq!((png.get_pixel(0, 0).0[3], png.get_pixel(32, 32).0), (0, [220, 30, 30, 255]));
+
+        let mut config = config_from(serde_json::json!({ "format": ["JPEG"], "background": { "required": "#0000FF" }, "png_profile": "RGB8" }));
+        assert_eq!(
+            convert_with_preview("logo.png", "image/png", &encode_png(&transparent_logo(64)), &config, &NoopObserver).unwrap_err().to_string(),
+            "Invalid config: png_profile drops the alpha channel the original PNG keeps"
+        );
+        config.target_spec.png_profile = None;
+        let pair = convert_with_preview("logo.png", "image/png", &encode_png(&transparent_logo(64)), &config, &NoopObserver).unwrap();
+        let jpeg = image::load_from_memory(&pair.preview.decode_data().unwrap()).unwrap().to_rgb8();
+        assert!(jpeg.get_pixel(0, 0).0[2] >= 250 && jpeg.get_pixel(0, 0).0[0] <= 5, "{:?}", jpeg.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn thumbnail_comes_from_the_same_decode_as_the_conversion() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(400, 300)));
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 200 }, "print_orientation": "Portrait" }));
+        let ThumbnailPair { converted, thumbnail } = convert_with_thumbnail("photo.png", "image/png", &png, &config, 100, &NoopObserver).unwrap();
+        assert!(converted.compliant);
+        assert_eq!(converted.data_url, convert("photo.png", "image/png", &png, &config, &NoopObserver).unwrap().data_url);
+        // Turned like the conversion, then shrunk to fit
+        assert_eq!((thumbnail.width, thumbnail.height), (75, 100));
+        assert!(thumbnail.data_url.starts_with("data:image/jpeg;base64,"));
+
+        // A small output is not enlarged
+        let thumbnail = convert_with_thumbnail("photo.png", "image/png", &png, &config, 1000, &NoopObserver).unwrap().thumbnail;
+        assert_eq!((thumbnail.width, thumbnail.height), (300, 400));
+        assert_eq!(
+            convert_with_thumbnail("photo.png", "image/png", &png, &config, 0, &NoopObserver).unwrap_err().to_string(),
+            "Invalid config: thumb_max_edge must be at least 1"
+        );
+    }
+
+    #[test]
+    fn png_optimize_writes_indexed_color_with_transparency() {
+        let logo = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba(match (x / 16 + y / 16) % 3 {
+                0 => [0, 0, 0, 0],
+                1 => [20, 60, 160, 255],
+                _ => [20, 60, 160, 128],
+            })
+        }));
+        let png = encode_png(&logo);
+        let mut config = config_from(serde_json::json!({ "format": ["PNG"] }));
+        let plain = convert("stamp.png", "image/png", &png, &config, &NoopObserver).unwrap().decode_data().unwrap();
+        config.target_spec.png_optimize = Some(true);
+        let optimized = convert("stamp.png", "image/png", &png, &config, &NoopObserver).unwrap().decode_data().unwrap();
+
+        // IHDR color type 3 is indexed color
+        assert_eq!((plain[25], optimized[25]), (6, 3));
+        assert!(optimized.len() < plain.len(), "{} >= {}", optimized.len(), plain.len());
+        let decoded = image::load_from_memory(&optimized).unwrap().to_rgba8();
+        assert_eq!(decoded, logo.to_rgba8());
+
+        // Too many colors for a palette: opaque images drop the alpha channel instead
+        let photo = encode_png(&image::DynamicImage::ImageRgb8(gradient(64, 64)));
+        let optimized = convert("photo.png", "image/png", &photo, &config, &NoopObserver).unwrap().decode_data().unwrap();
+        assert_eq!(optimized[25], 2);
+    }
+
+    #[test]
+    fn output_color_type_holds_whatever_the_source() {
+        let gray = encode_png(&image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, _| image::Luma([x as u8 * 4]))));
+        // Few enough colors that the Best profile would otherwise write a palette
+        let flat = encode_png(&image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb(if (x / 8 + y / 8) % 2 == 0 { [0, 0, 0] } else { [200, 30, 40] })
+        })));
+        for (color_type, ihdr) in [("rgb8", 2), ("rgba8", 6)] {
+            let config = config_from(serde_json::json!({ "format": ["PNG"], "performance_profile": "Best", "output_color_type": color_type }));
+            for png in [&gray, &flat] {
+                let output = convert("scan.png", "image/png", png, &config, &NoopObserver).unwrap().decode_data().unwrap();
+                assert_eq!((output[24], output[25]), (8, ihdr), "{}", color_type);
+            }
+        }
+        let config = config_from(serde_json::json!({ "format": ["JPEG"], "output_color_type": "rgb8" }));
+        let output = convert("scan.png", "image/png", &gray, &config, &NoopObserver).unwrap().decode_data().unwrap();
+        assert_eq!(declared_color_type(&output).as_deref(), Some("rgb8"));
+
+        // The header is checked as well, whatever wrote it
+        let config = config_from(serde_json::json!({ "format": ["PNG"], "performance_profile": "Best" }));
+        let indexed = convert("scan.png", "image/png", &flat, &config, &NoopObserver).unwrap().decode_data().unwrap();
+        let spec = spec_from(serde_json::json!({ "format": ["PNG"], "output_color_type": "rgb8" }));
+        assert_eq!(conversion_violations(&indexed, &None, &spec), ["Output is indexed color but output_color_type requires rgb8"]);
+    }
+
+    #[test]
+    fn png_profile_fixes_the_byte_layout() {
+        let stamp = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, y| {
+            image::Rgba(if (x / 8 + y / 8) % 2 == 0 { [0, 0, 0, 0] } else { [180, 30, 40, 255] })
+        }));
+        let convert_with = |source: &image::DynamicImage, profile: &str| {
+            let config = config_from(serde_json::json!({ "format": ["PNG"], "png_profile": profile }));
+            convert("stamp.png", "image/png", &encode_png(source), &config, &NoopObserver).map(|converted| converted.decode_data().unwrap())
+        };
+
+        // IHDR bit depth, then color type: 0 grayscale, 2 RGB, 6 RGBA, 3 indexed
+        for (profile, color_type) in [("Grayscale8", 0), ("RGB8", 2), ("RGBA8", 6), ("Indexed8", 3)] {
+            let output = convert_with(&stamp, profile).unwrap();
+            assert_eq!((output[24], output[25]), (8, color_type), "{}", profile);
+            assert_eq!(output, convert_with(&stamp, profile).unwrap(), "{}", profile);
+        }
+        let indexed = convert_with(&stamp, "Indexed8").unwrap();
+        assert_eq!(image::load_from_memory(&indexed).unwrap().to_rgba8(), stamp.to_rgba8());
+
+        let photo = image::DynamicImage::ImageRgb8(gradient(64, 64));
+        assert_eq!(
+            convert_with(&photo, "Indexed8").unwrap_err().to_string(),
+            "png_profile Indexed8 allows at most 256 colors; set posterize_levels to reduce them"
+        );
+    }
+
+    #[test]
+    fn png_interlaced_writes_adam7_passes_that_decode_to_the_same_pixels() {
+        let stamp = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(13, 7, |x, y| {
+            image::Rgba(if (x + y) % 3 == 0 { [0, 0, 0, 0] } else { [180, 30, 40, 255] })
+        }));
+        let photo = image::DynamicImage::ImageRgb8(gradient(67, 45));
+        let tiny = image::DynamicImage::ImageRgb8(gradient(3, 2));
+        for (source, layout) in [(&photo, serde_json::json!({})), (&photo, serde_json::json!({ "png_profile": "RGB8" })), (&stamp, serde_json::json!({ "png_optimize": true })), (&tiny, serde_json::json!({}))] {
+            let mut spec = serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 200 } });
+            spec.as_object_mut().unwrap().extend(layout.as_object().unwrap().clone());
+            let plain = convert("stamp.png", "image/png", &encode_png(source), &config_from(spec.clone()), &NoopObserver).unwrap();
+            spec["png_interlaced"] = serde_json::json!(true);
+            let interlaced = convert("stamp.png", "image/png", &encode_png(source), &config_from(spec), &NoopObserver).unwrap();
+            let (plain, interlaced) = (plain.decode_data().unwrap(), interlaced.decode_data().unwrap());
+            // IHDR interlace method, after the bit depth, color type, compression and filter
+            assert_eq!((plain[28], interlaced[28]), (0, 1));
+            assert_eq!(&plain[24..26], &interlaced[24..26]);
+            let decode = |png: &[u8]| image::load_from_memory(png).unwrap().to_rgba8();
+            assert_eq!(decode(&interlaced), decode(&plain));
+        }
+    }
+
+    // Invariants of calculate_target_dimensions over arbitrary specs. Shrunk
+    // counterexamples belong in the explicit tests above, not only in proptest's cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    mod properties {
+        use super::*;
+        use crate::core::spec::validate_config;
+        use proptest::prelude::*;
+
+        fn side() -> impl Strategy<Value = Option<u32>> {
+            prop::option::of(1u32..4000)
+        }
+
+        fn ratio() -> impl Strategy<Value = Option<f32>> {
+            prop::option::of(0.2f32..5.0)
+        }
+
+        fn pixels() -> impl Strategy<Value = Option<serde_json::Value>> {
+            prop_oneof![
+                Just(None),
+                (1u32..4000, 1u32..4000).prop_map(|(w, h)| Some(serde_json::json!({ "width": w, "height": h }))),
+                (side(), side(), side(), side()).prop_map(|(min_w, max_w, min_h, max_h)| Some(serde_json::json!({
+                    "min_width": min_w, "max_width": max_w, "min_height": min_h, "max_height": max_h
+                }))),
+            ]
+        }
+
+        fn aspect_json(min: Option<f32>, max: Option<f32>, hw_min: Option<f32>, hw_max: Option<f32>) -> serde_json::Value {
+            serde_json::json!({ "min": min, "max": max, "height_to_width_min": hw_min, "height_to_width_max": hw_max })
+        }
+
+        fn aspect() -> impl Strategy<Value = Option<serde_json::Value>> {
+            prop::option::of((ratio(), ratio(), ratio(), ratio()).prop_map(|(min, max, hw_min, hw_max)| aspect_json(min, max, hw_min, hw_max)))
+        }
+
+        // Optional (min, max) with min <= max when both are present
+        fn ordered_sides() -> impl Strategy<Value = (Option<u32>, Option<u32>)> {
+            (side(), side()).prop_map(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) => (Some(a.min(b)), Some(a.max(b))),
+                other => other,
+            })
+        }
+
+        // Bounds (min, max, height_to_width_min, height_to_width_max) that all admit the
+        // width / height ratio `ratio`, so they are consistent by construction
+        fn overlapping_ratios() -> impl Strategy<Value = (Option<f32>, Option<f32>, Option<f32>, Option<f32>)> {
+            let slack = || prop::option::of(0.0f32..2.0);
+            (0.25f32..4.0, slack(), slack(), slack(), slack()).prop_map(|(ratio, min, max, hw_min, hw_max)| {
+                (
+                    min.map(|s| ratio / (1.0 + s)),
+                    max.map(|s| ratio * (1.0 + s)),
+                    hw_min.map(|s| 1.0 / (ratio * (1.0 + s))),
+                    hw_max.map(|s| (1.0 + s) / ratio),
+                )
+            })
+        }
+
+        fn physical() -> impl Strategy<Value = Option<serde_json::Value>> {
+            prop::option::of((0.0f32..30.0, 0.0f32..30.0).prop_map(|(w, h)| serde_json::json!({ "width": w, "height": h })))
+        }
+
+        fn spec(
+            pixels: Option<serde_json::Value>,
+            aspect: Option<serde_json::Value>,
+            physical: Option<serde_json::Value>,
+            megapixels: Option<f32>,
+        ) -> DocumentSpec {
+            spec_from(serde_json::json!({
+                "pixels": pixels,
+                "aspect_ratio": aspect,
+                "dimensions_cm": physical,
+                "max_megapixels": megapixels,
+            }))
+        }
+
+        fn is_valid(spec: &DocumentSpec) -> bool {
+            let config = ConversionConfig { target_spec: spec.clone(), ..config_from(serde_json::json!({})) };
+            validate_config(&config).is_ok()
+        }
+
+        // Within one pixel of rounding on either axis
+        fn ratio_within(width: u32, height: u32, min: Option<f32>, max: Option<f32>) -> bool {
+            let (width, height) = (width as f64, height as f64);
+            min.is_none_or(|min| width + 1.0 >= height * min as f64 || width / (height - 1.0) >= min as f64)
+                && max.is_none_or(|max| width - 1.0 <= height * max as f64 || width / (height + 1.0) <= max as f64)
+        }
+
+        proptest! {
+            #[test]
+            fn valid_specs_give_at_least_one_pixel_deterministically(
+                width in 1u32..8000,
+                height in 1u32..8000,
+                pixels in pixels(),
+                aspect in aspect(),
+                physical in physical(),
+                megapixels in prop::option::of(0.01f32..20.0),
+            ) {
+                let spec = spec(pixels, aspect, physical, megapixels);
+                let result = calculate_target_dimensions(width, height, &spec);
+                prop_assert_eq!(calculate_target_dimensions(width, height, &spec), result.clone());
+                match result {
+                    Ok((target_width, target_height)) => prop_assert!(target_width >= 1 && target_height >= 1),
+                    Err(e) => prop_assert!(matches!(e, ConvertError::InvalidConfig(_)) && !is_valid(&spec), "{}", e),
+                }
+            }
+
+            #[test]
+            fn pixel_range_is_respected_without_physical_dimensions(
+                width in 1u32..8000,
+                height in 1u32..8000,
+                ((min_w, max_w), (min_h, max_h)) in (ordered_sides(), ordered_sides()),
+                aspect in prop::option::of(overlapping_ratios()),
+            ) {
+                let pixels = serde_json::json!({ "min_width": min_w, "max_width": max_w, "min_height": min_h, "max_height": max_h });
+                let aspect = aspect.map(|(min, max, hw_min, hw_max)| aspect_json(min, max, hw_min, hw_max));
+                let spec = spec(Some(pixels), aspect, None, None);
+                prop_assume!(is_valid(&spec));
+                let (target_width, target_height) = calculate_target_dimensions(width, height, &spec).unwrap();
+                prop_assert!((min_w.unwrap_or(1)..=max_w.unwrap_or(u32::MAX)).contains(&target_width), "width {}", target_width);
+                prop_assert!((min_h.unwrap_or(1)..=max_h.unwrap_or(u32::MAX)).contains(&target_height), "height {}", target_height);
+            }
+
+            #[test]
+            fn aspect_bounds_hold_within_one_pixel(
+                width in 1u32..8000,
+                height in 1u32..8000,
+                (min, max, hw_min, hw_max) in overlapping_ratios(),
+            ) {
+                let spec = spec(None, Some(aspect_json(min, max, hw_min, hw_max)), None, None);
+                prop_assume!(is_valid(&spec));
+                let (target_width, target_height) = calculate_target_dimensions(width, height, &spec).unwrap();
+                prop_assert!(ratio_within(target_width, target_height, min, max), "{}x{}", target_width, target_height);
+                prop_assert!(ratio_within(target_height, target_width, hw_min, hw_max), "{}x{}", target_width, target_height);
+                // Corrections never grow an axis past MAX_ASPECT_EDGE
+                prop_assert!(target_width <= width.max(MAX_ASPECT_EDGE) && target_height <= height.max(MAX_ASPECT_EDGE));
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::spec::{validate_config, ConversionConfig, DocumentSpec};
+use super::ConvertError;
+
+// Wire format of a preset bundle: `{ "version": "2025-2", "presets": [<ConversionConfig>, ...] }`
+#[derive(Deserialize)]
+struct PresetBundle {
+    version: Option<String>,
+    presets: Vec<serde_json::Value>,
+}
+
+/// Outcome of merging one preset bundle into a `PresetRegistry`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct PresetLoadReport {
+    pub version: Option<String>,
+    pub loaded: u32,
+    pub rejected: Vec<RejectedPreset>,
+    // The source already supplied this version (or the server answered 304), so the
+    // registry was left as it was
+    pub not_modified: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct RejectedPreset {
+    /// Position of the entry in the bundle's `presets` array
+    pub index: u32,
+    pub exam_type: Option<String>,
+    pub document_type: Option<String>,
+    pub error: String,
+}
+
+/// Conversion configs keyed by exam and document type. Bundles merged later override
+/// entries with the same key, so hosts merge their built-in presets first and remote
+/// ones after.
+#[derive(Debug, Clone, Default)]
+pub struct PresetRegistry {
+    presets: BTreeMap<(String, String), ConversionConfig>,
+    // Last bundle version merged from each source
+    versions: HashMap<String, String>,
+}
+
+impl PresetRegistry {
+    /// Merge the bundle `bundle_json` fetched from `source`. Every entry is validated
+    /// like `parse_config`; invalid ones are reported and skipped while the rest load.
+    /// Only a bundle that is not JSON of the expected shape is an error. A bundle whose
+    /// `version` matches the last one merged from `source` is not re-validated.
+    pub fn merge(&mut self, source: &str, bundle_json: &str) -> Result<PresetLoadReport, ConvertError> {
+        let bundle: PresetBundle = serde_json::from_str(bundle_json)
+            .map_err(|e| ConvertError::InvalidConfig(format!("preset bundle is malformed: {}", e)))?;
+        if bundle.version.is_some() && bundle.version.as_ref() == self.versions.get(source) {
+            return Ok(PresetLoadReport { version: bundle.version, loaded: 0, rejected: vec![], not_modified: true });
+        }
+
+        let mut report = PresetLoadReport { version: bundle.version.clone(), loaded: 0, rejected: vec![], not_modified: false };
+        for (index, entry) in bundle.presets.into_iter().enumerate() {
+            let key = |field: &str| entry.get(field).and_then(|v| v.as_str()).map(str::to_string);
+            let (exam_type, document_type) = (key("exam_type"), key("document_type"));
+            let config = serde_json::from_value::<ConversionConfig>(entry)
+                .map_err(|e| ConvertError::InvalidConfig(e.to_string()))
+                .and_then(|config| validate_config(&config).map(|_| config));
+            match config {
+                Ok(config) => {
+                    self.presets.insert((config.exam_type.clone(), config.document_type.clone()), config);
+                    report.loaded += 1;
+                }
+                Err(error) => report.rejected.push(RejectedPreset {
+                    index: index as u32,
+                    exam_type,
+                    document_type,
+                    error: error.to_string(),
+                }),
+            }
+        }
+        if let Some(version) = bundle.version {
+            self.versions.insert(source.to_string(), version);
+        }
+        Ok(report)
+    }
+
+    /// The config one file of a mixed batch converts with: `base` with `spec` as its
+    /// target spec (validated like `parse_config`), the preset for `document_type` under
+    /// `base`'s exam, or `base` itself when neither is given or `document_type` is its own.
+    pub fn config_for(
+        &self,
+        base: &ConversionConfig,
+        document_type: Option<&str>,
+        spec: Option<DocumentSpec>,
+    ) -> Result<ConversionConfig, ConvertError> {
+        match (spec, document_type) {
+            (Some(spec), document_type) => {
+                let config = ConversionConfig {
+                    document_type: document_type.unwrap_or(&base.document_type).to_string(),
+                    target_spec: spec,
+                    ..base.clone()
+                };
+                validate_config(&config)?;
+                Ok(config)
+            }
+            (None, Some(document_type)) if document_type != base.document_type => {
+                self.get(&base.exam_type, document_type).cloned().ok_or_else(|| {
+                    ConvertError::InvalidConfig(format!("no preset for exam {} document {}", base.exam_type, document_type))
+                })
+            }
+            (None, _) => Ok(base.clone()),
+        }
+    }
+
+    /// Version of the last bundle merged from `source`, if it declared one.
+    pub fn version(&self, source: &str) -> Option<&str> {
+        self.versions.get(source).map(String::as_str)
+    }
+
+    pub fn get(&self, exam_type: &str, document_type: &str) -> Option<&ConversionConfig> {
+        self.presets.get(&(exam_type.to_string(), document_type.to_string()))
+    }
+
+    /// Every preset, ordered by exam type and then document type.
+    pub fn presets(&self) -> impl Iterator<Item = &ConversionConfig> {
+        self.presets.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(version: &str, presets: serde_json::Value) -> String {
+        serde_json::json!({ "version": version, "presets": presets }).to_string()
+    }
+
+    fn preset(exam_type: &str, document_type: &str, max_kb: u32) -> serde_json::Value {
+        serde_json::json!({
+            "exam_type": exam_type,
+            "document_type": document_type,
+            "target_spec": { "format": ["JPEG"], "size_kb": { "max": max_kb } },
+        })
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected_individually() {
+        let mut registry = PresetRegistry::default();
+        let mut invalid = preset("NEET", "signature", 30);
+        invalid["target_spec"]["posterize_levels"] = serde_json::json!(1);
+        let report = registry
+            .merge("remote", &bundle("1", serde_json::json!([preset("NEET", "photo", 200), invalid, { "exam_type": "JEE" }])))
+            .unwrap();
+
+        assert_eq!(report.loaded, 1);
+        assert_eq!(report.rejected.len(), 2);
+        assert_eq!(
+            report.rejected[0],
+            RejectedPreset {
+                index: 1,
+                exam_type: Some("NEET".to_string()),
+                document_type: Some("signature".to_string()),
+                error: "Invalid config: posterize_levels must be at least 2, got 1".to_string(),
+            }
+        );
+        assert_eq!((report.rejected[1].index, report.rejected[1].document_type.as_deref()), (2, None));
+        assert!(registry.get("NEET", "photo").is_some());
+        assert!(registry.get("NEET", "signature").is_none());
+
+        assert!(registry.merge("remote", "[]").is_err());
+    }
+
+    #[test]
+    fn later_bundles_override_and_repeat_versions_are_skipped() {
+        let mut registry = PresetRegistry::default();
+        registry.merge("builtin", &bundle("1", serde_json::json!([preset("NEET", "photo", 200), preset("JEE", "photo", 300)]))).unwrap();
+        registry.merge("remote", &bundle("7", serde_json::json!([preset("NEET", "photo", 100)]))).unwrap();
+        assert_eq!(registry.get("NEET", "photo").unwrap().target_spec.size_kb.max, 100);
+        assert_eq!(registry.get("JEE", "photo").unwrap().target_spec.size_kb.max, 300);
+
+        let report = registry.merge("remote", &bundle("7", serde_json::json!([preset("NEET", "photo", 50)]))).unwrap();
+        assert!(report.not_modified);
+        assert_eq!(registry.version("remote"), Some("7"));
+        assert_eq!(registry.get("NEET", "photo").unwrap().target_spec.size_kb.max, 100);
+
+        let exams: Vec<&str> = registry.presets().map(|config| config.exam_type.as_str()).collect();
+        assert_eq!(exams, ["JEE", "NEET"]);
+    }
+
+    #[test]
+    fn each_file_of_a_mixed_batch_gets_its_own_config() {
+        let mut registry = PresetRegistry::default();
+        registry.merge("builtin", &bundle("1", serde_json::json!([preset("NEET", "signature", 20)]))).unwrap();
+        let base: ConversionConfig = serde_json::from_value(preset("NEET", "photo", 50)).unwrap();
+
+        let signature = registry.config_for(&base, Some("signature"), None).unwrap();
+        assert_eq!((signature.document_type.as_str(), signature.target_spec.size_kb.max), ("signature", 20));
+        assert_eq!(registry.config_for(&base, Some("photo"), None).unwrap().target_spec.size_kb.max, 50);
+        assert_eq!(
+            registry.config_for(&base, Some("thumb"), None).unwrap_err().to_string(),
+            "Invalid config: no preset for exam NEET document thumb"
+        );
+
+        let spec: DocumentSpec = serde_json::from_value(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 30 } })).unwrap();
+        let thumb = registry.config_for(&base, Some("thumb"), Some(spec.clone())).unwrap();
+        assert_eq!((thumb.exam_type.as_str(), thumb.document_type.as_str(), thumb.target_spec.format[0].as_str()), ("NEET", "thumb", "PNG"));
+        assert_eq!(registry.config_for(&base, None, Some(spec)).unwrap().document_type, "photo");
+
+        let invalid: DocumentSpec = serde_json::from_value(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 30 }, "posterize_levels": 1 })).unwrap();
+        assert_eq!(
+            registry.config_for(&base, None, Some(invalid)).unwrap_err().to_string(),
+            "Invalid config: posterize_levels must be at least 2, got 1"
+        );
+        let no_format: DocumentSpec = serde_json::from_value(serde_json::json!({ "format": [], "size_kb": { "max": 30 } })).unwrap();
+        assert_eq!(registry.config_for(&base, None, Some(no_format)).unwrap_err(), ConvertError::NoTargetFormat);
+    }
+}
@@ -0,0 +1,173 @@
+//! Provenance written into converted files for `embed_metadata`: a COM segment in JPEGs,
+//! tEXt chunks in PNGs and an XMP packet in PDFs. Re-encoded images carry nothing over
+//! from the input, so these are the only metadata they hold.
+
+use super::diagnostics::build_info;
+use super::events::Observer;
+use super::pdf::add_metadata;
+use super::spec::ConversionConfig;
+use super::timestamp::{format_time, LocalTime};
+
+pub(crate) struct Provenance {
+    exam_type: String,
+    document_type: String,
+    software: String,
+    // Unset when the host has no clock
+    converted: Option<String>,
+}
+
+impl Provenance {
+    pub(crate) fn new(config: &ConversionConfig, observer: &dyn Observer) -> Self {
+        let iso = |time: LocalTime| format_time("%Y-%m-%dT%H:%M:%S", &time).unwrap_or_default();
+        Provenance {
+            exam_type: config.exam_type.clone(),
+            document_type: config.document_type.clone(),
+            software: format!("summa2 {}", build_info().version),
+            converted: observer.local_time().map(iso),
+        }
+    }
+
+    // Keyword and value of each field, with the PNG keywords where the PNG spec has one
+    fn fields(&self) -> Vec<(&'static str, &str)> {
+        let mut fields =
+            vec![("Exam", self.exam_type.as_str()), ("Document", self.document_type.as_str()), ("Software", self.software.as_str())];
+        fields.extend(self.converted.as_deref().map(|time| ("Creation Time", time)));
+        fields
+    }
+
+    // All fields on one line, for formats with a single free-text slot
+    fn summary(&self) -> String {
+        self.fields().iter().map(|(key, value)| format!("{}={}", key.to_lowercase().replace(' ', "_"), value)).collect::<Vec<_>>().join("; ")
+    }
+
+    /// Bytes embedding adds to an image in `format`, so the size loop can leave room for
+    /// them; None for formats that get no metadata.
+    pub(crate) fn overhead(&self, format: &str) -> Option<usize> {
+        match format.to_uppercase().as_str() {
+            "JPEG" | "JPG" => Some(self.jpeg_segment().len()),
+            "PNG" => Some(self.png_chunks().len()),
+            _ => None,
+        }
+    }
+
+    /// `data` with the provenance added, or why it could not be.
+    pub(crate) fn embed(&self, data: &[u8], format: &str) -> Result<Vec<u8>, String> {
+        match format.to_uppercase().as_str() {
+            "JPEG" | "JPG" => {
+                // After the APPn segments, which readers expect straight after SOI
+                let mut at = 2;
+                while data.get(at) == Some(&0xFF) && data.get(at + 1).is_some_and(|marker| (0xE0..=0xEF).contains(marker)) {
+                    at += 2 + u16::from_be_bytes([data[at + 2], data[at + 3]]) as usize;
+                }
+                Ok([&data[..at], &self.jpeg_segment(), &data[at..]].concat())
+            }
+            // Straight after IHDR, which is always 25 bytes long
+            "PNG" => Ok([&data[..33], &self.png_chunks(), &data[33..]].concat()),
+            "PDF" => {
+                let pdf_a = data.windows(27).any(|window| window == b"<pdfaid:part>1</pdfaid:part>");
+                add_metadata(data, self.xmp(pdf_a).as_bytes())
+            }
+            _ => Err(format!("{} files cannot carry it", format)),
+        }
+    }
+
+    fn jpeg_segment(&self) -> Vec<u8> {
+        let text = self.summary();
+        let length = (text.len() + 2).min(u16::MAX as usize);
+        [&[0xFF, 0xFE][..], &(length as u16).to_be_bytes(), &text.as_bytes()[..length - 2]].concat()
+    }
+
+    fn png_chunks(&self) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        for (keyword, value) in self.fields() {
+            // tEXt is Latin-1
+            let text: Vec<u8> = value.chars().map(|c| u8::try_from(c as u32).unwrap_or(b'?')).collect();
+            let body = [b"tEXt", keyword.as_bytes(), &[0], &text].concat();
+            chunks.extend(((body.len() - 4) as u32).to_be_bytes());
+            chunks.extend(&body);
+            chunks.extend(crc32fast::hash(&body).to_be_bytes());
+        }
+        chunks
+    }
+
+    // Only the Dublin Core and XMP basic schemas, which PDF/A accepts without extension
+    // schemas; a PDF/A input keeps its identification
+    fn xmp(&self, pdf_a: bool) -> String {
+        let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let mut xmp = String::from("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        xmp.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+        xmp.push_str("<rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"");
+        if pdf_a {
+            xmp.push_str(" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"");
+        }
+        xmp.push_str(">\n");
+        xmp.push_str(&format!("<xmp:CreatorTool>{}</xmp:CreatorTool>\n", escape(&self.software)));
+        if let Some(converted) = &self.converted {
+            xmp.push_str(&format!("<xmp:MetadataDate>{}</xmp:MetadataDate>\n", converted));
+        }
+        xmp.push_str(&format!(
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            escape(&self.summary())
+        ));
+        if pdf_a {
+            xmp.push_str("<pdf:Producer>summa2 document converter</pdf:Producer>\n");
+            xmp.push_str("<pdfaid:part>1</pdfaid:part>\n<pdfaid:conformance>B</pdfaid:conformance>\n");
+        }
+        xmp.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>");
+        xmp
+    }
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+
+    fn provenance(converted: Option<&str>) -> Provenance {
+        Provenance {
+            exam_type: "NEET".to_string(),
+            document_type: "photo & sign".to_string(),
+            software: "summa2 1.2.3".to_string(),
+            converted: converted.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn images_get_a_comment_or_text_chunks() {
+        let provenance = provenance(Some("2024-03-09T07:05:42"));
+        let summary = "exam=NEET; document=photo & sign; software=summa2 1.2.3; creation_time=2024-03-09T07:05:42";
+
+        let mut jpeg = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8).write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(80)).unwrap();
+        let embedded = provenance.embed(&jpeg, "JPEG").unwrap();
+        assert_eq!(embedded.len(), jpeg.len() + provenance.overhead("JPEG").unwrap());
+        // SOI and the JFIF APP0 stay first
+        assert_eq!(embedded[..20], jpeg[..20]);
+        assert_eq!(embedded[20..24], [0xFF, 0xFE, 0, summary.len() as u8 + 2]);
+        assert_eq!(&embedded[24..24 + summary.len()], summary.as_bytes());
+        assert!(image::load_from_memory(&embedded).is_ok());
+
+        let png = crate::core::test_support::encode_png(&image::DynamicImage::new_rgb8(8, 8));
+        let embedded = provenance.embed(&png, "PNG").unwrap();
+        assert_eq!(embedded.len(), png.len() + provenance.overhead("PNG").unwrap());
+        let decoder = png::Decoder::new(embedded.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let texts: Vec<(&str, &str)> =
+            reader.info().uncompressed_latin1_text.iter().map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str())).collect();
+        assert_eq!(
+            texts,
+            [("Exam", "NEET"), ("Document", "photo & sign"), ("Software", "summa2 1.2.3"), ("Creation Time", "2024-03-09T07:05:42")]
+        );
+
+        assert_eq!(provenance.overhead("WEBP"), None);
+        assert_eq!(provenance.embed(&[], "WEBP").unwrap_err(), "WEBP files cannot carry it");
+    }
+
+    #[test]
+    fn xmp_escapes_the_fields_and_keeps_pdf_a_identification() {
+        let xmp = provenance(None).xmp(false);
+        assert!(xmp.contains("<xmp:CreatorTool>summa2 1.2.3</xmp:CreatorTool>"));
+        assert!(xmp.contains(">exam=NEET; document=photo &amp; sign; software=summa2 1.2.3</rdf:li>"));
+        assert!(!xmp.contains("MetadataDate") && !xmp.contains("pdfaid"));
+        assert!(provenance(None).xmp(true).contains("<pdfaid:part>1</pdfaid:part>\n<pdfaid:conformance>B</pdfaid:conformance>"));
+    }
+}
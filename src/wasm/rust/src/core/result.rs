@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+
+use super::background::BackgroundReport;
+use super::diagnostics::CodecInfo;
+use super::ink::DetectedInk;
+use super::scan::Quad;
+use super::spec::{AutoContrast, ColorType, DimensionsSpec, DocumentSpec, SourceProfile};
+use super::validation::parse_data_url;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ConversionResult {
+    pub success: bool,
+    pub files: Vec<ConvertedFile>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub failures: Vec<FailedFile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub skipped: Vec<SkippedFile>,
+    /// For a single-file conversion that failed its size limits, every encode tried
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub attempts: Vec<EncodeAttempt>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct FailedFile {
+    pub original_name: String,
+    pub input_hash: Option<String>,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// Every encode tried, when the file failed its size limits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub attempts: Vec<EncodeAttempt>,
+}
+
+/// One encode made while fitting the size limits, in the order they were tried.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct EncodeAttempt {
+    pub format: String,
+    /// None for lossless formats
+    pub quality: Option<u8>,
+    pub dimensions: [u32; 2],
+    pub size_kb: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct SkippedFile {
+    pub original_name: String,
+    pub input_hash: String,
+    pub reason: String,
+}
+
+/// One input of a batch as soon as it is done with, so hosts can show results before
+/// the whole batch finishes. Exactly one of `file`, `failure` and `skipped` is set,
+/// holding the entry the batch's `ConversionResult` will also carry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct FileCompletion {
+    /// Position of the input in the batch, from 0
+    pub file_index: u32,
+    pub file_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<ConvertedFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailedFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<SkippedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ConvertedFile {
+    pub original_name: String,
+    pub converted_name: String,
+    pub document_type: String,
+    pub format: String,
+    pub size_kb: u32,
+    /// Exact output size; `size_kb` is this rounded down
+    pub size_bytes: u32,
+    pub dimensions: Option<DimensionsSpec>,
+    /// Color type and bits per pixel the output's header declares; None for PDFs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_type: Option<ColorType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bit_depth: Option<u8>,
+    pub data_url: String,
+    pub applied_spec: DocumentSpec,
+    pub input_hash: String,
+    pub transformations: Vec<Transformation>,
+    /// How centrally the output sits inside the spec's limits, from 0 to 1; see `compliance_score`
+    pub compliance_score: f32,
+    /// False for a best-effort output (`return_best_effort`) that breaks the spec; the
+    /// limits it breaks are in `violations`
+    pub compliant: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub violations: Vec<String>,
+    pub codec_info: CodecInfo,
+    /// Allowed formats tried before `format`, in order, each given up on size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub format_fallbacks: Vec<FormatFallback>,
+    /// Things the user should know about an output that still meets the spec, such as a
+    /// PDF rasterized by `pdf_rasterize_dpi`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub debug: Vec<DebugIntermediate>,
+    /// Measured on the decoded source when the spec sets `color_mode`; see `ops::colorfulness`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colorfulness: Option<f32>,
+    /// Ink found on the source when the spec sets `ink_color`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ink: Option<DetectedInk>,
+    /// Background found around the subject when the spec sets `background`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<BackgroundReport>,
+    /// How the output's pixels map onto the source, for drawing the crop and padding over
+    /// it; None for PDFs and for images warped by `document_scan` or `deskew`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Geometry>,
+    /// Set on outputs of `tile`: where this tile sits in the resized image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile: Option<TilePosition>,
+    /// Handed back from the converter's result cache rather than converted again
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub cache_hit: bool,
+    /// Name of the file written into the directory set with `set_output_directory`; the
+    /// `data_url` is empty then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl ConvertedFile {
+    /// The converted bytes carried in `data_url`.
+    pub fn decode_data(&self) -> Option<Vec<u8>> {
+        parse_data_url(&self.data_url).ok().map(|(_, data)| data)
+    }
+}
+
+/// Where an output's pixels come from: the `crop` rectangle of the decoded source, turned
+/// clockwise by `rotation`, scaled by `scale` and surrounded by `pad`, in that order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct Geometry {
+    /// Decoded source size, as `[width, height]`
+    pub source: [u32; 2],
+    /// Kept part of the source in source pixels; fractional where a crop after resizing
+    /// falls between source pixels
+    pub crop: Region,
+    /// Quarter turns, in degrees clockwise
+    pub rotation: u16,
+    /// Output pixels per source pixel, across and down the output
+    pub scale: [f32; 2],
+    /// Margins around the picture, in output pixels
+    pub pad: Insets,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Geometry {
+    /// Replay the geometric `transformations` applied to a `source`-sized image. None when
+    /// one of them does not keep straight edges straight and upright (`Perspective`,
+    /// `Deskew`), or crops away the whole picture.
+    pub(crate) fn trace(source: (u32, u32), transformations: &[Transformation]) -> Option<Geometry> {
+        // Kept part of the source as [x, y, width, height], margins as [left, top, right,
+        // bottom] and the current output size
+        let mut crop = [0.0, 0.0, source.0 as f64, source.1 as f64];
+        let (mut rotation, mut scale, mut pad, mut size) = (0u16, [1.0f64, 1.0], [0.0f64; 4], [source.0 as f64, source.1 as f64]);
+        for transformation in transformations {
+            // Cutting frames off the sides keeps the rectangle between them
+            let remaining;
+            let transformation = match *transformation {
+                Transformation::RemoveEdges { left, top, right, bottom } => {
+                    let (width, height) = ((size[0] as u32).saturating_sub(left + right), (size[1] as u32).saturating_sub(top + bottom));
+                    remaining = Transformation::Trim { x: left, y: top, width, height };
+                    &remaining
+                }
+                _ => transformation,
+            };
+            match *transformation {
+                Transformation::Perspective { .. } | Transformation::Deskew { .. } => return None,
+                Transformation::Rotate { degrees } => {
+                    for _ in 0..degrees / 90 {
+                        // The left margin ends up on top, the bottom one on the left
+                        pad = [pad[3], pad[0], pad[1], pad[2]];
+                        scale.swap(0, 1);
+                        size.swap(0, 1);
+                        rotation = (rotation + 90) % 360;
+                    }
+                }
+                Transformation::Resize { from, to } => {
+                    let factor = [to[0] as f64 / from[0] as f64, to[1] as f64 / from[1] as f64];
+                    scale = [scale[0] * factor[0], scale[1] * factor[1]];
+                    pad = [pad[0] * factor[0], pad[1] * factor[1], pad[2] * factor[0], pad[3] * factor[1]];
+                    size = [to[0] as f64, to[1] as f64];
+                }
+                Transformation::Pad { left, top, right, bottom, .. } => {
+                    pad = [pad[0] + left as f64, pad[1] + top as f64, pad[2] + right as f64, pad[3] + bottom as f64];
+                    size = [size[0] + (left + right) as f64, size[1] + (top + bottom) as f64];
+                }
+                Transformation::Border { width, .. } => {
+                    let width = width as f64;
+                    pad = pad.map(|margin| margin + width);
+                    size = [size[0] + 2.0 * width, size[1] + 2.0 * width];
+                }
+                Transformation::Trim { x, y, width, height } | Transformation::Crop { x, y, width, height } => {
+                    let (x, y, width, height) = (x as f64, y as f64, width as f64, height as f64);
+                    // The cut through the picture, leaving whatever margin it keeps
+                    let left = x.max(pad[0]);
+                    let top = y.max(pad[1]);
+                    let right = (x + width).min(size[0] - pad[2]);
+                    let bottom = (y + height).min(size[1] - pad[3]);
+                    if right <= left || bottom <= top {
+                        return None;
+                    }
+                    // The cut in the turned, scaled crop's own pixels, turned back onto the source
+                    let [u0, u1] = [(left - pad[0]) / scale[0], (right - pad[0]) / scale[0]];
+                    let [v0, v1] = [(top - pad[1]) / scale[1], (bottom - pad[1]) / scale[1]];
+                    let [width_before, height_before] = [crop[2], crop[3]];
+                    let ([x0, x1], [y0, y1]) = match rotation {
+                        90 => ([v0, v1], [height_before - u1, height_before - u0]),
+                        180 => ([width_before - u1, width_before - u0], [height_before - v1, height_before - v0]),
+                        270 => ([width_before - v1, width_before - v0], [u0, u1]),
+                        _ => ([u0, u1], [v0, v1]),
+                    };
+                    crop = [crop[0] + x0, crop[1] + y0, x1 - x0, y1 - y0];
+                    pad = [left - x, top - y, x + width - right, y + height - bottom];
+                    size = [width, height];
+                }
+                _ => {}
+            }
+        }
+        Some(Geometry {
+            source: [source.0, source.1],
+            crop: Region { x: crop[0] as f32, y: crop[1] as f32, width: crop[2] as f32, height: crop[3] as f32 },
+            rotation,
+            scale: [scale[0] as f32, scale[1] as f32],
+            pad: Insets { left: pad[0].round() as u32, top: pad[1].round() as u32, right: pad[2].round() as u32, bottom: pad[3].round() as u32 },
+        })
+    }
+}
+
+/// An output format `convert` gave up on before moving to the next allowed one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct FormatFallback {
+    pub format: String,
+    pub reason: String,
+}
+
+/// The two outputs of `convert_with_preview`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct PreviewPair {
+    // JPEG on an opaque background, for showing where transparency cannot be
+    pub preview: ConvertedFile,
+    // PNG with the source's transparency, for submitting
+    pub original: ConvertedFile,
+}
+
+/// The two outputs of `convert_with_thumbnail`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ThumbnailPair {
+    pub converted: ConvertedFile,
+    pub thumbnail: Thumbnail,
+}
+
+/// A small copy of a converted image for showing beside the upload, in the same format.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct Thumbnail {
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Grid position of a tile; `x`/`y` are its top-left pixel in the resized image.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct TilePosition {
+    pub row: u32,
+    pub column: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Transformation {
+    ColorConvert { from: SourceProfile },
+    // `corners` are the detected page corners in the source, clockwise from top-left
+    Perspective { corners: Quad, width: u32, height: u32 },
+    // The page turned back by its clockwise tilt `degrees` and cropped to `width` x `height`
+    Deskew { degrees: f32, width: u32, height: u32 },
+    // Tone curve corrected for `gamma`
+    Gamma { gamma: f32 },
+    // Frames cut off each side of a scan for `scanner_edges`, in pixels
+    RemoveEdges { left: u32, top: u32, right: u32, bottom: u32 },
+    Trim { x: u32, y: u32, width: u32, height: u32 },
+    // Contrast restored for `auto_contrast`
+    AutoContrast { mode: AutoContrast },
+    // Centered crop, to a square for `pixels.square` or to the target for `fit_mode: "Cover"`
+    Crop { x: u32, y: u32, width: u32, height: u32 },
+    // Margins in pixels added around the resized image for `fit_mode: "Pad"`
+    Pad { left: u32, top: u32, right: u32, bottom: u32, color: String },
+    // A `width` pixel frame of `color` drawn around the fitted image for `border`
+    Border { width: u32, color: String },
+    Resize { from: [u32; 2], to: [u32; 2] },
+    Posterize { levels: u8 },
+    // Isolated specks painted over for `despeckle`
+    Despeckle { specks: u32 },
+    // The plain background around the subject was repainted in `color`
+    ReplaceBackground { color: String },
+    // Transparent pixels composited onto `color`, for the JPEG of `convert_with_preview`
+    Flatten { color: String },
+    // Clockwise, applied after the other pixel stages to meet `print_orientation`
+    Rotate { degrees: u16 },
+    // The date/time drawn by `timestamp_overlay`, on the upright finished image
+    Timestamp { text: String },
+    // A PDF rebuilt from its page scans downscaled to `dpi`, for `pdf_rasterize_dpi`
+    Rasterize { pages: u32, dpi: u32 },
+    // The clockwise `/Rotate` of scanned PDF page `page` (from 1) drawn into its content
+    PageRotate { page: u32, degrees: u16 },
+    // Blank scanned PDF pages dropped for `remove_blank_pages`, numbered as in the input
+    RemoveBlankPages { pages: Vec<u32> },
+    // Scanned PDF pages dropped for `remove_duplicate_pages` as rescans of an earlier one
+    RemoveDuplicatePages { pages: Vec<u32> },
+    // Scanned PDF pages reduced to a palette of at most `colors` colors for `max_pdf_colors`
+    ReduceColors { pages: u32, colors: u16 },
+    // A PDF rebuilt as PDF/A-1b around its unchanged page scans, for `pdf_a`
+    PdfA { pages: u32 },
+    // The paper behind a signature made transparent for `transparent_background`
+    TransparentBackground,
+    // Color dropped to meet `color_mode: "grayscale"`
+    Grayscale,
+    // `bytes` is the encoded size; in a conversion plan it is the size budget instead
+    Encode { format: String, quality: Option<u8>, bytes: usize },
+    // Provenance written into the output for `embed_metadata`, `bytes` long
+    EmbedMetadata { bytes: usize },
+    // An animated WebP input returned unchanged with all its `frames`, as WebP is allowed
+    KeepAnimation { frames: u32 },
+}
+
+// What convert_file would do with an input, derived from its header alone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ConversionPlan {
+    pub original_name: String,
+    pub converted_name: String,
+    pub source_format: String,
+    pub source_dimensions: Option<[u32; 2]>,
+    // EXIF orientation (1..=8) of the source; reported only, the pixels are not rotated
+    pub source_orientation: Option<u8>,
+    pub target_format: String,
+    pub target_dimensions: Option<[u32; 2]>,
+    pub max_size_kb: u32,
+    pub transformations: Vec<Transformation>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DebugIntermediate {
+    pub stage: String,
+    pub width: u32,
+    pub height: u32,
+    pub transformation: Option<Transformation>,
+    pub data_url: String,
+}
@@ -0,0 +1,523 @@
+use image::GenericImageView;
+use std::collections::VecDeque;
+
+use super::ops::{paper_level, INK_CONTRAST};
+use super::result::Insets;
+use super::spec::DocumentScanSpec;
+
+const DETECTION_MAX_EDGE: u32 = 512;
+// A detected quad covering more than this much of the frame is the frame itself
+const MAX_DOCUMENT_AREA: f32 = 0.95;
+// Tilt is measured at this size: a tenth of a degree still moves the far end of a
+// line by most of a pixel
+const SKEW_MAX_EDGE: u32 = 640;
+// Ink shares outside this range are not writing on paper
+const MIN_SKEW_INK: f32 = 0.001;
+const MAX_SKEW_INK: f32 = 0.3;
+// How much more the best angle must pile the ink up than level before it is believed
+const MIN_SKEW_GAIN: f64 = 1.05;
+// A side is framed when this much of its outermost line is off the page's color, and
+// this much of that is one color
+const MIN_FRAME_SHARE: f32 = 0.02;
+const MIN_FRAME_UNIFORMITY: f32 = 0.8;
+
+/// Corners of a detected document in source pixels: top-left, top-right, bottom-right,
+/// bottom-left.
+pub type Quad = [[u32; 2]; 4];
+
+// Finds a bright document lying on a darker background (a desk, a table cloth). The
+// luminance histogram is split with Otsu's method, the largest bright region is taken
+// as the page and its corners are the extreme points along both diagonals, which holds
+// for any reasonably framed convex quadrilateral.
+pub fn detect_document(img: &image::DynamicImage, spec: &DocumentScanSpec) -> Option<Quad> {
+    let (width, height) = img.dimensions();
+    let sample = if width > DETECTION_MAX_EDGE || height > DETECTION_MAX_EDGE {
+        img.resize(DETECTION_MAX_EDGE, DETECTION_MAX_EDGE, image::imageops::FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let luma = sample.to_luma8();
+    let (sample_width, sample_height) = luma.dimensions();
+    if sample_width < 8 || sample_height < 8 {
+        return None;
+    }
+
+    let threshold = otsu_threshold(luma.as_raw())?;
+    let mask: Vec<bool> = luma.as_raw().iter().map(|&v| v > threshold).collect();
+    let region = largest_region(&mask, sample_width as usize, sample_height as usize);
+
+    let mut corners = [(0usize, 0usize); 4];
+    let mut best = [i64::MIN; 4];
+    for &index in &region {
+        let (x, y) = ((index % sample_width as usize) as i64, (index / sample_width as usize) as i64);
+        // top-left, top-right, bottom-right, bottom-left
+        let scores = [-(x + y), x - y, x + y, y - x];
+        for corner in 0..4 {
+            if scores[corner] > best[corner] {
+                best[corner] = scores[corner];
+                corners[corner] = (x as usize, y as usize);
+            }
+        }
+    }
+
+    let frame_area = (sample_width * sample_height) as f32;
+    let area = quad_area(&corners);
+    if area < frame_area * spec.min_area || area > frame_area * MAX_DOCUMENT_AREA || !is_convex(&corners) {
+        return None;
+    }
+
+    let scale_x = width as f32 / sample_width as f32;
+    let scale_y = height as f32 / sample_height as f32;
+    Some(corners.map(|(x, y)| {
+        [
+            ((x as f32 + 0.5) * scale_x).min(width as f32 - 1.0) as u32,
+            ((y as f32 + 0.5) * scale_y).min(height as f32 - 1.0) as u32,
+        ]
+    }))
+}
+
+/// Flatten the quadrilateral `corners` into an upright rectangle as wide as its longer
+/// horizontal edge and as tall as its longer vertical edge.
+pub fn warp_perspective(img: &image::DynamicImage, corners: &Quad) -> image::DynamicImage {
+    let point = |i: usize| (corners[i][0] as f64, corners[i][1] as f64);
+    let distance = |a: usize, b: usize| {
+        let ((ax, ay), (bx, by)) = (point(a), point(b));
+        ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt()
+    };
+    let width = distance(0, 1).max(distance(3, 2)).round().max(1.0) as u32;
+    let height = distance(0, 3).max(distance(1, 2)).round().max(1.0) as u32;
+
+    let target = [
+        (0.0, 0.0),
+        ((width - 1) as f64, 0.0),
+        ((width - 1) as f64, (height - 1) as f64),
+        (0.0, (height - 1) as f64),
+    ];
+    let source = [point(0), point(1), point(2), point(3)];
+    let homography = match solve_homography(&target, &source) {
+        Some(h) => h,
+        None => return img.clone(),
+    };
+
+    let rgba = img.to_rgba8();
+    let warped = image::RgbaImage::from_fn(width, height, |u, v| {
+        let (u, v) = (u as f64, v as f64);
+        let w = homography[6] * u + homography[7] * v + 1.0;
+        let x = (homography[0] * u + homography[1] * v + homography[2]) / w;
+        let y = (homography[3] * u + homography[4] * v + homography[5]) / w;
+        sample_bilinear(&rgba, x, y)
+    });
+    if img.color().has_alpha() {
+        image::DynamicImage::ImageRgba8(warped)
+    } else {
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(warped).to_rgb8())
+    }
+}
+
+/// The clockwise tilt of the lines of writing in `img`, in degrees within ±45, or None when
+/// it does not look like dark writing on light paper. Ink is projected onto the vertical
+/// at each candidate angle: level lines pile up into a few tall bins, so the angle whose
+/// profile has the most energy is the one the lines run at.
+pub fn detect_skew(img: &image::DynamicImage) -> Option<f32> {
+    let luma = img.thumbnail(SKEW_MAX_EDGE, SKEW_MAX_EDGE).to_luma8();
+    let threshold = paper_level(&luma).checked_sub(INK_CONTRAST)?;
+    let ink: Vec<(f32, f32)> = luma
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0[0] < threshold)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    let share = ink.len() as f32 / luma.len().max(1) as f32;
+    if !(MIN_SKEW_INK..=MAX_SKEW_INK).contains(&share) {
+        return None;
+    }
+
+    // Lines at ±45° through the whole sample still land inside the bins
+    let (width, height) = (luma.width() as f32, luma.height() as f32);
+    let mut bins = vec![0u32; (height + 2.0 * width) as usize + 2];
+    let mut energy = |degrees: f32| {
+        bins.iter_mut().for_each(|bin| *bin = 0);
+        let slope = degrees.to_radians().tan();
+        for &(x, y) in &ink {
+            bins[(y - x * slope + width).round() as usize] += 1;
+        }
+        bins.iter().map(|&count| (count as u64).pow(2)).sum::<u64>()
+    };
+    // Half-degree steps, then twentieths around the best of them
+    let coarse = (-90..=90).map(|step| step * 10).max_by_key(|&step| energy(step as f32 / 20.0))?;
+    let fine = (coarse - 10..=coarse + 10).map(|step: i32| step.clamp(-900, 900)).max_by_key(|&step| energy(step as f32 / 20.0))? as f32 / 20.0;
+    // Writing without lines to speak of (a lone word, a blot) is as flat at any angle
+    if (energy(fine) as f64) < energy(0.0) as f64 * MIN_SKEW_GAIN {
+        return Some(0.0);
+    }
+    Some(fine)
+}
+
+/// Turn `img` back by its clockwise tilt `degrees` and crop the wedges the turn leaves at
+/// the corners, keeping the largest upright rectangle inside the turned image.
+pub fn deskew(img: &image::DynamicImage, degrees: f32) -> image::DynamicImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as f64, height as f64);
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let (long, short) = if w >= h { (w, h) } else { (h, w) };
+    let (crop_width, crop_height) = if short <= 2.0 * sin * cos * long || (sin - cos).abs() < 1e-10 {
+        // Only a triangle's worth fits, touching the long edges
+        let half = short / 2.0;
+        if w >= h { (half / sin, half / cos) } else { (half / cos, half / sin) }
+    } else {
+        let cos_2a = cos * cos - sin * sin;
+        ((w * cos - h * sin) / cos_2a, (h * cos - w * sin) / cos_2a)
+    };
+    let (crop_width, crop_height) = (crop_width.floor().max(1.0) as u32, crop_height.floor().max(1.0) as u32);
+
+    // Each output pixel is read from the source turned clockwise about the centre
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let (source_x, source_y) = ((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+    let (target_x, target_y) = ((crop_width - 1) as f64 / 2.0, (crop_height - 1) as f64 / 2.0);
+    let rgba = img.to_rgba8();
+    let straightened = image::RgbaImage::from_fn(crop_width, crop_height, |u, v| {
+        let (u, v) = (u as f64 - target_x, v as f64 - target_y);
+        sample_bilinear(&rgba, source_x + u * cos - v * sin, source_y + u * sin + v * cos)
+    });
+    if img.color().has_alpha() {
+        image::DynamicImage::ImageRgba8(straightened)
+    } else {
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(straightened).to_rgb8())
+    }
+}
+
+/// How far frames reach in from each side of `img` (see `ScannerEdgesSpec`). The page is
+/// the median color of the middle of the image; a side is framed when its outermost line
+/// is partly off that by more than `tolerance` and mostly one color, and each line across
+/// the side is followed inward while it keeps that color. A line that keeps it all the way
+/// to the middle runs down a frame along the neighbouring side instead, unless most of
+/// them do, when the frame reaches the middle.
+pub fn detect_scanner_edges(img: &image::DynamicImage, tolerance: u8) -> Insets {
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let near = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= tolerance);
+    let middle = image::imageops::crop_imm(&rgb, width / 4, height / 4, (width / 2).max(1), (height / 2).max(1)).to_image();
+    let paper = median_color(middle.pixels().map(|pixel| pixel.0));
+
+    let mut insets = [0; 4];
+    for (side, inset) in insets.iter_mut().enumerate() {
+        let (along, across) = if side % 2 == 0 { (height, width) } else { (width, height) };
+        // `depth` pixels in from the side, at `offset` along it
+        let pixel = |offset: u32, depth: u32| {
+            let (x, y) = match side {
+                0 => (depth, offset),
+                1 => (offset, depth),
+                2 => (width - 1 - depth, offset),
+                _ => (offset, height - 1 - depth),
+            };
+            rgb.get_pixel(x, y).0
+        };
+        let outer: Vec<[u8; 3]> = (0..along).map(|offset| pixel(offset, 0)).filter(|&color| !near(color, paper)).collect();
+        if (outer.len() as f32) < MIN_FRAME_SHARE * along as f32 {
+            continue;
+        }
+        let frame = median_color(outer.iter().copied());
+        if (outer.iter().filter(|&&color| near(color, frame)).count() as f32) < MIN_FRAME_UNIFORMITY * outer.len() as f32 {
+            continue;
+        }
+        let middle = across / 2;
+        let mut depths: Vec<u32> = (0..along)
+            .map(|offset| {
+                (0..middle)
+                    .take_while(|&depth| {
+                        let color = pixel(offset, depth);
+                        near(color, frame) && !near(color, paper)
+                    })
+                    .count() as u32
+            })
+            .collect();
+        if depths.iter().filter(|&&depth| depth == middle).count() * 2 > depths.len() {
+            *inset = middle;
+            continue;
+        }
+        depths.iter_mut().filter(|depth| **depth == middle).for_each(|depth| *depth = 0);
+        // The deepest reach held over a few neighbouring lines, so a stroke running off
+        // the page into the frame does not carry the cut in with it
+        let window = (along / 200) as usize + 1;
+        *inset = (0..depths.len())
+            .map(|i| depths[i.saturating_sub(window)..(i + window + 1).min(depths.len())].iter().copied().min().unwrap_or(0))
+            .max()
+            .unwrap_or(0);
+    }
+    let [left, top, right, bottom] = insets;
+    Insets { left, top, right, bottom }
+}
+
+// Per-channel median
+fn median_color(colors: impl Iterator<Item = [u8; 3]>) -> [u8; 3] {
+    let mut histograms = [[0u64; 256]; 3];
+    let mut count = 0u64;
+    for color in colors {
+        for (histogram, value) in histograms.iter_mut().zip(color) {
+            histogram[value as usize] += 1;
+        }
+        count += 1;
+    }
+    histograms.map(|histogram| {
+        let mut seen = 0;
+        histogram.iter().position(|&n| {
+            seen += n;
+            seen * 2 > count
+        }).unwrap_or(0) as u8
+    })
+}
+
+fn otsu_threshold(values: &[u8]) -> Option<u8> {
+    let mut histogram = [0u64; 256];
+    values.iter().for_each(|&v| histogram[v as usize] += 1);
+    let total = values.len() as f64;
+    let sum: f64 = histogram.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum();
+
+    let (mut weight_below, mut sum_below) = (0f64, 0f64);
+    let mut best: Option<(f64, u8)> = None;
+    for (value, &count) in histogram.iter().enumerate() {
+        weight_below += count as f64;
+        sum_below += value as f64 * count as f64;
+        let weight_above = total - weight_below;
+        if weight_below == 0.0 || weight_above == 0.0 {
+            continue;
+        }
+        let mean_below = sum_below / weight_below;
+        let mean_above = (sum - sum_below) / weight_above;
+        let variance = weight_below * weight_above * (mean_below - mean_above).powi(2);
+        if best.is_none_or(|(v, _)| variance > v) {
+            best = Some((variance, value as u8));
+        }
+    }
+    best.map(|(_, threshold)| threshold)
+}
+
+// Indices of the largest 4-connected set of `true` cells
+fn largest_region(mask: &[bool], width: usize, height: usize) -> Vec<usize> {
+    let mut seen = vec![false; mask.len()];
+    let mut largest = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || seen[start] {
+            continue;
+        }
+        let mut region = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        seen[start] = true;
+        while let Some(index) = queue.pop_front() {
+            region.push(index);
+            let (x, y) = (index % width, index / width);
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < width).then(|| index + 1),
+                (y > 0).then(|| index - width),
+                (y + 1 < height).then(|| index + width),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if mask[next] && !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+    largest
+}
+
+fn quad_area(corners: &[(usize, usize); 4]) -> f32 {
+    let twice: i64 = (0..4)
+        .map(|i| {
+            let (x1, y1) = corners[i];
+            let (x2, y2) = corners[(i + 1) % 4];
+            x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64
+        })
+        .sum();
+    twice.abs() as f32 / 2.0
+}
+
+fn is_convex(corners: &[(usize, usize); 4]) -> bool {
+    let crosses: Vec<i64> = (0..4)
+        .map(|i| {
+            let (ax, ay) = corners[i];
+            let (bx, by) = corners[(i + 1) % 4];
+            let (cx, cy) = corners[(i + 2) % 4];
+            (bx as i64 - ax as i64) * (cy as i64 - by as i64) - (by as i64 - ay as i64) * (cx as i64 - bx as i64)
+        })
+        .collect();
+    crosses.iter().all(|&c| c > 0) || crosses.iter().all(|&c| c < 0)
+}
+
+// Coefficients h0..h7 of the projective map taking each `from` point to its `to` point
+fn solve_homography(from: &[(f64, f64); 4], to: &[(f64, f64); 4]) -> Option<[f64; 8]> {
+    let mut rows = [[0f64; 9]; 8];
+    for i in 0..4 {
+        let ((u, v), (x, y)) = (from[i], to[i]);
+        rows[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+        rows[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+    }
+
+    // Gaussian elimination with partial pivoting
+    for column in 0..8 {
+        let pivot = (column..8).max_by(|&a, &b| rows[a][column].abs().total_cmp(&rows[b][column].abs()))?;
+        if rows[pivot][column].abs() < 1e-9 {
+            return None;
+        }
+        rows.swap(column, pivot);
+        let pivot_row = rows[column];
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index != column {
+                let factor = row[column] / pivot_row[column];
+                for (value, pivot_value) in row.iter_mut().zip(pivot_row.iter()).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    let mut solution = [0f64; 8];
+    for (i, value) in solution.iter_mut().enumerate() {
+        *value = rows[i][8] / rows[i][i];
+    }
+    Some(solution)
+}
+
+fn sample_bilinear(img: &image::RgbaImage, x: f64, y: f64) -> image::Rgba<u8> {
+    let (width, height) = img.dimensions();
+    let x = x.clamp(0.0, (width - 1) as f64);
+    let y = y.clamp(0.0, (height - 1) as f64);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+    let mut out = [0u8; 4];
+    for (channel, value) in out.iter_mut().enumerate() {
+        let at = |px: u32, py: u32| img.get_pixel(px, py).0[channel] as f64;
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    image::Rgba(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support::{scanned_paper, tilted_paper};
+
+    const CORNERS: Quad = [[60, 40], [330, 60], [350, 260], [40, 240]];
+
+    fn inside(quad: &Quad, x: f32, y: f32) -> bool {
+        (0..4).all(|i| {
+            let [ax, ay] = quad[i];
+            let [bx, by] = quad[(i + 1) % 4];
+            (bx as f32 - ax as f32) * (y - ay as f32) - (by as f32 - ay as f32) * (x - ax as f32) >= 0.0
+        })
+    }
+
+    fn photo_of_document() -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(400, 300, |x, y| {
+            if inside(&CORNERS, x as f32, y as f32) {
+                image::Rgb([240, 238, 228])
+            } else {
+                image::Rgb([50, 40, 35])
+            }
+        }))
+    }
+
+    fn spec() -> DocumentScanSpec {
+        serde_json::from_value(serde_json::json!({})).unwrap()
+    }
+
+    #[test]
+    fn detects_document_corners() {
+        let corners = detect_document(&photo_of_document(), &spec()).unwrap();
+        for (found, expected) in corners.iter().zip(CORNERS.iter()) {
+            assert!(
+                found[0].abs_diff(expected[0]) <= 3 && found[1].abs_diff(expected[1]) <= 3,
+                "{:?} vs {:?}",
+                corners,
+                CORNERS
+            );
+        }
+    }
+
+    #[test]
+    fn warp_flattens_document_to_its_bounds() {
+        let img = photo_of_document();
+        let corners = detect_document(&img, &spec()).unwrap();
+        let flat = warp_perspective(&img, &corners).to_luma8();
+        let (width, height) = flat.dimensions();
+        assert!((305..=315).contains(&width) && (198..=206).contains(&height), "{}x{}", width, height);
+
+        // Only the page survives, apart from anti-aliased edge pixels
+        let dark = flat.pixels().filter(|p| p.0[0] < 128).count();
+        assert!(dark * 100 < flat.len(), "{} dark pixels", dark);
+    }
+
+    #[test]
+    fn ignores_images_without_a_document() {
+        let uniform = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(200, 200, image::Rgb([120, 120, 120])));
+        assert_eq!(detect_document(&uniform, &spec()), None);
+
+        // A page filling the whole frame is already flat enough
+        let page = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(200, 200, |x, y| {
+            image::Rgb(if x == 0 || y == 0 { [40, 40, 40] } else { [240, 240, 240] })
+        }));
+        assert_eq!(detect_document(&page, &spec()), None);
+    }
+
+    #[test]
+    fn measures_the_tilt_of_lines_of_writing() {
+        for degrees in [3.0, -4.5, 8.0] {
+            let tilted = image::DynamicImage::ImageRgb8(tilted_paper(600, 800, 8, degrees));
+            let skew = detect_skew(&tilted).unwrap();
+            assert!((skew - degrees).abs() <= 0.2, "{} measured as {}", degrees, skew);
+        }
+        let level = image::DynamicImage::ImageRgb8(scanned_paper(600, 800, 8));
+        assert!(detect_skew(&level).unwrap().abs() <= 0.1);
+        let blank = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(300, 400, image::Rgb([240, 240, 240])));
+        assert_eq!(detect_skew(&blank), None);
+    }
+
+    #[test]
+    fn deskew_levels_the_lines_and_crops_the_corners() {
+        let tilted = image::DynamicImage::ImageRgb8(tilted_paper(600, 800, 8, 4.0));
+        let straightened = deskew(&tilted, 4.0);
+        let (width, height) = straightened.dimensions();
+        // The largest upright rectangle inside a 600x800 frame turned by 4°
+        assert!((546..=549).contains(&width) && (761..=764).contains(&height), "{}x{}", width, height);
+        assert!(detect_skew(&straightened).unwrap().abs() <= 0.1);
+        assert_eq!(deskew(&tilted, 0.0).dimensions(), (600, 800));
+    }
+
+    #[test]
+    fn measures_scanner_bed_and_skew_wedges_but_not_writing() {
+        let page = scanned_paper(600, 800, 8);
+        // The dark bed beside a page 40 pixels narrower than the glass, and a wedge 60 wide
+        // and 30 deep where the top right corner lay askew
+        let scan = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(600, 800, |x, y| {
+            if x < 40 || x >= 540 + 2 * y { image::Rgb([28, 30, 34]) } else { *page.get_pixel(x, y) }
+        }));
+        let Insets { left, top, right, bottom } = detect_scanner_edges(&scan, 40);
+        assert_eq!(left, 40);
+        assert!((25..=30).contains(&top), "{}", top);
+        assert!((45..=60).contains(&right), "{}", right);
+        assert_eq!(bottom, 0);
+
+        // Lines of writing and plain paper are not frames
+        assert_eq!(detect_scanner_edges(&image::DynamicImage::ImageRgb8(page), 40), Insets { left: 0, top: 0, right: 0, bottom: 0 });
+    }
+
+    #[test]
+    fn homography_maps_corners_exactly() {
+        let from = [(0.0, 0.0), (99.0, 0.0), (99.0, 49.0), (0.0, 49.0)];
+        let to = [(10.0, 5.0), (120.0, 20.0), (110.0, 90.0), (0.0, 70.0)];
+        let h = solve_homography(&from, &to).unwrap();
+        for ((u, v), (x, y)) in from.iter().zip(to.iter()) {
+            let w = h[6] * u + h[7] * v + 1.0;
+            assert!(((h[0] * u + h[1] * v + h[2]) / w - x).abs() < 1e-6);
+            assert!(((h[3] * u + h[4] * v + h[5]) / w - y).abs() < 1e-6);
+        }
+    }
+}
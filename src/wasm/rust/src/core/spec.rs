@@ -0,0 +1,2311 @@
+use serde::{Deserialize, Serialize};
+
+use super::timestamp::{format_time, LocalTime};
+use super::ConvertError;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DocumentSpec {
+    // Allowed outputs for every input type; image_formats and document_formats, when
+    // set, take over for their inputs
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub format: Vec<String>,
+    // Outputs for image inputs in order of preference
+    pub image_formats: Option<Vec<String>>,
+    // Outputs for PDF inputs; only PDF pass-through is supported
+    pub document_formats: Option<Vec<String>>,
+    // What image inputs get when no image format is allowed
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub on_missing_format: MissingFormatPolicy,
+    // Limits, or a single size string like "50KB" for the maximum
+    #[serde(deserialize_with = "size_spec_or_text")]
+    #[cfg_attr(feature = "typescript", ts(type = "SizeSpec | string"))]
+    pub size_kb: SizeSpec,
+    pub dimensions_cm: Option<PhysicalDimensionsSpec>,
+    pub dimensions_mm: Option<PhysicalDimensionsSpec>,
+    pub pixels: Option<PixelSpec>,
+    pub aspect_ratio: Option<AspectRatioSpec>,
+    pub resolution_px_per_inch: Option<u32>,
+    pub posterize_levels: Option<u8>,
+    pub auto_trim: Option<AutoTrimSpec>,
+    pub max_megapixels: Option<f32>,
+    pub document_scan: Option<DocumentScanSpec>,
+    // Turns signatures and pages photographed sideways or upside down upright by their
+    // writing, before sizing; for signature and document types, not photos
+    pub auto_rotate: Option<AutoRotateSpec>,
+    // Straightens pages and signatures photographed a few degrees off level, cropping the
+    // corners the turn leaves, before trimming and sizing; for signature and document types
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub deskew: bool,
+    // Tilts beyond this many degrees (10 when unset) are left alone with a warning, as
+    // more likely misread than that crooked
+    pub deskew_max_angle: Option<f32>,
+    // Cuts scanner bed, the dark wedges of a skewed scan and old frames off the edges of
+    // a scan, after straightening and before trimming and sizing
+    pub scanner_edges: Option<ScannerEdgesSpec>,
+    // Paints over dust and pepper noise on signatures and scanned pages once the image is
+    // sized, so it neither shows nor costs bytes; for signature and document types
+    pub despeckle: Option<DespeckleSpec>,
+    // Lifts (above 1) or deepens (below 1) the mid-tones of scanners whose output is
+    // consistently too dark or too light, before any other tonal step; 0.3 to 3, and 1
+    // leaves the image alone
+    pub gamma: Option<f32>,
+    // Restores the contrast of washed-out copies before sizing; see `AutoContrast`
+    pub auto_contrast: Option<AutoContrast>,
+    // Tile size and clip limit for `auto_contrast: "clahe"`
+    pub clahe: Option<ClaheSpec>,
+    // Unit of the JFIF density field in JPEG output, for portals that check it
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub density_unit: DensityUnit,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub dct_method: DctMethod,
+    // JPEG qualities the size loop may use, tried from highest to lowest, so repeated
+    // conversions land on the same few settings instead of anywhere in between
+    pub quality_ladder: Option<Vec<u8>>,
+    // Whose 0..100 scale JPEG qualities are given in, here and in reported encodes; each
+    // is mapped to the bundled encoder's own scale only when encoding
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub quality_scale: QualityScale,
+    // Shrink PNG output losslessly: indexed color when the image has at most 256 colors,
+    // no alpha channel when it is opaque, and maximum zlib compression. Unset follows
+    // `performance_profile`.
+    pub png_optimize: Option<bool>,
+    // Fixed color type, bit depth and row filter for PNG output, for verifiers that check
+    // the byte layout; takes the place of the encoder's own choices
+    pub png_profile: Option<PngProfile>,
+    // Writes PNG output Adam7-interlaced, for viewers that draw it progressively. The
+    // passes compress worse than whole rows, so the file is larger and size_kb is
+    // checked against that.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub png_interlaced: bool,
+    // Channels and bit depth every image output must have, for backends that read
+    // nothing else: "rgb8" (JPEG, or PNG without alpha) or "rgba8" (PNG only). Indexed
+    // and grayscale PNGs are never written, and the encoded header is checked as well.
+    pub output_color_type: Option<OutputColorType>,
+    // MIME type the data URL (and so the history Blob) is labelled with, for portals that
+    // expect a non-canonical one such as "image/jpg". Only the label changes; the bytes
+    // are encoded for the format as usual.
+    pub output_mime_override: Option<String>,
+    // Color type and bits per pixel the encoded output must declare, as specs word them:
+    // "24-bit color" is rgb at 24, "8-bit grayscale" is grayscale at 8. Output is written
+    // that way (JPEG as grayscale or RGB, PNG without alpha unless rgba is asked for); a
+    // bare bit depth of 8 means grayscale. Checked against the encoded header.
+    pub required_bit_depth: Option<u8>,
+    pub required_color_type: Option<ColorType>,
+    // Makes the paper behind a signature transparent in PNG output, fading the ink's
+    // anti-aliased edge rather than cutting it at a threshold, so it can be stamped over
+    // documents. Picks PNG when it is allowed; other document types are left opaque.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub transparent_background: bool,
+    // Whether the source must be in color or grayscale, judged on the decoded pixels
+    pub color_mode: Option<ColorMode>,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub on_color_mismatch: ColorMismatchPolicy,
+    // Orientation the output must have for the print form, enforced after resizing
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub print_orientation: PrintOrientation,
+    // How the image reaches target dimensions of a different aspect ratio
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub fit_mode: FitMode,
+    // Resize by whole factors with nearest-neighbour sampling so QR codes and barcodes
+    // stay scannable, padding the rest of the target like fit_mode Pad
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub integer_scaling: bool,
+    // Resample in linear light rather than on sRGB values, which keeps fine bright
+    // detail (hairlines, text on white, checked fabric) from darkening when downscaled.
+    // Slower, so only the `Best` profile turns it on when unset.
+    pub linear_light_resize: Option<bool>,
+    // Downscales by more than this factor (3 when unset, or as `performance_profile`
+    // sets it) halve the image with a box filter until the final resample has 2 to 4
+    // times to go, which is much faster on camera-sized inputs and aliases less. 0
+    // resamples in one pass whatever the factor.
+    pub pre_reduce_above: Option<f32>,
+    // Speed against quality and size for the settings above the spec leaves unset; see
+    // `PerformanceProfile`
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub performance_profile: PerformanceProfile,
+    // Rescale the image when no quality lands inside size_kb: smaller when even the
+    // lowest quality is over the maximum, larger when full quality is under the minimum.
+    // For specs whose pixel size is not fixed.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub flexible_dimensions: bool,
+    // Ink colors a signature may be written in; set on signature presets
+    pub ink_color: Option<InkColorSpec>,
+    // Plain backdrop a photo must have, optionally repainted when it is off
+    pub background: Option<BackgroundSpec>,
+    // Last resort for a PDF over size_kb: rebuild it from its page scans downscaled to
+    // this resolution, lowering JPEG quality until it fits. Its text stops being
+    // selectable, and only PDFs whose pages are each one JPEG scan can be rebuilt.
+    // Builds without the `pdf-raster` feature fail these PDFs as codec_unavailable.
+    pub pdf_rasterize_dpi: Option<u32>,
+    // Rebuild PDFs as PDF/A-1b for portals that archive uploads: the page scans are
+    // copied unchanged into a new file with an sRGB output intent and PDF/A metadata.
+    // PDFs with text or drawings fail, since their fonts cannot be embedded here.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub pdf_a: bool,
+    // Scanned PDF pages with at most this share of ink pixels count as blank (0.002 when
+    // unset); see `ops::ink_coverage`. Blank pages are reported as warnings, or dropped
+    // with `remove_blank_pages`
+    pub blank_page_max_ink: Option<f32>,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub remove_blank_pages: bool,
+    // Scanned PDF pages whose `ops::page_hash` agrees with an earlier page's on at least
+    // this share of bits (0.9 when unset) are taken for rescans of it. They are reported
+    // as warnings, or dropped with `remove_duplicate_pages`
+    pub duplicate_page_min_similarity: Option<f32>,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub remove_duplicate_pages: bool,
+    // Print-shop limit on a scanned PDF's colors: each page scan is reduced to a palette
+    // of at most this many colors (2 to 256) and stored losslessly, so JPEG cannot bring
+    // colors back. Flat scans also shrink; photos can grow.
+    pub max_pdf_colors: Option<u16>,
+    // Fixed template the image is placed on as the last pixel stage; the pixel limits
+    // apply to the canvas rather than to the image on it
+    pub canvas: Option<CanvasSpec>,
+    // Solid frame some boards require around the photograph; the pixel limits apply to
+    // the framed image, so the picture inside is fitted that much smaller
+    pub border: Option<BorderSpec>,
+    // Camera-style date/time stamp drawn onto the finished image
+    pub timestamp_overlay: Option<TimestampOverlaySpec>,
+    // Least real detail the source must carry at the physical print size. Upscaling
+    // reaches the pixel count but not this; requires dimensions_cm or dimensions_mm.
+    pub min_effective_dpi: Option<u32>,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub on_low_resolution: ColorMismatchPolicy,
+}
+
+impl DocumentSpec {
+    // Output formats allowed for image inputs. The flat list can name PDF for PDF inputs,
+    // which images cannot be written as, so it is skipped there.
+    pub(crate) fn allowed_image_formats(&self) -> Vec<&str> {
+        match &self.image_formats {
+            Some(formats) => formats.iter().map(String::as_str).collect(),
+            None => self.format.iter().map(String::as_str).filter(|format| *format != "PDF").collect(),
+        }
+    }
+
+    // `png_optimize`, `linear_light_resize` and `pre_reduce_above` as given, or as the
+    // performance profile sets them. The profile leaves out settings the spec rules out.
+    #[cfg_attr(not(feature = "png"), allow(dead_code))]
+    pub(crate) fn png_optimized(&self) -> bool {
+        self.png_optimize.unwrap_or(self.performance_profile == PerformanceProfile::Best && self.png_layout().is_none())
+    }
+
+    // The PNG byte layout `png_profile`, `output_color_type` or the required color type
+    // and bit depth fix, if any does
+    pub(crate) fn png_layout(&self) -> Option<PngProfile> {
+        self.png_profile.or(self.output_color_type.map(OutputColorType::png_profile)).or(self.required_layout())
+    }
+
+    // The 8-bit layout `required_color_type` and `required_bit_depth` ask for
+    pub(crate) fn required_layout(&self) -> Option<PngProfile> {
+        match (self.required_color_type, self.required_bit_depth) {
+            (Some(ColorType::Grayscale), _) | (None, Some(8)) => Some(PngProfile::Grayscale8),
+            (Some(ColorType::Rgb), _) | (None, Some(24)) => Some(PngProfile::Rgb8),
+            (Some(ColorType::Rgba), _) | (None, Some(32)) => Some(PngProfile::Rgba8),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn linear_light(&self) -> bool {
+        self.linear_light_resize.unwrap_or(self.performance_profile == PerformanceProfile::Best && !self.integer_scaling)
+    }
+
+    pub(crate) fn pre_reduce_threshold(&self) -> f32 {
+        self.pre_reduce_above.unwrap_or(self.performance_profile.pre_reduce_above())
+    }
+
+    pub(crate) fn allowed_document_formats(&self) -> Vec<&str> {
+        self.document_formats.as_ref().unwrap_or(&self.format).iter().map(String::as_str).collect()
+    }
+
+    // Whether any input could be given an output format
+    pub(crate) fn has_target_format(&self) -> bool {
+        self.on_missing_format == MissingFormatPolicy::Jpeg
+            || !self.allowed_image_formats().is_empty()
+            || !self.allowed_document_formats().is_empty()
+    }
+
+    // Print width, height and units per inch for a `width`×`height` source, a missing
+    // axis following the source's aspect ratio; dimensions_mm wins over dimensions_cm
+    pub(crate) fn physical_size(&self, width: u32, height: u32) -> Option<(f32, f32, f32)> {
+        let (size, units_per_inch) = match (&self.dimensions_mm, &self.dimensions_cm) {
+            (Some(mm), _) => (mm, 25.4),
+            (None, Some(cm)) => (cm, 2.54),
+            (None, None) => return None,
+        };
+        let aspect = width as f32 / height as f32;
+        match (size.width, size.height) {
+            (Some(width), Some(height)) => Some((width, height, units_per_inch)),
+            (Some(width), None) => Some((width, width / aspect, units_per_inch)),
+            (None, Some(height)) => Some((height * aspect, height, units_per_inch)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct AutoTrimSpec {
+    // Maximum per-channel difference from the detected border color
+    #[serde(default = "default_trim_tolerance")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub tolerance: u8,
+    // Fraction of each axis that must survive trimming, otherwise nothing is trimmed
+    #[serde(default = "default_trim_min_retained")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub min_retained: f32,
+}
+
+pub(crate) fn default_trim_tolerance() -> u8 {
+    24
+}
+
+pub(crate) fn default_trim_min_retained() -> f32 {
+    0.5
+}
+
+// A frame is a band of one color, other than the page's, running in from an edge of the
+// image: the scanner lid or bed around a small original, a printed border, or the
+// wedges a page placed askew leaves in the corners. How far it reaches is measured
+// along the whole edge, so a wedge is cut where it is deepest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ScannerEdgesSpec {
+    // Largest fraction of the width or height cut from any one side, at most 0.25; a
+    // frame reaching further is more likely content, and nothing is cut
+    #[serde(default = "default_edges_max_crop")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub max_crop: f32,
+    // Maximum per-channel difference within a frame, and from it to the page
+    #[serde(default = "default_edges_tolerance")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub tolerance: u8,
+}
+
+fn default_edges_max_crop() -> f32 {
+    0.15
+}
+
+fn default_edges_tolerance() -> u8 {
+    40
+}
+
+// Flattens a photographed page: finds its four corners, corrects the perspective and
+// crops to the page before any other processing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DocumentScanSpec {
+    // Smallest fraction of the frame a detected page may cover; smaller finds are ignored
+    #[serde(default = "default_scan_min_area")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub min_area: f32,
+}
+
+fn default_scan_min_area() -> f32 {
+    0.2
+}
+
+// Reads which way the writing runs (see `orient::detect_orientation`) and turns the
+// image by 90°, 180° or 270° to read upright. Images that are not writing on paper are
+// left alone.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct AutoRotateSpec {
+    // Confidence (0 to 1) below which a turn is only suggested in a warning
+    #[serde(default = "default_rotate_min_confidence")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub min_confidence: f32,
+}
+
+fn default_rotate_min_confidence() -> f32 {
+    0.4
+}
+
+// Which marks count as dust: isolated ones of at most `max_size` ink pixels, counted in
+// the sized image. Ink within 2 pixels belongs to the same mark and small marks within
+// 8 pixels of a larger one are kept, so fine strokes and the dots of an i survive.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DespeckleSpec {
+    #[serde(default = "default_despeckle_max_size")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub max_size: u32,
+}
+
+fn default_despeckle_max_size() -> u32 {
+    8
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ClaheSpec {
+    // Side of the square tiles equalized on their own, in source pixels
+    #[serde(default = "default_clahe_tile_size")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub tile_size: u32,
+    // How many times an even share of a tile's pixels one level may hold before the rest
+    // is spread over all levels; lower keeps noise in flat paper from being amplified
+    #[serde(default = "default_clahe_clip_limit")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub clip_limit: f32,
+}
+
+pub(crate) fn default_clahe_tile_size() -> u32 {
+    128
+}
+
+pub(crate) fn default_clahe_clip_limit() -> f32 {
+    2.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct BackgroundSpec {
+    // `#RRGGBB`
+    pub required: String,
+    // Repaint a plain background that is not `required`; otherwise it is only reported
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub auto_replace: bool,
+    // Per-channel difference within which pixels belong to the background, and within
+    // which the background already counts as `required`
+    #[serde(default = "default_trim_tolerance")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub tolerance: u8,
+}
+
+impl BackgroundSpec {
+    pub fn required_rgb(&self) -> Option<[u8; 3]> {
+        parse_rgb(&self.required)
+    }
+}
+
+// `#RRGGBB` to its channels
+pub(crate) fn parse_rgb(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// The image is fitted inside `scale` of the canvas width and height, keeping its aspect
+// ratio, and placed by percentage of the space left over: 0 against the left or top
+// edge, 100 against the right or bottom one, 50 centered
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct CanvasSpec {
+    pub width: u32,
+    pub height: u32,
+    // `#RRGGBB` fill around the image
+    #[serde(default = "default_canvas_background")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub background: String,
+    #[serde(default = "default_canvas_percent")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub x_percent: f32,
+    #[serde(default = "default_canvas_percent")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub y_percent: f32,
+    #[serde(default = "default_canvas_scale")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub scale: f32,
+}
+
+// `width_px` of `color` (`#RRGGBB`, black when unset) on every side of the output
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct BorderSpec {
+    pub width_px: u32,
+    #[serde(default = "default_border_color")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub color: String,
+}
+
+// The stamp is drawn in the 5×7 bitmap font of the contact sheet, so `format` is limited
+// to printable ASCII. `Now` takes the host's local time at conversion; `Exif` takes the
+// source's EXIF `DateTimeOriginal` and fails the conversion when there is none.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct TimestampOverlaySpec {
+    // `%Y`, `%m`, `%d`, `%H`, `%M` and `%S` are filled in, `%%` is a percent sign
+    #[serde(default = "default_timestamp_format")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub format: String,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub position: OverlayPosition,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub source: TimestampSource,
+    // `#RRGGBB` of the text
+    #[serde(default = "default_timestamp_color")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub color: String,
+    // `#RRGGBB` box behind the text and its opacity, 0 to 1; semi-opaque keeps the text
+    // legible on any image while showing what it covers
+    #[serde(default = "default_timestamp_box_color")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub box_color: String,
+    #[serde(default = "default_timestamp_box_opacity")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub box_opacity: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum TimestampSource {
+    #[default]
+    Now,
+    Exif,
+}
+
+fn default_timestamp_format() -> String {
+    "%Y-%m-%d %H:%M".to_string()
+}
+
+fn default_timestamp_color() -> String {
+    "#FFA500".to_string()
+}
+
+fn default_timestamp_box_color() -> String {
+    "#000000".to_string()
+}
+
+fn default_timestamp_box_opacity() -> f32 {
+    0.6
+}
+
+// Below this scans stop being legible; above it rasterizing rarely makes a PDF smaller
+const MIN_RASTERIZE_DPI: u32 = 50;
+const MAX_RASTERIZE_DPI: u32 = 300;
+
+// Past these a gamma washes a page out or crushes it to black rather than correcting it
+const MIN_GAMMA: f32 = 0.3;
+const MAX_GAMMA: f32 = 3.0;
+
+// A border may take at most this share of the output's shortest edge on each side;
+// wider ones frame more than they show
+const MAX_BORDER_SHARE: f32 = 0.1;
+
+fn default_canvas_background() -> String {
+    "#FFFFFF".to_string()
+}
+
+fn default_border_color() -> String {
+    "#000000".to_string()
+}
+
+fn default_canvas_percent() -> f32 {
+    50.0
+}
+
+fn default_canvas_scale() -> f32 {
+    1.0
+}
+
+// The ink is the pixels clearly darker than the paper; its dominant hue and mean
+// saturation are compared against `allowed`. Signatures with no ink found pass.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct InkColorSpec {
+    pub allowed: Vec<InkColor>,
+    // `Convert` is not supported; ink is never recolored
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub on_mismatch: ColorMismatchPolicy,
+}
+
+// A built-in range by name ("black", "blue", "red" or "green") or a custom one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(untagged)]
+pub enum InkColor {
+    Named(String),
+    Range(InkRange),
+}
+
+// Hue is in degrees; a range whose `hue_min` is above `hue_max` wraps through 0, as reds
+// do. Saturation is HSV saturation from 0 to 1. A range without hue bounds matches
+// unsaturated ink of any hue, which is how black is described.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct InkRange {
+    pub name: String,
+    pub hue_min: Option<f32>,
+    pub hue_max: Option<f32>,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub saturation_min: f32,
+    #[serde(default = "default_saturation_max")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub saturation_max: f32,
+}
+
+fn default_saturation_max() -> f32 {
+    1.0
+}
+
+impl InkColor {
+    pub(crate) const BUILT_IN: [&'static str; 4] = ["black", "blue", "red", "green"];
+
+    /// The range this entry stands for, or None for an unknown built-in name.
+    pub fn range(&self) -> Option<InkRange> {
+        let (name, hue, saturation) = match self {
+            InkColor::Range(range) => return Some(range.clone()),
+            InkColor::Named(name) => match name.as_str() {
+                "black" => ("black", None, (0.0, 0.3)),
+                "blue" => ("blue", Some((190.0, 260.0)), (0.25, 1.0)),
+                "red" => ("red", Some((330.0, 20.0)), (0.25, 1.0)),
+                "green" => ("green", Some((80.0, 170.0)), (0.25, 1.0)),
+                _ => return None,
+            },
+        };
+        Some(InkRange {
+            name: name.to_string(),
+            hue_min: hue.map(|(min, _)| min),
+            hue_max: hue.map(|(_, max)| max),
+            saturation_min: saturation.0,
+            saturation_max: saturation.1,
+        })
+    }
+}
+
+impl InkRange {
+    /// Whether ink of this hue (None when unsaturated) and saturation falls in the range.
+    pub fn matches(&self, hue: Option<f32>, saturation: f32) -> bool {
+        if !(self.saturation_min..=self.saturation_max).contains(&saturation) {
+            return false;
+        }
+        match (self.hue_min.zip(self.hue_max), hue) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some((min, max)), Some(hue)) if min <= max => (min..=max).contains(&hue),
+            (Some((min, max)), Some(hue)) => hue >= min || hue <= max,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct SizeSpec {
+    // KB as numbers, or size strings in any unit `parse_size_kb` reads
+    #[serde(default, deserialize_with = "optional_size_kb")]
+    #[cfg_attr(feature = "typescript", ts(type = "number | string | null", optional))]
+    pub min: Option<u32>,
+    #[serde(deserialize_with = "size_kb")]
+    #[cfg_attr(feature = "typescript", ts(type = "number | string"))]
+    pub max: u32,
+}
+
+/// KB (of 1024 bytes, like every `size_kb`) in a size string such as "50KB", "1 MB",
+/// "512 kib" or "51200B". Units are B, KB, KiB, MB and MiB in any case, K and M for
+/// short, and KB when left out; KB and KiB are both 1024 bytes, as upload portals mean
+/// them. The size must come to whole KB, under 4 GiB.
+pub fn parse_size_kb(text: &str) -> Result<u32, String> {
+    let invalid = || format!("size {:?} is not a number with a unit of B, KB, KiB, MB or MiB, like \"50KB\"", text);
+    let trimmed = text.trim();
+    let (number, unit) = trimmed.split_at(trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len()));
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let bytes_per_unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "b" => 1.0,
+        "" | "k" | "kb" | "kib" => 1024.0,
+        "m" | "mb" | "mib" => 1024.0 * 1024.0,
+        _ => return Err(invalid()),
+    };
+    let kb = number * bytes_per_unit / 1024.0;
+    if kb.fract() != 0.0 {
+        return Err(format!("size {:?} is not a whole number of KB", text));
+    }
+    if kb > MAX_SIZE_KB as f64 {
+        return Err(format!("size {:?} is over the {} KB limit", text, MAX_SIZE_KB));
+    }
+    Ok(kb as u32)
+}
+
+// Largest `size_kb` whose byte count still fits a u32, as the pipeline computes it
+const MAX_SIZE_KB: u32 = u32::MAX / 1024;
+
+// A KB count given as a number or a size string
+struct SizeKb(u32);
+
+impl<'de> Deserialize<'de> for SizeKb {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SizeKbVisitor;
+
+        impl serde::de::Visitor<'_> for SizeKbVisitor {
+            type Value = SizeKb;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a number of KB or a size string like \"50KB\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<SizeKb, E> {
+                match u32::try_from(value) {
+                    Ok(kb) if kb <= MAX_SIZE_KB => Ok(SizeKb(kb)),
+                    _ => Err(E::custom(format!("size {} KB is over the {} KB limit", value, MAX_SIZE_KB))),
+                }
+            }
+
+            // serde-wasm-bindgen hands JS numbers over as i64 when they are safe integers
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<SizeKb, E> {
+                match u64::try_from(value) {
+                    Ok(value) => self.visit_u64(value),
+                    Err(_) => Err(E::custom(format!("size {} KB is negative", value))),
+                }
+            }
+
+            // and as f64 otherwise
+            fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<SizeKb, E> {
+                if !value.is_finite() || value.fract() != 0.0 || value < 0.0 {
+                    return Err(E::custom(format!("size {} KB is not a whole number of KB", value)));
+                }
+                self.visit_u64(value as u64)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<SizeKb, E> {
+                parse_size_kb(value).map(SizeKb).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(SizeKbVisitor)
+    }
+}
+
+fn size_kb<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    SizeKb::deserialize(deserializer).map(|size| size.0)
+}
+
+fn optional_size_kb<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Option::<SizeKb>::deserialize(deserializer).map(|size| size.map(|size| size.0))
+}
+
+fn size_spec_or_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<SizeSpec, D::Error> {
+    struct SizeSpecVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for SizeSpecVisitor {
+        type Value = SizeSpec;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("size limits or a size string like \"50KB\"")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<SizeSpec, E> {
+            parse_size_kb(value).map(|max| SizeSpec { min: None, max }).map_err(E::custom)
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<SizeSpec, A::Error> {
+            SizeSpec::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(SizeSpecVisitor)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct DimensionsSpec {
+    pub width: f32,
+    pub height: f32,
+}
+
+// A print size; give only one axis to keep the source's proportions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct PhysicalDimensionsSpec {
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+}
+
+// Exact or maximum pixel sizes below this are typos, like a 0 left in an unused field:
+// clamping to them would make an image too small for anyone to review
+const MIN_PIXEL_BOUND: u32 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct PixelSpec {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub min: Option<PixelDimensions>,
+    pub max: Option<PixelDimensions>,
+    // Bounds on width × height whatever the aspect, for portals that cap megapixels
+    pub min_total_pixels: Option<u32>,
+    pub max_total_pixels: Option<u32>,
+    // Output exactly width == height: the source is center-cropped to a square before
+    // resizing, so no ratio rounding can leave it a pixel off
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub square: bool,
+}
+
+impl PixelSpec {
+    // Inclusive bounds of a range-style spec; missing sides are unbounded
+    pub(crate) fn range(&self) -> Result<PixelRange, String> {
+        let range = PixelRange {
+            min_width: self.min_width.or_else(|| self.min.as_ref().map(|m| m.width)).unwrap_or(1).max(1),
+            max_width: self.max_width.or_else(|| self.max.as_ref().map(|m| m.width)).unwrap_or(u32::MAX),
+            min_height: self.min_height.or_else(|| self.min.as_ref().map(|m| m.height)).unwrap_or(1).max(1),
+            max_height: self.max_height.or_else(|| self.max.as_ref().map(|m| m.height)).unwrap_or(u32::MAX),
+        };
+        if range.min_width > range.max_width {
+            return Err(format!("pixels min width {} exceeds max width {}", range.min_width, range.max_width));
+        }
+        if range.min_height > range.max_height {
+            return Err(format!("pixels min height {} exceeds max height {}", range.min_height, range.max_height));
+        }
+        Ok(range)
+    }
+
+    // Sizes the output would be clamped to that are too small to be meant
+    fn check_bounds(&self) -> Result<(), String> {
+        let bounds = [
+            ("width", self.width),
+            ("height", self.height),
+            ("max_width", self.max_width),
+            ("max_height", self.max_height),
+            ("max.width", self.max.as_ref().map(|max| max.width)),
+            ("max.height", self.max.as_ref().map(|max| max.height)),
+        ];
+        match bounds.into_iter().find_map(|(name, bound)| bound.filter(|&bound| bound < MIN_PIXEL_BOUND).map(|bound| (name, bound))) {
+            Some((name, bound)) => Err(format!("pixels {} must be at least {}, got {}", name, MIN_PIXEL_BOUND, bound)),
+            None => Ok(()),
+        }
+    }
+
+    // Contradictions between the total pixel bounds, the spec's `max_megapixels` and the
+    // per-axis bounds
+    fn check_totals(&self, max_megapixels: Option<f32>) -> Result<(), String> {
+        if self.max_total_pixels == Some(0) {
+            return Err("pixels max_total_pixels must be positive, got 0".to_string());
+        }
+        if let Some(max_total) = self.max_total_pixels.filter(|&max_total| max_total < MIN_PIXEL_BOUND * MIN_PIXEL_BOUND) {
+            return Err(format!(
+                "pixels max_total_pixels must be at least {} ({}x{}), got {}",
+                MIN_PIXEL_BOUND * MIN_PIXEL_BOUND, MIN_PIXEL_BOUND, MIN_PIXEL_BOUND, max_total
+            ));
+        }
+        let max_total = self.max_total_pixels.map_or(u64::MAX, u64::from);
+        let min_total = self.min_total_pixels.map_or(0, u64::from);
+        if min_total > max_total {
+            return Err(format!("pixels min_total_pixels {} exceeds max_total_pixels {}", min_total, max_total));
+        }
+        let megapixel_budget = max_megapixels.map_or(u64::MAX, |megapixels| (megapixels as f64 * 1_000_000.0).floor() as u64);
+        if min_total > megapixel_budget {
+            return Err(format!("pixels min_total_pixels {} exceeds max_megapixels {}", min_total, max_megapixels.unwrap_or_default()));
+        }
+        let (smallest, largest) = match (self.width, self.height) {
+            (Some(width), Some(height)) => ((width, height), (width, height)),
+            _ => {
+                let range = self.range()?;
+                ((range.min_width, range.min_height), (range.max_width, range.max_height))
+            }
+        };
+        let total = |(width, height): (u32, u32)| width as u64 * height as u64;
+        if total(smallest) > max_total {
+            return Err(format!(
+                "pixels {}x{} ({} pixels) exceeds max_total_pixels {}",
+                smallest.0, smallest.1, total(smallest), max_total
+            ));
+        }
+        if total(smallest) > megapixel_budget {
+            return Err(format!(
+                "pixels {}x{} ({} pixels) exceeds max_megapixels {}",
+                smallest.0, smallest.1, total(smallest), max_megapixels.unwrap_or_default()
+            ));
+        }
+        if total(largest) < min_total {
+            return Err(format!(
+                "pixels {}x{} ({} pixels) is below min_total_pixels {}",
+                largest.0, largest.1, total(largest), min_total
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct PixelRange {
+    pub min_width: u32,
+    pub max_width: u32,
+    pub min_height: u32,
+    pub max_height: u32,
+}
+
+impl PixelRange {
+    pub const UNBOUNDED: PixelRange = PixelRange { min_width: 1, max_width: u32::MAX, min_height: 1, max_height: u32::MAX };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct PixelDimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct AspectRatioSpec {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub height_to_width_min: Option<f32>,
+    pub height_to_width_max: Option<f32>,
+}
+
+impl AspectRatioSpec {
+    // Lowest and highest width / height every bound admits
+    pub(crate) fn width_to_height_bounds(&self) -> (f32, f32) {
+        let lowest = self.min.unwrap_or(0.0).max(self.height_to_width_max.map_or(0.0, |r| 1.0 / r));
+        let highest = self.max.unwrap_or(f32::INFINITY).min(self.height_to_width_min.map_or(f32::INFINITY, |r| 1.0 / r));
+        (lowest, highest)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ConversionConfig {
+    pub exam_type: String,
+    pub document_type: String,
+    pub target_spec: DocumentSpec,
+    // Attaches PNG snapshots of every pipeline stage to each result. Diagnostic use only:
+    // it multiplies memory use per conversion and must stay off in production.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub debug_intermediates: bool,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub extension_case: ExtensionCase,
+    // Color space untagged inputs are assumed to be in. Embedded ICC profiles are not
+    // read, so this applies to every input in the conversion.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub assume_source_profile: SourceProfile,
+    pub memory_check: Option<MemoryCheckSpec>,
+    // Most pixels an input's header may declare before it is refused undecoded, as a
+    // decompression bomb: a few KB of PNG can claim gigapixels of flat color. A plain
+    // number on the JS side, like the u64 counters in SessionStats
+    #[serde(default = "default_max_decoded_pixels")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<f64>", optional))]
+    pub max_decoded_pixels: u64,
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub on_feature_loss: FeatureLossPolicy,
+    // Hand back the closest output instead of failing when it breaks the size or pixel
+    // limits, flagged `compliant: false` with its `violations`. For internal review only;
+    // such a file must not be submitted.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub return_best_effort: bool,
+    // Output file name without its extension, from the placeholders in
+    // `FILENAME_PLACEHOLDERS`. Unset names outputs `{document_type}_{name}`.
+    pub filename_template: Option<String>,
+    // Most encodes one conversion may make across quality steps, rescaling and format
+    // fallback before it fails with the attempts it made
+    #[serde(default = "default_max_attempts")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub max_attempts: u32,
+    // Writes the exam, document type, converter version and conversion time into the
+    // output (JPEG comment, PNG text, PDF XMP). The bytes count against `size_kb`.
+    #[serde(default)]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub embed_metadata: bool,
+}
+
+// The most the decoder's own 512MB allocation limit lets through at 4 bytes a pixel
+pub(crate) const DEFAULT_MAX_DECODED_PIXELS: u64 = 128 * 1024 * 1024;
+
+fn default_max_decoded_pixels() -> u64 {
+    DEFAULT_MAX_DECODED_PIXELS
+}
+
+fn default_max_attempts() -> u32 {
+    20
+}
+
+// `{name}` is the input's base name; `{capture_date}` is its EXIF capture date as
+// YYYY-MM-DD, or the base name when it has none
+pub(crate) const FILENAME_PLACEHOLDERS: [&str; 3] = ["document_type", "name", "capture_date"];
+
+// Refuses to decode images whose pixel buffer would not fit in the memory the host
+// reports as available. Hosts that cannot tell skip the check unless `available_mb`
+// supplies the figure.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct MemoryCheckSpec {
+    // Share of the available memory one decoded image may take
+    #[serde(default = "default_memory_max_fraction")]
+    #[cfg_attr(feature = "typescript", ts(as = "Option<_>", optional))]
+    pub max_fraction: f32,
+    // Overrides the host's estimate of available memory
+    pub available_mb: Option<u32>,
+}
+
+fn default_memory_max_fraction() -> f32 {
+    0.5
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum ExtensionCase {
+    #[default]
+    Lower,
+    Upper,
+    // Uppercase when the uploaded file's extension was all uppercase (e.g. IMG_001.JPG)
+    PreserveInput,
+}
+
+// `Inch` and `Cm` write `resolution_px_per_inch` (150 when unset) in that unit; `None`
+// writes a unitless 1:1 pixel aspect ratio, as before the option existed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum DensityUnit {
+    Inch,
+    Cm,
+    #[default]
+    None,
+}
+
+// Byte layouts `png_profile` locks PNG output to, all at 8 bits per channel. Grayscale8
+// and RGB8 drop the alpha channel; Indexed8 keeps it in a tRNS chunk but needs an image
+// of at most 256 distinct colors (posterize_levels 6 guarantees that).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum PngProfile {
+    Grayscale8,
+    #[serde(rename = "RGB8")]
+    Rgb8,
+    #[serde(rename = "RGBA8")]
+    Rgba8,
+    Indexed8,
+}
+
+impl PngProfile {
+    pub fn keeps_alpha(self) -> bool {
+        matches!(self, PngProfile::Rgba8 | PngProfile::Indexed8)
+    }
+}
+
+// The true-color layouts `output_color_type` can require, at 8 bits per channel
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum OutputColorType {
+    Rgb8,
+    Rgba8,
+}
+
+impl OutputColorType {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputColorType::Rgb8 => "rgb8",
+            OutputColorType::Rgba8 => "rgba8",
+        }
+    }
+
+    // The formats that can be written in it
+    fn formats(self) -> &'static [&'static str] {
+        match self {
+            OutputColorType::Rgb8 => &["JPEG", "PNG"],
+            OutputColorType::Rgba8 => &["PNG"],
+        }
+    }
+
+    fn png_profile(self) -> PngProfile {
+        match self {
+            OutputColorType::Rgb8 => PngProfile::Rgb8,
+            OutputColorType::Rgba8 => PngProfile::Rgba8,
+        }
+    }
+}
+
+// Color types images are stored in, as headers declare them. Outputs can be required
+// to be grayscale, rgb or rgba; the others are only reported.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum ColorType {
+    Grayscale,
+    GrayscaleAlpha,
+    Rgb,
+    Rgba,
+    Indexed,
+    Cmyk,
+}
+
+impl ColorType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::GrayscaleAlpha => "grayscale_alpha",
+            ColorType::Rgb => "rgb",
+            ColorType::Rgba => "rgba",
+            ColorType::Indexed => "indexed",
+            ColorType::Cmyk => "cmyk",
+        }
+    }
+
+    // Samples per pixel; an indexed pixel is one palette index
+    pub fn channels(self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba | ColorType::Cmyk => 4,
+        }
+    }
+}
+
+// Forward DCT used by the JPEG encoder. The bundled encoder (in native and WASM builds
+// alike) only implements libjpeg's accurate integer transform, `Islow`; being integer-only
+// it gives the same bytes on every platform. Other libjpeg methods (`ifast`, `float`) are
+// rejected when the config is parsed rather than silently ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum DctMethod {
+    #[default]
+    Islow,
+}
+
+// Reference encoders whose quality numbers `quality_scale` accepts. `Native` is the
+// bundled encoder's scale, that of the IJG libjpeg; IrfanView, GIMP and ImageMagick
+// build on libjpeg too, so `IrfanView` is the same scale under the name a requirement
+// may cite. `Photoshop` is the 0..100 scale of Save for Web and Export As, which holds
+// more detail at a given number; it is mapped through the approximate libjpeg
+// equivalents of its quantization tables, interpolated between:
+//
+//   Photoshop  0  10  30  50  60  80  100
+//   libjpeg   12  32  56  72  78  89   98
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum QualityScale {
+    #[default]
+    Native,
+    IrfanView,
+    Photoshop,
+}
+
+// One knob for the speed of a conversion against the quality and size of its output.
+// `Fast` suits phones, `Best` desktops; each sets:
+//
+//               resize filter  JPEG qualities tried  pre_reduce_above  png_optimize  linear_light_resize
+//   Fast        Triangle       90 to 10 by 20        2                 off           off
+//   Balanced    Lanczos3       90 to 10 by 10        3                 off           off
+//   Best        Lanczos3       90 to 10 by 5         6                 on            on
+//
+// The qualities are those of the size loop and of host encoders; `quality_ladder`
+// replaces them. `pre_reduce_above`, `png_optimize` and `linear_light_resize` given in
+// the spec win over the profile, and `Best` leaves `png_optimize` off with a
+// `png_profile` and `linear_light_resize` off with `integer_scaling`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum PerformanceProfile {
+    Fast,
+    #[default]
+    Balanced,
+    Best,
+}
+
+impl PerformanceProfile {
+    /// The filter every resample but `integer_scaling` uses.
+    pub fn resize_filter(self) -> image::imageops::FilterType {
+        match self {
+            PerformanceProfile::Fast => image::imageops::FilterType::Triangle,
+            PerformanceProfile::Balanced | PerformanceProfile::Best => image::imageops::FilterType::Lanczos3,
+        }
+    }
+
+    /// JPEG qualities the size loop tries, highest first.
+    pub fn qualities(self) -> Vec<u8> {
+        let step = match self {
+            PerformanceProfile::Fast => 20,
+            PerformanceProfile::Balanced => 10,
+            PerformanceProfile::Best => 5,
+        };
+        (10..=90).rev().step_by(step).collect()
+    }
+
+    fn pre_reduce_above(self) -> f32 {
+        match self {
+            PerformanceProfile::Fast => 2.0,
+            PerformanceProfile::Balanced => 3.0,
+            PerformanceProfile::Best => 6.0,
+        }
+    }
+}
+
+const PHOTOSHOP_TO_LIBJPEG: [(u8, u8); 7] = [(0, 12), (10, 32), (30, 56), (50, 72), (60, 78), (80, 89), (100, 98)];
+
+impl QualityScale {
+    /// `quality` on this scale as the bundled encoder's quality, 1..=100.
+    pub fn native_quality(self, quality: u8) -> u8 {
+        let quality = quality.min(100);
+        let native = match self {
+            QualityScale::Native | QualityScale::IrfanView => quality,
+            QualityScale::Photoshop => {
+                let upper = PHOTOSHOP_TO_LIBJPEG.iter().position(|&(from, _)| from >= quality).unwrap_or(0).max(1);
+                let ((from_low, to_low), (from_high, to_high)) = (PHOTOSHOP_TO_LIBJPEG[upper - 1], PHOTOSHOP_TO_LIBJPEG[upper]);
+                let fraction = (quality - from_low) as f32 / (from_high - from_low) as f32;
+                (to_low as f32 + fraction * (to_high - to_low) as f32).round() as u8
+            }
+        };
+        native.max(1)
+    }
+}
+
+// `Stretch` maps the darkest and lightest 1% of the image to black and white. `Clahe`
+// equalizes each tile on its own, which also evens out a scan lit from one side, but can
+// shift skin tones in photos.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum AutoContrast {
+    Stretch,
+    Clahe,
+}
+
+// Scans toned by paper or a scanner cast still count as grayscale; see `ops::colorfulness`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    Color,
+    Grayscale,
+    Any,
+}
+
+// What to do when the source does not match `color_mode`. `Convert` drops the color of
+// a color source when grayscale is required; it cannot add color, so it is only valid
+// with `color_mode: "grayscale"`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMismatchPolicy {
+    #[default]
+    Fail,
+    Warn,
+    Convert,
+}
+
+// `Portrait` and `Landscape` rotate the finished image 90° clockwise when its longer edge
+// runs the other way; square images are left alone. This ignores EXIF orientation and
+// the content, so it is only for forms that fix the layout. `Auto` keeps the orientation
+// the resize produced.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum PrintOrientation {
+    Portrait,
+    Landscape,
+    #[default]
+    Auto,
+}
+
+// `Fail` rejects configs that list no output format at all, and image inputs to specs
+// that only allow PDF, with `NoTargetFormat`. `Jpeg` writes such images as JPEG.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum MissingFormatPolicy {
+    #[default]
+    Fail,
+    Jpeg,
+}
+
+// `Stretch` resizes straight to the target dimensions, distorting the image when the
+// aspect ratio differs. `Pad` fits the whole image inside and fills the margins with
+// `background.required` (white without one); `Cover` fills the target and crops the
+// overflow evenly from both sides. All three produce exactly the target dimensions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum FitMode {
+    #[default]
+    Stretch,
+    Pad,
+    Cover,
+}
+
+// What to do when the target format cannot carry part of the source (animation frames,
+// transparency). `Warn` flattens and logs a warning event; `Fail` rejects the input.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum FeatureLossPolicy {
+    #[default]
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS))]
+pub enum SourceProfile {
+    #[default]
+    #[serde(rename = "sRGB")]
+    Srgb,
+    #[serde(rename = "AdobeRGB")]
+    AdobeRgb,
+    #[serde(rename = "DisplayP3")]
+    DisplayP3,
+}
+
+/// Parse and validate a `ConversionConfig` from its JSON form.
+pub fn parse_config(config_json: &str) -> Result<ConversionConfig, ConvertError> {
+    let config: ConversionConfig = serde_json::from_str(config_json)
+        .map_err(|e| ConvertError::InvalidConfig(e.to_string()))?;
+    validate_config(&config)?;
+    Ok(config)
+}
+
+pub(crate) fn validate_config(config: &ConversionConfig) -> Result<(), ConvertError> {
+    if !config.target_spec.has_target_format() {
+        return Err(ConvertError::NoTargetFormat);
+    }
+    check_config_values(config).map_err(ConvertError::InvalidConfig)
+}
+
+// The checks behind `validate_config` that report as `InvalidConfig`
+fn check_config_values(config: &ConversionConfig) -> Result<(), String> {
+    if let Some(format) = config.target_spec.image_formats.iter().flatten().find(|format| *format == "PDF") {
+        return Err(format!("image_formats cannot include {}, images are not written as PDF", format));
+    }
+    if let Some(format) = config.target_spec.document_formats.iter().flatten().find(|format| *format != "PDF") {
+        return Err(format!("document_formats only supports PDF, got {}", format));
+    }
+    if let Some(levels) = config.target_spec.posterize_levels {
+        if levels < 2 {
+            return Err(format!("posterize_levels must be at least 2, got {}", levels));
+        }
+    }
+    if config.max_attempts == 0 {
+        return Err("max_attempts must be at least 1".to_string());
+    }
+    if config.max_decoded_pixels == 0 {
+        return Err("max_decoded_pixels must be at least 1".to_string());
+    }
+    if let Some(template) = &config.filename_template {
+        let literal = FILENAME_PLACEHOLDERS
+            .iter()
+            .fold(template.clone(), |rest, placeholder| rest.replace(&format!("{{{}}}", placeholder), ""));
+        if template.trim().is_empty() {
+            return Err("filename_template cannot be empty".to_string());
+        }
+        if literal.contains(['{', '}']) {
+            return Err(format!(
+                "filename_template {:?} has an unknown placeholder, expected {{document_type}}, {{name}} or {{capture_date}}",
+                template
+            ));
+        }
+        if template.contains(['/', '\\']) {
+            return Err(format!("filename_template {:?} cannot contain path separators", template));
+        }
+    }
+    if let Some(ladder) = &config.target_spec.quality_ladder {
+        if ladder.is_empty() {
+            return Err("quality_ladder needs at least one quality".to_string());
+        }
+        if let Some(quality) = ladder.iter().find(|quality| !(1..=100).contains(*quality)) {
+            return Err(format!("quality_ladder qualities must be between 1 and 100, got {}", quality));
+        }
+    }
+    if let Some(megapixels) = config.target_spec.max_megapixels {
+        if megapixels.is_nan() || megapixels <= 0.0 {
+            return Err(format!("max_megapixels must be positive, got {}", megapixels));
+        }
+        // The pipeline floors the budget to whole pixels, so compare what it will use
+        if (megapixels as f64 * 1_000_000.0).floor() < (MIN_PIXEL_BOUND * MIN_PIXEL_BOUND) as f64 {
+            return Err(format!(
+                "max_megapixels {} allows fewer than {} pixels ({}x{})",
+                megapixels, MIN_PIXEL_BOUND * MIN_PIXEL_BOUND, MIN_PIXEL_BOUND, MIN_PIXEL_BOUND
+            ));
+        }
+    }
+    if let Some(trim) = &config.target_spec.auto_trim {
+        if !(trim.min_retained > 0.0 && trim.min_retained <= 1.0) {
+            return Err(format!("auto_trim.min_retained must be in (0, 1], got {}", trim.min_retained));
+        }
+    }
+    if config.target_spec.quality_scale != QualityScale::Native && !config.target_spec.allowed_image_formats().contains(&"JPEG") {
+        return Err("quality_scale needs JPEG among the allowed image formats".to_string());
+    }
+    if config.target_spec.png_profile.is_some() {
+        if config.target_spec.png_optimize == Some(true) {
+            return Err("png_profile cannot be combined with png_optimize, which picks the layout itself".to_string());
+        }
+        if !config.target_spec.allowed_image_formats().contains(&"PNG") {
+            return Err("png_profile needs PNG among the allowed image formats".to_string());
+        }
+    }
+    if config.target_spec.png_interlaced && !config.target_spec.allowed_image_formats().contains(&"PNG") {
+        return Err("png_interlaced needs PNG among the allowed image formats".to_string());
+    }
+    if let Some(color_type) = config.target_spec.output_color_type {
+        if config.target_spec.png_profile.is_some() {
+            return Err("output_color_type cannot be combined with png_profile, which fixes the color type itself".to_string());
+        }
+        if config.target_spec.png_optimize == Some(true) {
+            return Err("output_color_type cannot be combined with png_optimize, which may write indexed color".to_string());
+        }
+        if let Some(format) = config.target_spec.allowed_image_formats().into_iter().find(|format| !color_type.formats().contains(format)) {
+            return Err(format!("output_color_type {} cannot be met by {} output", color_type.name(), format));
+        }
+    }
+    if let Some(mime_type) = &config.target_spec.output_mime_override {
+        let (kind, subtype) = mime_type.split_once('/').unwrap_or_default();
+        let token = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_graphic() && !"/;,\"".contains(c));
+        if !token(kind) || !token(subtype) {
+            return Err(format!("output_mime_override must be a type/subtype MIME type, got \"{}\"", mime_type));
+        }
+        let mut formats = config.target_spec.allowed_image_formats();
+        formats.extend(config.target_spec.allowed_document_formats());
+        formats.sort_unstable();
+        formats.dedup();
+        if formats.len() > 1 {
+            return Err(format!("output_mime_override would label every output format, so only one may be allowed, got {}", formats.join(", ")));
+        }
+    }
+    if let Some(layout) = config.target_spec.required_layout() {
+        let spec = &config.target_spec;
+        let requirement = match (spec.required_color_type, spec.required_bit_depth) {
+            (Some(color_type), _) => format!("required_color_type {}", color_type.name()),
+            (None, bits) => format!("required_bit_depth {}", bits.unwrap_or_default()),
+        };
+        if let (Some(color_type), Some(bits)) = (spec.required_color_type, spec.required_bit_depth) {
+            if bits != 8 * color_type.channels() {
+                return Err(format!("required_bit_depth {} does not match {}, which is {}-bit", bits, requirement, 8 * color_type.channels()));
+            }
+        }
+        if spec.png_profile.is_some() || spec.output_color_type.is_some() {
+            return Err(format!("{} cannot be combined with png_profile or output_color_type, which fix the layout themselves", requirement));
+        }
+        if spec.png_optimize == Some(true) {
+            return Err(format!("{} cannot be combined with png_optimize, which may write indexed color", requirement));
+        }
+        let formats: &[&str] = if layout == PngProfile::Rgba8 { &["PNG"] } else { &["JPEG", "PNG"] };
+        if let Some(format) = spec.allowed_image_formats().into_iter().find(|format| !formats.contains(format)) {
+            return Err(format!("{} cannot be met by {} output", requirement, format));
+        }
+        if spec.transparent_background && layout != PngProfile::Rgba8 {
+            return Err(format!("{} drops the alpha channel transparent_background needs", requirement));
+        }
+    } else if let Some(color_type) = config.target_spec.required_color_type {
+        return Err(format!("required_color_type {} cannot be written; only grayscale, rgb and rgba can", color_type.name()));
+    } else if let Some(bits) = config.target_spec.required_bit_depth {
+        return Err(format!("required_bit_depth must be 8, 24 or 32 bits per pixel, got {}", bits));
+    }
+    if config.target_spec.transparent_background {
+        if !config.target_spec.allowed_image_formats().contains(&"PNG") {
+            return Err("transparent_background needs PNG among the allowed image formats".to_string());
+        }
+        if config.target_spec.png_profile.is_some_and(|profile| !profile.keeps_alpha()) {
+            return Err("png_profile drops the alpha channel transparent_background needs".to_string());
+        }
+        if config.target_spec.output_color_type == Some(OutputColorType::Rgb8) {
+            return Err("output_color_type rgb8 drops the alpha channel transparent_background needs".to_string());
+        }
+    }
+    if let Some(memory) = &config.memory_check {
+        if !(memory.max_fraction > 0.0 && memory.max_fraction <= 1.0) {
+            return Err(format!("memory_check.max_fraction must be in (0, 1], got {}", memory.max_fraction));
+        }
+    }
+    if let Some(pixels) = &config.target_spec.pixels {
+        pixels.check_bounds()?;
+        if pixels.width.is_none() || pixels.height.is_none() {
+            pixels.range()?;
+        }
+        pixels.check_totals(config.target_spec.max_megapixels)?;
+        if pixels.square {
+            if let (Some(width), Some(height)) = (pixels.width, pixels.height) {
+                if width != height {
+                    return Err(format!("pixels square requires width to equal height, got {}x{}", width, height));
+                }
+            } else {
+                let range = pixels.range()?;
+                if range.min_width.max(range.min_height) > range.max_width.min(range.max_height) {
+                    return Err("pixels square does not fit the width and height ranges".to_string());
+                }
+            }
+        }
+    }
+    if let Some(aspect) = &config.target_spec.aspect_ratio {
+        let bounds = [
+            ("min", aspect.min),
+            ("max", aspect.max),
+            ("height_to_width_min", aspect.height_to_width_min),
+            ("height_to_width_max", aspect.height_to_width_max),
+        ];
+        for (name, bound) in bounds {
+            if let Some(ratio) = bound {
+                if !(ratio > 0.0 && ratio.is_finite()) {
+                    return Err(format!("aspect_ratio.{} must be positive, got {}", name, ratio));
+                }
+            }
+        }
+        // Both pairs constrain width / height; together they must leave some ratio open
+        let (lowest, highest) = aspect.width_to_height_bounds();
+        if lowest > highest {
+            return Err("aspect_ratio bounds do not overlap".to_string());
+        }
+        if config.target_spec.pixels.as_ref().is_some_and(|pixels| pixels.square) && !(lowest..=highest).contains(&1.0) {
+            return Err("pixels square conflicts with the aspect_ratio bounds".to_string());
+        }
+    }
+    if let Some(scan) = &config.target_spec.document_scan {
+        if !(scan.min_area > 0.0 && scan.min_area <= 1.0) {
+            return Err(format!("document_scan.min_area must be in (0, 1], got {}", scan.min_area));
+        }
+    }
+    if let Some(rotate) = &config.target_spec.auto_rotate {
+        if !(0.0..=1.0).contains(&rotate.min_confidence) {
+            return Err(format!("auto_rotate.min_confidence must be in [0, 1], got {}", rotate.min_confidence));
+        }
+    }
+    for (name, size) in [("dimensions_cm", &config.target_spec.dimensions_cm), ("dimensions_mm", &config.target_spec.dimensions_mm)] {
+        let Some(size) = size else { continue };
+        if size.width.is_none() && size.height.is_none() {
+            return Err(format!("{} needs a width or a height", name));
+        }
+        for length in [size.width, size.height].into_iter().flatten() {
+            if !(length > 0.0 && length.is_finite()) {
+                return Err(format!("{} must be positive, got {}", name, length));
+            }
+        }
+    }
+    if config.target_spec.integer_scaling && config.target_spec.fit_mode == FitMode::Cover {
+        return Err("integer_scaling pads to the target and cannot be combined with fit_mode Cover".to_string());
+    }
+    if config.target_spec.integer_scaling && config.target_spec.linear_light_resize == Some(true) {
+        return Err("linear_light_resize has no effect with integer_scaling, which copies pixels without blending".to_string());
+    }
+    if config.target_spec.flexible_dimensions {
+        let spec = &config.target_spec;
+        if let Some((width, height)) = spec.pixels.as_ref().and_then(|pixels| pixels.width.zip(pixels.height)) {
+            return Err(format!("flexible_dimensions needs a pixel size that can change, but pixels fixes it at {}x{}", width, height));
+        }
+        if spec.dimensions_cm.is_some() || spec.dimensions_mm.is_some() {
+            return Err("flexible_dimensions cannot be combined with dimensions_cm or dimensions_mm, which fix the pixel size".to_string());
+        }
+    }
+    if config.target_spec.on_color_mismatch == ColorMismatchPolicy::Convert
+        && config.target_spec.color_mode != Some(ColorMode::Grayscale)
+    {
+        return Err("on_color_mismatch convert requires color_mode grayscale".to_string());
+    }
+    if let Some(dpi) = config.target_spec.min_effective_dpi {
+        let resolution = config.target_spec.resolution_px_per_inch.unwrap_or(150);
+        if config.target_spec.dimensions_cm.is_none() && config.target_spec.dimensions_mm.is_none() {
+            return Err("min_effective_dpi requires dimensions_cm or dimensions_mm".to_string());
+        }
+        if dpi > resolution {
+            return Err(format!("min_effective_dpi {} exceeds resolution_px_per_inch {}", dpi, resolution));
+        }
+    }
+    if config.target_spec.on_low_resolution == ColorMismatchPolicy::Convert {
+        return Err("on_low_resolution convert is not supported".to_string());
+    }
+    if let Some(background) = &config.target_spec.background {
+        if background.required_rgb().is_none() {
+            return Err(format!("background.required must be a #RRGGBB color, got {:?}", background.required));
+        }
+    }
+    if let Some(canvas) = &config.target_spec.canvas {
+        check_canvas(canvas, &config.target_spec)?;
+    }
+    if let Some(border) = &config.target_spec.border {
+        check_border(border, &config.target_spec)?;
+    }
+    if let Some(stamp) = &config.target_spec.timestamp_overlay {
+        check_timestamp_overlay(stamp)?;
+    }
+    if let Some(dpi) = config.target_spec.pdf_rasterize_dpi.filter(|dpi| !(MIN_RASTERIZE_DPI..=MAX_RASTERIZE_DPI).contains(dpi)) {
+        return Err(format!(
+            "pdf_rasterize_dpi must be between {} and {}, got {}",
+            MIN_RASTERIZE_DPI, MAX_RASTERIZE_DPI, dpi
+        ));
+    }
+    if let Some(factor) = config.target_spec.pre_reduce_above.filter(|&factor| factor != 0.0 && !(2.0..).contains(&factor)) {
+        return Err(format!("pre_reduce_above must be 0 or at least 2, got {}", factor));
+    }
+    if let Some(gamma) = config.target_spec.gamma.filter(|gamma| !(MIN_GAMMA..=MAX_GAMMA).contains(gamma)) {
+        return Err(format!("gamma must be between {} and {}, got {}", MIN_GAMMA, MAX_GAMMA, gamma));
+    }
+    if let Some(clahe) = &config.target_spec.clahe {
+        if config.target_spec.auto_contrast != Some(AutoContrast::Clahe) {
+            return Err("clahe settings need auto_contrast \"clahe\"".to_string());
+        }
+        if clahe.tile_size < 8 {
+            return Err(format!("clahe.tile_size must be at least 8, got {}", clahe.tile_size));
+        }
+        if !(1.0..).contains(&clahe.clip_limit) {
+            return Err(format!("clahe.clip_limit must be at least 1, got {}", clahe.clip_limit));
+        }
+    }
+    if config.target_spec.despeckle.as_ref().is_some_and(|despeckle| despeckle.max_size == 0) {
+        return Err("despeckle.max_size must be at least 1".to_string());
+    }
+    if let Some(angle) = config.target_spec.deskew_max_angle.filter(|angle| !(*angle > 0.0 && *angle <= 45.0)) {
+        return Err(format!("deskew_max_angle must be in (0, 45], got {}", angle));
+    }
+    if let Some(edges) = config.target_spec.scanner_edges.as_ref().filter(|edges| !(edges.max_crop > 0.0 && edges.max_crop <= 0.25)) {
+        return Err(format!("scanner_edges.max_crop must be in (0, 0.25], got {}", edges.max_crop));
+    }
+    if let Some(max_ink) = config.target_spec.blank_page_max_ink.filter(|max_ink| !(0.0..1.0).contains(max_ink)) {
+        return Err(format!("blank_page_max_ink must be in [0, 1), got {}", max_ink));
+    }
+    if let Some(similarity) = config.target_spec.duplicate_page_min_similarity.filter(|similarity| !(*similarity > 0.0 && *similarity <= 1.0)) {
+        return Err(format!("duplicate_page_min_similarity must be in (0, 1], got {}", similarity));
+    }
+    if let Some(colors) = config.target_spec.max_pdf_colors {
+        if !(2..=256).contains(&colors) {
+            return Err(format!("max_pdf_colors must be between 2 and 256, got {}", colors));
+        }
+        if config.target_spec.pdf_rasterize_dpi.is_some() {
+            return Err("max_pdf_colors cannot be combined with pdf_rasterize_dpi, whose JPEG pages would bring colors back".to_string());
+        }
+    }
+    if config.target_spec.pdf_a && !config.target_spec.allowed_document_formats().contains(&"PDF") {
+        return Err("pdf_a needs PDF among the allowed document formats".to_string());
+    }
+    if let Some(ink) = &config.target_spec.ink_color {
+        if ink.allowed.is_empty() {
+            return Err("ink_color.allowed must not be empty".to_string());
+        }
+        if ink.on_mismatch == ColorMismatchPolicy::Convert {
+            return Err("ink_color.on_mismatch convert is not supported".to_string());
+        }
+        for color in &ink.allowed {
+            let Some(range) = color.range() else {
+                return Err(format!(
+                    "ink_color.allowed has unknown color {}, expected {} or a range",
+                    serde_json::json!(color),
+                    InkColor::BUILT_IN.join(", ")
+                ));
+            };
+            if range.hue_min.is_some() != range.hue_max.is_some() {
+                return Err(format!("ink_color range {} needs both hue_min and hue_max", range.name));
+            }
+            let hues_valid = [range.hue_min, range.hue_max].into_iter().flatten().all(|hue| (0.0..=360.0).contains(&hue));
+            let saturation_valid = 0.0 <= range.saturation_min
+                && range.saturation_min <= range.saturation_max
+                && range.saturation_max <= 1.0;
+            if !hues_valid || !saturation_valid {
+                return Err(format!(
+                    "ink_color range {} must have hues in [0, 360] and saturations in [0, 1], min before max",
+                    range.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Any time fills the placeholders, so a sample one shows up bad ones and characters the
+// stamp's font has no glyph for
+fn check_timestamp_overlay(stamp: &TimestampOverlaySpec) -> Result<(), String> {
+    let sample = LocalTime { year: 2000, month: 1, day: 1, hour: 0, minute: 0, second: 0 };
+    let text = format_time(&stamp.format, &sample).map_err(|placeholder| {
+        format!(
+            "timestamp_overlay.format {:?} has an unknown placeholder {}, expected %Y, %m, %d, %H, %M, %S or %%",
+            stamp.format, placeholder
+        )
+    })?;
+    if text.is_empty() || !text.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return Err(format!("timestamp_overlay.format must be non-empty printable ASCII, got {:?}", stamp.format));
+    }
+    for (name, color) in [("color", &stamp.color), ("box_color", &stamp.box_color)] {
+        if parse_rgb(color).is_none() {
+            return Err(format!("timestamp_overlay.{} must be a #RRGGBB color, got {:?}", name, color));
+        }
+    }
+    if !(0.0..=1.0).contains(&stamp.box_opacity) {
+        return Err(format!("timestamp_overlay.box_opacity must be in [0, 1], got {}", stamp.box_opacity));
+    }
+    Ok(())
+}
+
+// The border is checked against the shortest edge the output can have, when the spec
+// bounds it
+fn check_border(border: &BorderSpec, spec: &DocumentSpec) -> Result<(), String> {
+    if border.width_px == 0 {
+        return Err("border.width_px must be at least 1".to_string());
+    }
+    if parse_rgb(&border.color).is_none() {
+        return Err(format!("border.color must be a #RRGGBB color, got {:?}", border.color));
+    }
+    if spec.flexible_dimensions {
+        return Err("flexible_dimensions cannot be combined with border, which would be rescaled with the image".to_string());
+    }
+    let pixels = spec.pixels.as_ref();
+    let edges = [
+        spec.canvas.as_ref().map(|canvas| canvas.width.min(canvas.height)),
+        pixels.and_then(|pixels| pixels.width),
+        pixels.and_then(|pixels| pixels.height),
+        pixels.and_then(|pixels| pixels.max_width),
+        pixels.and_then(|pixels| pixels.max_height),
+        pixels.and_then(|pixels| pixels.max.as_ref()).map(|max| max.width.min(max.height)),
+    ];
+    match edges.into_iter().flatten().min() {
+        Some(edge) if border.width_px as f32 > edge as f32 * MAX_BORDER_SHARE => Err(format!(
+            "border.width_px {} is more than a tenth of the {}px output edge",
+            border.width_px, edge
+        )),
+        _ => Ok(()),
+    }
+}
+
+// The canvas is the output, so it must satisfy every pixel limit on its own
+fn check_canvas(canvas: &CanvasSpec, spec: &DocumentSpec) -> Result<(), String> {
+    let (width, height) = (canvas.width, canvas.height);
+    if width == 0 || height == 0 {
+        return Err(format!("canvas must have a positive size, got {}x{}", width, height));
+    }
+    if parse_rgb(&canvas.background).is_none() {
+        return Err(format!("canvas.background must be a #RRGGBB color, got {:?}", canvas.background));
+    }
+    for (name, percent) in [("x_percent", canvas.x_percent), ("y_percent", canvas.y_percent)] {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(format!("canvas.{} must be in [0, 100], got {}", name, percent));
+        }
+    }
+    if !(canvas.scale > 0.0 && canvas.scale <= 1.0) {
+        return Err(format!("canvas.scale must be in (0, 1], got {}", canvas.scale));
+    }
+    if spec.flexible_dimensions {
+        return Err("flexible_dimensions cannot be combined with canvas, which fixes the pixel size".to_string());
+    }
+    let outside = |limit: &str| Err(format!("canvas {}x{} breaks the spec's {}", width, height, limit));
+    if let Some(pixels) = &spec.pixels {
+        let range = pixels.range()?;
+        let exact = pixels.width.is_none_or(|w| w == width) && pixels.height.is_none_or(|h| h == height);
+        if !exact || !(range.min_width..=range.max_width).contains(&width) || !(range.min_height..=range.max_height).contains(&height) {
+            return outside("pixel size");
+        }
+        if pixels.square && width != height {
+            return outside("square requirement");
+        }
+        let total = width as u64 * height as u64;
+        if pixels.max_total_pixels.is_some_and(|max| total > max as u64) || pixels.min_total_pixels.is_some_and(|min| total < min as u64) {
+            return outside("total pixel limits");
+        }
+    }
+    if let Some(aspect) = &spec.aspect_ratio {
+        let (lowest, highest) = aspect.width_to_height_bounds();
+        if !(lowest..=highest).contains(&(width as f32 / height as f32)) {
+            return outside("aspect_ratio bounds");
+        }
+    }
+    let turned = match spec.print_orientation {
+        PrintOrientation::Portrait => width > height,
+        PrintOrientation::Landscape => height > width,
+        PrintOrientation::Auto => false,
+    };
+    if turned {
+        return outside("print_orientation");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn photoshop_qualities_map_onto_the_libjpeg_scale() {
+        let native = |scale: QualityScale| [0, 10, 20, 50, 55, 80, 100].map(|quality| scale.native_quality(quality));
+        assert_eq!(native(QualityScale::Native), [1, 10, 20, 50, 55, 80, 100]);
+        assert_eq!(native(QualityScale::IrfanView), native(QualityScale::Native));
+        assert_eq!(native(QualityScale::Photoshop), [12, 32, 44, 72, 75, 89, 98]);
+        let photoshop: Vec<u8> = (0..=100).map(|quality| QualityScale::Photoshop.native_quality(quality)).collect();
+        assert!(photoshop.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn quality_scale_needs_jpeg_output() {
+        assert_eq!(
+            parse_config(&with_spec(r#""quality_scale":"Photoshop","image_formats":["PNG"]"#)).unwrap_err().to_string(),
+            "Invalid config: quality_scale needs JPEG among the allowed image formats"
+        );
+    }
+
+    #[test]
+    fn quality_ladders_are_checked() {
+        assert!(parse_config(&with_spec(r#""quality_ladder":[85,75,65,55,45]"#)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""quality_ladder":[85,0]"#)).unwrap_err().to_string(),
+            "Invalid config: quality_ladder qualities must be between 1 and 100, got 0"
+        );
+    }
+
+    #[test]
+    fn performance_profiles_fill_in_what_the_spec_leaves_unset() {
+        use crate::core::test_support::spec_from;
+        let settings = |json: serde_json::Value| {
+            let spec = spec_from(json);
+            (spec.png_optimized(), spec.linear_light(), spec.pre_reduce_threshold())
+        };
+        assert_eq!(settings(serde_json::json!({})), (false, false, 3.0));
+        assert_eq!(settings(serde_json::json!({ "performance_profile": "Fast" })), (false, false, 2.0));
+        assert_eq!(settings(serde_json::json!({ "performance_profile": "Best" })), (true, true, 6.0));
+        // Settings given in the spec win, and Best leaves out what the spec rules out
+        let overridden = serde_json::json!({ "performance_profile": "Best", "png_optimize": false, "pre_reduce_above": 0 });
+        assert_eq!(settings(overridden), (false, true, 0.0));
+        let constrained = serde_json::json!({ "performance_profile": "Best", "png_profile": "RGB8", "integer_scaling": true });
+        assert_eq!(settings(constrained), (false, false, 6.0));
+        assert_eq!(settings(serde_json::json!({ "performance_profile": "Fast", "linear_light_resize": true })), (false, true, 2.0));
+
+        assert_eq!(PerformanceProfile::Fast.qualities(), [90, 70, 50, 30, 10]);
+        assert_eq!(PerformanceProfile::Balanced.qualities(), [90, 80, 70, 60, 50, 40, 30, 20, 10]);
+        assert_eq!(PerformanceProfile::Best.qualities().len(), 17);
+        assert_eq!(PerformanceProfile::Fast.resize_filter(), image::imageops::FilterType::Triangle);
+    }
+
+    #[test]
+    fn pre_reduce_above_must_be_off_or_at_least_two() {
+        assert_eq!(
+            parse_config(&with_spec(r#""pre_reduce_above":1.5"#)).unwrap_err().to_string(),
+            "Invalid config: pre_reduce_above must be 0 or at least 2, got 1.5"
+        );
+        assert!(parse_config(&with_spec(r#""pre_reduce_above":0"#)).is_ok());
+    }
+
+    #[test]
+    fn size_strings_are_read_as_kilobytes() {
+        assert_eq!(parse_size_kb("50KB"), Ok(50));
+        assert_eq!(parse_size_kb(" 512 kib "), Ok(512));
+        assert_eq!(parse_size_kb("1MB"), Ok(1024));
+        assert_eq!(parse_size_kb("1.5 MiB"), Ok(1536));
+        assert_eq!(parse_size_kb("51200b"), Ok(50));
+        assert_eq!(parse_size_kb("20"), Ok(20));
+        assert_eq!(parse_size_kb("1000B").unwrap_err(), r#"size "1000B" is not a whole number of KB"#);
+        assert_eq!(parse_size_kb("50 GB").unwrap_err(), r#"size "50 GB" is not a number with a unit of B, KB, KiB, MB or MiB, like "50KB""#);
+        assert!(parse_size_kb("KB").is_err() && parse_size_kb("-5KB").is_err() && parse_size_kb("").is_err());
+        assert_eq!(parse_size_kb("4095MB"), Ok(4095 * 1024));
+        assert_eq!(parse_size_kb("4096MB").unwrap_err(), r#"size "4096MB" is over the 4194303 KB limit"#);
+
+        let config = |size_kb: &str| {
+            parse_config(&format!(r#"{{"exam_type":"NEET","document_type":"photo","target_spec":{{"format":["JPEG"],"size_kb":{}}}}}"#, size_kb))
+                .map(|config| (config.target_spec.size_kb.min, config.target_spec.size_kb.max))
+        };
+        assert_eq!(config(r#""1MB""#), Ok((None, 1024)));
+        assert_eq!(config(r#"{"min":"10 KiB","max":"0.5mb"}"#), Ok((Some(10), 512)));
+        assert_eq!(config(r#"{"min":10,"max":100}"#), Ok((Some(10), 100)));
+        assert_eq!(config(r#"{"max":4194303}"#), Ok((None, 4194303)));
+        assert!(config(r#"{"max":4194304}"#).unwrap_err().to_string().contains("size 4194304 KB is over the 4194303 KB limit"));
+        assert!(config(r#""4096MB""#).is_err());
+        assert!(config(r#"{"max":"lots"}"#).unwrap_err().to_string().starts_with(r#"Invalid config: size "lots" is not a number"#));
+    }
+
+    #[test]
+    fn sizes_are_read_from_signed_and_float_numbers() {
+        // serde-wasm-bindgen passes JS numbers as i64 or f64, never u64 as serde_json does
+        use serde::de::value::{Error, MapDeserializer};
+        let size = |value| SizeSpec::deserialize(MapDeserializer::<_, Error>::new([("max", value)].into_iter())).map(|size| size.max);
+        let float_size = |value| SizeSpec::deserialize(MapDeserializer::<_, Error>::new([("max", value)].into_iter())).map(|size| size.max);
+        assert_eq!(size(50i64), Ok(50));
+        assert_eq!(size(-5i64).unwrap_err().to_string(), "size -5 KB is negative");
+        assert_eq!(size(4194304i64).unwrap_err().to_string(), "size 4194304 KB is over the 4194303 KB limit");
+        assert_eq!(float_size(20.0f64), Ok(20));
+        assert_eq!(float_size(20.5f64).unwrap_err().to_string(), "size 20.5 KB is not a whole number of KB");
+        assert!(float_size(f64::INFINITY).is_err() && float_size(-1.0f64).is_err() && float_size(1e12f64).is_err());
+    }
+
+    fn with_spec(spec: &str) -> String {
+        format!(r#"{{"exam_type":"NEET","document_type":"photo","target_spec":{{"format":["JPEG"],"size_kb":{{"max":100}},{}}}}}"#, spec)
+    }
+
+    #[test]
+    fn configs_need_a_target_spec_with_a_format() {
+        assert!(matches!(parse_config("{}"), Err(ConvertError::InvalidConfig(_))));
+        let no_format = r#"{"exam_type":"NEET","document_type":"photo","target_spec":{"format":[],"size_kb":{"max":100}}}"#;
+        assert_eq!(parse_config(no_format).unwrap_err(), ConvertError::NoTargetFormat);
+        assert_eq!(parse_config(no_format).unwrap_err().code(), Some("no_target_format"));
+        assert!(parse_config(&no_format.replace(r#""format":[]"#, r#""on_missing_format":"Jpeg""#)).is_ok());
+    }
+
+    #[test]
+    fn pixel_bounds_are_checked() {
+        // A zero or one pixel bound is a typo, not a 1x1 output
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"max_width":0,"max_height":800}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels max_width must be at least 16, got 0"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"max":{"width":600,"height":1}}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels max.height must be at least 16, got 1"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"width":1,"height":1}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels width must be at least 16, got 1"
+        );
+        assert!(parse_config(&with_spec(r#""pixels":{"min_width":0,"max_width":16}"#)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"min":{"width":600,"height":10},"max":{"width":500,"height":20}}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels min width 600 exceeds max width 500"
+        );
+    }
+
+    #[test]
+    fn pixel_totals_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"width":800,"height":600,"max_total_pixels":300000}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels 800x600 (480000 pixels) exceeds max_total_pixels 300000"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"max_width":400,"max_height":400,"min_total_pixels":200000}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels 400x400 (160000 pixels) is below min_total_pixels 200000"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"min_total_pixels":5000,"max_total_pixels":4000}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels min_total_pixels 5000 exceeds max_total_pixels 4000"
+        );
+        assert!(parse_config(&with_spec(r#""pixels":{"width":400,"height":750,"max_total_pixels":300000}"#)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"max_total_pixels":100}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels max_total_pixels must be at least 256 (16x16), got 100"
+        );
+    }
+
+    #[test]
+    fn max_megapixels_is_checked_against_the_pixel_bounds() {
+        assert_eq!(
+            parse_config(&with_spec(r#""max_megapixels":0.0001"#)).unwrap_err().to_string(),
+            "Invalid config: max_megapixels 0.0001 allows fewer than 256 pixels (16x16)"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"width":800,"height":600},"max_megapixels":0.3"#)).unwrap_err().to_string(),
+            "Invalid config: pixels 800x600 (480000 pixels) exceeds max_megapixels 0.3"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"min_total_pixels":500000},"max_megapixels":0.3"#)).unwrap_err().to_string(),
+            "Invalid config: pixels min_total_pixels 500000 exceeds max_megapixels 0.3"
+        );
+        assert!(parse_config(&with_spec(r#""pixels":{"min_width":400,"min_height":500},"max_megapixels":0.3"#)).is_ok());
+    }
+
+    #[test]
+    fn square_pixels_must_fit_the_other_bounds() {
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"width":350,"height":360,"square":true}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels square requires width to equal height, got 350x360"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"min_width":400,"max_height":300,"square":true}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels square does not fit the width and height ranges"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"square":true},"aspect_ratio":{"min":1.2}"#)).unwrap_err().to_string(),
+            "Invalid config: pixels square conflicts with the aspect_ratio bounds"
+        );
+    }
+
+    #[test]
+    fn aspect_ratio_bounds_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""aspect_ratio":{"min":0}"#)).unwrap_err().to_string(),
+            "Invalid config: aspect_ratio.min must be positive, got 0"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""aspect_ratio":{"min":0.8,"height_to_width_min":1.5}"#)).unwrap_err().to_string(),
+            "Invalid config: aspect_ratio bounds do not overlap"
+        );
+    }
+
+    #[test]
+    fn dct_method_only_accepts_islow() {
+        assert!(parse_config(&with_spec(r#""dct_method":"Islow""#)).is_ok());
+        let error = parse_config(&with_spec(r#""dct_method":"Ifast""#)).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid config: unknown variant `Ifast`, expected `Islow`"), "{}", error);
+    }
+
+    #[test]
+    fn color_mismatch_conversion_needs_grayscale() {
+        assert!(parse_config(&with_spec(r#""color_mode":"grayscale","on_color_mismatch":"convert""#)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""color_mode":"color","on_color_mismatch":"convert""#)).unwrap_err().to_string(),
+            "Invalid config: on_color_mismatch convert requires color_mode grayscale"
+        );
+    }
+
+    #[test]
+    fn print_dimensions_need_a_width_or_height() {
+        assert_eq!(
+            parse_config(&with_spec(r#""dimensions_cm":{}"#)).unwrap_err().to_string(),
+            "Invalid config: dimensions_cm needs a width or a height"
+        );
+        assert!(parse_config(&with_spec(r#""dimensions_mm":{"height":45}"#)).is_ok());
+    }
+
+    #[test]
+    fn min_effective_dpi_needs_a_reachable_print_size() {
+        assert_eq!(
+            parse_config(&with_spec(r#""min_effective_dpi":200"#)).unwrap_err().to_string(),
+            "Invalid config: min_effective_dpi requires dimensions_cm or dimensions_mm"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""min_effective_dpi":200,"dimensions_cm":{"width":3.5,"height":4.5}"#)).unwrap_err().to_string(),
+            "Invalid config: min_effective_dpi 200 exceeds resolution_px_per_inch 150"
+        );
+    }
+
+    #[test]
+    fn flexible_dimensions_need_a_pixel_size_that_can_change() {
+        assert_eq!(
+            parse_config(&with_spec(r#""flexible_dimensions":true,"pixels":{"width":300,"height":400}"#)).unwrap_err().to_string(),
+            "Invalid config: flexible_dimensions needs a pixel size that can change, but pixels fixes it at 300x400"
+        );
+        assert!(parse_config(&with_spec(r#""flexible_dimensions":true,"pixels":{"max_width":2000}"#)).is_ok());
+    }
+
+    #[test]
+    fn integer_scaling_conflicts_are_rejected() {
+        assert_eq!(
+            parse_config(&with_spec(r#""integer_scaling":true,"fit_mode":"Cover""#)).unwrap_err().to_string(),
+            "Invalid config: integer_scaling pads to the target and cannot be combined with fit_mode Cover"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""integer_scaling":true,"linear_light_resize":true"#)).unwrap_err().to_string(),
+            "Invalid config: linear_light_resize has no effect with integer_scaling, which copies pixels without blending"
+        );
+    }
+
+    #[test]
+    fn posterize_levels_must_be_at_least_two() {
+        let config = r#"{"exam_type":"NEET","document_type":"photo","target_spec":{"format":["JPEG"],"size_kb":{"max":100},"posterize_levels":1}}"#;
+        assert_eq!(
+            parse_config(config).unwrap_err().to_string(),
+            "Invalid config: posterize_levels must be at least 2, got 1"
+        );
+    }
+
+    #[test]
+    fn ink_colors_are_checked() {
+        assert!(parse_config(&with_spec(
+            r#""ink_color":{"allowed":["black",{"name":"violet","hue_min":260,"hue_max":300,"saturation_min":0.2}]}"#
+        ))
+        .is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""ink_color":{"allowed":["purple"]}"#)).unwrap_err().to_string(),
+            r#"Invalid config: ink_color.allowed has unknown color "purple", expected black, blue, red, green or a range"#
+        );
+    }
+
+    #[test]
+    fn background_colors_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""background":{"required":"white"}"#)).unwrap_err().to_string(),
+            r#"Invalid config: background.required must be a #RRGGBB color, got "white""#
+        );
+    }
+
+    #[test]
+    fn filename_templates_are_checked() {
+        let with_template = |template: &str| {
+            format!(r#"{{"exam_type":"NEET","document_type":"photo","target_spec":{{"format":["JPEG"],"size_kb":{{"max":100}}}},"filename_template":{}}}"#, template)
+        };
+        assert!(parse_config(&with_template(r#""{document_type}_{capture_date}""#)).is_ok());
+        assert_eq!(
+            parse_config(&with_template(r#""{document_type}_{date}""#)).unwrap_err().to_string(),
+            r#"Invalid config: filename_template "{document_type}_{date}" has an unknown placeholder, expected {document_type}, {name} or {capture_date}"#
+        );
+        assert_eq!(
+            parse_config(&with_template(r#""../{name}""#)).unwrap_err().to_string(),
+            r#"Invalid config: filename_template "../{name}" cannot contain path separators"#
+        );
+    }
+
+    #[test]
+    fn timestamp_overlays_are_checked() {
+        assert!(parse_config(&with_spec(r#""timestamp_overlay":{"format":"%d.%m.%Y","source":"Exif"}"#)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""timestamp_overlay":{"format":"%Y %B"}"#)).unwrap_err().to_string(),
+            r#"Invalid config: timestamp_overlay.format "%Y %B" has an unknown placeholder %B, expected %Y, %m, %d, %H, %M, %S or %%"#
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""timestamp_overlay":{"format":"%d·%m"}"#)).unwrap_err().to_string(),
+            r#"Invalid config: timestamp_overlay.format must be non-empty printable ASCII, got "%d·%m""#
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""timestamp_overlay":{"box_color":"black"}"#)).unwrap_err().to_string(),
+            r#"Invalid config: timestamp_overlay.box_color must be a #RRGGBB color, got "black""#
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""timestamp_overlay":{"box_opacity":1.5}"#)).unwrap_err().to_string(),
+            "Invalid config: timestamp_overlay.box_opacity must be in [0, 1], got 1.5"
+        );
+    }
+
+    #[test]
+    fn canvas_placement_is_checked() {
+        let canvas = r#""canvas":{"width":600,"height":800,"x_percent":50,"y_percent":20,"scale":0.6}"#;
+        assert!(parse_config(&with_spec(canvas)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(&format!(r#"{},"pixels":{{"width":300,"height":400}}"#, canvas))).unwrap_err().to_string(),
+            "Invalid config: canvas 600x800 breaks the spec's pixel size"
+        );
+        assert_eq!(
+            parse_config(&with_spec(&format!(r#"{},"print_orientation":"Landscape""#, canvas))).unwrap_err().to_string(),
+            "Invalid config: canvas 600x800 breaks the spec's print_orientation"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""canvas":{"width":600,"height":800,"x_percent":120}"#)).unwrap_err().to_string(),
+            "Invalid config: canvas.x_percent must be in [0, 100], got 120"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""canvas":{"width":600,"height":800,"scale":0}"#)).unwrap_err().to_string(),
+            "Invalid config: canvas.scale must be in (0, 1], got 0"
+        );
+    }
+
+    #[test]
+    fn border_width_and_color_are_checked() {
+        let border = r#""pixels":{"width":350,"height":450},"border":{"width_px":4}"#;
+        assert!(parse_config(&with_spec(border)).is_ok());
+        assert_eq!(
+            parse_config(&with_spec(r#""pixels":{"width":350,"height":450},"border":{"width_px":40}"#)).unwrap_err().to_string(),
+            "Invalid config: border.width_px 40 is more than a tenth of the 350px output edge"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""border":{"width_px":0}"#)).unwrap_err().to_string(),
+            "Invalid config: border.width_px must be at least 1"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""border":{"width_px":2,"color":"navy"}"#)).unwrap_err().to_string(),
+            "Invalid config: border.color must be a #RRGGBB color, got \"navy\""
+        );
+    }
+
+    #[test]
+    fn clahe_settings_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""auto_contrast":"stretch","clahe":{}"#)).unwrap_err().to_string(),
+            "Invalid config: clahe settings need auto_contrast \"clahe\""
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""auto_contrast":"clahe","clahe":{"clip_limit":0.5}"#)).unwrap_err().to_string(),
+            "Invalid config: clahe.clip_limit must be at least 1, got 0.5"
+        );
+    }
+
+    #[test]
+    fn gamma_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""gamma":0"#)).unwrap_err().to_string(),
+            "Invalid config: gamma must be between 0.3 and 3, got 0"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""gamma":4.5"#)).unwrap_err().to_string(),
+            "Invalid config: gamma must be between 0.3 and 3, got 4.5"
+        );
+        assert!(parse_config(&with_spec(r#""gamma":1.4"#)).is_ok());
+    }
+
+    #[test]
+    fn despeckle_size_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""despeckle":{"max_size":0}"#)).unwrap_err().to_string(),
+            "Invalid config: despeckle.max_size must be at least 1"
+        );
+    }
+
+    #[test]
+    fn deskew_max_angle_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""deskew_max_angle":60"#)).unwrap_err().to_string(),
+            "Invalid config: deskew_max_angle must be in (0, 45], got 60"
+        );
+    }
+
+    #[test]
+    fn scanner_edge_crop_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""scanner_edges":{"max_crop":0.4}"#)).unwrap_err().to_string(),
+            "Invalid config: scanner_edges.max_crop must be in (0, 0.25], got 0.4"
+        );
+    }
+
+    #[test]
+    fn blank_page_max_ink_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""blank_page_max_ink":1.5"#)).unwrap_err().to_string(),
+            "Invalid config: blank_page_max_ink must be in [0, 1), got 1.5"
+        );
+    }
+
+    #[test]
+    fn duplicate_page_similarity_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""duplicate_page_min_similarity":0"#)).unwrap_err().to_string(),
+            "Invalid config: duplicate_page_min_similarity must be in (0, 1], got 0"
+        );
+    }
+
+    #[test]
+    fn auto_rotate_confidence_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""auto_rotate":{"min_confidence":1.5}"#)).unwrap_err().to_string(),
+            "Invalid config: auto_rotate.min_confidence must be in [0, 1], got 1.5"
+        );
+    }
+
+    #[test]
+    fn document_formats_only_support_pdf() {
+        assert_eq!(
+            parse_config(&with_spec(r#""document_formats":["JPEG"]"#)).unwrap_err().to_string(),
+            "Invalid config: document_formats only supports PDF, got JPEG"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""pdf_a":true,"document_formats":[]"#)).unwrap_err().to_string(),
+            "Invalid config: pdf_a needs PDF among the allowed document formats"
+        );
+    }
+
+    #[test]
+    fn pdf_rasterize_dpi_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""pdf_rasterize_dpi":600"#)).unwrap_err().to_string(),
+            "Invalid config: pdf_rasterize_dpi must be between 50 and 300, got 600"
+        );
+    }
+
+    #[test]
+    fn max_pdf_colors_is_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""max_pdf_colors":1"#)).unwrap_err().to_string(),
+            "Invalid config: max_pdf_colors must be between 2 and 256, got 1"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""max_pdf_colors":16,"pdf_rasterize_dpi":150"#)).unwrap_err().to_string(),
+            "Invalid config: max_pdf_colors cannot be combined with pdf_rasterize_dpi, whose JPEG pages would bring colors back"
+        );
+    }
+
+    #[test]
+    fn png_profiles_need_png_output() {
+        assert_eq!(
+            parse_config(&with_spec(r#""png_profile":"RGB8","png_optimize":true"#)).unwrap_err().to_string(),
+            "Invalid config: png_profile cannot be combined with png_optimize, which picks the layout itself"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""png_profile":"Grayscale8""#)).unwrap_err().to_string(),
+            "Invalid config: png_profile needs PNG among the allowed image formats"
+        );
+        assert!(parse_config(&with_spec(r#""png_profile":"Indexed8","image_formats":["PNG"]"#)).is_ok());
+    }
+
+    #[test]
+    fn png_interlacing_needs_png_output() {
+        assert_eq!(
+            parse_config(&with_spec(r#""png_interlaced":true"#)).unwrap_err().to_string(),
+            "Invalid config: png_interlaced needs PNG among the allowed image formats"
+        );
+    }
+
+    #[test]
+    fn transparent_backgrounds_need_png_with_alpha() {
+        assert_eq!(
+            parse_config(&with_spec(r#""transparent_background":true"#)).unwrap_err().to_string(),
+            "Invalid config: transparent_background needs PNG among the allowed image formats"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""transparent_background":true,"image_formats":["PNG"],"png_profile":"RGB8""#)).unwrap_err().to_string(),
+            "Invalid config: png_profile drops the alpha channel transparent_background needs"
+        );
+        assert!(parse_config(&with_spec(r#""transparent_background":true,"image_formats":["JPEG","PNG"]"#)).is_ok());
+    }
+
+    #[test]
+    fn output_color_types_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""output_color_type":"rgba8""#)).unwrap_err().to_string(),
+            "Invalid config: output_color_type rgba8 cannot be met by JPEG output"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""output_color_type":"rgb8","png_optimize":true"#)).unwrap_err().to_string(),
+            "Invalid config: output_color_type cannot be combined with png_optimize, which may write indexed color"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""output_color_type":"rgba8","image_formats":["PNG"],"png_profile":"Indexed8""#)).unwrap_err().to_string(),
+            "Invalid config: output_color_type cannot be combined with png_profile, which fixes the color type itself"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""output_color_type":"rgb8","image_formats":["PNG"],"transparent_background":true"#)).unwrap_err().to_string(),
+            "Invalid config: output_color_type rgb8 drops the alpha channel transparent_background needs"
+        );
+        assert!(parse_config(&with_spec(r#""output_color_type":"rgb8","image_formats":["JPEG","PNG"]"#)).is_ok());
+    }
+
+    #[test]
+    fn output_mime_overrides_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""output_mime_override":"image/jpg; charset=x""#)).unwrap_err().to_string(),
+            "Invalid config: output_mime_override must be a type/subtype MIME type, got \"image/jpg; charset=x\""
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""output_mime_override":"image/jpg","image_formats":["JPEG","PNG"]"#)).unwrap_err().to_string(),
+            "Invalid config: output_mime_override would label every output format, so only one may be allowed, got JPEG, PNG"
+        );
+        assert!(parse_config(&with_spec(r#""output_mime_override":"image/jpg""#)).is_ok());
+    }
+
+    #[test]
+    fn required_color_types_and_bit_depths_are_checked() {
+        assert_eq!(
+            parse_config(&with_spec(r#""required_bit_depth":16"#)).unwrap_err().to_string(),
+            "Invalid config: required_bit_depth must be 8, 24 or 32 bits per pixel, got 16"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""required_color_type":"cmyk""#)).unwrap_err().to_string(),
+            "Invalid config: required_color_type cmyk cannot be written; only grayscale, rgb and rgba can"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""required_color_type":"rgb","required_bit_depth":8"#)).unwrap_err().to_string(),
+            "Invalid config: required_bit_depth 8 does not match required_color_type rgb, which is 24-bit"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""required_bit_depth":32"#)).unwrap_err().to_string(),
+            "Invalid config: required_bit_depth 32 cannot be met by JPEG output"
+        );
+        assert_eq!(
+            parse_config(&with_spec(r#""required_color_type":"rgb","output_color_type":"rgb8""#)).unwrap_err().to_string(),
+            "Invalid config: required_color_type rgb cannot be combined with png_profile or output_color_type, which fix the layout themselves"
+        );
+        assert!(parse_config(&with_spec(r#""required_color_type":"grayscale","required_bit_depth":8"#)).is_ok());
+    }
+
+    #[test]
+    fn attempt_and_decode_limits_must_be_positive() {
+        let zero_attempts = r#"{"exam_type":"NEET","document_type":"photo","target_spec":{"format":["JPEG"],"size_kb":{"max":100}},"max_attempts":0}"#;
+        assert_eq!(parse_config(zero_attempts).unwrap_err().to_string(), "Invalid config: max_attempts must be at least 1");
+        let zero_pixels = r#"{"exam_type":"NEET","document_type":"photo","target_spec":{"format":["JPEG"],"size_kb":{"max":100}},"max_decoded_pixels":0}"#;
+        assert_eq!(parse_config(zero_pixels).unwrap_err().to_string(), "Invalid config: max_decoded_pixels must be at least 1");
+    }
+}
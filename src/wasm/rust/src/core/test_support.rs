@@ -0,0 +1,157 @@
+// Several helpers are only used by tests that need every default codec
+#![cfg_attr(not(all(feature = "jpeg", feature = "png", feature = "webp")), allow(dead_code))]
+
+use super::{ConversionConfig, DocumentSpec};
+
+// Merges `json` over a minimal JPEG, 100KB spec
+pub fn spec_from(json: serde_json::Value) -> DocumentSpec {
+    let mut base = serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } });
+    base.as_object_mut().unwrap().extend(json.as_object().unwrap().clone());
+    serde_json::from_value(base).unwrap()
+}
+
+pub fn config_from(spec: serde_json::Value) -> ConversionConfig {
+    serde_json::from_value(serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": spec_from(spec),
+    }))
+    .unwrap()
+}
+
+pub fn gradient(width: u32, height: u32) -> image::RgbImage {
+    image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+// A scanned page: off-white paper with faint scanner noise and a few dust specks, and
+// `lines` lines of dark "text" across it
+pub fn scanned_paper(width: u32, height: u32, lines: u32) -> image::RgbImage {
+    image::RgbImage::from_fn(width, height, |x, y| {
+        let line = (1..=lines).any(|line| y.abs_diff(height * line / (lines + 1)) < height / 60 && x > width / 10 && x < width * 9 / 10);
+        let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761) >> 28;
+        if line {
+            image::Rgb([30, 30, 40])
+        } else if x % 97 == 13 && y % 89 == 7 {
+            image::Rgb([90, 90, 90])
+        } else {
+            image::Rgb([236 + noise as u8, 232 + noise as u8, 220 + noise as u8])
+        }
+    })
+}
+
+// `scanned_paper` photographed turned `degrees` clockwise, with bare paper showing
+// where the turned page leaves the frame
+pub fn tilted_paper(width: u32, height: u32, lines: u32, degrees: f32) -> image::RgbImage {
+    let page = scanned_paper(width, height, lines);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    image::RgbImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+        let (page_x, page_y) = (center_x + dx * cos + dy * sin, center_y - dx * sin + dy * cos);
+        if page_x >= 0.0 && page_y >= 0.0 && page_x < width as f32 && page_y < height as f32 {
+            *page.get_pixel(page_x as u32, page_y as u32)
+        } else {
+            image::Rgb([236, 232, 220])
+        }
+    })
+}
+
+#[cfg(feature = "png")]
+pub fn encode_png(img: &image::DynamicImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).unwrap();
+    png
+}
+
+// An animated WebP of `frames` frames, each the same lossless 1×1 transparent pixel,
+// `padding` bytes of XMP making up the rest of the file
+pub fn animated_webp(frames: u32, padding: usize) -> Vec<u8> {
+    let chunk = |tag: &[u8], body: &[u8]| {
+        let mut chunk = [tag, &(body.len() as u32).to_le_bytes()[..], body].concat();
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    };
+    let pixel = chunk(b"VP8L", &[0x2f, 0x00, 0x00, 0x00, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88, 0x88, 0xfe, 0x07, 0x00]);
+    // Animation, alpha and XMP flags, then a 1×1 canvas
+    let mut body = chunk(b"VP8X", &[0x16, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    body.extend(chunk(b"ANIM", &[0, 0, 0, 0, 0, 0]));
+    for _ in 0..frames {
+        body.extend(chunk(b"ANMF", &[&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0][..], &pixel].concat()));
+    }
+    body.extend(chunk(b"XMP ", &vec![b' '; padding]));
+    [b"RIFF", &(body.len() as u32 + 4).to_le_bytes()[..], b"WEBP", &body].concat()
+}
+
+#[cfg(feature = "jpeg")]
+pub fn jpeg_with_exif(tiff: &[u8]) -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    image::DynamicImage::ImageRgb8(gradient(8, 8))
+        .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(80))
+        .unwrap();
+    let mut segment = b"Exif\0\0".to_vec();
+    segment.extend_from_slice(tiff);
+    let mut out = jpeg[..2].to_vec();
+    out.extend_from_slice(&[0xff, 0xe1]);
+    out.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(&segment);
+    out.extend_from_slice(&jpeg[2..]);
+    out
+}
+
+// A little-endian TIFF whose IFD0 points to an EXIF IFD holding DateTimeOriginal
+pub fn tiff_with_capture_date(date: &[u8; 19]) -> Vec<u8> {
+    let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+        [&tag.to_le_bytes()[..], &kind.to_le_bytes(), &count.to_le_bytes(), &value.to_le_bytes()].concat()
+    };
+    let mut out = b"II*\0".to_vec();
+    out.extend_from_slice(&8u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&entry(0x8769, 4, 1, 26));
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&entry(0x9003, 2, 20, 44));
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(date);
+    out.push(0);
+    out
+}
+
+// An upright PDF of `(jpeg, width_px, height_px)` scans on pages of the given sizes
+pub fn jpeg_pdf(scans: &[(Vec<u8>, u32, u32)], sizes: &[(f32, f32)]) -> Vec<u8> {
+    let pages: Vec<_> = scans.iter().map(|(jpeg, width, height)| (super::pdf::PageImage::Jpeg(jpeg.clone()), *width, *height)).collect();
+    super::pdf::write_pdf(&pages, sizes, &[], false)
+}
+
+// A 300×200 signature in a hairline pen: a one-pixel stroke, steep enough in places to
+// break into diagonal steps, with a loop and the dot of an i beside it
+pub fn fine_signature() -> image::RgbImage {
+    image::RgbImage::from_fn(300, 200, |x, y| {
+        let body = (y as f32 - 120.0 - 8.0 * (x as f32 / 6.0).sin()).abs() < 0.6 && (20..280).contains(&x);
+        let stem = x == 150 && (70..120).contains(&y);
+        let dot = (100..102).contains(&x) && (105..107).contains(&y);
+        image::Rgb(if body || stem || dot { [40, 40, 110] } else { [245, 245, 245] })
+    })
+}
+
+// `img` with three specks of scanner dust well away from anything else
+pub fn dusty(img: &image::RgbImage) -> image::RgbImage {
+    let mut dusty = img.clone();
+    for (x, y) in [(30, 30), (31, 30), (270, 40), (200, 180), (201, 181)] {
+        dusty.put_pixel(x, y, image::Rgb([80, 80, 80]));
+    }
+    dusty
+}
+
+// A 300×200 signature: a wavy stroke with loops rising from it, ending as far from the
+// edges as it starts
+pub fn signature() -> image::RgbImage {
+    image::RgbImage::from_fn(300, 200, |x, y| {
+        let body = (y as f32 - 120.0 - 8.0 * (x as f32 / 6.0).sin()).abs() < 2.0 && (20..280).contains(&x);
+        let loops = [50, 150, 250].iter().any(|&loop_x: &u32| x.abs_diff(loop_x) < 2 && (70..120).contains(&y));
+        image::Rgb(if body || loops { [20, 30, 120] } else { [245, 245, 245] })
+    })
+}
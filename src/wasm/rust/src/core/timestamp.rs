@@ -0,0 +1,149 @@
+use image::GenericImageView;
+
+use super::font::{draw_text, GLYPH_ADVANCE, GLYPH_HEIGHT};
+use super::spec::{parse_rgb, OverlayPosition, TimestampOverlaySpec};
+
+/// A wall-clock date and time as a camera stamps it, without a time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// `format` with `%Y`, `%m`, `%d`, `%H`, `%M`, `%S` and `%%` filled in from `time`, or
+/// the first placeholder it does not know.
+pub fn format_time(format: &str, time: &LocalTime) -> Result<String, String> {
+    let mut text = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => text.push_str(&format!("{:04}", time.year)),
+            Some('m') => text.push_str(&format!("{:02}", time.month)),
+            Some('d') => text.push_str(&format!("{:02}", time.day)),
+            Some('H') => text.push_str(&format!("{:02}", time.hour)),
+            Some('M') => text.push_str(&format!("{:02}", time.minute)),
+            Some('S') => text.push_str(&format!("{:02}", time.second)),
+            Some('%') => text.push('%'),
+            other => return Err(format!("%{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(text)
+}
+
+// The stamp's font pixels are about this share of the image's shorter edge high, so it
+// reads the same on a passport photo and a full-resolution one
+const STAMP_HEIGHT_PERCENT: u32 = 4;
+
+/// `text` drawn into a corner of the image on its backing box. The font is scaled to the
+/// image and shrunk until the stamp fits its width; at the smallest size longer text is
+/// cut off at the edge.
+pub(crate) fn draw_timestamp(img: &image::DynamicImage, text: &str, spec: &TimestampOverlaySpec) -> image::DynamicImage {
+    let (width, height) = img.dimensions();
+    let stamp_size = |scale: u32| {
+        let text_width = (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(1) * scale;
+        // Padding inside the box and margin outside it are two font pixels each
+        (text_width + 8 * scale, GLYPH_HEIGHT * scale + 8 * scale)
+    };
+    let mut scale = (width.min(height) * STAMP_HEIGHT_PERCENT / 100 / GLYPH_HEIGHT).max(1);
+    while scale > 1 && stamp_size(scale).0 > width {
+        scale -= 1;
+    }
+    let (stamp_width, stamp_height) = stamp_size(scale);
+    let (box_width, box_height) = (stamp_width - 4 * scale, stamp_height - 4 * scale);
+    let left = match spec.position {
+        OverlayPosition::TopLeft | OverlayPosition::BottomLeft => 2 * scale,
+        OverlayPosition::TopRight | OverlayPosition::BottomRight => width.saturating_sub(stamp_width - 2 * scale),
+    };
+    let top = match spec.position {
+        OverlayPosition::TopLeft | OverlayPosition::TopRight => 2 * scale,
+        OverlayPosition::BottomLeft | OverlayPosition::BottomRight => height.saturating_sub(stamp_height - 2 * scale),
+    };
+
+    let mut rgba = img.to_rgba8();
+    let [box_red, box_green, box_blue] = parse_rgb(&spec.box_color).unwrap_or([0, 0, 0]);
+    let opacity = spec.box_opacity.clamp(0.0, 1.0);
+    for y in top..(top + box_height).min(height) {
+        for x in left..(left + box_width).min(width) {
+            // The box goes over the pixel, so transparent images get a box as opaque as it
+            let pixel = rgba.get_pixel_mut(x, y);
+            let below = pixel.0[3] as f32 / 255.0 * (1.0 - opacity);
+            let alpha = opacity + below;
+            if alpha > 0.0 {
+                for (channel, color) in [box_red, box_green, box_blue].into_iter().enumerate() {
+                    pixel.0[channel] = ((color as f32 * opacity + pixel.0[channel] as f32 * below) / alpha).round() as u8;
+                }
+            }
+            pixel.0[3] = (alpha * 255.0).round() as u8;
+        }
+    }
+
+    // The font draws onto RGB, so the glyphs are drawn as a mask and copied across
+    let mut mask = image::RgbImage::new(box_width, box_height);
+    draw_text(&mut mask, 2 * scale, 2 * scale, text, scale, image::Rgb([255, 255, 255]));
+    let [red, green, blue] = parse_rgb(&spec.color).unwrap_or([255, 255, 255]);
+    for (x, y, pixel) in mask.enumerate_pixels() {
+        if pixel.0[0] != 0 && left + x < width && top + y < height {
+            rgba.put_pixel(left + x, top + y, image::Rgba([red, green, blue, 255]));
+        }
+    }
+    if img.color().has_alpha() {
+        image::DynamicImage::ImageRgba8(rgba)
+    } else {
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(rgba).to_rgb8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: LocalTime = LocalTime { year: 2024, month: 3, day: 9, hour: 7, minute: 5, second: 42 };
+
+    fn spec(position: OverlayPosition) -> TimestampOverlaySpec {
+        serde_json::from_value(serde_json::json!({ "position": position })).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(format_time("%Y-%m-%d %H:%M:%S", &TIME).unwrap(), "2024-03-09 07:05:42");
+        assert_eq!(format_time("%d/%m/%Y 100%%", &TIME).unwrap(), "09/03/2024 100%");
+        assert_eq!(format_time("%Y %B", &TIME).unwrap_err(), "%B");
+        assert_eq!(format_time("%Y %", &TIME).unwrap_err(), "%");
+    }
+
+    #[test]
+    fn stamp_sits_in_its_corner_on_a_translucent_box() {
+        let white = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(400, 300, image::Rgb([255, 255, 255])));
+        let stamped = draw_timestamp(&white, "2024-03-09 07:05", &spec(OverlayPosition::BottomRight)).to_rgb8();
+        assert_eq!(stamped.dimensions(), (400, 300));
+        assert_eq!(*stamped.get_pixel(0, 0), image::Rgb([255, 255, 255]));
+        assert_eq!(*stamped.get_pixel(399, 299), image::Rgb([255, 255, 255]));
+        let changed: Vec<(u32, u32)> =
+            stamped.enumerate_pixels().filter(|(_, _, pixel)| pixel.0 != [255, 255, 255]).map(|(x, y, _)| (x, y)).collect();
+        assert!(changed.iter().all(|&(x, y)| x > 200 && y > 250), "{:?}", changed.first());
+        // The box lets some of the image through, and the text is drawn in full
+        let colors: std::collections::HashSet<[u8; 3]> = stamped.pixels().map(|pixel| pixel.0).collect();
+        assert!(colors.contains(&[102, 102, 102]) && colors.contains(&[255, 165, 0]), "{:?}", colors);
+
+        let stamped = draw_timestamp(&white, "07:05", &spec(OverlayPosition::TopLeft)).to_rgb8();
+        assert!(stamped.enumerate_pixels().filter(|(_, _, pixel)| pixel.0 != [255, 255, 255]).all(|(x, y, _)| x < 100 && y < 50));
+    }
+
+    #[test]
+    fn stamps_shrink_to_fit_and_keep_transparency_around_them() {
+        let clear = image::DynamicImage::ImageRgba8(image::RgbaImage::new(60, 40));
+        let stamped = draw_timestamp(&clear, "2024-03-09 07:05:42", &spec(OverlayPosition::BottomLeft));
+        assert!(stamped.color().has_alpha());
+        let stamped = stamped.to_rgba8();
+        assert_eq!(stamped.get_pixel(59, 0).0[3], 0);
+        assert!(stamped.pixels().any(|pixel| pixel.0[3] == 153));
+    }
+}
@@ -0,0 +1,216 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::codecs::sniff_format;
+use super::compliance::compliance_score;
+use super::pipeline::conversion_violations;
+use super::spec::{DimensionsSpec, DocumentSpec};
+use super::ConvertError;
+
+/// How a finished file measures up to a spec, for outputs stored earlier that are
+/// checked again without converting them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "typescript", derive(ts_rs::TS), ts(optional_fields = nullable))]
+pub struct ValidationReport {
+    pub valid: bool,
+    /// Format found in the data itself, named as in `ConvertedFile::format`
+    pub format: String,
+    pub size_kb: u32,
+    pub size_bytes: u32,
+    /// None for PDFs
+    pub dimensions: Option<DimensionsSpec>,
+    pub compliance_score: f32,
+    /// Every limit the file breaks; empty when `valid`
+    pub violations: Vec<String>,
+}
+
+/// The MIME type and bytes of a base64 `data:` URL, as `ConvertedFile::data_url` carries
+/// them. A bare base64 string is taken as well, with an empty MIME type.
+pub fn parse_data_url(data_url: &str) -> Result<(String, Vec<u8>), ConvertError> {
+    let invalid = |reason: &str| ConvertError::Read(format!("Invalid data URL: {}", reason));
+    let (mime_type, encoded) = match data_url.trim().strip_prefix("data:") {
+        Some(rest) => {
+            let (header, encoded) = rest.split_once(',').ok_or_else(|| invalid("no comma before the data"))?;
+            let mut parameters = header.split(';');
+            let mime_type = parameters.next().unwrap_or_default().trim().to_ascii_lowercase();
+            if !parameters.any(|parameter| parameter.trim().eq_ignore_ascii_case("base64")) {
+                return Err(invalid("only base64-encoded data is supported"));
+            }
+            (mime_type, encoded)
+        }
+        None => (String::new(), data_url),
+    };
+    // Data URLs copied out of HTML or JSON are often wrapped
+    let encoded: String = encoded.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(&encoded)
+        .map_err(|e| invalid(&format!("bad base64 ({})", e)))?;
+    if data.is_empty() {
+        return Err(invalid("no data"));
+    }
+    Ok((mime_type, data))
+}
+
+/// Check `data` against the spec's format, size, pixel and aspect ratio limits. The
+/// format is sniffed from the bytes; a `file_type` that names a different one is itself
+/// a violation, since portals reject files whose type and content disagree.
+pub fn validate_data(file_type: &str, data: &[u8], spec: &DocumentSpec) -> Result<ValidationReport, ConvertError> {
+    let format = sniff_format(data).ok_or_else(|| {
+        ConvertError::UnsupportedFileType(if file_type.is_empty() { "unrecognised data" } else { file_type }.to_string())
+    })?;
+    let mut violations = Vec::new();
+    let declared = declared_format(file_type);
+    if declared.is_some_and(|declared| declared != format) {
+        violations.push(format!("Declared as {} but the data is {}", file_type, format));
+    }
+    let allowed = if format == "PDF" { spec.allowed_document_formats() } else { spec.allowed_image_formats() };
+    if !allowed.iter().any(|allowed| same_format(allowed, format)) {
+        violations.push(format!("Format {} is not allowed, expected {}", format, allowed.join(" or ")));
+    }
+
+    let dimensions = if format == "PDF" {
+        None
+    } else {
+        let (width, height) = image::io::Reader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .map_err(|e| ConvertError::Decode(format!("Failed to read image header: {}", e)))?
+            .into_dimensions()
+            .map_err(|e| ConvertError::Decode(format!("Failed to read image header: {}", e)))?;
+        Some(DimensionsSpec { width: width as f32, height: height as f32 })
+    };
+    violations.extend(conversion_violations(data, &dimensions, spec));
+    if let Some(dimensions) = &dimensions {
+        violations.extend(dimension_violations(dimensions.width as u32, dimensions.height as u32, spec));
+    }
+
+    Ok(ValidationReport {
+        valid: violations.is_empty(),
+        format: format.to_string(),
+        size_kb: (data.len() / 1024) as u32,
+        size_bytes: data.len() as u32,
+        compliance_score: compliance_score(data.len(), dimensions.as_ref(), spec),
+        dimensions,
+        violations,
+    })
+}
+
+// The per-axis pixel and aspect ratio limits; `conversion_violations` covers the rest
+fn dimension_violations(width: u32, height: u32, spec: &DocumentSpec) -> Vec<String> {
+    let mut violations = Vec::new();
+    if let Some(pixels) = &spec.pixels {
+        match (pixels.width, pixels.height) {
+            (Some(required_width), Some(required_height)) if (width, height) != (required_width, required_height) => {
+                violations.push(format!(
+                    "Image is {}x{} but the spec requires {}x{}",
+                    width, height, required_width, required_height
+                ));
+            }
+            (Some(_), Some(_)) => {}
+            _ => {
+                if let Ok(range) = pixels.range() {
+                    for (axis, value, min, max) in [
+                        ("width", width, range.min_width, range.max_width),
+                        ("height", height, range.min_height, range.max_height),
+                    ] {
+                        if value < min {
+                            violations.push(format!("Image {} {}px is below the minimum of {}px", axis, value, min));
+                        } else if value > max {
+                            violations.push(format!("Image {} {}px is above the maximum of {}px", axis, value, max));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(aspect) = &spec.aspect_ratio {
+        let ratio = width as f32 / height as f32;
+        let (lowest, highest) = aspect.width_to_height_bounds();
+        if !(lowest..=highest).contains(&ratio) {
+            violations.push(format!(
+                "Aspect ratio {:.2} (width / height) is outside the allowed {:.2} to {:.2}",
+                ratio, lowest, highest
+            ));
+        }
+    }
+    violations
+}
+
+fn declared_format(file_type: &str) -> Option<&'static str> {
+    match file_type {
+        "image/jpeg" | "image/jpg" => Some("JPEG"),
+        "image/png" => Some("PNG"),
+        "image/webp" => Some("WEBP"),
+        "application/pdf" => Some("PDF"),
+        _ => None,
+    }
+}
+
+fn same_format(allowed: &str, format: &str) -> bool {
+    let canonical = |name: &str| if name.eq_ignore_ascii_case("JPG") { "JPEG".to_string() } else { name.to_ascii_uppercase() };
+    canonical(allowed) == canonical(format)
+}
+
+#[cfg(all(test, feature = "jpeg", feature = "png"))]
+mod tests {
+    use super::*;
+    use crate::core::test_support::{encode_png, gradient, spec_from};
+
+    fn jpeg(width: u32, height: u32, quality: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::DynamicImage::ImageRgb8(gradient(width, height))
+            .write_to(&mut std::io::Cursor::new(&mut out), image::ImageOutputFormat::Jpeg(quality))
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn data_urls_are_parsed_or_refused_clearly() {
+        let png = encode_png(&image::DynamicImage::ImageRgb8(gradient(8, 8)));
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&png);
+        assert_eq!(parse_data_url(&format!("data:image/png;base64,{}", encoded)).unwrap(), ("image/png".to_string(), png.clone()));
+        assert_eq!(parse_data_url(&format!("data:IMAGE/PNG;name=a.png;base64,\n{}\n", encoded)).unwrap().0, "image/png");
+        assert_eq!(parse_data_url(&encoded).unwrap(), (String::new(), png));
+
+        let error = |url: &str| parse_data_url(url).unwrap_err().to_string();
+        assert_eq!(error("data:image/png;base64"), "Invalid data URL: no comma before the data");
+        assert_eq!(error("data:text/plain,hello"), "Invalid data URL: only base64-encoded data is supported");
+        assert!(error("data:image/png;base64,@@@").starts_with("Invalid data URL: bad base64"));
+        assert_eq!(error("data:image/png;base64,"), "Invalid data URL: no data");
+    }
+
+    #[test]
+    fn validation_lists_every_broken_limit() {
+        let spec = |max_kb: u32, format: &str| {
+            spec_from(serde_json::json!({
+                "format": [format],
+                "size_kb": { "max": max_kb },
+                "pixels": { "width": 200, "height": 230 },
+                "aspect_ratio": { "min": 0.7, "max": 0.9 },
+            }))
+        };
+        let wide = jpeg(300, 200, 90);
+        let report = validate_data("image/png", &wide, &spec(1, "JPEG")).unwrap();
+        assert!(!report.valid);
+        assert_eq!((report.format.as_str(), report.dimensions.as_ref().map(|d| (d.width, d.height))), ("JPEG", Some((300.0, 200.0))));
+        assert_eq!(
+            report.violations,
+            [
+                "Declared as image/png but the data is JPEG".to_string(),
+                format!("File too large: {}KB, maximum allowed: 1KB", report.size_kb),
+                "Image is 300x200 but the spec requires 200x230".to_string(),
+                "Aspect ratio 1.50 (width / height) is outside the allowed 0.70 to 0.90".to_string(),
+            ]
+        );
+
+        let fitting = jpeg(200, 230, 50);
+        let report = validate_data("image/jpeg", &fitting, &spec(100, "JPG")).unwrap();
+        assert!(report.valid && report.violations.is_empty(), "{:?}", report.violations);
+        assert!(report.compliance_score > 0.0);
+
+        assert_eq!(validate_data("", &fitting, &spec(100, "PNG")).unwrap().violations, ["Format JPEG is not allowed, expected PNG"]);
+        assert_eq!(
+            validate_data("", b"not an image", &spec(100, "PNG")).unwrap_err().to_string(),
+            "Unsupported file type: unrecognised data"
+        );
+    }
+}
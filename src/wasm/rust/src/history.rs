@@ -0,0 +1,165 @@
+//! Conversion history kept in IndexedDB, so converted files survive a reload part way
+//! through an application. `DocumentConverter` saves to it when the `history_max_bytes`
+//! option is set; records are the `HistoryEntry` fields plus the output as a `Blob`.
+
+use js_sys::Reflect;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbObjectStore, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+use crate::core::{self, ConversionConfig, ConvertError, ConvertedFile, HistoryEntry};
+use crate::wasm::storage_error;
+
+#[wasm_bindgen(typescript_custom_section)]
+const HISTORY_TYPES: &str = r#"
+export type HistoryItem = HistoryEntry & { blob: Blob };
+"#;
+
+const DATABASE: &str = "summa2-history";
+const STORE: &str = "outputs";
+
+/// Store a converted file, first evicting the oldest entries that would take the
+/// history over `max_bytes`.
+pub(crate) async fn save(converted: &ConvertedFile, config: &ConversionConfig, max_bytes: u32) -> Result<(), ConvertError> {
+    let (mime_type, data) = core::parse_data_url(&converted.data_url)?;
+    if data.len() > max_bytes as usize {
+        return Err(ConvertError::Write(format!(
+            "{} is {} bytes, more than history_max_bytes {}",
+            converted.converted_name,
+            data.len(),
+            max_bytes
+        )));
+    }
+    let entry = HistoryEntry {
+        id: 0,
+        converted_name: converted.converted_name.clone(),
+        document_type: config.document_type.clone(),
+        exam_type: config.exam_type.clone(),
+        size_bytes: data.len() as u32,
+        timestamp: js_sys::Date::now(),
+        spec_hash: core::spec_hash(&config.target_spec),
+    };
+    let record = entry.serialize(&serde_wasm_bindgen::Serializer::json_compatible()).map_err(JsValue::from).map_err(storage_error)?;
+    let write = async {
+        // The store assigns the id
+        Reflect::delete_property(record.unchecked_ref::<js_sys::Object>(), &JsValue::from_str("id"))?;
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type(&mime_type);
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&data[..]));
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        Reflect::set(&record, &JsValue::from_str("blob"), &blob)?;
+
+        let database = open().await?;
+        let result = async {
+            let (transaction, store) = store(&database, IdbTransactionMode::Readwrite)?;
+            let entries = to_entries(settle(&store.get_all()?).await?)?;
+            for id in core::history_evictions(&entries, entry.size_bytes, max_bytes) {
+                store.delete(&JsValue::from(id))?;
+            }
+            store.add(&record)?;
+            committed(&transaction).await
+        }
+        .await;
+        database.close();
+        result
+    };
+    write.await.map_err(storage_error)
+}
+
+/// Every entry, newest first.
+pub(crate) async fn list() -> Result<Vec<HistoryEntry>, JsValue> {
+    let database = open().await?;
+    let records = async { settle(&store(&database, IdbTransactionMode::Readonly)?.1.get_all()?).await }.await;
+    database.close();
+    let mut entries = to_entries(records?)?;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.id));
+    Ok(entries)
+}
+
+/// The record saved under `id` with its `blob`, or undefined.
+pub(crate) async fn get(id: u32) -> Result<JsValue, JsValue> {
+    let database = open().await?;
+    let record = async { settle(&store(&database, IdbTransactionMode::Readonly)?.1.get(&JsValue::from(id))?).await }.await;
+    database.close();
+    record
+}
+
+pub(crate) async fn clear() -> Result<(), JsValue> {
+    let database = open().await?;
+    let result = async {
+        let (transaction, store) = store(&database, IdbTransactionMode::Readwrite)?;
+        store.clear()?;
+        committed(&transaction).await
+    }
+    .await;
+    database.close();
+    result
+}
+
+// `indexedDB` is looked up on the global so pages and workers both work. Browsers that
+// withhold it in private windows either leave it out or fail the open.
+async fn open() -> Result<IdbDatabase, JsValue> {
+    let factory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?;
+    if factory.is_undefined() || factory.is_null() {
+        return Err(JsValue::from_str("IndexedDB is not available"));
+    }
+    let request = factory.unchecked_into::<web_sys::IdbFactory>().open_with_u32(DATABASE, 1)?;
+    // Runs inside the versionchange transaction, the only place object stores can be made
+    let upgrade = Closure::<dyn FnMut()>::new({
+        let request = request.clone();
+        move || {
+            if let Ok(database) = request.result() {
+                let parameters = web_sys::IdbObjectStoreParameters::new();
+                parameters.set_key_path(&JsValue::from_str("id"));
+                parameters.set_auto_increment(true);
+                let _ = database.unchecked_into::<IdbDatabase>().create_object_store_with_optional_parameters(STORE, &parameters);
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+    let database = settle(&request).await;
+    request.set_onupgradeneeded(None);
+    Ok(database?.unchecked_into())
+}
+
+fn store(database: &IdbDatabase, mode: IdbTransactionMode) -> Result<(IdbTransaction, IdbObjectStore), JsValue> {
+    let transaction = database.transaction_with_str_and_mode(STORE, mode)?;
+    let store = transaction.object_store(STORE)?;
+    Ok((transaction, store))
+}
+
+// The request's result once it succeeds, or the DOMException it failed with
+async fn settle(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let done = js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    match JsFuture::from(done).await {
+        Ok(_) => request.result(),
+        Err(event) => Err(request.error().ok().flatten().map_or(event, JsValue::from)),
+    }
+}
+
+// Waits for the writes to land. A failed request aborts the transaction, and a full
+// quota only shows up here, so the abort carries every failure.
+async fn committed(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    let done = js_sys::Promise::new(&mut |resolve, reject| {
+        transaction.set_oncomplete(Some(&resolve));
+        transaction.set_onabort(Some(&reject));
+    });
+    JsFuture::from(done)
+        .await
+        .map(|_| ())
+        .map_err(|event| transaction.error().map_or(event, JsValue::from))
+}
+
+fn to_entries(records: JsValue) -> Result<Vec<HistoryEntry>, JsValue> {
+    js_sys::Array::from(&records)
+        .iter()
+        .map(|record| {
+            Reflect::delete_property(record.unchecked_ref::<js_sys::Object>(), &JsValue::from_str("blob"))?;
+            Ok(serde_wasm_bindgen::from_value(record)?)
+        })
+        .collect()
+}
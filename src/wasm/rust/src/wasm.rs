@@ -0,0 +1,1080 @@
+//! wasm-bindgen bindings: translates `File`/`JsValue` to and from the core pipeline.
+
+use js_sys::{Reflect, Uint8Array};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::File;
+
+use crate::history;
+use crate::core::{
+    self, ConversionConfig, ConvertError, ConverterOptions, LogEvent, PresetLoadReport, PresetRegistry, LogEventKind, LogLevel, Observer,
+    ProgressEvent, ProgressStage, ResultCache, SessionStats,
+};
+
+// Import the `console.log` function from the `console` module
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    pub(crate) fn log(s: &str);
+}
+
+// Declarations for the JSON shapes passed across the boundary, generated from the core
+// types and checked by tests/typescript.rs
+#[wasm_bindgen(typescript_custom_section)]
+const TYPESCRIPT_TYPES: &str = include_str!("../types/summa2.d.ts");
+
+#[wasm_bindgen(typescript_custom_section)]
+const SPEC_ENTRY_TYPES: &str = r#"
+export type SpecEntry = { file: File; spec?: DocumentSpec | null; document_type?: string | null };
+"#;
+
+// Thumbnail edge for `contact_sheet` when the caller gives none
+const DEFAULT_CONTACT_CELL: u32 = 160;
+
+#[wasm_bindgen]
+pub struct DocumentConverter {
+    // Behind RefCells so `dispose` can release everything while a conversion is in
+    // flight; conversions hold their own `Rc` of the config
+    config: RefCell<Option<Rc<ConversionConfig>>>,
+    options: ConverterOptions,
+    presets: RefCell<PresetRegistry>,
+    // ETag of the last preset bundle fetched from each URL
+    preset_etags: RefCell<HashMap<String, String>>,
+    log_callback: RefCell<Option<js_sys::Function>>,
+    progress_callback: RefCell<Option<js_sys::Function>>,
+    file_callback: RefCell<Option<js_sys::Function>>,
+    // (file index, file count) of the conversion currently reporting progress
+    progress_position: Cell<(u32, u32)>,
+    progress_percent: Cell<f32>,
+    // Bumped by cancel_all(); conversions started under an older generation stop
+    cancel_generation: Cell<u32>,
+    stats: Cell<SessionStats>,
+    // Where convert_files stores outputs instead of returning them as data URLs
+    output_directory: RefCell<Option<web_sys::FileSystemDirectoryHandle>>,
+    // Recent outputs, with the `result_cache_bytes` option
+    result_cache: RefCell<Option<ResultCache>>,
+    disposed: Cell<bool>,
+}
+
+// Per-call state captured when a conversion starts. The core pipeline polls it for
+// cancellation at every stage boundary and reports events and progress through it.
+struct Job<'a> {
+    converter: &'a DocumentConverter,
+    signal: Option<web_sys::AbortSignal>,
+    generation: u32,
+    // Date.now() after which the call counts as cancelled, from `timeout_ms`
+    deadline: Option<f64>,
+}
+
+impl Observer for Job<'_> {
+    fn event(&self, level: LogLevel, event: LogEventKind, file: Option<&str>, fields: serde_json::Value) {
+        self.converter.emit(level, event, file, fields);
+    }
+
+    fn progress(&self, file_name: &str, stage: ProgressStage, pass: u32) {
+        self.converter.report_progress(file_name, stage, pass);
+    }
+
+    fn record(&self, update: &dyn Fn(&mut SessionStats)) {
+        self.converter.record(update);
+    }
+
+    fn available_memory(&self) -> Option<u64> {
+        browser_available_memory()
+    }
+
+    fn local_time(&self) -> Option<core::LocalTime> {
+        Some(browser_local_time())
+    }
+
+    fn is_cancelled(&self) -> bool {
+        let aborted = self.signal.as_ref().is_some_and(|signal| signal.aborted());
+        let timed_out = self.deadline.is_some_and(|deadline| js_sys::Date::now() > deadline);
+        aborted || timed_out || self.generation != self.converter.cancel_generation.get()
+    }
+}
+
+impl Default for DocumentConverter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl DocumentConverter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> DocumentConverter {
+        Self::from_options(ConverterOptions::default())
+    }
+
+    /// Create a converter with construction-time `ConverterOptions`; omitted fields take
+    /// their defaults. Rejects with "Invalid options: ..." naming the offending field.
+    #[wasm_bindgen]
+    pub fn with_options(
+        #[wasm_bindgen(unchecked_param_type = "ConverterOptions")] options: JsValue,
+    ) -> Result<DocumentConverter, JsValue> {
+        let options = if options.is_undefined() || options.is_null() {
+            ConverterOptions::default()
+        } else {
+            serde_wasm_bindgen::from_value(options).map_err(|e| to_js(ConvertError::InvalidOptions(e.to_string())))?
+        };
+        core::validate_options(&options).map_err(to_js)?;
+        Ok(Self::from_options(options))
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "ConverterOptions")]
+    pub fn get_options(&self) -> Result<JsValue, JsValue> {
+        Ok(self.options.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+    }
+
+    fn from_options(options: ConverterOptions) -> DocumentConverter {
+        DocumentConverter {
+            config: RefCell::new(None),
+            presets: RefCell::new(PresetRegistry::default()),
+            preset_etags: RefCell::new(HashMap::new()),
+            log_callback: RefCell::new(None),
+            progress_callback: RefCell::new(None),
+            file_callback: RefCell::new(None),
+            progress_position: Cell::new((0, 1)),
+            progress_percent: Cell::new(0.0),
+            cancel_generation: Cell::new(0),
+            stats: Cell::new(SessionStats::default()),
+            output_directory: RefCell::new(None),
+            result_cache: RefCell::new(options.result_cache_bytes.map(|max_bytes| ResultCache::new(max_bytes as usize))),
+            options,
+            disposed: Cell::new(false),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_config(&mut self, config_json: &str) -> Result<(), JsValue> {
+        self.check_disposed()?;
+        match core::parse_config(config_json) {
+            Ok(config) => {
+                self.emit(
+                    LogLevel::Info,
+                    LogEventKind::Config,
+                    None,
+                    serde_json::json!({ "exam_type": config.exam_type, "document_type": config.document_type }),
+                );
+                *self.config.get_mut() = Some(Rc::new(config));
+                Ok(())
+            }
+            Err(e) => {
+                self.emit(LogLevel::Warn, LogEventKind::Config, None, serde_json::json!({ "error": e.to_string() }));
+                Err(JsValue::from_str(&e.to_string()))
+            }
+        }
+    }
+
+    /// Fetch a `ConversionConfig` kept on the integrator's server and use it as the config,
+    /// as `set_config` would. Network, CORS, HTTP and validation failures each reject
+    /// with a message naming `url`; the current config is kept then.
+    #[wasm_bindgen]
+    pub async fn set_config_from_url(&mut self, url: String) -> Result<(), JsValue> {
+        self.check_disposed()?;
+        let (_, body) = fetch_text(&url, None, "config").await?;
+        let body = body.ok_or_else(|| JsValue::from_str(&format!("Failed to fetch config from {}: empty response", url)))?;
+        let config = core::parse_config(&body).map_err(|e| {
+            self.emit(LogLevel::Warn, LogEventKind::Config, None, serde_json::json!({ "url": url, "error": e.to_string() }));
+            JsValue::from_str(&format!("Config from {} was rejected: {}", url, e))
+        })?;
+        self.emit(
+            LogLevel::Info,
+            LogEventKind::Config,
+            None,
+            serde_json::json!({ "url": url, "exam_type": config.exam_type, "document_type": config.document_type }),
+        );
+        *self.config.get_mut() = Some(Rc::new(config));
+        Ok(())
+    }
+
+    /// Merge a preset bundle (`{ version, presets: [ConversionConfig, ...] }`) shipped with
+    /// the app. Load built-in presets this way before any remote bundle so remote entries
+    /// override them.
+    #[wasm_bindgen(unchecked_return_type = "PresetLoadReport")]
+    pub fn load_presets(&mut self, bundle_json: &str) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        let report = self.presets.get_mut().merge("local", bundle_json).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&report)?)
+    }
+
+    /// Fetch a preset bundle and merge it over the presets loaded so far. Entries that
+    /// fail validation are listed in `rejected` and the rest still load. Repeat loads
+    /// revalidate with the bundle's ETag and skip bundles whose `version` is unchanged;
+    /// both report `not_modified: true`.
+    #[wasm_bindgen(unchecked_return_type = "PresetLoadReport")]
+    pub async fn load_presets_from_url(&mut self, url: String) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        let (response, body) = fetch_text(&url, self.preset_etags.get_mut().get(&url), "presets").await?;
+        let report = match body {
+            Some(body) => {
+                if let Ok(Some(etag)) = response.headers().get("ETag") {
+                    self.preset_etags.get_mut().insert(url.clone(), etag);
+                }
+                self.presets.get_mut().merge(&url, &body).map_err(to_js)?
+            }
+            None => PresetLoadReport {
+                version: self.presets.get_mut().version(&url).map(str::to_string),
+                loaded: 0,
+                rejected: vec![],
+                not_modified: true,
+            },
+        };
+        self.emit(
+            LogLevel::Info,
+            LogEventKind::Presets,
+            None,
+            serde_json::json!({
+                "url": url,
+                "loaded": report.loaded,
+                "rejected": report.rejected.len(),
+                "not_modified": report.not_modified,
+            }),
+        );
+        Ok(serde_wasm_bindgen::to_value(&report)?)
+    }
+
+    /// Every loaded preset, as `ConversionConfig` objects.
+    #[wasm_bindgen(unchecked_return_type = "ConversionConfig[]")]
+    pub fn get_presets(&self) -> Result<JsValue, JsValue> {
+        let registry = self.presets.borrow();
+        let presets: Vec<&ConversionConfig> = registry.presets().collect();
+        Ok(serde_wasm_bindgen::to_value(&presets)?)
+    }
+
+    /// Use the loaded preset for `exam_type` and `document_type` as the config.
+    #[wasm_bindgen]
+    pub fn use_preset(&mut self, exam_type: &str, document_type: &str) -> Result<(), JsValue> {
+        self.check_disposed()?;
+        match self.presets.get_mut().get(exam_type, document_type) {
+            Some(config) => {
+                *self.config.get_mut() = Some(Rc::new(config.clone()));
+                Ok(())
+            }
+            None => Err(JsValue::from_str(&format!("No preset for exam {} document {}", exam_type, document_type))),
+        }
+    }
+
+    /// Receive one structured `LogEvent` object per pipeline stage.
+    #[wasm_bindgen]
+    pub fn set_log_callback(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "(event: LogEvent) => void")] callback: js_sys::Function,
+    ) {
+        *self.log_callback.get_mut() = Some(callback);
+    }
+
+    /// Also write structured events to the console as single-line JSON.
+    #[wasm_bindgen]
+    pub fn set_json_console(&mut self, enabled: bool) {
+        self.options.json_console = enabled;
+    }
+
+    /// Receive `ProgressEvent` objects at stage transitions and between encode passes.
+    #[wasm_bindgen]
+    pub fn set_progress_callback(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "(event: ProgressEvent) => void")] callback: js_sys::Function,
+    ) {
+        *self.progress_callback.get_mut() = Some(callback);
+    }
+
+    /// Receive a `FileCompletion` as each input of a batch is converted, fails or is
+    /// skipped, so results can be listed while the rest are still converting. The
+    /// promise still resolves to the whole `ConversionResult` at the end.
+    #[wasm_bindgen]
+    pub fn set_file_callback(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "(completion: FileCompletion) => void")] callback: js_sys::Function,
+    ) {
+        *self.file_callback.get_mut() = Some(callback);
+    }
+
+    /// Round-trip a tiny image through every compiled-in codec and report per-codec
+    /// pass/fail plus the crate version, to catch broken builds before real uploads.
+    #[wasm_bindgen(unchecked_return_type = "SelfTestReport")]
+    pub fn self_test() -> JsValue {
+        serde_wasm_bindgen::to_value(&core::run_self_test()).unwrap_or(JsValue::NULL)
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "SessionStats")]
+    pub fn get_stats(&self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.stats.get())?)
+    }
+
+    #[wasm_bindgen]
+    pub fn reset_stats(&mut self) {
+        self.stats.set(SessionStats::default());
+    }
+
+    /// Describe the transformations `convert_file` would apply, using only the image
+    /// header. Nothing is decoded or encoded, so this is cheap enough to run on pick.
+    #[wasm_bindgen(unchecked_return_type = "ConversionPlan")]
+    pub async fn plan_conversion(&self, file: File) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let data = self.read_file(&file).await?;
+        let plan = core::plan(&file.name(), &file.type_(), &data, &config).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&plan)?)
+    }
+
+    /// Check a stored output against the current spec without converting it. Takes a
+    /// base64 data URL, such as a converted file's `data_url`, or bare base64. Returns a
+    /// report listing every limit it breaks, and throws when the string is not a
+    /// well-formed data URL or holds no recognisable image or PDF.
+    #[wasm_bindgen(unchecked_return_type = "ValidationReport")]
+    pub fn validate_data_url(&self, data_url: &str) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let (file_type, data) = core::parse_data_url(data_url).map_err(to_js)?;
+        let report = core::validate_data(&file_type, &data, &config.target_spec).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&report)?)
+    }
+
+    /// Luminance, color and edge statistics for an input, for integrators building
+    /// their own heuristics (dark? low contrast? line art?). Also reports the EXIF
+    /// `orientation` so a manual rotation control can start from the camera's baseline.
+    #[wasm_bindgen(unchecked_return_type = "ImageAnalysis")]
+    pub async fn analyze_image(&self, file: File) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        let data = self.read_file(&file).await?;
+        let analysis = core::analyze_data(&data).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&analysis)?)
+    }
+
+    /// Drop every output kept by the `result_cache_bytes` option, so the next conversion
+    /// of each file runs again.
+    #[wasm_bindgen]
+    pub fn clear_cache(&self) {
+        if let Some(cache) = self.result_cache.borrow_mut().as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// Cancel every in-flight conversion. Conversions started afterwards are unaffected.
+    #[wasm_bindgen]
+    pub fn cancel_all(&self) {
+        self.cancel_generation.set(self.cancel_generation.get().wrapping_add(1));
+    }
+
+    /// Release the config and callbacks and cancel in-flight conversions, for hosts that
+    /// keep the object around after they are done with it, along with the result cache.
+    /// Input and output buffers only live for the duration of a call, so nothing else is
+    /// retained. Conversions and `set_config` fail with "Converter disposed" afterwards;
+    /// `free()` still releases the object itself. Safe to call while a conversion is
+    /// running, which then resolves as cancelled.
+    #[wasm_bindgen]
+    pub fn dispose(&self) {
+        self.cancel_all();
+        self.config.replace(None);
+        self.presets.replace(PresetRegistry::default());
+        self.preset_etags.borrow_mut().clear();
+        self.log_callback.replace(None);
+        self.progress_callback.replace(None);
+        self.file_callback.replace(None);
+        self.stats.set(SessionStats::default());
+        self.output_directory.replace(None);
+        self.result_cache.replace(None);
+        self.disposed.set(true);
+    }
+
+    /// Have `convert_files` write each output into a directory as soon as it is converted,
+    /// rather than holding every output as a data URL until the batch resolves. `handle`
+    /// is a `FileSystemDirectoryHandle`, typically the origin private file system from
+    /// `navigator.storage.getDirectory()`; `null` goes back to in-memory results.
+    ///
+    /// Outputs are written under their `converted_name`, with `_2`, `_3`, ... added to
+    /// names repeated within a batch or already in the directory, so files from earlier
+    /// batches are kept.
+    /// Their results carry the name in `path` and an empty `data_url`, and
+    /// `max_batch_size` no longer applies. A write that runs out of storage fails its
+    /// file with `error_code: "quota_exceeded"`, and one the browser refuses with
+    /// `"permission_denied"`.
+    #[wasm_bindgen]
+    pub fn set_output_directory(
+        &mut self,
+        #[wasm_bindgen(unchecked_param_type = "FileSystemDirectoryHandle | null")] handle: JsValue,
+    ) -> Result<(), JsValue> {
+        self.check_disposed()?;
+        *self.output_directory.get_mut() = if handle.is_null() || handle.is_undefined() {
+            None
+        } else {
+            Some(handle.dyn_into().map_err(|_| {
+                JsValue::from_str("set_output_directory expects a FileSystemDirectoryHandle or null")
+            })?)
+        };
+        Ok(())
+    }
+
+    /// Entries saved to the conversion history, newest first. Converters created with the
+    /// `history_max_bytes` option save every successful `convert_file` and batch output;
+    /// any converter can read the history, including one from before a reload.
+    #[wasm_bindgen(unchecked_return_type = "HistoryEntry[]")]
+    pub async fn list_history(&self) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        let entries = history::list().await.map_err(|e| to_js(storage_error(e)))?;
+        Ok(serde_wasm_bindgen::to_value(&entries)?)
+    }
+
+    /// The history entry `id` with its output as `blob`, or undefined once it has been
+    /// evicted or cleared.
+    #[wasm_bindgen(unchecked_return_type = "HistoryItem | undefined")]
+    pub async fn get_history_item(&self, id: u32) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        history::get(id).await.map_err(|e| to_js(storage_error(e)))
+    }
+
+    #[wasm_bindgen]
+    pub async fn clear_history(&self) -> Result<(), JsValue> {
+        self.check_disposed()?;
+        history::clear().await.map_err(|e| to_js(storage_error(e)))
+    }
+
+    /// Convert one file. `signal` may be an `AbortSignal`; when it fires the
+    /// conversion stops at the next stage boundary with `error_code: "cancelled"`.
+    /// With `memory_check` configured, inputs too large to decode safely fail with
+    /// `error_code: "insufficient_memory"` instead of exhausting the tab, and with
+    /// `on_feature_loss: "Fail"` inputs that would lose transparency or animation frames
+    /// fail with `error_code: "feature_loss"`. Inputs declaring more pixels than
+    /// `max_decoded_pixels` fail undecoded with `error_code: "decompression_bomb"`.
+    #[wasm_bindgen(unchecked_return_type = "ConversionResult")]
+    pub async fn convert_file(&self, file: File, signal: JsValue) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let job = self.start_job(signal);
+        self.start_progress(0, 1);
+        let mut outcome = match self.read_file(&file).await.map_err(read_error) {
+            Ok(data) => self.convert_cached(&file, &data, &core::sha256_hex(&data), &config, &job).await,
+            Err(error) => Err(error),
+        };
+        if let Ok(converted) = &mut outcome {
+            self.remember(converted, &config).await;
+        }
+        let result = core::single_result(&file.name(), outcome, &job);
+        self.resolve(&result)
+    }
+
+    /// Resize an image per the spec and slice it into `tile_size`×`tile_size` tiles for
+    /// zoomable viewers. Resolves to an array of converted files in row-major order, each
+    /// with `tile: { row, column, x, y }`; right and bottom edge tiles may be smaller.
+    /// Rejects when `tile_size` would cut the image into more than 4096 tiles.
+    #[wasm_bindgen(unchecked_return_type = "ConvertedFile[]")]
+    pub async fn tile_image(&self, file: File, tile_size: u32) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let job = self.start_job(JsValue::UNDEFINED);
+
+        self.start_progress(0, 1);
+        let data = self.read_file(&file).await.map_err(read_error);
+        let outcome = data.and_then(|data| core::tile(&file.name(), &file.type_(), &data, &config, tile_size, &job));
+        let tiles = core::count_outcome(&file.name(), outcome, &job).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&tiles)?)
+    }
+
+    /// Convert one image to land as close under `target_bytes` as it can, ignoring
+    /// `size_kb`, for binding a size slider. Resolves to the converted file, whose
+    /// `size_bytes` is the size achieved; rejects when no quality or scale fits.
+    #[wasm_bindgen(unchecked_return_type = "ConvertedFile")]
+    pub async fn convert_to_target_bytes(&self, file: File, target_bytes: usize) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let job = self.start_job(JsValue::UNDEFINED);
+
+        self.start_progress(0, 1);
+        let data = self.read_file(&file).await.map_err(read_error);
+        let outcome = data.and_then(|data| core::convert_to_target_bytes(&file.name(), &file.type_(), &data, &config, target_bytes, &job));
+        let converted = core::count_outcome(&file.name(), outcome, &job).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&converted)?)
+    }
+
+    /// Convert one transparent image into both a JPEG `preview` on a white background
+    /// (`background.required` when set) and a transparent PNG `original`, decoded once so
+    /// the two match. Each is sized and validated against the spec; rejects when either
+    /// cannot meet it.
+    #[wasm_bindgen(unchecked_return_type = "PreviewPair")]
+    pub async fn convert_with_preview(&self, file: File) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let job = self.start_job(JsValue::UNDEFINED);
+
+        self.start_progress(0, 1);
+        let data = self.read_file(&file).await.map_err(read_error);
+        let outcome = data.and_then(|data| core::convert_with_preview(&file.name(), &file.type_(), &data, &config, &job));
+        let pair = core::count_outcome(&file.name(), outcome, &job).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&pair)?)
+    }
+
+    /// Convert one image and, from the same decode, a thumbnail of the result no larger
+    /// than `thumb_max_edge` on either side, for showing a preview beside the upload.
+    /// Resolves to `{ converted, thumbnail }`; rejects as `convert_file` would.
+    #[wasm_bindgen(unchecked_return_type = "ThumbnailPair")]
+    pub async fn convert_with_thumbnail(&self, file: File, thumb_max_edge: u32) -> Result<JsValue, JsValue> {
+        let config = self.config()?;
+        let job = self.start_job(JsValue::UNDEFINED);
+
+        self.start_progress(0, 1);
+        let data = self.read_file(&file).await.map_err(read_error);
+        let outcome = data.and_then(|data| core::convert_with_thumbnail(&file.name(), &file.type_(), &data, &config, thumb_max_edge, &job));
+        let pair = core::count_outcome(&file.name(), outcome, &job).map_err(to_js)?;
+        Ok(serde_wasm_bindgen::to_value(&pair)?)
+    }
+
+    /// Convert a batch of files. Inputs whose SHA-256 hex digest appears in
+    /// `completed_hashes` are skipped, so an interrupted batch can be resumed by
+    /// passing back the `input_hash` of every file converted so far.
+    ///
+    /// Every output is held as a data URL until the call resolves, so batches over the
+    /// `max_batch_size` option (200 by default) reject before any file is read. For
+    /// large batches set an output directory with `set_output_directory`, or call
+    /// `convert_file` per file so each result can be released before the next.
+    #[wasm_bindgen(unchecked_return_type = "ConversionResult")]
+    pub async fn convert_files(
+        &self,
+        files: js_sys::Array,
+        completed_hashes: Option<js_sys::Array>,
+        signal: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        self.check_disposed()?;
+        let output_directory = self.output_directory.borrow().clone();
+        if output_directory.is_none() {
+            self.options.check_batch_size(files.length() as usize).map_err(to_js)?;
+        }
+        let completed: HashSet<String> = completed_hashes
+            .map(|hashes| hashes.iter().filter_map(|h| h.as_string()).collect())
+            .unwrap_or_default();
+        let result = self.run_batch(files, completed, signal, output_directory.as_ref()).await?;
+        self.resolve(&result)
+    }
+
+    /// Convert a form's documents in one call, each with its own rules. An entry with a
+    /// `spec` is converted with the config's other settings and that spec, under its
+    /// `document_type` if given; one with only a `document_type` uses the loaded preset
+    /// for it under the config's exam. Every entry is resolved before any file is read,
+    /// so an invalid spec or missing preset rejects the whole call, naming the file.
+    #[wasm_bindgen(unchecked_return_type = "ConversionResult")]
+    pub async fn convert_with_specs(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "SpecEntry[]")] entries: js_sys::Array,
+        signal: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let base = self.config()?;
+        let output_directory = self.output_directory.borrow().clone();
+        if output_directory.is_none() {
+            self.options.check_batch_size(entries.length() as usize).map_err(to_js)?;
+        }
+        let mut files = Vec::with_capacity(entries.length() as usize);
+        for entry in entries.iter() {
+            let field = |key: &str| Reflect::get(&entry, &JsValue::from_str(key)).ok().filter(|value| !value.is_undefined() && !value.is_null());
+            let file: File = field("file")
+                .and_then(|file| file.dyn_into().ok())
+                .ok_or_else(|| JsValue::from_str("convert_with_specs expects entries of the form { file, spec } or { file, document_type }"))?;
+            let spec = field("spec")
+                .map(|spec| serde_wasm_bindgen::from_value(spec).map_err(|e| ConvertError::InvalidConfig(e.to_string())))
+                .transpose();
+            let document_type = field("document_type").and_then(|document_type| document_type.as_string());
+            let config = spec
+                .and_then(|spec| self.presets.borrow().config_for(&base, document_type.as_deref(), spec))
+                .map_err(|error| JsValue::from_str(&format!("{}: {}", file.name(), error)))?;
+            files.push((file, config));
+        }
+        let entries = files.iter().map(|(file, config)| (file.clone(), config)).collect();
+        let result = self.run_entries(&base, entries, HashSet::new(), signal, output_directory.as_ref()).await?;
+        self.resolve(&result)
+    }
+
+    /// Convert a batch of files and package the converted ones into a single ZIP `Blob`,
+    /// named as in `converted_name`. Files that fail are left out; the promise rejects
+    /// when none convert, or on any failure with `reject_on_error`. Use `convert_files`
+    /// when the per-file report is needed. Every output is held until the ZIP is built,
+    /// so batches over `max_batch_size` reject as in `convert_files`.
+    #[wasm_bindgen]
+    pub async fn convert_files_to_zip(&self, files: js_sys::Array, signal: JsValue) -> Result<web_sys::Blob, JsValue> {
+        self.options.check_batch_size(files.length() as usize).map_err(to_js)?;
+        let result = self.run_batch(files, HashSet::new(), signal, None).await?;
+        let error = result.error.as_deref().unwrap_or("No files were converted");
+        if result.files.is_empty() || (self.options.reject_on_error && !result.success) {
+            return Err(JsValue::from_str(error));
+        }
+        let archive = core::zip_files(&result.files).map_err(to_js)?;
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("application/zip");
+        let parts = js_sys::Array::of1(&Uint8Array::from(&archive[..]));
+        web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+    }
+
+    /// Convert a batch of files and lay the converted ones out as a single PNG `Blob`
+    /// of labelled thumbnails, `columns` per row, for previewing everything before
+    /// submitting. Each thumbnail fits a `cell_size` square (160px by default). Files
+    /// that fail are left out; the promise rejects when none convert, or before any file
+    /// is read when the batch is over `max_batch_size`.
+    #[wasm_bindgen]
+    pub async fn contact_sheet(
+        &self,
+        files: js_sys::Array,
+        columns: u32,
+        cell_size: Option<u32>,
+    ) -> Result<web_sys::Blob, JsValue> {
+        self.options.check_batch_size(files.length() as usize).map_err(to_js)?;
+        let result = self.run_batch(files, HashSet::new(), JsValue::UNDEFINED, None).await?;
+        if result.files.is_empty() {
+            return Err(JsValue::from_str(result.error.as_deref().unwrap_or("No files were converted")));
+        }
+        let sheet = core::contact_sheet(&result.files, columns, cell_size.unwrap_or(DEFAULT_CONTACT_CELL))
+            .map_err(to_js)?;
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("image/png");
+        let parts = js_sys::Array::of1(&Uint8Array::from(&sheet[..]));
+        web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+    }
+
+    async fn run_batch(
+        &self,
+        files: js_sys::Array,
+        completed: HashSet<String>,
+        signal: JsValue,
+        output_directory: Option<&web_sys::FileSystemDirectoryHandle>,
+    ) -> Result<core::ConversionResult, JsValue> {
+        let config = self.config()?;
+        let entries = files
+            .iter()
+            .map(|file| file.dyn_into().map(|file: File| (file, &*config)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| JsValue::from_str("convert_files expects an array of File objects"))?;
+        self.run_entries(&config, entries, completed, signal, output_directory).await
+    }
+
+    // `run_batch` with a config per file; `base` is the batch's own
+    async fn run_entries(
+        &self,
+        base: &ConversionConfig,
+        entries: Vec<(File, &ConversionConfig)>,
+        completed: HashSet<String>,
+        signal: JsValue,
+        output_directory: Option<&web_sys::FileSystemDirectoryHandle>,
+    ) -> Result<core::ConversionResult, JsValue> {
+        let job = self.start_job(signal);
+        let mut written = HashSet::new();
+
+        let count = entries.len() as u32;
+        let mut batch = core::Batch::new(base, completed, entries.len());
+        for (index, (file, config)) in entries.into_iter().enumerate() {
+            if batch.should_stop(&job) {
+                break;
+            }
+            self.start_progress(index as u32, count);
+            let data = self.read_file(&file).await.map_err(read_error);
+            if let Some((data, input_hash)) = batch.admit(&file.name(), data, &job) {
+                let mut outcome = self.convert_cached(&file, &data, &input_hash, config, &job).await;
+                if let Ok(converted) = &mut outcome {
+                    self.remember(converted, config).await;
+                }
+                let outcome = match (outcome, output_directory) {
+                    (Ok(converted), Some(directory)) => write_output(directory, converted, &mut written).await,
+                    (outcome, _) => outcome,
+                };
+                batch.record(&file.name(), input_hash, outcome, &job);
+            }
+            self.report_completion(&batch, index);
+        }
+        Ok(batch.finish())
+    }
+
+    // `convert_data`, answered from the result cache when it holds the output
+    async fn convert_cached(
+        &self,
+        file: &File,
+        data: &[u8],
+        input_hash: &str,
+        config: &ConversionConfig,
+        job: &Job<'_>,
+    ) -> Result<core::ConvertedFile, ConvertError> {
+        let hit = self.result_cache.borrow_mut().as_mut().map(|cache| cache.get(&file.name(), data, input_hash, config));
+        let Some(hit) = hit else {
+            return convert_data(&file.name(), &file.type_(), data, input_hash, config, job).await;
+        };
+        if let Some(converted) = hit {
+            self.record(&|stats| stats.cache_hits += 1);
+            self.emit(LogLevel::Info, LogEventKind::CacheHit, Some(&file.name()), serde_json::json!({ "input_hash": converted.input_hash }));
+            return Ok(converted);
+        }
+        let outcome = convert_data(&file.name(), &file.type_(), data, input_hash, config, job).await;
+        // Gone when the converter was disposed during the conversion
+        if let (Ok(converted), Some(cache)) = (&outcome, self.result_cache.borrow_mut().as_mut()) {
+            cache.insert(converted, config);
+        }
+        outcome
+    }
+
+    // Saves a successful conversion to the history when `history_max_bytes` is set. The
+    // history is a convenience, so when storage fails (private windows, a full quota)
+    // the file only gains a warning. Cache hits were saved when first converted.
+    async fn remember(&self, converted: &mut core::ConvertedFile, config: &ConversionConfig) {
+        let max_bytes = match self.options.history_max_bytes {
+            Some(max_bytes) if !converted.cache_hit => max_bytes,
+            _ => return,
+        };
+        if let Err(error) = history::save(converted, config, max_bytes).await {
+            self.emit(
+                LogLevel::Warn,
+                LogEventKind::History,
+                Some(&converted.original_name),
+                serde_json::json!({ "error": error.to_string() }),
+            );
+            converted.warnings.push(format!("Not saved to history: {}", error));
+        }
+    }
+
+    // With `reject_on_error`, a failed result rejects with its error message instead
+    fn resolve(&self, result: &core::ConversionResult) -> Result<JsValue, JsValue> {
+        match &result.error {
+            Some(error) if self.options.reject_on_error && !result.success => Err(JsValue::from_str(error)),
+            _ => Ok(serde_wasm_bindgen::to_value(result)?),
+        }
+    }
+
+    fn check_disposed(&self) -> Result<(), JsValue> {
+        if self.disposed.get() {
+            return Err(JsValue::from_str("Converter disposed"));
+        }
+        Ok(())
+    }
+
+    fn config(&self) -> Result<Rc<ConversionConfig>, JsValue> {
+        self.check_disposed()?;
+        self.config.borrow().clone().ok_or_else(|| JsValue::from_str("Configuration not set"))
+    }
+
+    async fn read_file(&self, file: &File) -> Result<Vec<u8>, JsValue> {
+        self.report_progress(&file.name(), ProgressStage::Reading, 0);
+        let array_buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await?;
+        let uint8_array = Uint8Array::new(&array_buffer);
+        let data = uint8_array.to_vec();
+        self.emit(
+            LogLevel::Info,
+            LogEventKind::Read,
+            Some(&file.name()),
+            serde_json::json!({ "bytes": data.len(), "mime": file.type_() }),
+        );
+        Ok(data)
+    }
+
+    fn emit(&self, level: LogLevel, event: LogEventKind, file: Option<&str>, fields: serde_json::Value) {
+        let callback = self.log_callback.borrow().clone();
+        if level < self.options.log_level || (callback.is_none() && !self.options.json_console) {
+            return;
+        }
+        let fields = match fields {
+            serde_json::Value::Object(map) => map,
+            _ => serde_json::Map::new(),
+        };
+        let event = LogEvent {
+            ts: js_sys::Date::now(),
+            level,
+            event,
+            file: file.map(str::to_string),
+            fields,
+        };
+
+        // Observability must never break a conversion, so sink errors are swallowed
+        if let Some(callback) = callback {
+            if let Ok(value) = event.serialize(&serde_wasm_bindgen::Serializer::json_compatible()) {
+                let _ = callback.call1(&JsValue::NULL, &value);
+            }
+        }
+        if self.options.json_console {
+            if let Ok(line) = serde_json::to_string(&event) {
+                log(&line);
+            }
+        }
+    }
+
+    fn record(&self, update: &dyn Fn(&mut SessionStats)) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    fn start_job(&self, signal: JsValue) -> Job<'_> {
+        Job {
+            converter: self,
+            signal: signal.dyn_into::<web_sys::AbortSignal>().ok(),
+            generation: self.cancel_generation.get(),
+            deadline: self.options.timeout_ms.map(|ms| js_sys::Date::now() + ms as f64),
+        }
+    }
+
+    fn report_completion(&self, batch: &core::Batch, file_index: usize) {
+        let (callback, completion) = match (self.file_callback.borrow().clone(), batch.completion(file_index)) {
+            (Some(callback), Some(completion)) => (callback, completion),
+            _ => return,
+        };
+        // Like progress handlers, a throwing handler must not stop the batch
+        if let Ok(value) = serde_wasm_bindgen::to_value(&completion) {
+            let _ = callback.call1(&JsValue::NULL, &value);
+        }
+    }
+
+    fn start_progress(&self, file_index: u32, file_count: u32) {
+        self.progress_position.set((file_index, file_count.max(1)));
+        self.progress_percent.set(0.0);
+    }
+
+    fn report_progress(&self, file_name: &str, stage: ProgressStage, pass: u32) {
+        let callback = match self.progress_callback.borrow().clone() {
+            Some(callback) => callback,
+            None => return,
+        };
+        let (file_index, file_count) = self.progress_position.get();
+        let file_percent = core::stage_percent(stage, pass);
+        let overall = ((file_index as f32 + file_percent / 100.0) / file_count as f32 * 100.0)
+            .max(self.progress_percent.get());
+        self.progress_percent.set(overall);
+
+        let event = ProgressEvent {
+            file_name: file_name.to_string(),
+            stage,
+            pass,
+            overall_percent: overall,
+            file_index,
+            file_count,
+        };
+        // A throwing progress handler must never abort the conversion
+        if let Ok(value) = serde_wasm_bindgen::to_value(&event) {
+            let _ = callback.call1(&JsValue::NULL, &value);
+        }
+    }
+}
+
+// Chrome's non-standard `performance.memory` gives the remaining JS heap; elsewhere
+// `navigator.deviceMemory` (device RAM in GB, coarsely rounded) is the only hint.
+// Looked up reflectively so workers and browsers without either API just get None.
+fn browser_available_memory() -> Option<u64> {
+    let get = |target: &JsValue, key: &str| {
+        js_sys::Reflect::get(target, &JsValue::from_str(key))
+            .ok()
+            .filter(|value| !value.is_undefined() && !value.is_null())
+    };
+    let global = js_sys::global().into();
+    if let Some(memory) = get(&global, "performance").and_then(|performance| get(&performance, "memory")) {
+        let limit = get(&memory, "jsHeapSizeLimit").and_then(|v| v.as_f64());
+        let used = get(&memory, "usedJSHeapSize").and_then(|v| v.as_f64());
+        if let (Some(limit), Some(used)) = (limit, used) {
+            return Some((limit - used).max(0.0) as u64);
+        }
+    }
+    get(&global, "navigator")
+        .and_then(|navigator| get(&navigator, "deviceMemory"))
+        .and_then(|gb| gb.as_f64())
+        .map(|gb| (gb * 1024.0 * 1024.0 * 1024.0) as u64)
+}
+
+// The page's or worker's wall clock in its own time zone, as a camera would stamp it
+pub(crate) fn browser_local_time() -> core::LocalTime {
+    let now = js_sys::Date::new_0();
+    core::LocalTime {
+        year: now.get_full_year() as u16,
+        month: now.get_month() as u8 + 1,
+        day: now.get_date() as u8,
+        hour: now.get_hours() as u8,
+        minute: now.get_minutes() as u8,
+        second: now.get_seconds() as u8,
+    }
+}
+
+/// Convert one read input, whose `sha256_hex` is `input_hash`. Formats this build has
+/// no encoder for (WebP) are encoded by the browser through `OffscreenCanvas.convertToBlob`,
+/// in windows and workers alike, running the core's quality loop one awaited encode at a
+/// time. When the browser cannot encode them or misses the size cap, the spec's later
+/// formats are tried as in the core. Without `OffscreenCanvas` they fail as unsupported,
+/// as in the core.
+pub(crate) async fn convert_data(
+    file_name: &str,
+    file_type: &str,
+    data: &[u8],
+    input_hash: &str,
+    config: &ConversionConfig,
+    observer: &dyn Observer,
+) -> Result<core::ConvertedFile, ConvertError> {
+    let has_canvas = js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("OffscreenCanvas")).unwrap_or(false);
+    if has_canvas {
+        if let Some(mut encode) = core::prepare_host_encode(file_name, file_type, data, config, observer)? {
+            let mime_type = core::get_mime_type(&encode.target_format);
+            while let Some(quality) = encode.next_quality(file_name, observer)? {
+                match canvas_encode(&encode.rgba, mime_type, quality).await {
+                    Ok(bytes) => encode.push(file_name, bytes, observer),
+                    Err(error) => return encode.fall_back(file_name, data, error, config, observer),
+                }
+            }
+            return encode.finish(file_name, data, "OffscreenCanvas", config, observer);
+        }
+    }
+    core::convert_hashed(file_name, file_type, data, input_hash, config, observer)
+}
+
+// Browsers fall back to PNG for types they cannot encode rather than failing, so the
+// blob's type is checked
+async fn canvas_encode(rgba: &image::RgbaImage, mime_type: &str, quality: u8) -> Result<Vec<u8>, ConvertError> {
+    let failed = |error: JsValue| ConvertError::Encode(format!("OffscreenCanvas encoding failed: {:?}", error));
+    let canvas = web_sys::OffscreenCanvas::new(rgba.width(), rgba.height()).map_err(failed)?;
+    let context: web_sys::OffscreenCanvasRenderingContext2d = canvas
+        .get_context("2d")
+        .map_err(failed)?
+        .ok_or_else(|| failed(JsValue::from_str("no 2d context")))?
+        .unchecked_into();
+    let pixels =
+        web_sys::ImageData::new_with_u8_clamped_array_and_sh(wasm_bindgen::Clamped(rgba.as_raw()), rgba.width(), rgba.height())
+            .map_err(failed)?;
+    context.put_image_data(&pixels, 0.0, 0.0).map_err(failed)?;
+
+    let options = web_sys::ImageEncodeOptions::new();
+    options.set_type(mime_type);
+    options.set_quality(quality as f64 / 100.0);
+    let promise = canvas.convert_to_blob_with_options(&options).map_err(failed)?;
+    let blob: web_sys::Blob = wasm_bindgen_futures::JsFuture::from(promise).await.map_err(failed)?.unchecked_into();
+    if blob.type_() != mime_type {
+        return Err(ConvertError::Encode(format!(
+            "This browser cannot encode {} (its canvas produced {})",
+            mime_type,
+            blob.type_()
+        )));
+    }
+    let buffer = wasm_bindgen_futures::JsFuture::from(blob.array_buffer()).await.map_err(failed)?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+// Moves a converted file's bytes into `directory`, leaving `path` in place of its data URL.
+// A failed write is aborted so no partial file is left behind.
+async fn write_output(
+    directory: &web_sys::FileSystemDirectoryHandle,
+    mut converted: core::ConvertedFile,
+    written: &mut HashSet<String>,
+) -> Result<core::ConvertedFile, ConvertError> {
+    let data = converted
+        .decode_data()
+        .ok_or_else(|| ConvertError::Write(format!("{} has an undecodable data URL", converted.converted_name)))?;
+    let mut name = core::unique_name(&converted.converted_name, written);
+    while entry_exists(directory, &name).await? {
+        name = core::unique_name(&converted.converted_name, written);
+    }
+    let options = web_sys::FileSystemGetFileOptions::new();
+    options.set_create(true);
+    let file: web_sys::FileSystemFileHandle =
+        wasm_bindgen_futures::JsFuture::from(directory.get_file_handle_with_options(&name, &options))
+            .await
+            .map_err(storage_error)?
+            .unchecked_into();
+    let writable: web_sys::FileSystemWritableFileStream =
+        wasm_bindgen_futures::JsFuture::from(file.create_writable()).await.map_err(storage_error)?.unchecked_into();
+    let write = match writable.write_with_u8_array(&data) {
+        Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = write {
+        let _ = wasm_bindgen_futures::JsFuture::from(writable.abort()).await;
+        return Err(storage_error(error));
+    }
+    wasm_bindgen_futures::JsFuture::from(writable.close()).await.map_err(storage_error)?;
+    converted.data_url = String::new();
+    converted.path = Some(name);
+    Ok(converted)
+}
+
+// Whether `directory` already holds a file or directory called `name`
+async fn entry_exists(directory: &web_sys::FileSystemDirectoryHandle, name: &str) -> Result<bool, ConvertError> {
+    let error = match wasm_bindgen_futures::JsFuture::from(directory.get_file_handle(name)).await {
+        Ok(_) => return Ok(true),
+        Err(error) => error,
+    };
+    match Reflect::get(&error, &JsValue::from_str("name")).ok().and_then(|name| name.as_string()).as_deref() {
+        Some("NotFoundError") => Ok(false),
+        // A directory of that name
+        Some("TypeMismatchError") => Ok(true),
+        _ => Err(storage_error(error)),
+    }
+}
+
+// File system and IndexedDB calls reject with DOMExceptions, told apart by name
+pub(crate) fn storage_error(error: JsValue) -> ConvertError {
+    let field = |key: &str| js_sys::Reflect::get(&error, &JsValue::from_str(key)).ok().and_then(|value| value.as_string());
+    let message = field("message").unwrap_or_else(|| format!("{:?}", error));
+    match field("name").as_deref() {
+        Some("QuotaExceededError") => ConvertError::QuotaExceeded(message),
+        Some("NotAllowedError" | "SecurityError") => ConvertError::PermissionDenied(message),
+        _ => ConvertError::Write(format!("Failed to write output: {}", message)),
+    }
+}
+
+// GETs `url` with the global `fetch` (present in windows and workers), sending
+// If-None-Match when an ETag is known. The body is None when the server answers 304.
+// `what` names the download in error messages.
+async fn fetch_text(url: &str, etag: Option<&String>, what: &str) -> Result<(web_sys::Response, Option<String>), JsValue> {
+    let headers = web_sys::Headers::new()?;
+    if let Some(etag) = etag {
+        headers.set("If-None-Match", etag)?;
+    }
+    let init = web_sys::RequestInit::new();
+    init.set_method("GET");
+    init.set_headers(&headers);
+    let request = web_sys::Request::new_with_str_and_init(url, &init)?;
+
+    let fetch: js_sys::Function = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("fetch"))?
+        .dyn_into()
+        .map_err(|_| JsValue::from_str("fetch is not available in this context"))?;
+    let promise: js_sys::Promise = fetch.call1(&JsValue::NULL, &request)?.dyn_into()?;
+    // Browsers reject a CORS refusal with the same bare TypeError as a dropped
+    // connection, and hide which it was from the page
+    let response: web_sys::Response = wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(|error| {
+            let message = js_sys::Reflect::get(&error, &JsValue::from_str("message")).ok().and_then(|message| message.as_string());
+            JsValue::from_str(&format!(
+                "Failed to fetch {} from {}: {}. The server may be unreachable, or it may not allow requests from this origin (CORS)",
+                what,
+                url,
+                message.unwrap_or_else(|| format!("{:?}", error))
+            ))
+        })?
+        .dyn_into()?;
+    if response.status() == 304 {
+        return Ok((response, None));
+    }
+    if !response.ok() {
+        return Err(JsValue::from_str(&format!("Failed to fetch {} from {}: HTTP {}", what, url, response.status())));
+    }
+    let text = wasm_bindgen_futures::JsFuture::from(response.text()?).await?;
+    Ok((response, text.as_string()))
+}
+
+fn to_js(error: ConvertError) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+fn read_error(error: JsValue) -> ConvertError {
+    ConvertError::Read(format!("Failed to read file: {:?}", error))
+}
+
+// Announce the build when the module loads, as the single-line JSON `json_console` writes
+#[wasm_bindgen(start)]
+pub fn main() {
+    if let Ok(line) = serde_json::to_string(&core::startup_event(js_sys::Date::now())) {
+        log(&line);
+    }
+}
+
+/// Version, git commit, cargo profile and enabled features of this build, to tell
+/// which converter a browser has cached when triaging a report.
+#[wasm_bindgen(unchecked_return_type = "BuildInfo")]
+pub fn version() -> JsValue {
+    serde_wasm_bindgen::to_value(&core::build_info()).unwrap_or(JsValue::NULL)
+}
+
+/// Names of the image formats this build can decode, e.g. `["JPEG", "PNG", "WEBP"]`.
+/// Codecs are chosen with cargo features, so a trimmed build may list fewer.
+#[wasm_bindgen(unchecked_return_type = "string[]")]
+pub fn supported_input_formats() -> JsValue {
+    serde_wasm_bindgen::to_value(&core::supported_input_formats()).unwrap_or(JsValue::NULL)
+}
+
+/// Target formats this build can produce; `PDF` is always present since PDFs pass through.
+#[wasm_bindgen(unchecked_return_type = "string[]")]
+pub fn supported_output_formats() -> JsValue {
+    serde_wasm_bindgen::to_value(&core::supported_output_formats()).unwrap_or(JsValue::NULL)
+}
@@ -0,0 +1,217 @@
+//! Message protocol for hosting the converter in a Web Worker, so conversions never
+//! block the page. The worker script is only glue:
+//!
+//! ```js
+//! import init, { handle_message } from "./document_converter.js";
+//! const ready = init();
+//! self.onmessage = async ({ data }) => {
+//!   const reply = await ready.then(() => handle_message(data));
+//!   self.postMessage(reply, reply.buffers ?? []);
+//! };
+//! ```
+//!
+//! `js/worker_client.js` is the matching page side. Requests are
+//! `{ id, op: "set_config" | "convert" | "cancel", payload }` and each gets one final
+//! reply, `{ id, result, buffers, types }` or `{ id, error }`. While converting, the
+//! worker also posts `{ id, progress }` messages itself. Output bytes come back only as
+//! transferable `ArrayBuffer`s in `buffers`, one per `result.files` entry with its MIME
+//! type in `types`; the files' `data_url`s are left empty so the bytes are not sent
+//! twice, and the page builds a `Blob` (or data URL) from the buffer when it needs one.
+
+use js_sys::{Reflect, Uint8Array};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+use crate::core::{self, ConversionConfig, ConvertError, Observer, ProgressEvent, ProgressStage};
+use crate::wasm::{browser_local_time, convert_data};
+
+#[wasm_bindgen(typescript_custom_section)]
+const WORKER_TYPES: &str = r#"
+export interface WorkerFile {
+    name: string;
+    type: string;
+    data: ArrayBuffer | Uint8Array;
+}
+
+export type WorkerRequest =
+    | { id: number; op: "set_config"; payload: ConversionConfig | string }
+    | { id: number; op: "convert"; payload: { files: WorkerFile[] } }
+    | { id: number; op: "cancel"; payload?: null };
+
+export type WorkerReply =
+    | { id: number; progress: ProgressEvent }
+    | { id: number; result: ConversionResult | null; buffers: ArrayBuffer[]; types: string[] }
+    | { id: number; error: string };
+"#;
+
+// The worker's converter state. Conversions clone the config out before their first
+// await, so a set_config arriving mid-batch applies to the next convert only.
+#[derive(Default)]
+struct WorkerState {
+    config: RefCell<Option<Rc<ConversionConfig>>>,
+    // Bumped by "cancel"; conversions started under an older generation stop
+    cancel_generation: Cell<u32>,
+}
+
+// A final reply's result, with the output bytes and their MIME types for "convert"
+type Reply = (JsValue, js_sys::Array, js_sys::Array);
+
+thread_local! {
+    static STATE: WorkerState = WorkerState::default();
+}
+
+// Progress and cancellation for one file of a "convert" request
+struct WorkerJob<'a> {
+    id: f64,
+    generation: u32,
+    file_index: u32,
+    file_count: u32,
+    // Highest overall percent posted so far, so progress never moves backwards
+    percent: &'a Cell<f32>,
+}
+
+impl Observer for WorkerJob<'_> {
+    fn progress(&self, file_name: &str, stage: ProgressStage, pass: u32) {
+        let file_percent = core::stage_percent(stage, pass);
+        let overall = ((self.file_index as f32 + file_percent / 100.0) / self.file_count as f32 * 100.0)
+            .max(self.percent.get());
+        self.percent.set(overall);
+        let event = ProgressEvent {
+            file_name: file_name.to_string(),
+            stage,
+            pass,
+            overall_percent: overall,
+            file_index: self.file_index,
+            file_count: self.file_count,
+        };
+        if let Ok(progress) = serde_wasm_bindgen::to_value(&event) {
+            post_progress(&reply(self.id, "progress", &progress));
+        }
+    }
+
+    fn local_time(&self) -> Option<core::LocalTime> {
+        Some(browser_local_time())
+    }
+
+    fn is_cancelled(&self) -> bool {
+        STATE.with(|state| state.cancel_generation.get() != self.generation)
+    }
+}
+
+/// Handle one request from the page and resolve to its final reply, which the worker
+/// script posts with `reply.buffers` as the transfer list. Never rejects: failures
+/// resolve to `{ id, error }` with the message the direct API would reject with.
+#[wasm_bindgen(unchecked_return_type = "WorkerReply")]
+pub async fn handle_message(#[wasm_bindgen(unchecked_param_type = "WorkerRequest")] message: JsValue) -> JsValue {
+    let id = get(&message, "id").as_f64().unwrap_or(f64::NAN);
+    let payload = get(&message, "payload");
+    let outcome = match get(&message, "op").as_string().as_deref() {
+        Some("set_config") => set_config(&payload).map(|()| (JsValue::NULL, js_sys::Array::new(), js_sys::Array::new())),
+        Some("convert") => convert(id, &payload).await,
+        Some("cancel") => {
+            STATE.with(|state| state.cancel_generation.set(state.cancel_generation.get().wrapping_add(1)));
+            Ok((JsValue::NULL, js_sys::Array::new(), js_sys::Array::new()))
+        }
+        op => Err(JsValue::from_str(&format!("Unknown worker op {:?}, expected set_config, convert or cancel", op))),
+    };
+    match outcome {
+        Ok((result, buffers, types)) => {
+            let message = reply(id, "result", &result);
+            let _ = Reflect::set(&message, &JsValue::from_str("buffers"), &buffers);
+            let _ = Reflect::set(&message, &JsValue::from_str("types"), &types);
+            message
+        }
+        Err(error) => reply(id, "error", &error),
+    }
+}
+
+// Takes the config as an object or as the JSON string `set_config` takes
+fn set_config(payload: &JsValue) -> Result<(), JsValue> {
+    let json = match payload.as_string() {
+        Some(json) => json,
+        None => js_sys::JSON::stringify(payload)?.as_string().unwrap_or_default(),
+    };
+    let config = core::parse_config(&json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    STATE.with(|state| *state.config.borrow_mut() = Some(Rc::new(config)));
+    Ok(())
+}
+
+async fn convert(id: f64, payload: &JsValue) -> Result<Reply, JsValue> {
+    let config = STATE
+        .with(|state| state.config.borrow().clone())
+        .ok_or_else(|| JsValue::from_str("Configuration not set"))?;
+    let files: js_sys::Array = get(payload, "files")
+        .dyn_into()
+        .map_err(|_| JsValue::from_str("convert expects payload.files, an array of { name, type, data }"))?;
+    let generation = STATE.with(|state| state.cancel_generation.get());
+    let percent = Cell::new(0.0);
+
+    let mut batch = core::Batch::new(&config, HashSet::new(), files.length() as usize);
+    for (index, file) in files.iter().enumerate() {
+        // Let a "cancel" queued behind this request run before the next file starts
+        yield_to_event_loop().await;
+        let job = WorkerJob { id, generation, file_index: index as u32, file_count: files.length().max(1), percent: &percent };
+        if batch.should_stop(&job) {
+            break;
+        }
+        let name = get(&file, "name").as_string().unwrap_or_default();
+        let file_type = get(&file, "type").as_string().unwrap_or_else(|| core::mime_type_from_name(&name).to_string());
+        let data = get(&file, "data");
+        let data = if data.is_instance_of::<js_sys::ArrayBuffer>() || data.is_instance_of::<Uint8Array>() {
+            Ok(Uint8Array::new(&data).to_vec())
+        } else {
+            Err(ConvertError::Read(format!("Failed to read file: {} has no ArrayBuffer data", name)))
+        };
+        if let Some((data, input_hash)) = batch.admit(&name, data, &job) {
+            let outcome = convert_data(&name, &file_type, &data, &input_hash, &config, &job).await;
+            batch.record(&name, input_hash, outcome, &job);
+        }
+    }
+    let mut result = batch.finish();
+
+    let (buffers, types) = (js_sys::Array::new(), js_sys::Array::new());
+    for file in &mut result.files {
+        let (mime_type, data) = core::parse_data_url(&file.data_url).unwrap_or_default();
+        buffers.push(&Uint8Array::from(&data[..]).buffer());
+        types.push(&JsValue::from_str(&mime_type));
+        file.data_url = String::new();
+    }
+    Ok((serde_wasm_bindgen::to_value(&result)?, buffers, types))
+}
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    Reflect::get(target, &JsValue::from_str(key)).unwrap_or(JsValue::UNDEFINED)
+}
+
+fn reply(id: f64, key: &str, value: &JsValue) -> JsValue {
+    let message = js_sys::Object::new();
+    let _ = Reflect::set(&message, &JsValue::from_str("id"), &JsValue::from_f64(id));
+    let _ = Reflect::set(&message, &JsValue::from_str(key), value);
+    message.into()
+}
+
+// Progress goes straight to the page from inside a worker. Outside one (a page calling
+// handle_message directly) there is no one to post to and it is dropped.
+fn post_progress(message: &JsValue) {
+    let global = js_sys::global();
+    if get(&global, "WorkerGlobalScope").is_undefined() {
+        return;
+    }
+    if let Ok(post) = get(&global, "postMessage").dyn_into::<js_sys::Function>() {
+        let _ = post.call1(&global, message);
+    }
+}
+
+// A macrotask turn, so message events queued behind the running request get handled
+async fn yield_to_event_loop() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        if let Ok(set_timeout) = get(&js_sys::global(), "setTimeout").dyn_into::<js_sys::Function>() {
+            let _ = set_timeout.call2(&JsValue::NULL, &resolve, &JsValue::from(0));
+        } else {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
@@ -0,0 +1,130 @@
+//! End-to-end runs of the `summa2` binary against the shared conversion core.
+
+use document_converter::core::{self, ConversionResult};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn workdir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_config(dir: &Path, spec: serde_json::Value) -> PathBuf {
+    let path = dir.join("spec.json");
+    let config = serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": spec,
+    });
+    std::fs::write(&path, config.to_string()).unwrap();
+    path
+}
+
+fn write_png(dir: &Path, name: &str, width: u32, height: u32) -> PathBuf {
+    let img = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8])
+    });
+    let path = dir.join(name);
+    img.save(&path).unwrap();
+    path
+}
+
+fn run(config: &Path, out: &Path, inputs: &[&Path]) -> (Output, Option<ConversionResult>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_summa2"))
+        .arg("convert")
+        .arg("--config")
+        .arg(config)
+        .arg("--out")
+        .arg(out)
+        .args(inputs)
+        .output()
+        .unwrap();
+    let report = serde_json::from_slice(&output.stdout).ok();
+    (output, report)
+}
+
+#[test]
+fn converts_images_and_pdfs_into_out_dir() {
+    let dir = workdir("converts");
+    let config = write_config(&dir, serde_json::json!({
+        "format": ["JPEG", "PDF"],
+        "size_kb": { "max": 100 },
+        "pixels": { "width": 200, "height": 250 }
+    }));
+    let photo = write_png(&dir, "photo.png", 600, 800);
+    let pdf = dir.join("marks.pdf");
+    std::fs::write(&pdf, b"%PDF-1.4\n%%EOF\n").unwrap();
+    let out = dir.join("out");
+
+    let (output, report) = run(&config, &out, &[&photo, &pdf]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = report.unwrap();
+    assert!(report.success);
+    let names: Vec<&str> = report.files.iter().map(|f| f.converted_name.as_str()).collect();
+    assert_eq!(names, ["photo_photo.jpg", "photo_marks.pdf"]);
+
+    let jpeg = std::fs::read(out.join("photo_photo.jpg")).unwrap();
+    assert_eq!(report.files[0].input_hash, core::sha256_hex(&std::fs::read(&photo).unwrap()));
+    assert_eq!(report.files[0].decode_data().unwrap(), jpeg);
+    let decoded = image::load_from_memory(&jpeg).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (200, 250));
+    assert_eq!(std::fs::read(out.join("photo_marks.pdf")).unwrap(), b"%PDF-1.4\n%%EOF\n");
+}
+
+#[test]
+fn report_matches_library_conversion() {
+    let dir = workdir("matches_library");
+    let spec = serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 50 }, "max_megapixels": 0.1 });
+    let config = write_config(&dir, spec.clone());
+    let photo = write_png(&dir, "scan.png", 640, 480);
+
+    let (output, report) = run(&config, &dir.join("out"), &[&photo]);
+    assert!(output.status.success());
+    let from_cli = &report.unwrap().files[0];
+
+    let config = core::parse_config(&std::fs::read_to_string(&config).unwrap()).unwrap();
+    let data = std::fs::read(&photo).unwrap();
+    let from_library = core::convert("scan.png", "image/png", &data, &config, &core::NoopObserver).unwrap();
+    assert_eq!(from_cli.data_url, from_library.data_url);
+    assert_eq!(from_cli.transformations, from_library.transformations);
+}
+
+#[test]
+fn failed_inputs_are_reported_with_exit_code_1() {
+    let dir = workdir("failures");
+    let config = write_config(&dir, serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 1 } }));
+    let pdf = dir.join("big.pdf");
+    std::fs::write(&pdf, vec![b'%'; 4096]).unwrap();
+    let missing = dir.join("missing.png");
+
+    let (output, report) = run(&config, &dir.join("out"), &[&pdf, &missing]);
+    assert_eq!(output.status.code(), Some(1));
+    let report = report.unwrap();
+    assert!(!report.success);
+    assert_eq!(report.error.as_deref(), Some("2 of 2 files failed to convert"));
+    assert_eq!(
+        report.failures[0].error,
+        "Conversion failed: PDF file too large: 4KB, maximum allowed: 1KB; the smallest achieved was 4KB. Reduce the \
+         resolution of its page images, or set pdf_rasterize_dpi to rasterize the pages"
+    );
+    assert_eq!(report.failures[1].original_name, "missing.png");
+    assert_eq!(report.failures[1].input_hash, None);
+}
+
+#[test]
+fn invalid_config_exits_with_code_2() {
+    let dir = workdir("invalid_config");
+    let config = write_config(&dir, serde_json::json!({
+        "format": ["JPEG"],
+        "size_kb": { "max": 100 },
+        "posterize_levels": 1
+    }));
+    let photo = write_png(&dir, "photo.png", 10, 10);
+
+    let (output, report) = run(&config, &dir.join("out"), &[&photo]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(report.is_none());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("posterize_levels must be at least 2"));
+}
@@ -0,0 +1,151 @@
+//! Golden-fixture expectations shared by the native (`tests/golden.rs`) and browser
+//! (`tests/web.rs`) suites. Cases live in `tests/fixtures/golden.json`; after an
+//! intentional output change, regenerate them with
+//! `GOLDEN_BLESS=1 cargo test --test golden` and review the diff.
+
+#![allow(dead_code)]
+
+use document_converter::core::{sha256_hex, ConvertedFile};
+use serde::{Deserialize, Serialize};
+
+pub const GOLDEN_JSON: &str = include_str!("../fixtures/golden.json");
+
+// Relative size band written when blessing; re-encoding noise stays well inside it
+const SIZE_BAND: f64 = 0.1;
+// Sample positions as fractions of the output, written when blessing
+const SAMPLE_POINTS: [(f32, f32); 5] = [(0.1, 0.1), (0.9, 0.1), (0.5, 0.5), (0.1, 0.9), (0.9, 0.9)];
+const LOSSY_TOLERANCE: u8 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GoldenCase {
+    pub name: String,
+    pub fixture: String,
+    pub mime: String,
+    pub spec: serde_json::Value,
+    // Also run by the wasm-bindgen suite
+    #[serde(default)]
+    pub wasm: bool,
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Expectation {
+    pub format: String,
+    pub dimensions: Option<[u32; 2]>,
+    pub size_bytes: [usize; 2],
+    pub samples: Vec<Sample>,
+    pub tolerance: u8,
+    // SHA-256 of the output bytes, so any change to them shows in the blessed diff
+    #[serde(default)]
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sample {
+    pub x: u32,
+    pub y: u32,
+    pub rgba: [u8; 4],
+}
+
+pub fn cases() -> Vec<GoldenCase> {
+    serde_json::from_str(GOLDEN_JSON).expect("tests/fixtures/golden.json is malformed")
+}
+
+pub fn fixture(name: &str) -> &'static [u8] {
+    match name {
+        "photo.jpg" => include_bytes!("../fixtures/photo.jpg"),
+        "transparent.png" => include_bytes!("../fixtures/transparent.png"),
+        "document.pdf" => include_bytes!("../fixtures/document.pdf"),
+        other => panic!("unknown golden fixture {}", other),
+    }
+}
+
+pub fn config_json(case: &GoldenCase) -> String {
+    serde_json::json!({
+        "exam_type": "GOLDEN",
+        "document_type": case.name,
+        "target_spec": case.spec,
+    })
+    .to_string()
+}
+
+/// Every way `converted` differs from the case's expectations.
+pub fn check(case: &GoldenCase, converted: &ConvertedFile) -> Vec<String> {
+    let expect = &case.expect;
+    let mut mismatches = Vec::new();
+    let mut mismatch = |message: String| mismatches.push(format!("{}: {}", case.name, message));
+
+    if converted.format != expect.format {
+        mismatch(format!("format {} != {}", converted.format, expect.format));
+    }
+    let data = match converted.decode_data() {
+        Some(data) => data,
+        None => {
+            mismatch("data URL does not decode".to_string());
+            return mismatches;
+        }
+    };
+    let sha256 = sha256_hex(&data);
+    if sha256 != expect.sha256 {
+        mismatch(format!("output sha256 {} != {}", sha256, expect.sha256));
+    }
+    let [min, max] = expect.size_bytes;
+    if !(min..=max).contains(&data.len()) {
+        mismatch(format!("size {} outside {}..={}", data.len(), min, max));
+    }
+
+    let dimensions = converted.dimensions.as_ref().map(|d| [d.width as u32, d.height as u32]);
+    if dimensions != expect.dimensions {
+        mismatch(format!("dimensions {:?} != {:?}", dimensions, expect.dimensions));
+    }
+    if expect.samples.is_empty() {
+        return mismatches;
+    }
+    let decoded = match image::load_from_memory(&data) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => {
+            mismatch(format!("output does not decode: {}", e));
+            return mismatches;
+        }
+    };
+    for sample in &expect.samples {
+        let actual = decoded.get_pixel(sample.x, sample.y).0;
+        let off = actual.iter().zip(sample.rgba.iter()).any(|(a, b)| a.abs_diff(*b) > expect.tolerance);
+        if off {
+            mismatch(format!("pixel ({}, {}) {:?} != {:?} ±{}", sample.x, sample.y, actual, sample.rgba, expect.tolerance));
+        }
+    }
+    mismatches
+}
+
+/// Expectations describing `converted` as it is now.
+pub fn bless(converted: &ConvertedFile) -> Expectation {
+    let data = converted.decode_data().expect("data URL does not decode");
+    let dimensions = converted.dimensions.as_ref().map(|d| [d.width as u32, d.height as u32]);
+    let samples = match (dimensions, image::load_from_memory(&data)) {
+        (Some([width, height]), Ok(img)) => {
+            let rgba = img.to_rgba8();
+            SAMPLE_POINTS
+                .iter()
+                .map(|&(fx, fy)| {
+                    let (x, y) = ((width as f32 * fx) as u32, (height as f32 * fy) as u32);
+                    Sample { x, y, rgba: rgba.get_pixel(x, y).0 }
+                })
+                .collect()
+        }
+        _ => vec![],
+    };
+    let lossy = matches!(converted.format.as_str(), "JPEG" | "JPG");
+    Expectation {
+        format: converted.format.clone(),
+        dimensions,
+        size_bytes: [
+            (data.len() as f64 * (1.0 - SIZE_BAND)).floor() as usize,
+            (data.len() as f64 * (1.0 + SIZE_BAND)).ceil() as usize,
+        ],
+        samples,
+        tolerance: if lossy { LOSSY_TOLERANCE } else { 0 },
+        sha256: sha256_hex(&data),
+    }
+}
@@ -0,0 +1,31 @@
+//! Golden-fixture regression checks for the conversion core. Set `GOLDEN_BLESS=1`
+//! to rewrite `tests/fixtures/golden.json` from the current output instead.
+
+mod common;
+
+use common::golden;
+use document_converter::core;
+
+#[test]
+fn outputs_match_golden_expectations() {
+    let bless = std::env::var_os("GOLDEN_BLESS").is_some();
+    let mut cases = golden::cases();
+    let mut mismatches = Vec::new();
+    for case in &mut cases {
+        let config = core::parse_config(&golden::config_json(case)).unwrap();
+        let data = golden::fixture(&case.fixture);
+        let converted = core::convert(&case.fixture, &case.mime, data, &config, &core::NoopObserver)
+            .unwrap_or_else(|e| panic!("{}: {}", case.name, e));
+        if bless {
+            case.expect = golden::bless(&converted);
+        } else {
+            mismatches.extend(golden::check(case, &converted));
+        }
+    }
+
+    if bless {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden.json");
+        std::fs::write(path, serde_json::to_string_pretty(&cases).unwrap() + "\n").unwrap();
+    }
+    assert!(mismatches.is_empty(), "golden mismatches (rerun with GOLDEN_BLESS=1 if intended):\n{}", mismatches.join("\n"));
+}
@@ -0,0 +1,114 @@
+//! Keeps `types/summa2.d.ts` in step with the serde types it describes. The wasm
+//! package re-exports that file, so a stale copy means integrators get wrong types. After
+//! changing a JS-facing type, regenerate it with
+//! `GOLDEN_BLESS=1 cargo test --features typescript --test typescript`.
+
+use document_converter::core::*;
+use ts_rs::TS;
+
+const DECLARATIONS: &str = include_str!("../types/summa2.d.ts");
+
+const HEADER: &str = "// Generated from the Rust serde types by tests/typescript.rs. Do not edit by hand.\n";
+
+fn generate() -> String {
+    let declarations = [
+        ConversionConfig::decl(),
+        DocumentSpec::decl(),
+        SizeSpec::decl(),
+        DimensionsSpec::decl(),
+        PhysicalDimensionsSpec::decl(),
+        PixelSpec::decl(),
+        PixelDimensions::decl(),
+        AspectRatioSpec::decl(),
+        AutoTrimSpec::decl(),
+        DocumentScanSpec::decl(),
+        AutoRotateSpec::decl(),
+        ScannerEdgesSpec::decl(),
+        DespeckleSpec::decl(),
+        AutoContrast::decl(),
+        ClaheSpec::decl(),
+        MemoryCheckSpec::decl(),
+        ExtensionCase::decl(),
+        SourceProfile::decl(),
+        FeatureLossPolicy::decl(),
+        DensityUnit::decl(),
+        DctMethod::decl(),
+        ColorMode::decl(),
+        ColorMismatchPolicy::decl(),
+        PrintOrientation::decl(),
+        FitMode::decl(),
+        MissingFormatPolicy::decl(),
+        InkColorSpec::decl(),
+        InkColor::decl(),
+        InkRange::decl(),
+        BackgroundSpec::decl(),
+        BorderSpec::decl(),
+        CanvasSpec::decl(),
+        PngProfile::decl(),
+        OutputColorType::decl(),
+        ColorType::decl(),
+        QualityScale::decl(),
+        PerformanceProfile::decl(),
+        TimestampOverlaySpec::decl(),
+        OverlayPosition::decl(),
+        TimestampSource::decl(),
+        ConverterOptions::decl(),
+        ConversionResult::decl(),
+        ConvertedFile::decl(),
+        FailedFile::decl(),
+        SkippedFile::decl(),
+        FileCompletion::decl(),
+        TilePosition::decl(),
+        Geometry::decl(),
+        Region::decl(),
+        Insets::decl(),
+        PreviewPair::decl(),
+        ThumbnailPair::decl(),
+        Thumbnail::decl(),
+        FormatFallback::decl(),
+        EncodeAttempt::decl(),
+        Transformation::decl(),
+        DebugIntermediate::decl(),
+        ConversionPlan::decl(),
+        PresetLoadReport::decl(),
+        RejectedPreset::decl(),
+        DetectedInk::decl(),
+        BackgroundReport::decl(),
+        ValidationReport::decl(),
+        HistoryEntry::decl(),
+        ImageAnalysis::decl(),
+        LuminanceStats::decl(),
+        SelfTestReport::decl(),
+        BuildInfo::decl(),
+        CodecCheck::decl(),
+        CodecInfo::decl(),
+        SessionStats::decl(),
+        ProgressEvent::decl(),
+        ProgressStage::decl(),
+        LogEvent::decl(),
+        LogLevel::decl(),
+        LogEventKind::decl(),
+        serde_json::Value::decl(),
+    ];
+    let mut out = HEADER.to_string();
+    for declaration in declarations {
+        out.push_str("\nexport ");
+        out.push_str(&declaration);
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn declarations_match_rust_types() {
+    let generated = generate();
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("types/summa2.d.ts");
+        std::fs::write(path, &generated).unwrap();
+        return;
+    }
+    assert!(
+        generated == DECLARATIONS,
+        "types/summa2.d.ts is out of date; rerun with GOLDEN_BLESS=1 and commit the result"
+    );
+}
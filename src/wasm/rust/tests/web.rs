@@ -0,0 +1,505 @@
+//! Tests against the JS-facing API, run in a headless browser:
+//! `wasm-pack test --headless --chrome` (or `--firefox`).
+
+#![cfg(target_arch = "wasm32")]
+
+mod common;
+
+use common::golden;
+use document_converter::core::{startup_event, BuildInfo, ConversionResult, ConverterOptions, FileCompletion, HistoryEntry, LogLevel, PreviewPair, SessionStats, ThumbnailPair, Transformation, ValidationReport};
+use document_converter::{handle_message, version, DocumentConverter};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const PHOTO_JPG: &[u8] = include_bytes!("fixtures/photo.jpg");
+const TRANSPARENT_PNG: &[u8] = include_bytes!("fixtures/transparent.png");
+const DOCUMENT_PDF: &[u8] = include_bytes!("fixtures/document.pdf");
+
+fn file(bytes: &[u8], name: &str, mime: &str) -> web_sys::File {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = web_sys::FilePropertyBag::new();
+    options.set_type(mime);
+    web_sys::File::new_with_u8_array_sequence_and_options(&parts, name, &options).unwrap()
+}
+
+fn converter(spec: serde_json::Value) -> DocumentConverter {
+    let config = serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": spec,
+    });
+    let mut converter = DocumentConverter::new();
+    converter.set_config(&config.to_string()).unwrap();
+    converter
+}
+
+async fn convert(converter: &DocumentConverter, file: web_sys::File) -> ConversionResult {
+    let value = converter.convert_file(file, JsValue::UNDEFINED).await.unwrap();
+    serde_wasm_bindgen::from_value(value).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn set_config_reports_parse_errors() {
+    let mut converter = DocumentConverter::new();
+    let error = converter.set_config("{\"exam_type\":").unwrap_err().as_string().unwrap();
+    assert!(error.starts_with("Invalid config: "), "{}", error);
+
+    let invalid = serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 }, "posterize_levels": 1 },
+    });
+    let error = converter.set_config(&invalid.to_string()).unwrap_err().as_string().unwrap();
+    assert_eq!(error, "Invalid config: posterize_levels must be at least 2, got 1");
+}
+
+#[wasm_bindgen_test]
+async fn config_is_fetched_from_a_url() {
+    let json_url = |config: serde_json::Value| format!("data:application/json,{}", js_sys::encode_uri_component(&config.to_string()));
+    let config = serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } },
+    });
+    let mut converter = DocumentConverter::new();
+    converter.set_config_from_url(json_url(config.clone())).await.unwrap();
+    assert!(convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await.success);
+
+    let mut invalid = config;
+    invalid["target_spec"]["posterize_levels"] = serde_json::json!(1);
+    let url = json_url(invalid);
+    let error = converter.set_config_from_url(url.clone()).await.unwrap_err().as_string().unwrap();
+    assert_eq!(error, format!("Config from {} was rejected: Invalid config: posterize_levels must be at least 2, got 1", url));
+
+    let error = converter.set_config_from_url("http://127.0.0.1:9/config.json".to_string()).await.unwrap_err().as_string().unwrap();
+    assert!(error.starts_with("Failed to fetch config from http://127.0.0.1:9/config.json: ") && error.ends_with("(CORS)"), "{}", error);
+}
+
+#[wasm_bindgen_test]
+async fn convert_file_requires_config() {
+    let error = DocumentConverter::new()
+        .convert_file(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), JsValue::UNDEFINED)
+        .await
+        .unwrap_err();
+    assert_eq!(error.as_string().as_deref(), Some("Configuration not set"));
+}
+
+#[wasm_bindgen_test]
+fn options_round_trip_and_are_validated() {
+    let options = serde_wasm_bindgen::to_value(&serde_json::json!({ "log_level": "warn", "timeout_ms": 5000 })).unwrap();
+    let converter = DocumentConverter::with_options(options).unwrap();
+    let options: ConverterOptions = serde_wasm_bindgen::from_value(converter.get_options().unwrap()).unwrap();
+    assert_eq!(options, ConverterOptions { log_level: LogLevel::Warn, timeout_ms: Some(5000), ..ConverterOptions::default() });
+
+    let invalid = serde_wasm_bindgen::to_value(&serde_json::json!({ "timeout_ms": 0 })).unwrap();
+    let error = DocumentConverter::with_options(invalid).err().unwrap().as_string().unwrap();
+    assert_eq!(error, "Invalid options: timeout_ms must be positive, got 0");
+}
+
+#[wasm_bindgen_test]
+async fn in_memory_batches_are_capped() {
+    let options = serde_wasm_bindgen::to_value(&serde_json::json!({ "max_batch_size": 1 })).unwrap();
+    let mut converter = DocumentConverter::with_options(options).unwrap();
+    let config = serde_json::json!({ "exam_type": "NEET", "document_type": "photo", "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } } });
+    converter.set_config(&config.to_string()).unwrap();
+    let files = || js_sys::Array::of2(&file(PHOTO_JPG, "a.jpg", "image/jpeg"), &file(PHOTO_JPG, "b.jpg", "image/jpeg"));
+    let expected = "2 files exceed max_batch_size 1; convert them in smaller batches or into an output directory";
+    let zip = converter.convert_files_to_zip(files(), JsValue::UNDEFINED).await.unwrap_err();
+    assert_eq!(zip.as_string().as_deref(), Some(expected));
+    let sheet = converter.contact_sheet(files(), 2, None).await.unwrap_err();
+    assert_eq!(sheet.as_string().as_deref(), Some(expected));
+}
+
+#[wasm_bindgen_test]
+async fn conversions_are_kept_in_history() {
+    let config = serde_json::json!({
+        "exam_type": "NEET",
+        "document_type": "photo",
+        "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } },
+    });
+    let with_history = |max_bytes: u32| {
+        let options = serde_wasm_bindgen::to_value(&serde_json::json!({ "history_max_bytes": max_bytes })).unwrap();
+        let mut converter = DocumentConverter::with_options(options).unwrap();
+        converter.set_config(&config.to_string()).unwrap();
+        converter
+    };
+    let converter = with_history(1_000_000);
+    converter.clear_history().await.unwrap();
+    let result = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    assert!(result.success && result.files[0].warnings.iter().all(|w| !w.contains("history")));
+
+    let entries: Vec<HistoryEntry> = serde_wasm_bindgen::from_value(converter.list_history().await.unwrap()).unwrap();
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(
+        (entry.converted_name.as_str(), entry.exam_type.as_str(), entry.document_type.as_str(), entry.size_bytes),
+        (result.files[0].converted_name.as_str(), "NEET", "photo", result.files[0].size_bytes)
+    );
+    let item = converter.get_history_item(entry.id).await.unwrap();
+    let blob: web_sys::Blob = js_sys::Reflect::get(&item, &"blob".into()).unwrap().into();
+    assert_eq!((blob.size() as u32, blob.type_()), (entry.size_bytes, "image/jpeg".to_string()));
+
+    // A file over the cap is not saved, but still converts
+    let result = convert(&with_history(10), file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    assert!(result.success);
+    assert!(result.files[0].warnings.iter().any(|w| w.starts_with("Not saved to history:")), "{:?}", result.files[0].warnings);
+
+    converter.clear_history().await.unwrap();
+    assert!(converter.get_history_item(entry.id).await.unwrap().is_undefined());
+    assert_eq!(js_sys::Array::from(&converter.list_history().await.unwrap()).length(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn repeat_conversions_come_from_the_result_cache() {
+    let options = serde_wasm_bindgen::to_value(&serde_json::json!({ "result_cache_bytes": 1_000_000 })).unwrap();
+    let mut converter = DocumentConverter::with_options(options).unwrap();
+    let config = serde_json::json!({ "exam_type": "NEET", "document_type": "photo", "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } } });
+    converter.set_config(&config.to_string()).unwrap();
+
+    let first = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await.files.remove(0);
+    let again = convert(&converter, file(PHOTO_JPG, "again.jpg", "image/jpeg")).await.files.remove(0);
+    assert!(!first.cache_hit && again.cache_hit);
+    assert_eq!((again.converted_name.as_str(), &again.data_url), ("photo_again.jpg", &first.data_url));
+    let stats: SessionStats = serde_wasm_bindgen::from_value(converter.get_stats().unwrap()).unwrap();
+    assert_eq!((stats.files_succeeded, stats.cache_hits), (2, 1));
+
+    converter.clear_cache();
+    assert!(!convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await.files[0].cache_hit);
+}
+
+#[wasm_bindgen_test]
+async fn single_file_helpers_count_files_in_stats() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    converter.tile_image(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), 256).await.unwrap();
+    converter.convert_to_target_bytes(file(b"plain text", "notes.txt", "text/plain"), 10_000).await.unwrap_err();
+    let stats: SessionStats = serde_wasm_bindgen::from_value(converter.get_stats().unwrap()).unwrap();
+    assert_eq!((stats.files_attempted, stats.files_succeeded, stats.files_failed), (2, 1, 1));
+}
+
+#[wasm_bindgen_test]
+async fn reject_on_error_rejects_failed_conversions() {
+    let options = serde_wasm_bindgen::to_value(&serde_json::json!({ "reject_on_error": true })).unwrap();
+    let mut converter = DocumentConverter::with_options(options).unwrap();
+    let config = serde_json::json!({ "exam_type": "NEET", "document_type": "photo", "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } } });
+    converter.set_config(&config.to_string()).unwrap();
+    let error = converter
+        .convert_file(file(b"not an image", "notes.txt", "text/plain"), JsValue::UNDEFINED)
+        .await
+        .unwrap_err();
+    assert_eq!(error.as_string().as_deref(), Some("Conversion failed: Unsupported file type: text/plain"));
+}
+
+#[wasm_bindgen_test]
+async fn batch_reports_each_file_as_it_completes() {
+    let mut converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    let completions = js_sys::Array::new();
+    converter.set_file_callback(js_sys::Function::new_with_args("completion", "this.push(completion)").bind0(&completions));
+    let files = js_sys::Array::of2(&file(PHOTO_JPG, "photo.jpg", "image/jpeg"), &file(b"plain text", "notes.txt", "text/plain"));
+    let value = converter.convert_files(files, None, JsValue::UNDEFINED).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+
+    let completions: Vec<FileCompletion> = completions.iter().map(|c| serde_wasm_bindgen::from_value(c).unwrap()).collect();
+    assert_eq!(completions.len(), 2);
+    assert_eq!(completions[0].file.as_ref().unwrap().data_url, result.files[0].data_url);
+    assert_eq!((completions[1].file_index, completions[1].file_count), (1, 2));
+    assert_eq!(completions[1].failure.as_ref().unwrap().error, result.failures[0].error);
+}
+
+#[wasm_bindgen_test]
+async fn batch_is_packaged_as_zip() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    let files = js_sys::Array::of2(
+        &file(PHOTO_JPG, "photo.jpg", "image/jpeg"),
+        &file(TRANSPARENT_PNG, "transparent.png", "image/png"),
+    );
+    let blob = converter.convert_files_to_zip(files, JsValue::UNDEFINED).await.unwrap();
+    assert_eq!(blob.type_(), "application/zip");
+    assert!(blob.size() > 0.0);
+}
+
+#[wasm_bindgen_test]
+async fn worker_protocol_round_trips_conversion_results() {
+    let request = |id: u32, op: &str, payload: JsValue| {
+        let message = js_sys::Object::new();
+        js_sys::Reflect::set(&message, &"id".into(), &id.into()).unwrap();
+        js_sys::Reflect::set(&message, &"op".into(), &op.into()).unwrap();
+        js_sys::Reflect::set(&message, &"payload".into(), &payload).unwrap();
+        message.into()
+    };
+    let field = |reply: &JsValue, key: &str| js_sys::Reflect::get(reply, &key.into()).unwrap();
+
+    let reply = handle_message(request(1, "convert", JsValue::UNDEFINED)).await;
+    assert_eq!(field(&reply, "error").as_string().as_deref(), Some("Configuration not set"));
+
+    let config = serde_json::json!({ "exam_type": "NEET", "document_type": "photo", "target_spec": { "format": ["JPEG"], "size_kb": { "max": 100 } } });
+    let reply = handle_message(request(2, "set_config", config.to_string().into())).await;
+    assert!(field(&reply, "error").is_undefined());
+    assert_eq!(field(&reply, "id").as_f64(), Some(2.0));
+
+    let photo = js_sys::Object::new();
+    js_sys::Reflect::set(&photo, &"name".into(), &"photo.jpg".into()).unwrap();
+    js_sys::Reflect::set(&photo, &"type".into(), &"image/jpeg".into()).unwrap();
+    js_sys::Reflect::set(&photo, &"data".into(), &js_sys::Uint8Array::from(PHOTO_JPG).buffer()).unwrap();
+    let payload = js_sys::Object::new();
+    js_sys::Reflect::set(&payload, &"files".into(), &js_sys::Array::of1(&photo)).unwrap();
+    let reply = handle_message(request(3, "convert", payload.into())).await;
+    let result: ConversionResult = serde_wasm_bindgen::from_value(field(&reply, "result")).unwrap();
+    assert!(result.success, "{:?}", result.error);
+    // The bytes travel once, as a transferable buffer, and not again in the data URL
+    assert!(result.files[0].data_url.is_empty());
+    let buffers: js_sys::Array = field(&reply, "buffers").into();
+    assert_eq!(buffers.length(), 1);
+    let output = js_sys::Uint8Array::new(&buffers.get(0)).to_vec();
+    assert_eq!((output.len() as u32, &output[..2]), (result.files[0].size_bytes, &[0xFF, 0xD8][..]));
+    let types: js_sys::Array = field(&reply, "types").into();
+    assert_eq!(types.get(0).as_string().as_deref(), Some("image/jpeg"));
+
+    let reply = handle_message(request(4, "resize", JsValue::UNDEFINED)).await;
+    assert_eq!(
+        field(&reply, "error").as_string().as_deref(),
+        Some(r#"Unknown worker op Some("resize"), expected set_config, convert or cancel"#)
+    );
+}
+
+#[wasm_bindgen_test]
+async fn batch_outputs_are_written_to_the_output_directory() {
+    let storage = js_sys::Reflect::get(&js_sys::global(), &"navigator".into())
+        .and_then(|navigator| js_sys::Reflect::get(&navigator, &"storage".into()))
+        .unwrap();
+    let get_directory: js_sys::Function = js_sys::Reflect::get(&storage, &"getDirectory".into()).unwrap().into();
+    let directory = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(get_directory.call0(&storage).unwrap()))
+        .await
+        .unwrap();
+
+    let mut converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    assert!(converter.set_output_directory(JsValue::from_str("converted/")).is_err());
+    converter.set_output_directory(directory.clone()).unwrap();
+    let files = js_sys::Array::of2(&file(PHOTO_JPG, "photo.jpg", "image/jpeg"), &file(PHOTO_JPG, "photo.jpg", "image/jpeg"));
+    let value = converter.convert_files(files, None, JsValue::UNDEFINED).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+    assert!(result.success, "{:?}", result.error);
+    let paths: Vec<_> = result.files.iter().map(|f| f.path.clone().unwrap()).collect();
+    assert_eq!(paths.len(), 2);
+    assert_ne!(paths[0], paths[1]);
+    for converted in &result.files {
+        assert!(converted.data_url.is_empty());
+        let handle: web_sys::FileSystemFileHandle = wasm_bindgen_futures::JsFuture::from(
+            web_sys::FileSystemDirectoryHandle::from(directory.clone()).get_file_handle(converted.path.as_deref().unwrap()),
+        )
+        .await
+        .unwrap()
+        .into();
+        let written: web_sys::File = wasm_bindgen_futures::JsFuture::from(handle.get_file()).await.unwrap().into();
+        assert_eq!(written.size() as u32, converted.size_bytes);
+    }
+
+    // A second batch into the same directory keeps the first batch's files
+    let files = js_sys::Array::of1(&file(PHOTO_JPG, "photo.jpg", "image/jpeg"));
+    let value = converter.convert_files(files, None, JsValue::UNDEFINED).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+    assert!(!paths.contains(result.files[0].path.as_ref().unwrap()));
+
+    converter.set_output_directory(JsValue::NULL).unwrap();
+    let result = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    assert!(result.files[0].path.is_none() && !result.files[0].data_url.is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn disposed_converter_rejects_further_calls() {
+    let mut converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    converter.dispose();
+    let error = converter
+        .convert_file(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), JsValue::UNDEFINED)
+        .await
+        .unwrap_err();
+    assert_eq!(error.as_string().as_deref(), Some("Converter disposed"));
+    let config = serde_json::json!({ "exam_type": "NEET", "document_type": "photo", "target_spec": { "size_kb": { "max": 100 } } });
+    assert_eq!(converter.set_config(&config.to_string()).unwrap_err().as_string().as_deref(), Some("Converter disposed"));
+}
+
+#[wasm_bindgen_test]
+async fn dispose_cancels_a_running_conversion() {
+    let converter = std::rc::Rc::new(converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } })));
+    let running = converter.clone();
+    let pending = wasm_bindgen_futures::future_to_promise(async move {
+        running.convert_file(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), JsValue::UNDEFINED).await
+    });
+    // One microtask lets the conversion start and wait on its file read
+    wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&JsValue::NULL)).await.unwrap();
+    converter.dispose();
+    let value = wasm_bindgen_futures::JsFuture::from(pending).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+    assert_eq!(result.error_code.as_deref(), Some("cancelled"));
+}
+
+#[wasm_bindgen_test]
+async fn jpeg_is_recompressed_under_size_cap() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 12 } }));
+    let result = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    assert!(result.success, "{:?}", result.error);
+    assert_eq!(result.error_code, None);
+
+    let converted = &result.files[0];
+    assert_eq!(converted.converted_name, "photo_photo.jpg");
+    assert_eq!(converted.format, "JPEG");
+    assert!(converted.size_kb <= 12);
+    assert!(converted.decode_data().unwrap().len() <= 12 * 1024);
+    match converted.transformations.last() {
+        Some(Transformation::Encode { quality: Some(quality), .. }) => assert!(*quality < 90),
+        other => panic!("expected a lossy encode, got {:?}", other),
+    }
+}
+
+#[wasm_bindgen_test]
+async fn stored_outputs_revalidate_from_their_data_url() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 12 } }));
+    let result = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    let report: ValidationReport =
+        serde_wasm_bindgen::from_value(converter.validate_data_url(&result.files[0].data_url).unwrap()).unwrap();
+    assert!(report.valid, "{:?}", report.violations);
+    assert_eq!(report.format, "JPEG");
+
+    let stricter = self::converter(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 1 } }));
+    let report: ValidationReport =
+        serde_wasm_bindgen::from_value(stricter.validate_data_url(&result.files[0].data_url).unwrap()).unwrap();
+    assert!(!report.valid);
+    assert_eq!(report.violations[0], "Format JPEG is not allowed, expected PNG");
+
+    let error = converter.validate_data_url("data:image/jpeg,raw").unwrap_err();
+    assert_eq!(error.as_string().as_deref(), Some("Invalid data URL: only base64-encoded data is supported"));
+}
+
+#[wasm_bindgen_test]
+async fn webp_is_encoded_by_the_browser() {
+    let converter = converter(serde_json::json!({ "format": ["WEBP"], "size_kb": { "max": 20 } }));
+    let result = convert(&converter, file(PHOTO_JPG, "photo.jpg", "image/jpeg")).await;
+    assert!(result.success, "{:?}", result.error);
+    let converted = &result.files[0];
+    assert_eq!((converted.format.as_str(), converted.converted_name.as_str()), ("WEBP", "photo_photo.webp"));
+    assert_eq!(converted.codec_info.encoder.as_deref(), Some("OffscreenCanvas"));
+    let data = converted.decode_data().unwrap();
+    assert!(data.len() <= 20 * 1024);
+    assert_eq!((&data[..4], &data[8..12]), (&b"RIFF"[..], &b"WEBP"[..]));
+}
+
+#[wasm_bindgen_test]
+async fn transparent_png_becomes_jpeg() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 50 } }));
+    let result = convert(&converter, file(TRANSPARENT_PNG, "logo.png", "image/png")).await;
+    assert!(result.success, "{:?}", result.error);
+
+    let converted = &result.files[0];
+    assert_eq!(converted.converted_name, "photo_logo.jpg");
+    assert!(converted.data_url.starts_with("data:image/jpeg;base64,"));
+    let dimensions = converted.dimensions.as_ref().unwrap();
+    assert_eq!((dimensions.width, dimensions.height), (64.0, 48.0));
+}
+
+#[wasm_bindgen_test]
+async fn transparent_png_gets_a_jpeg_preview_and_a_png_original() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 50 } }));
+    let pair = converter.convert_with_preview(file(TRANSPARENT_PNG, "logo.png", "image/png")).await.unwrap();
+    let PreviewPair { preview, original } = serde_wasm_bindgen::from_value(pair).unwrap();
+    assert_eq!((preview.converted_name.as_str(), original.converted_name.as_str()), ("photo_logo.jpg", "photo_logo.png"));
+    assert!(preview.data_url.starts_with("data:image/jpeg;base64,"));
+    assert!(original.data_url.starts_with("data:image/png;base64,"));
+    let size = |converted: &document_converter::core::ConvertedFile| {
+        let dimensions = converted.dimensions.as_ref().unwrap();
+        (dimensions.width, dimensions.height)
+    };
+    assert_eq!((size(&preview), size(&original)), ((64.0, 48.0), (64.0, 48.0)));
+}
+
+#[wasm_bindgen_test]
+async fn thumbnail_is_returned_with_the_conversion() {
+    let converter = converter(serde_json::json!({ "format": ["PNG"], "size_kb": { "max": 50 } }));
+    let pair = converter.convert_with_thumbnail(file(TRANSPARENT_PNG, "logo.png", "image/png"), 32).await.unwrap();
+    let ThumbnailPair { converted, thumbnail } = serde_wasm_bindgen::from_value(pair).unwrap();
+    assert_eq!(converted.converted_name, "photo_logo.png");
+    assert!(thumbnail.data_url.starts_with("data:image/png;base64,"));
+    assert_eq!((thumbnail.width, thumbnail.height), (32, 24));
+}
+
+#[wasm_bindgen_test]
+async fn each_entry_is_converted_with_its_own_spec() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    let entry = |file: web_sys::File, fields: serde_json::Value| {
+        let entry = serde_wasm_bindgen::to_value(&fields).unwrap();
+        js_sys::Reflect::set(&entry, &JsValue::from_str("file"), &file).unwrap();
+        entry
+    };
+    let signature = serde_json::json!({ "document_type": "signature", "spec": { "format": ["PNG"], "size_kb": { "max": 50 } } });
+    let entries = js_sys::Array::of2(
+        &entry(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), serde_json::json!({})),
+        &entry(file(TRANSPARENT_PNG, "sign.png", "image/png"), signature),
+    );
+    let value = converter.convert_with_specs(entries, JsValue::UNDEFINED).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+    assert!(result.success);
+    let names: Vec<(&str, &str)> = result.files.iter().map(|f| (f.converted_name.as_str(), f.format.as_str())).collect();
+    assert_eq!(names, [("photo_photo.jpg", "JPEG"), ("signature_sign.png", "PNG")]);
+    assert_eq!(result.files[1].applied_spec.size_kb.max, 50);
+
+    let missing = js_sys::Array::of1(&entry(file(PHOTO_JPG, "photo.jpg", "image/jpeg"), serde_json::json!({ "document_type": "thumb" })));
+    let error = converter.convert_with_specs(missing, JsValue::UNDEFINED).await.unwrap_err();
+    assert_eq!(error.as_string().as_deref(), Some("photo.jpg: Invalid config: no preset for exam NEET document thumb"));
+}
+
+#[wasm_bindgen_test]
+async fn entry_specs_take_numeric_sizes_from_js_objects() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    // A spec written as a JS literal, whose numbers reach serde as i64 rather than u64
+    let spec = js_sys::JSON::parse(r#"{ "format": ["JPEG"], "size_kb": { "min": 1, "max": 50 } }"#).unwrap();
+    let entry = js_sys::Object::new();
+    js_sys::Reflect::set(&entry, &JsValue::from_str("file"), &file(PHOTO_JPG, "photo.jpg", "image/jpeg")).unwrap();
+    js_sys::Reflect::set(&entry, &JsValue::from_str("spec"), &spec).unwrap();
+    let value = converter.convert_with_specs(js_sys::Array::of1(&entry), JsValue::UNDEFINED).await.unwrap();
+    let result: ConversionResult = serde_wasm_bindgen::from_value(value).unwrap();
+    assert!(result.success, "{:?}", result.error);
+    let size_kb = &result.files[0].applied_spec.size_kb;
+    assert_eq!((size_kb.min, size_kb.max), (Some(1), 50));
+}
+
+#[wasm_bindgen_test]
+async fn pdf_passes_through_unchanged() {
+    let converter = converter(serde_json::json!({ "format": ["PDF"], "size_kb": { "max": 100 } }));
+    let result = convert(&converter, file(DOCUMENT_PDF, "marks.pdf", "application/pdf")).await;
+    assert!(result.success, "{:?}", result.error);
+
+    let converted = &result.files[0];
+    assert_eq!(converted.converted_name, "photo_marks.pdf");
+    assert_eq!(converted.format, "PDF");
+    assert_eq!(converted.decode_data().unwrap(), DOCUMENT_PDF);
+    assert!(converted.dimensions.is_none());
+}
+
+#[wasm_bindgen_test]
+async fn unsupported_type_is_reported_in_result() {
+    let converter = converter(serde_json::json!({ "format": ["JPEG"], "size_kb": { "max": 100 } }));
+    let result = convert(&converter, file(b"plain text", "notes.txt", "text/plain")).await;
+    assert!(!result.success);
+    assert!(result.files.is_empty());
+    assert_eq!(result.error.as_deref(), Some("Conversion failed: Unsupported file type: text/plain"));
+    assert_eq!(result.error_code, None);
+}
+
+#[wasm_bindgen_test]
+async fn outputs_match_golden_expectations() {
+    let mut mismatches = Vec::new();
+    for case in golden::cases().iter().filter(|case| case.wasm) {
+        let mut converter = DocumentConverter::new();
+        converter.set_config(&golden::config_json(case)).unwrap();
+        let result = convert(&converter, file(golden::fixture(&case.fixture), &case.fixture, &case.mime)).await;
+        assert!(result.success, "{}: {:?}", case.name, result.error);
+        mismatches.extend(golden::check(case, &result.files[0]));
+    }
+    assert!(mismatches.is_empty(), "golden mismatches:\n{}", mismatches.join("\n"));
+}
+
+#[wasm_bindgen_test]
+fn version_matches_the_startup_log_line() {
+    let info: BuildInfo = serde_wasm_bindgen::from_value(version()).unwrap();
+    assert_eq!(startup_event(0.0).fields["build"], info.to_string());
+}
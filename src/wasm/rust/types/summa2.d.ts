@@ -0,0 +1,330 @@
+// Generated from the Rust serde types by tests/typescript.rs. Do not edit by hand.
+
+export type ConversionConfig = { exam_type: string, document_type: string, target_spec: DocumentSpec, debug_intermediates?: boolean, extension_case?: ExtensionCase, assume_source_profile?: SourceProfile, memory_check?: MemoryCheckSpec | null, max_decoded_pixels?: number, on_feature_loss?: FeatureLossPolicy, return_best_effort?: boolean, filename_template?: string | null, max_attempts?: number, embed_metadata?: boolean, };
+
+export type DocumentSpec = { format?: Array<string>, image_formats?: Array<string> | null, document_formats?: Array<string> | null, on_missing_format?: MissingFormatPolicy, size_kb: SizeSpec | string, dimensions_cm?: PhysicalDimensionsSpec | null, dimensions_mm?: PhysicalDimensionsSpec | null, pixels?: PixelSpec | null, aspect_ratio?: AspectRatioSpec | null, resolution_px_per_inch?: number | null, posterize_levels?: number | null, auto_trim?: AutoTrimSpec | null, max_megapixels?: number | null, document_scan?: DocumentScanSpec | null, auto_rotate?: AutoRotateSpec | null, deskew?: boolean, deskew_max_angle?: number | null, scanner_edges?: ScannerEdgesSpec | null, despeckle?: DespeckleSpec | null, gamma?: number | null, auto_contrast?: AutoContrast | null, clahe?: ClaheSpec | null, density_unit?: DensityUnit, dct_method?: DctMethod, quality_ladder?: Array<number> | null, quality_scale?: QualityScale, png_optimize?: boolean | null, png_profile?: PngProfile | null, png_interlaced?: boolean, output_color_type?: OutputColorType | null, output_mime_override?: string | null, required_bit_depth?: number | null, required_color_type?: ColorType | null, transparent_background?: boolean, color_mode?: ColorMode | null, on_color_mismatch?: ColorMismatchPolicy, print_orientation?: PrintOrientation, fit_mode?: FitMode, integer_scaling?: boolean, linear_light_resize?: boolean | null, pre_reduce_above?: number | null, performance_profile?: PerformanceProfile, flexible_dimensions?: boolean, ink_color?: InkColorSpec | null, background?: BackgroundSpec | null, pdf_rasterize_dpi?: number | null, pdf_a?: boolean, blank_page_max_ink?: number | null, remove_blank_pages?: boolean, duplicate_page_min_similarity?: number | null, remove_duplicate_pages?: boolean, max_pdf_colors?: number | null, canvas?: CanvasSpec | null, border?: BorderSpec | null, timestamp_overlay?: TimestampOverlaySpec | null, min_effective_dpi?: number | null, on_low_resolution?: ColorMismatchPolicy, };
+
+export type SizeSpec = { min?: number | string | null, max: number | string, };
+
+export type DimensionsSpec = { width: number, height: number, };
+
+export type PhysicalDimensionsSpec = { width?: number | null, height?: number | null, };
+
+export type PixelSpec = { width?: number | null, height?: number | null, min_width?: number | null, min_height?: number | null, max_width?: number | null, max_height?: number | null, min?: PixelDimensions | null, max?: PixelDimensions | null, min_total_pixels?: number | null, max_total_pixels?: number | null, square?: boolean, };
+
+export type PixelDimensions = { width: number, height: number, };
+
+export type AspectRatioSpec = { min?: number | null, max?: number | null, height_to_width_min?: number | null, height_to_width_max?: number | null, };
+
+export type AutoTrimSpec = { tolerance?: number, min_retained?: number, };
+
+export type DocumentScanSpec = { min_area?: number, };
+
+export type AutoRotateSpec = { min_confidence?: number, };
+
+export type ScannerEdgesSpec = { max_crop?: number, tolerance?: number, };
+
+export type DespeckleSpec = { max_size?: number, };
+
+export type AutoContrast = "stretch" | "clahe";
+
+export type ClaheSpec = { tile_size?: number, clip_limit?: number, };
+
+export type MemoryCheckSpec = { max_fraction?: number, available_mb?: number | null, };
+
+export type ExtensionCase = "lower" | "upper" | "preserve_input";
+
+export type SourceProfile = "sRGB" | "AdobeRGB" | "DisplayP3";
+
+export type FeatureLossPolicy = "Warn" | "Fail";
+
+export type DensityUnit = "Inch" | "Cm" | "None";
+
+export type DctMethod = "Islow";
+
+export type ColorMode = "color" | "grayscale" | "any";
+
+export type ColorMismatchPolicy = "fail" | "warn" | "convert";
+
+export type PrintOrientation = "Portrait" | "Landscape" | "Auto";
+
+export type FitMode = "Stretch" | "Pad" | "Cover";
+
+export type MissingFormatPolicy = "Fail" | "Jpeg";
+
+export type InkColorSpec = { allowed: Array<InkColor>, on_mismatch?: ColorMismatchPolicy, };
+
+export type InkColor = string | InkRange;
+
+export type InkRange = { name: string, hue_min?: number | null, hue_max?: number | null, saturation_min?: number, saturation_max?: number, };
+
+export type BackgroundSpec = { required: string, auto_replace?: boolean, tolerance?: number, };
+
+export type BorderSpec = { width_px: number, color?: string, };
+
+export type CanvasSpec = { width: number, height: number, background?: string, x_percent?: number, y_percent?: number, scale?: number, };
+
+export type PngProfile = "Grayscale8" | "RGB8" | "RGBA8" | "Indexed8";
+
+export type OutputColorType = "rgb8" | "rgba8";
+
+export type ColorType = "grayscale" | "grayscale_alpha" | "rgb" | "rgba" | "indexed" | "cmyk";
+
+export type QualityScale = "Native" | "IrfanView" | "Photoshop";
+
+export type PerformanceProfile = "Fast" | "Balanced" | "Best";
+
+export type TimestampOverlaySpec = { format?: string, position?: OverlayPosition, source?: TimestampSource, color?: string, box_color?: string, box_opacity?: number, };
+
+export type OverlayPosition = "TopLeft" | "TopRight" | "BottomLeft" | "BottomRight";
+
+export type TimestampSource = "Now" | "Exif";
+
+export type ConverterOptions = { log_level?: LogLevel, json_console?: boolean, timeout_ms?: number | null, reject_on_error?: boolean, max_batch_size?: number, history_max_bytes?: number | null, result_cache_bytes?: number | null, };
+
+export type ConversionResult = { success: boolean, files: Array<ConvertedFile>, error?: string | null, error_code?: string | null, failures?: Array<FailedFile>, skipped?: Array<SkippedFile>, 
+/**
+ * For a single-file conversion that failed its size limits, every encode tried
+ */
+attempts?: Array<EncodeAttempt>, };
+
+export type ConvertedFile = { original_name: string, converted_name: string, document_type: string, format: string, size_kb: number, 
+/**
+ * Exact output size; `size_kb` is this rounded down
+ */
+size_bytes: number, dimensions?: DimensionsSpec | null, 
+/**
+ * Color type and bits per pixel the output's header declares; None for PDFs
+ */
+color_type?: ColorType | null, bit_depth?: number | null, data_url: string, applied_spec: DocumentSpec, input_hash: string, transformations: Array<Transformation>, 
+/**
+ * How centrally the output sits inside the spec's limits, from 0 to 1; see `compliance_score`
+ */
+compliance_score: number, 
+/**
+ * False for a best-effort output (`return_best_effort`) that breaks the spec; the
+ * limits it breaks are in `violations`
+ */
+compliant: boolean, violations?: Array<string>, codec_info: CodecInfo, 
+/**
+ * Allowed formats tried before `format`, in order, each given up on size
+ */
+format_fallbacks?: Array<FormatFallback>, 
+/**
+ * Things the user should know about an output that still meets the spec, such as a
+ * PDF rasterized by `pdf_rasterize_dpi`
+ */
+warnings?: Array<string>, debug?: Array<DebugIntermediate>, 
+/**
+ * Measured on the decoded source when the spec sets `color_mode`; see `ops::colorfulness`
+ */
+colorfulness?: number | null, 
+/**
+ * Ink found on the source when the spec sets `ink_color`
+ */
+ink?: DetectedInk | null, 
+/**
+ * Background found around the subject when the spec sets `background`
+ */
+background?: BackgroundReport | null, 
+/**
+ * How the output's pixels map onto the source, for drawing the crop and padding over
+ * it; None for PDFs and for images warped by `document_scan` or `deskew`
+ */
+geometry?: Geometry | null, 
+/**
+ * Set on outputs of `tile`: where this tile sits in the resized image
+ */
+tile?: TilePosition | null, 
+/**
+ * Handed back from the converter's result cache rather than converted again
+ */
+cache_hit?: boolean, 
+/**
+ * Name of the file written into the directory set with `set_output_directory`; the
+ * `data_url` is empty then
+ */
+path?: string | null, };
+
+export type FailedFile = { original_name: string, input_hash?: string | null, error: string, error_code?: string | null, 
+/**
+ * Every encode tried, when the file failed its size limits
+ */
+attempts?: Array<EncodeAttempt>, };
+
+export type SkippedFile = { original_name: string, input_hash: string, reason: string, };
+
+export type FileCompletion = { 
+/**
+ * Position of the input in the batch, from 0
+ */
+file_index: number, file_count: number, file?: ConvertedFile | null, failure?: FailedFile | null, skipped?: SkippedFile | null, };
+
+export type TilePosition = { row: number, column: number, x: number, y: number, };
+
+export type Geometry = { 
+/**
+ * Decoded source size, as `[width, height]`
+ */
+source: [number, number], 
+/**
+ * Kept part of the source in source pixels; fractional where a crop after resizing
+ * falls between source pixels
+ */
+crop: Region, 
+/**
+ * Quarter turns, in degrees clockwise
+ */
+rotation: number, 
+/**
+ * Output pixels per source pixel, across and down the output
+ */
+scale: [number, number], 
+/**
+ * Margins around the picture, in output pixels
+ */
+pad: Insets, };
+
+export type Region = { x: number, y: number, width: number, height: number, };
+
+export type Insets = { left: number, top: number, right: number, bottom: number, };
+
+export type PreviewPair = { preview: ConvertedFile, original: ConvertedFile, };
+
+export type ThumbnailPair = { converted: ConvertedFile, thumbnail: Thumbnail, };
+
+export type Thumbnail = { data_url: string, width: number, height: number, };
+
+export type FormatFallback = { format: string, reason: string, };
+
+export type EncodeAttempt = { format: string, 
+/**
+ * None for lossless formats
+ */
+quality?: number | null, dimensions: [number, number], size_kb: number, };
+
+export type Transformation = { "op": "color_convert", from: SourceProfile, } | { "op": "perspective", corners: [[number, number], [number, number], [number, number], [number, number]], width: number, height: number, } | { "op": "deskew", degrees: number, width: number, height: number, } | { "op": "gamma", gamma: number, } | { "op": "remove_edges", left: number, top: number, right: number, bottom: number, } | { "op": "trim", x: number, y: number, width: number, height: number, } | { "op": "auto_contrast", mode: AutoContrast, } | { "op": "crop", x: number, y: number, width: number, height: number, } | { "op": "pad", left: number, top: number, right: number, bottom: number, color: string, } | { "op": "border", width: number, color: string, } | { "op": "resize", from: [number, number], to: [number, number], } | { "op": "posterize", levels: number, } | { "op": "despeckle", specks: number, } | { "op": "replace_background", color: string, } | { "op": "flatten", color: string, } | { "op": "rotate", degrees: number, } | { "op": "timestamp", text: string, } | { "op": "rasterize", pages: number, dpi: number, } | { "op": "page_rotate", page: number, degrees: number, } | { "op": "remove_blank_pages", pages: Array<number>, } | { "op": "remove_duplicate_pages", pages: Array<number>, } | { "op": "reduce_colors", pages: number, colors: number, } | { "op": "pdf_a", pages: number, } | { "op": "transparent_background" } | { "op": "grayscale" } | { "op": "encode", format: string, quality: number | null, bytes: number, } | { "op": "embed_metadata", bytes: number, } | { "op": "keep_animation", frames: number, };
+
+export type DebugIntermediate = { stage: string, width: number, height: number, transformation?: Transformation | null, data_url: string, };
+
+export type ConversionPlan = { original_name: string, converted_name: string, source_format: string, source_dimensions?: [number, number] | null, source_orientation?: number | null, target_format: string, target_dimensions?: [number, number] | null, max_size_kb: number, transformations: Array<Transformation>, notes: Array<string>, };
+
+export type PresetLoadReport = { version?: string | null, loaded: number, rejected: Array<RejectedPreset>, not_modified: boolean, };
+
+export type RejectedPreset = { 
+/**
+ * Position of the entry in the bundle's `presets` array
+ */
+index: number, exam_type?: string | null, document_type?: string | null, error: string, };
+
+export type DetectedInk = { 
+/**
+ * Name of the allowed range the ink fell in; otherwise the built-in color it looks
+ * like, or "other"
+ */
+color: string, allowed: boolean, 
+/**
+ * Dominant hue in degrees; absent when the ink is unsaturated (black or gray)
+ */
+hue?: number | null, 
+/**
+ * Mean HSV saturation of the ink pixels, from 0 to 1
+ */
+saturation: number, 
+/**
+ * Mean ink color, for showing the user what was detected
+ */
+rgb: [number, number, number], };
+
+export type BackgroundReport = { 
+/**
+ * Detected background color as `#RRGGBB`
+ */
+color: string, 
+/**
+ * Fraction of the image connected to the border in that color
+ */
+coverage: number, 
+/**
+ * Whether the detected color was already within tolerance of `required`
+ */
+matches: boolean, replaced: boolean, 
+/**
+ * Why a mismatched background was left alone
+ */
+warning?: string | null, };
+
+export type ValidationReport = { valid: boolean, 
+/**
+ * Format found in the data itself, named as in `ConvertedFile::format`
+ */
+format: string, size_kb: number, size_bytes: number, 
+/**
+ * None for PDFs
+ */
+dimensions?: DimensionsSpec | null, compliance_score: number, 
+/**
+ * Every limit the file breaks; empty when `valid`
+ */
+violations: Array<string>, };
+
+export type HistoryEntry = { 
+/**
+ * Assigned by the store in the order entries were saved
+ */
+id: number, converted_name: string, document_type: string, exam_type: string, size_bytes: number, 
+/**
+ * Milliseconds since the Unix epoch
+ */
+timestamp: number, 
+/**
+ * `spec_hash` of the spec the file was converted to, so entries made under
+ * requirements that have since changed can be told apart
+ */
+spec_hash: string, };
+
+export type ImageAnalysis = { width: number, height: number, luminance: LuminanceStats, unique_colors: number, edge_density: number, has_transparency: boolean, 
+/**
+ * Color type and bits per pixel the image is stored in: as its JPEG or PNG header
+ * declares them for `analyze_data`, so a palette stays indexed, or as decoded
+ */
+color_type: ColorType, bit_depth: number, 
+/**
+ * EXIF orientation (1..=8) of the source, as recorded; the statistics above are
+ * computed on the stored pixels, unrotated. Only `analyze_data` can read it.
+ */
+orientation?: number | null, };
+
+export type LuminanceStats = { mean: number, min: number, max: number, stddev: number, };
+
+export type SelfTestReport = { version: string, passed: boolean, codecs: Array<CodecCheck>, };
+
+export type BuildInfo = { version: string, git_commit: string, profile: string, features: Array<string>, };
+
+export type CodecCheck = { format: string, passed: boolean, error?: string | null, };
+
+export type CodecInfo = { 
+/**
+ * Version of the `image` crate, which decodes and resizes every image input
+ */
+image_version: string, 
+/**
+ * Crate that encoded the output: `image` for JPEG, `png` for PNG, or the host
+ * encoder for formats built without one (`OffscreenCanvas` for WebP in browsers);
+ * absent for PDFs, which are passed through unchanged
+ */
+encoder?: string | null, encoder_version?: string | null, };
+
+export type SessionStats = { files_attempted: number, files_succeeded: number, files_failed: number, files_cancelled: number, files_skipped: number, input_bytes: number, output_bytes: number, encode_passes: number, cache_hits: number, };
+
+export type ProgressEvent = { file_name: string, stage: ProgressStage, pass: number, overall_percent: number, file_index: number, file_count: number, };
+
+export type ProgressStage = "reading" | "decoding" | "resizing" | "encoding" | "validating";
+
+export type LogEvent = { ts: number, level: LogLevel, event: LogEventKind, file?: string | null, fields: { [key in string]?: JsonValue }, };
+
+export type LogLevel = "debug" | "info" | "warn" | "error";
+
+export type LogEventKind = "read" | "decode" | "resize" | "posterize" | "trim" | "encode_pass" | "encode" | "pdf_check" | "validate" | "converted" | "failed" | "color_convert" | "document_scan" | "tile" | "feature_loss" | "color_check" | "rotate" | "ink_check" | "background" | "resolution_check" | "format_fallback" | "cache_hit" | "pdf_rasterize" | "history" | "timestamp" | "deskew" | "despeckle" | "auto_contrast" | "gamma" | "animation" | "scanner_edges" | "transparency" | "config" | "presets" | "batch" | "startup";
+
+export type JsonValue = number | string | boolean | Array<JsonValue> | { [key in string]?: JsonValue } | null;